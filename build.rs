@@ -0,0 +1,136 @@
+//构建脚本：把 resources/ 下的全部文件压缩后嵌入内核映像，生成 src/resources.rs 使用的资源表；
+//把 abi/syscalls.golden 转换成 src/abi.rs 在编译时比较的常量
+//资源名是相对 resources/ 的路径(用 / 分隔)，按名字排序，同样的输入总是生成同样的映像
+//压缩格式(解压见 src/resources.rs)：控制字节 c < 0x80 后面是 c+1 个原样的字节；
+//c >= 0x80 表示重复前面的内容，长度 (c & 0x7f) + 3，后面两字节(小端)是往回的距离
+//压缩后不比原来小的文件原样存放
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 0x7f + MIN_MATCH;
+const MAX_LITERALS: usize = 0x80;
+const WINDOW: usize = 0xffff;
+const CHAIN_LIMIT: usize = 64; //每个位置最多尝试的候选
+
+fn collect(dir: &Path, prefix: &str, out: &mut Vec<(String, PathBuf)>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        let full = if prefix.is_empty() { name.clone() } else { format!("{}/{}", prefix, name) };
+        if path.is_dir() {
+            collect(&path, &full, out);
+        } else {
+            out.push((full, path));
+        }
+    }
+}
+
+fn flush_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        out.push((chunk.len() - 1) as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+//贪心的 LZ77：每个位置找最长的匹配，三字节前缀相同的位置串成链
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut chains: HashMap<[u8; 3], Vec<usize>> = HashMap::new();
+    let mut literal_start = 0;
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0); //(长度, 距离)
+        if pos + MIN_MATCH <= data.len() {
+            let key = [data[pos], data[pos + 1], data[pos + 2]];
+            if let Some(chain) = chains.get(&key) {
+                for &candidate in chain.iter().rev().take(CHAIN_LIMIT) {
+                    if pos - candidate > WINDOW {
+                        break;
+                    }
+                    let len = data[candidate..].iter().zip(&data[pos..]).take(MAX_MATCH).take_while(|(a, b)| a == b).count();
+                    if len > best.0 {
+                        best = (len, pos - candidate);
+                    }
+                }
+            }
+        }
+        let step = if best.0 >= MIN_MATCH { best.0 } else { 1 };
+        for i in pos..(pos + step).min(data.len().saturating_sub(MIN_MATCH - 1)) {
+            chains.entry([data[i], data[i + 1], data[i + 2]]).or_default().push(i);
+        }
+        if best.0 >= MIN_MATCH {
+            flush_literals(&mut out, &data[literal_start..pos]);
+            out.push(0x80 | (best.0 - MIN_MATCH) as u8);
+            out.extend_from_slice(&(best.1 as u16).to_le_bytes());
+            literal_start = pos + best.0;
+        }
+        pos += step;
+    }
+    flush_literals(&mut out, &data[literal_start..]);
+    out
+}
+
+//黄金文件的格式见 src/abi.rs，写错的行直接让构建失败
+fn abi_golden(path: &Path) -> String {
+    let text = fs::read_to_string(path).unwrap();
+    let mut syscalls = String::new();
+    let mut structs = String::new();
+    for (number, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [] => {}
+            [first, ..] if first.starts_with('#') => {}
+            ["syscall", number, name, args @ ..] if number.parse::<u64>().is_ok() => {
+                syscalls.push_str(&format!("    Syscall {{ number: {}, name: {:?}, args: &{:?} }},\n", number, name, args));
+            }
+            ["struct", name, size] if size.parse::<u64>().is_ok() => {
+                structs.push_str(&format!("    Struct {{ name: {:?}, size: {} }},\n", name, size));
+            }
+            _ => panic!("{}:{}: malformed line: {}", path.display(), number + 1, line),
+        }
+    }
+    format!(
+        "//由 build.rs 生成\nconst GOLDEN_SYSCALLS: &[Syscall] = &[\n{}];\nconst GOLDEN_STRUCTS: &[Struct] = &[\n{}];\n",
+        syscalls, structs
+    )
+}
+
+fn main() {
+    let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("resources");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed=resources");
+
+    let mut files = Vec::new();
+    collect(&root, "", &mut files);
+    files.sort();
+
+    let mut table = String::from("//由 build.rs 生成\n&[\n");
+    for (i, (name, path)) in files.iter().enumerate() {
+        println!("cargo:rerun-if-changed={}", path.display());
+        let data = fs::read(path).unwrap();
+        let packed = compress(&data);
+        let compressed = packed.len() < data.len();
+        let blob = out_dir.join(format!("resource{}.bin", i));
+        fs::write(&blob, if compressed { &packed } else { &data }).unwrap();
+        table.push_str(&format!(
+            "    Resource {{ name: {:?}, size: {}, compressed: {}, data: include_bytes!({:?}) }},\n",
+            name,
+            data.len(),
+            compressed,
+            blob.display().to_string()
+        ));
+    }
+    table.push_str("]\n");
+    fs::write(out_dir.join("resources.rs"), table).unwrap();
+
+    let golden = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("abi/syscalls.golden");
+    println!("cargo:rerun-if-changed={}", golden.display());
+    fs::write(out_dir.join("abi_golden.rs"), abi_golden(&golden)).unwrap();
+}
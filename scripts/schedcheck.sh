@@ -0,0 +1,29 @@
+#!/bin/sh
+# 在 QEMU 的 -icount 下启动内核并运行 schedcheck(见 src/schedcheck.rs)，结果是 pass 时退出码为 0
+# 用法：scripts/schedcheck.sh [超时秒数]，需要 cargo bootimage 和 qemu-system-x86_64
+set -eu
+cd "$(dirname "$0")/.."
+cargo bootimage
+image=target/x86_64-joakim_os/debug/bootimage-Joakim_os.bin
+log=$(mktemp)
+trap 'rm -f "$log"' EXIT
+
+# 命令行通过 fw_cfg 传入；内核不会自己关机，看到结果后结束 QEMU
+timeout "${1:-120}" qemu-system-x86_64 \
+    -drive format=raw,file="$image" \
+    -icount shift=0 -nic none -display none -serial stdio \
+    -fw_cfg name=opt/joakimos/cmdline,string=schedcheck >"$log" 2>&1 &
+qemu=$!
+until grep -q "^schedcheck: result" "$log"; do
+    kill -0 "$qemu" 2>/dev/null || break
+    sleep 1
+done
+kill "$qemu" 2>/dev/null || true
+wait "$qemu" 2>/dev/null || true
+
+if ! grep "^schedcheck: " "$log"; then
+    echo "schedcheck: no result, boot log:" >&2
+    cat "$log" >&2
+    exit 1
+fi
+grep -q "^schedcheck: result pass" "$log"
@@ -0,0 +1,136 @@
+//用户程序看到的内核 ABI：系统调用号、参数的排列和通过指针交换的结构大小
+//黄金文件 abi/syscalls.golden 由 build.rs 读入，编译时与这里的表逐项比较，不一致时构建失败，
+//避免按旧头文件编译的用户程序悄悄出错；有意修改 ABI 时同步改黄金文件(shell 命令 abi 输出当前的表)
+//黄金文件每行一项，# 开头的行是注释：
+//  syscall <调用号> <名字> [<参数>:<类型> ...]   参数依次放在 rdi、rsi、rdx
+//  struct <名字> <大小>                         用户内存中的结构
+//类型：u64/i64 是数值，*T 是指向用户内存的指针，*T[n] 是 n 个元素的数组
+use crate::signal;
+use crate::syscall::*;
+use crate::usermode::UserContext;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+pub struct Syscall {
+    pub number: u64,
+    pub name: &'static str,
+    pub args: &'static [&'static str],
+}
+
+pub struct Struct {
+    pub name: &'static str,
+    pub size: u64,
+}
+
+pub const SYSCALLS: &[Syscall] = &[
+    Syscall { number: SYS_WRITE, name: "write", args: &["fd:u64", "buf:*u8", "len:u64"] },
+    Syscall { number: SYS_EXIT, name: "exit", args: &["code:i64"] },
+    Syscall { number: SYS_SLEEP, name: "sleep", args: &["ms:u64"] },
+    Syscall { number: SYS_GETPID, name: "getpid", args: &[] },
+    Syscall { number: SYS_READ, name: "read", args: &["fd:u64", "buf:*u8", "len:u64"] },
+    Syscall { number: SYS_PIPE, name: "pipe", args: &["fds:*u32[2]"] },
+    Syscall { number: SYS_FCNTL, name: "fcntl", args: &["fd:u64", "cmd:u64", "arg:u64"] },
+    Syscall { number: SYS_UDP_CONNECT, name: "udp_connect", args: &["addr:u64", "peer_port:u64", "local_port:u64"] },
+    Syscall { number: SYS_SOCKETPAIR, name: "socketpair", args: &["kind:u64", "fds:*u32[2]"] },
+    Syscall { number: SYS_BIND, name: "bind", args: &["path:*u8", "len:u64", "kind:u64"] },
+    Syscall { number: SYS_CONNECT, name: "connect", args: &["path:*u8", "len:u64"] },
+    Syscall { number: SYS_ACCEPT, name: "accept", args: &["fd:u64"] },
+    Syscall { number: SYS_SENDMSG, name: "sendmsg", args: &["fd:u64", "msg:*msg_header"] },
+    Syscall { number: SYS_RECVMSG, name: "recvmsg", args: &["fd:u64", "msg:*msg_header"] },
+    Syscall { number: SYS_MMAP, name: "mmap", args: &["len:u64", "prot:u64", "fd:i64"] },
+    Syscall { number: SYS_MUNMAP, name: "munmap", args: &["addr:u64", "len:u64"] },
+    Syscall { number: SYS_SHM_OPEN, name: "shm_open", args: &["name:*u8", "len:u64", "flags:u64"] },
+    Syscall { number: SYS_SHM_MAP, name: "shm_map", args: &["fd:u64", "len:u64", "prot:u64"] },
+    Syscall { number: SYS_SHM_UNLINK, name: "shm_unlink", args: &["name:*u8", "len:u64"] },
+    Syscall { number: SYS_FORK, name: "fork", args: &[] },
+    Syscall { number: SYS_EXEC, name: "exec", args: &["path:*u8", "len:u64", "argv:**u8"] },
+    Syscall { number: SYS_WAIT, name: "wait", args: &["pid:u64", "status:*i64"] },
+    Syscall { number: SYS_KILL, name: "kill", args: &["pid:u64", "sig:u64"] },
+    Syscall { number: SYS_SIGACTION, name: "sigaction", args: &["sig:u64", "handler:u64", "restorer:u64"] },
+    Syscall { number: SYS_SIGRETURN, name: "sigreturn", args: &[] },
+    Syscall { number: SYS_GETUID, name: "getuid", args: &[] },
+    Syscall { number: SYS_GETGID, name: "getgid", args: &[] },
+    Syscall { number: SYS_SETUID, name: "setuid", args: &["uid:u64", "gid:u64"] },
+];
+
+pub const STRUCTS: &[Struct] = &[
+    Struct { name: "msg_header", size: MSG_HEADER_SIZE },
+    Struct { name: "user_context", size: size_of::<UserContext>() as u64 },
+    Struct { name: "signal_frame", size: signal::FRAME_SIZE },
+];
+
+//build.rs 从黄金文件生成的 GOLDEN_SYSCALLS 和 GOLDEN_STRUCTS
+include!(concat!(env!("OUT_DIR"), "/abi_golden.rs"));
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn syscalls_eq(a: &[Syscall], b: &[Syscall]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i].number != b[i].number || !str_eq(a[i].name, b[i].name) || a[i].args.len() != b[i].args.len() {
+            return false;
+        }
+        let mut j = 0;
+        while j < a[i].args.len() {
+            if !str_eq(a[i].args[j], b[i].args[j]) {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn structs_eq(a: &[Struct], b: &[Struct]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if !str_eq(a[i].name, b[i].name) || a[i].size != b[i].size {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+const _: () = assert!(SYSCALLS.len() == SYSCALL_COUNT, "abi::SYSCALLS does not list every system call");
+const _: () = assert!(syscalls_eq(SYSCALLS, GOLDEN_SYSCALLS), "system call ABI differs from abi/syscalls.golden");
+const _: () = assert!(structs_eq(STRUCTS, GOLDEN_STRUCTS), "ABI struct sizes differ from abi/syscalls.golden");
+
+//当前的表，格式与黄金文件相同
+pub fn report() -> Vec<String> {
+    let mut lines = Vec::new();
+    for syscall in SYSCALLS {
+        let mut line = format!("syscall {} {}", syscall.number, syscall.name);
+        for arg in syscall.args {
+            line.push(' ');
+            line.push_str(arg);
+        }
+        lines.push(line);
+    }
+    for s in STRUCTS {
+        lines.push(format!("struct {} {}", s.name, s.size));
+    }
+    lines
+}
@@ -0,0 +1,114 @@
+//伙伴系统：块的大小是 2 的幂并且按大小对齐，每种大小一个空闲链表，链表指针放在空闲块的开头
+//分配时取能满足的最小块，大块逐次对半分开；释放时地址只差一位的伙伴也空闲就合并成大一倍的块
+//对齐要求不超过块大小时自然满足；浪费最多接近一半，换来分配和释放都只要 O(log n) 次分裂或合并
+use super::KernelAllocator;
+use core::alloc::Layout;
+use core::ptr;
+
+const MIN_ORDER: usize = 4; //最小的块 16 字节，能放下链表指针
+const ORDERS: usize = usize::BITS as usize;
+
+pub struct Buddy {
+    free: [usize; ORDERS], //每种大小的空闲链表头，0 表示空
+    size: usize,
+    used: usize,
+}
+
+impl Buddy {
+    pub const fn new() -> Buddy {
+        Buddy { free: [0; ORDERS], size: 0, used: 0 }
+    }
+
+    unsafe fn push(&mut self, order: usize, block: usize) {
+        *(block as *mut usize) = self.free[order];
+        self.free[order] = block;
+    }
+
+    unsafe fn pop(&mut self, order: usize) -> Option<usize> {
+        let block = self.free[order];
+        if block == 0 {
+            return None;
+        }
+        self.free[order] = *(block as *const usize);
+        Some(block)
+    }
+
+    //从 order 的空闲链表中找到并取出 block，不在链表里时返回 false
+    unsafe fn remove(&mut self, order: usize, block: usize) -> bool {
+        let mut link = &mut self.free[order] as *mut usize;
+        while *link != 0 {
+            if *link == block {
+                *link = *(block as *const usize);
+                return true;
+            }
+            link = *link as *mut usize;
+        }
+        false
+    }
+}
+
+//满足 layout 的块的大小(取 2 的对数)
+fn order(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align()).max(1 << MIN_ORDER).checked_next_power_of_two()?;
+    Some(size.trailing_zeros() as usize)
+}
+
+impl KernelAllocator for Buddy {
+    fn name(&self) -> &'static str {
+        "buddy"
+    }
+
+    //把内存切成尽量大的、按大小对齐的块
+    unsafe fn init(&mut self, start: *mut u8, size: usize) {
+        let end = start as usize + size;
+        let mut block = (start as usize + (1 << MIN_ORDER) - 1) & !((1 << MIN_ORDER) - 1);
+        while block + (1 << MIN_ORDER) <= end {
+            let mut order = (block.trailing_zeros() as usize).min(ORDERS - 1);
+            while block + (1 << order) > end {
+                order -= 1;
+            }
+            self.push(order, block);
+            self.size += 1 << order;
+            block += 1 << order;
+        }
+    }
+
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let order = match order(layout) {
+            Some(order) => order,
+            None => return ptr::null_mut(),
+        };
+        let found = match (order..ORDERS).find(|&k| self.free[k] != 0) {
+            Some(found) => found,
+            None => return ptr::null_mut(),
+        };
+        unsafe {
+            let block = self.pop(found).unwrap();
+            //多余的后一半依次放回小一级的链表
+            for k in (order..found).rev() {
+                self.push(k, block + (1 << k));
+            }
+            self.used += 1 << order;
+            block as *mut u8
+        }
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let mut order = order(layout).unwrap();
+        let mut block = ptr as usize;
+        self.used -= 1 << order;
+        while order + 1 < ORDERS && self.remove(order, block ^ (1 << order)) {
+            block &= !(1 << order);
+            order += 1;
+        }
+        self.push(order, block);
+    }
+
+    fn used(&self) -> usize {
+        self.used
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+}
@@ -0,0 +1,60 @@
+//只向前分配：记下下一个空闲地址和还没有释放的分配个数，个数回到 0 时整个堆一起回收
+//长期持有的分配会让堆只增不减，只适合用来测量其他实现的开销
+use super::KernelAllocator;
+use core::alloc::Layout;
+use core::ptr;
+
+pub struct Bump {
+    start: usize,
+    end: usize,
+    next: usize,
+    live: usize, //还没有释放的分配个数
+}
+
+impl Bump {
+    pub const fn new() -> Bump {
+        Bump { start: 0, end: 0, next: 0, live: 0 }
+    }
+}
+
+impl KernelAllocator for Bump {
+    fn name(&self) -> &'static str {
+        "bump"
+    }
+
+    unsafe fn init(&mut self, start: *mut u8, size: usize) {
+        self.start = start as usize;
+        self.end = start as usize + size;
+        self.next = self.start;
+    }
+
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let start = match self.next.checked_add(layout.align() - 1) {
+            Some(addr) => addr & !(layout.align() - 1),
+            None => return ptr::null_mut(),
+        };
+        match start.checked_add(layout.size()) {
+            Some(end) if end <= self.end => {
+                self.next = end;
+                self.live += 1;
+                start as *mut u8
+            }
+            _ => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn deallocate(&mut self, _ptr: *mut u8, _layout: Layout) {
+        self.live -= 1;
+        if self.live == 0 {
+            self.next = self.start;
+        }
+    }
+
+    fn used(&self) -> usize {
+        self.next - self.start
+    }
+
+    fn size(&self) -> usize {
+        self.end - self.start
+    }
+}
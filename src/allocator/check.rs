@@ -0,0 +1,183 @@
+//与实现无关的堆测试：在一块从内核堆借来的 64 KiB 上新建一个分配器，依次检查
+//  basic    各种大小和对齐的分配：地址对齐、在范围内、互不重叠、写入的内容不被破坏，全部释放后 used 回到 0
+//  exhaust  一直分配到失败(必须返回空指针而不是出错)，全部释放后能重新分配大块
+//  stress   伪随机的分配和释放交错进行，最多同时持有 LIVE 个，释放前检查内容
+//随机数的种子固定，每次运行的序列相同，出错时可以重现
+use super::KernelAllocator;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+
+const SCRATCH_SIZE: usize = 64 * 1024;
+const STRESS_STEPS: usize = 4000;
+const LIVE: usize = 64;
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Report {
+    pub allocations: u64,
+    pub failures: u64, //stress 中内存不足的次数，不算错误
+    pub peak: usize,   //used 的最大值
+}
+
+struct Block {
+    addr: usize,
+    layout: Layout,
+    tag: u8,
+}
+
+struct Tester<'a, A: KernelAllocator> {
+    allocator: &'a mut A,
+    start: usize,
+    live: Vec<Block>,
+    report: Report,
+}
+
+impl<A: KernelAllocator> Tester<'_, A> {
+    //分配并检查地址，成功时用 tag 填满；内存不足时返回 Ok(false)
+    fn allocate(&mut self, layout: Layout, tag: u8) -> Result<bool, String> {
+        let addr = self.allocator.allocate(layout) as usize;
+        if addr == 0 {
+            return Ok(false);
+        }
+        self.report.allocations += 1;
+        self.report.peak = self.report.peak.max(self.allocator.used());
+        if !addr.is_multiple_of(layout.align()) {
+            return Err(format!("{:#x} is not aligned to {}", addr, layout.align()));
+        }
+        if addr < self.start || addr + layout.size() > self.start + SCRATCH_SIZE {
+            return Err(format!("{:#x}+{} is outside the heap", addr, layout.size()));
+        }
+        if let Some(other) = self.live.iter().find(|b| addr < b.addr + b.layout.size() && b.addr < addr + layout.size()) {
+            return Err(format!("{:#x}+{} overlaps {:#x}+{}", addr, layout.size(), other.addr, other.layout.size()));
+        }
+        unsafe { core::ptr::write_bytes(addr as *mut u8, tag, layout.size()) };
+        self.live.push(Block { addr, layout, tag });
+        Ok(true)
+    }
+
+    //检查内容后释放第 index 个
+    fn free(&mut self, index: usize) -> Result<(), String> {
+        let block = self.live.swap_remove(index);
+        let data = unsafe { core::slice::from_raw_parts(block.addr as *const u8, block.layout.size()) };
+        if let Some(offset) = data.iter().position(|&byte| byte != block.tag) {
+            return Err(format!("{:#x}+{} corrupted at offset {}", block.addr, block.layout.size(), offset));
+        }
+        unsafe { self.allocator.deallocate(block.addr as *mut u8, block.layout) };
+        Ok(())
+    }
+
+    fn free_all(&mut self) -> Result<(), String> {
+        while !self.live.is_empty() {
+            self.free(self.live.len() - 1)?;
+        }
+        match self.allocator.used() {
+            0 => Ok(()),
+            used => Err(format!("{} bytes still used after freeing everything", used)),
+        }
+    }
+
+    fn basic(&mut self) -> Result<(), String> {
+        const LAYOUTS: [(usize, usize); 10] =
+            [(1, 1), (7, 1), (8, 8), (24, 8), (100, 4), (256, 256), (1000, 8), (4096, 4096), (3000, 64), (16, 16)];
+        for (i, &(size, align)) in LAYOUTS.iter().enumerate() {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            if !self.allocate(layout, i as u8 + 1)? {
+                return Err(format!("out of memory allocating {} bytes aligned to {}", size, align));
+            }
+        }
+        self.free_all()
+    }
+
+    fn exhaust(&mut self) -> Result<(), String> {
+        let layout = Layout::from_size_align(1024, 8).unwrap();
+        let mut count = 0;
+        while self.allocate(layout, count as u8)? {
+            count += 1;
+            if count > SCRATCH_SIZE / 1024 {
+                return Err(format!("handed out {} blocks of 1 KiB from a {} KiB heap", count, SCRATCH_SIZE / 1024));
+            }
+        }
+        if count == 0 {
+            return Err(String::from("no 1 KiB block could be allocated"));
+        }
+        self.free_all()?;
+        let big = Layout::from_size_align(SCRATCH_SIZE / 4, 8).unwrap();
+        if !self.allocate(big, 0xaa)? {
+            return Err(String::from("memory was not reclaimed after freeing everything"));
+        }
+        self.free_all()
+    }
+
+    fn stress(&mut self) -> Result<(), String> {
+        let mut state = SEED;
+        let mut next = move || {
+            //xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for step in 0..STRESS_STEPS {
+            let r = next();
+            if !self.live.is_empty() && (self.live.len() >= LIVE || r % 3 == 0) {
+                self.free((r >> 8) as usize % self.live.len())?;
+                continue;
+            }
+            let size = if r % 16 == 1 { 1 + (r >> 8) as usize % 4096 } else { 1 + (r >> 8) as usize % 256 };
+            let align = 1 << ((r >> 32) % 7);
+            let layout = Layout::from_size_align(size, align).unwrap();
+            if !self.allocate(layout, step as u8)? {
+                self.report.failures += 1;
+            }
+        }
+        self.free_all()
+    }
+}
+
+fn run_tests<A: KernelAllocator>(tester: &mut Tester<A>) -> Result<(), String> {
+    tester.basic().map_err(|err| format!("basic: {}", err))?;
+    tester.exhaust().map_err(|err| format!("exhaust: {}", err))?;
+    tester.stress().map_err(|err| format!("stress: {}", err))
+}
+
+//在新的 allocator 上运行全部测试，出错时返回测试名和原因
+pub fn run<A: KernelAllocator>(mut allocator: A) -> Result<Report, String> {
+    let scratch_layout = Layout::from_size_align(SCRATCH_SIZE, 4096).unwrap();
+    let scratch = unsafe { alloc::alloc::alloc(scratch_layout) };
+    if scratch.is_null() {
+        return Err(String::from("no memory for the test heap"));
+    }
+    unsafe { allocator.init(scratch, SCRATCH_SIZE) };
+    let mut tester = Tester { allocator: &mut allocator, start: scratch as usize, live: Vec::with_capacity(LIVE), report: Report::default() };
+    let result = run_tests(&mut tester).map(|()| tester.report);
+    unsafe { alloc::alloc::dealloc(scratch, scratch_layout) };
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use crate::allocator::{buddy::Buddy, bump::Bump, linked_list::LinkedList, slab::Slab};
+
+    #[test]
+    fn bump() {
+        run(Bump::new()).unwrap();
+    }
+
+    #[test]
+    fn buddy() {
+        run(Buddy::new()).unwrap();
+    }
+
+    #[test]
+    fn slab() {
+        run(Slab::new()).unwrap();
+    }
+
+    #[test]
+    fn linked_list() {
+        run(LinkedList::new()).unwrap();
+    }
+}
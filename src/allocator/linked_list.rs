@@ -0,0 +1,39 @@
+//首次适配的空闲链表，由 linked_list_allocator 实现；默认的全局分配器
+use super::KernelAllocator;
+use core::alloc::Layout;
+use core::ptr::{self, NonNull};
+use linked_list_allocator::Heap;
+
+pub struct LinkedList(Heap);
+
+impl LinkedList {
+    pub const fn new() -> LinkedList {
+        LinkedList(Heap::empty())
+    }
+}
+
+impl KernelAllocator for LinkedList {
+    fn name(&self) -> &'static str {
+        "linked-list"
+    }
+
+    unsafe fn init(&mut self, start: *mut u8, size: usize) {
+        self.0.init(start, size);
+    }
+
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        self.0.allocate_first_fit(layout).map_or(ptr::null_mut(), |block| block.as_ptr())
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        self.0.deallocate(NonNull::new_unchecked(ptr), layout);
+    }
+
+    fn used(&self) -> usize {
+        self.0.used()
+    }
+
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+}
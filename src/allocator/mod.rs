@@ -0,0 +1,101 @@
+//内核堆：全局分配器的实现在构建时用 cargo 特性选择，都实现 KernelAllocator，换一种实现不需要改动其他代码
+//  alloc-bump   只向前分配，全部释放后才回收，最快，用来对比其他实现的开销
+//  alloc-buddy  伙伴系统，块大小是 2 的幂，释放时与相邻的伙伴合并
+//  alloc-slab   小对象按大小分类放在 4 KiB 的 slab 里，大的分配交给链表分配器
+//  都不选时使用 linked_list_allocator 的首次适配链表
+//check 是与实现无关的一致性和压力测试，shell 命令 allocator check 对选中的实现运行一遍
+use core::alloc::{GlobalAlloc, Layout};
+use spin::Mutex;
+
+pub mod buddy;
+pub mod bump;
+pub mod check;
+pub mod linked_list;
+pub mod slab;
+
+//内核堆直接放在 .bss 段里的一块静态数组上，不需要先建立分页映射
+const HEAP_SIZE: usize = 1024 * 1024; //堆大小 1 MiB
+
+static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+
+//一种堆的实现：管理 init 给出的一块内存，调用者负责加锁
+pub trait KernelAllocator {
+    fn name(&self) -> &'static str;
+
+    //只能调用一次，[start, start + size) 此后归分配器所有
+    unsafe fn init(&mut self, start: *mut u8, size: usize);
+
+    //失败时返回空指针
+    fn allocate(&mut self, layout: Layout) -> *mut u8;
+
+    //ptr 必须是用同样的 layout 从这个分配器得到的
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout);
+
+    //已分配的字节数(包括对齐和取整浪费的部分)
+    fn used(&self) -> usize;
+
+    fn size(&self) -> usize;
+}
+
+//alloc-* 特性最多选一个
+#[cfg(any(
+    all(feature = "alloc-bump", feature = "alloc-buddy"),
+    all(feature = "alloc-bump", feature = "alloc-slab"),
+    all(feature = "alloc-buddy", feature = "alloc-slab"),
+))]
+compile_error!("the alloc-bump, alloc-buddy and alloc-slab features are mutually exclusive");
+
+#[cfg(feature = "alloc-bump")]
+pub type Selected = bump::Bump;
+#[cfg(all(feature = "alloc-buddy", not(feature = "alloc-bump")))]
+pub type Selected = buddy::Buddy;
+#[cfg(all(feature = "alloc-slab", not(any(feature = "alloc-bump", feature = "alloc-buddy"))))]
+pub type Selected = slab::Slab;
+#[cfg(not(any(feature = "alloc-bump", feature = "alloc-buddy", feature = "alloc-slab")))]
+pub type Selected = linked_list::LinkedList;
+
+//用自旋锁包装一个实现，作为 alloc 库使用的全局分配器
+pub struct Locked<A>(Mutex<A>);
+
+impl<A> Locked<A> {
+    pub const fn new(allocator: A) -> Locked<A> {
+        Locked(Mutex::new(allocator))
+    }
+}
+
+unsafe impl<A: KernelAllocator> GlobalAlloc for Locked<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0.lock().allocate(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().deallocate(ptr, layout)
+    }
+}
+
+#[cfg_attr(not(test), global_allocator)] //把 ALLOCATOR 注册为 alloc 库(Box、Vec、String 等)使用的全局分配器
+static ALLOCATOR: Locked<Selected> = Locked::new(Selected::new());
+
+//必须在第一次使用 alloc 类型之前调用
+pub fn init() {
+    unsafe {
+        ALLOCATOR.0.lock().init(core::ptr::addr_of_mut!(HEAP) as *mut u8, HEAP_SIZE);
+    }
+}
+
+//选中的实现的名字
+pub fn name() -> &'static str {
+    ALLOCATOR.0.lock().name()
+}
+
+//内核堆已分配的字节数和总大小
+pub fn usage() -> (usize, usize) {
+    let heap = ALLOCATOR.0.lock();
+    (heap.used(), heap.size())
+}
+
+//内核堆的地址范围
+pub fn heap_range() -> (u64, u64) {
+    let start = core::ptr::addr_of!(HEAP) as u64;
+    (start, start + HEAP_SIZE as u64)
+}
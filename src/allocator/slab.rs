@@ -0,0 +1,154 @@
+//slab 与链表的混合：不超过 2 KiB 的分配按大小分成 8 类，每类从 4 KiB 的 slab 中切出同样大小的对象，
+//释放的对象放回本类的空闲链表；更大的分配(以及 slab 本身)由首次适配链表分配，底层内存不足时才把全空的 slab 还回去
+//内核里大部分分配是小对象，这样它们既快又不会把链表切碎
+use super::KernelAllocator;
+use core::alloc::Layout;
+use core::ptr::{self, NonNull};
+use linked_list_allocator::Heap;
+
+const CLASSES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+const SLAB_SIZE: usize = 4096;
+
+pub struct Slab {
+    free: [usize; CLASSES.len()], //每类的空闲对象链表头，0 表示空
+    backing: Heap,
+    used: usize,
+}
+
+impl Slab {
+    pub const fn new() -> Slab {
+        Slab { free: [0; CLASSES.len()], backing: Heap::empty(), used: 0 }
+    }
+
+    //从底层分配，不够时回收空的 slab 再试一次
+    fn backing_allocate(&mut self, layout: Layout) -> Option<usize> {
+        if let Ok(block) = self.backing.allocate_first_fit(layout) {
+            return Some(block.as_ptr() as usize);
+        }
+        if !self.reclaim() {
+            return None;
+        }
+        self.backing.allocate_first_fit(layout).ok().map(|block| block.as_ptr() as usize)
+    }
+
+    //从底层分配一个 slab，切成 class 类的对象放进空闲链表
+    fn grow(&mut self, class: usize) -> bool {
+        let slab = match self.backing_allocate(slab_layout()) {
+            Some(slab) => slab,
+            None => return false,
+        };
+        for object in (slab..slab + SLAB_SIZE).step_by(CLASSES[class]).rev() {
+            unsafe { *(object as *mut usize) = self.free[class] };
+            self.free[class] = object;
+        }
+        true
+    }
+
+    //class 的空闲链表中属于 slab 的对象个数
+    fn free_in_slab(&self, class: usize, slab: usize) -> usize {
+        let mut count = 0;
+        let mut object = self.free[class];
+        while object != 0 {
+            if object & !(SLAB_SIZE - 1) == slab {
+                count += 1;
+            }
+            object = unsafe { *(object as *const usize) };
+        }
+        count
+    }
+
+    //把对象全部空闲的 slab 从空闲链表中摘下来还给底层，有还回去的时返回 true
+    //要反复遍历链表，只在底层内存不足时调用
+    fn reclaim(&mut self) -> bool {
+        let mut reclaimed = false;
+        for (class, &size) in CLASSES.iter().enumerate() {
+            let mut object = self.free[class];
+            while object != 0 {
+                let slab = object & !(SLAB_SIZE - 1);
+                if self.free_in_slab(class, slab) < SLAB_SIZE / size {
+                    object = unsafe { *(object as *const usize) };
+                    continue;
+                }
+                unsafe {
+                    let mut link = &mut self.free[class] as *mut usize;
+                    while *link != 0 {
+                        if *link & !(SLAB_SIZE - 1) == slab {
+                            *link = *(*link as *const usize);
+                        } else {
+                            link = *link as *mut usize;
+                        }
+                    }
+                    self.backing.deallocate(NonNull::new_unchecked(slab as *mut u8), slab_layout());
+                }
+                reclaimed = true;
+                object = self.free[class]; //链表变了，从头再找
+            }
+        }
+        reclaimed
+    }
+}
+
+fn slab_layout() -> Layout {
+    Layout::from_size_align(SLAB_SIZE, SLAB_SIZE).unwrap()
+}
+
+//能放下 layout 的最小的类，对象按类的大小对齐
+fn class(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+    CLASSES.iter().position(|&class| size <= class)
+}
+
+impl KernelAllocator for Slab {
+    fn name(&self) -> &'static str {
+        "slab"
+    }
+
+    unsafe fn init(&mut self, start: *mut u8, size: usize) {
+        self.backing.init(start, size);
+    }
+
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let class = match class(layout) {
+            Some(class) => class,
+            None => {
+                return match self.backing_allocate(layout) {
+                    Some(block) => {
+                        self.used += layout.size();
+                        block as *mut u8
+                    }
+                    None => ptr::null_mut(),
+                };
+            }
+        };
+        if self.free[class] == 0 && !self.grow(class) {
+            return ptr::null_mut();
+        }
+        let object = self.free[class];
+        self.free[class] = unsafe { *(object as *const usize) };
+        self.used += CLASSES[class];
+        object as *mut u8
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        match class(layout) {
+            Some(class) => {
+                *(ptr as *mut usize) = self.free[class];
+                self.free[class] = ptr as usize;
+                self.used -= CLASSES[class];
+            }
+            None => {
+                self.backing.deallocate(NonNull::new_unchecked(ptr), layout);
+                self.used -= layout.size();
+            }
+        }
+    }
+
+    //交给使用者的字节数；空闲链表里的对象不算，但也不能用于大的分配
+    fn used(&self) -> usize {
+        self.used
+    }
+
+    fn size(&self) -> usize {
+        self.backing.size()
+    }
+}
@@ -0,0 +1,94 @@
+//审计记录：需要特权的操作(挂载、改变身份和所有者、重启和关机、远程控制台会话)每次一条，只追加，不能修改或删除
+//总大小有上限，超过时丢弃最早的记录；每条记录有递增的序号，audit show 能看出前面丢了多少条
+//用来事后弄清楚一次出问题的测试到底做了什么：谁(PID 和 uid)、什么时候、做了什么、结果如何
+use crate::cred;
+use crate::process;
+use crate::time;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use core::time::Duration;
+use spin::Mutex;
+
+const LOG_SIZE: usize = 16 * 1024; //全部记录占用的字节数上限
+const RECORD_OVERHEAD: usize = 40; //每条记录除了 detail 之外的大小
+const MAX_DETAIL: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Mount,
+    Unmount,
+    Chown,
+    SetUid,
+    Reboot,
+    Shutdown,
+    RemoteSession, //rconsole 打开了一个会话
+}
+
+impl Operation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::Mount => "mount",
+            Operation::Unmount => "unmount",
+            Operation::Chown => "chown",
+            Operation::SetUid => "setuid",
+            Operation::Reboot => "reboot",
+            Operation::Shutdown => "shutdown",
+            Operation::RemoteSession => "rconsole",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub sequence: u64,
+    pub time: Duration, //启动以后的时间
+    pub pid: u64,       //0 表示内核自己或者 shell
+    pub uid: u32,
+    pub operation: Operation,
+    pub detail: String,
+    pub allowed: bool, //操作是否成功(被拒绝或者出错时为 false)
+}
+
+struct Log {
+    records: VecDeque<Record>,
+    bytes: usize,
+    next: u64,
+}
+
+static LOG: Mutex<Log> = Mutex::new(Log { records: VecDeque::new(), bytes: 0, next: 0 });
+
+//追加一条记录，detail 太长时截断
+pub fn record(operation: Operation, detail: &str, allowed: bool) {
+    let mut end = detail.len().min(MAX_DETAIL);
+    while !detail.is_char_boundary(end) {
+        end -= 1;
+    }
+    let record = Record {
+        sequence: 0,
+        time: time::uptime(),
+        pid: process::current_pid(),
+        uid: cred::current().uid,
+        operation,
+        detail: String::from(&detail[..end]),
+        allowed,
+    };
+    let size = RECORD_OVERHEAD + record.detail.len();
+    let mut log = LOG.lock();
+    while log.bytes + size > LOG_SIZE {
+        match log.records.pop_front() {
+            Some(old) => log.bytes -= RECORD_OVERHEAD + old.detail.len(),
+            None => break,
+        }
+    }
+    let sequence = log.next;
+    log.next += 1;
+    log.bytes += size;
+    log.records.push_back(Record { sequence, ..record });
+}
+
+//按时间顺序的全部记录，以及已经被丢弃的最早记录条数
+pub fn records() -> (u64, VecDeque<Record>) {
+    let log = LOG.lock();
+    let dropped = log.records.front().map_or(log.next, |first| first.sequence);
+    (dropped, log.records.clone())
+}
@@ -0,0 +1,120 @@
+//控制台响铃：输出中的 BEL(0x07)不再显示成 ■，而是让 PC 扬声器响一下，或者把屏幕颜色反转一下(视觉响铃)
+//方式由命令行 bell=audible|visual|off 给出，shell 里可以用 bell 命令改变
+//扬声器接在 PIT 通道 2 上：通道 2 以方波模式输出 FREQUENCY，0x61 端口的第 0 位打开门控、第 1 位接通扬声器
+//发声后不等待，由时钟中断在 BEEP_TICKS 之后关掉扬声器；闪屏则反转、等待 FLASH、再反转回来
+//连续的 BEL(例如 cat 一个二进制文件)在 QUIET 之内只响一次
+use crate::cmdline;
+use crate::interrupts;
+use crate::time;
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use core::time::Duration;
+use x86_64::instructions::port::Port;
+
+pub const BEL: u8 = 0x07;
+
+const PIT_HZ: u32 = 1_193_182;
+const FREQUENCY: u32 = 750; //Hz
+const BEEP_TICKS: u64 = 10; //时钟中断 100 Hz，约 100 毫秒
+const FLASH: Duration = Duration::from_millis(50);
+const QUIET: Duration = Duration::from_millis(150);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Audible,
+    Visual,
+    Off,
+}
+
+pub const MODES: [Mode; 3] = [Mode::Audible, Mode::Visual, Mode::Off];
+
+impl Mode {
+    pub fn parse(name: &str) -> Option<Mode> {
+        MODES.iter().copied().find(|mode| mode.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Audible => "audible",
+            Mode::Visual => "visual",
+            Mode::Off => "off",
+        }
+    }
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Audible as u8);
+static STOP_AT: AtomicU64 = AtomicU64::new(0); //关掉扬声器的时钟节拍，0 表示没有在响
+static LAST: AtomicU64 = AtomicU64::new(u64::MAX); //上一次响铃时启动以来的微秒数
+static RINGS: AtomicU64 = AtomicU64::new(0);
+
+pub fn mode() -> Mode {
+    MODES[MODE.load(Ordering::Relaxed) as usize]
+}
+
+pub fn set_mode(mode: Mode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+//启动以来响过的次数(不算被合并掉的)
+pub fn rings() -> u64 {
+    RINGS.load(Ordering::Relaxed)
+}
+
+//启动时调用：bell=audible|visual|off，没有给出时用扬声器
+pub fn init() -> Result<(), &'static str> {
+    match cmdline::get("bell") {
+        None => Ok(()),
+        Some(name) => Mode::parse(name).map(set_mode).ok_or(name),
+    }
+}
+
+//遇到 BEL 时由控制台调用，invert 把屏幕的前景色和背景色对调(调用两次恢复原样)
+//调用者持有输出的锁并且关闭了中断，闪屏期间的等待会让输出停顿 FLASH
+pub fn ring(mut invert: impl FnMut()) {
+    let mode = mode();
+    if mode == Mode::Off {
+        return;
+    }
+    let now = time::uptime().as_micros() as u64;
+    let last = LAST.load(Ordering::Relaxed);
+    if last != u64::MAX && now.saturating_sub(last) < QUIET.as_micros() as u64 {
+        return;
+    }
+    LAST.store(now, Ordering::Relaxed);
+    RINGS.fetch_add(1, Ordering::Relaxed);
+    match mode {
+        Mode::Audible => beep(),
+        Mode::Visual => {
+            invert();
+            time::sleep(FLASH);
+            invert();
+        }
+        Mode::Off => {}
+    }
+}
+
+fn beep() {
+    let divisor = PIT_HZ / FREQUENCY;
+    unsafe {
+        Port::<u8>::new(0x43).write(0b1011_0110); //通道 2，先低后高字节，模式 3(方波)
+        let mut channel2 = Port::<u8>::new(0x42);
+        channel2.write(divisor as u8);
+        channel2.write((divisor >> 8) as u8);
+        let mut control = Port::<u8>::new(0x61);
+        let value = control.read();
+        control.write(value | 0x03);
+    }
+    STOP_AT.store(interrupts::ticks() + BEEP_TICKS, Ordering::Relaxed);
+}
+
+//时钟中断里调用，只用原子变量和端口，不加锁
+pub fn tick(now: u64) {
+    let stop = STOP_AT.load(Ordering::Relaxed);
+    if stop != 0 && now >= stop {
+        STOP_AT.store(0, Ordering::Relaxed);
+        let mut control = Port::<u8>::new(0x61);
+        unsafe {
+            let value = control.read();
+            control.write(value & !0x03);
+        }
+    }
+}
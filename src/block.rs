@@ -0,0 +1,150 @@
+//块设备抽象层：文件系统只依赖这个 trait，而不关心底层是 ATA、virtio 还是内存盘
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    NoDevice,    //设备不存在或未响应
+    OutOfRange,  //访问的块号超出设备容量
+    BadBuffer,   //缓冲区长度不是块大小的整数倍
+    Timeout,     //等待设备就绪超时
+    DeviceError, //设备返回错误状态(ERR/DF)
+    Backing,     //回环设备底层的文件读写失败
+}
+
+pub trait BlockDevice {
+    //每块的字节数(ATA 扇区为 512)
+    fn block_size(&self) -> usize;
+
+    //设备总块数
+    fn block_count(&self) -> u64;
+
+    //从第 lba 块开始读取，读取的块数由 buf.len() / block_size() 决定
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+
+    //从第 lba 块开始写入，写入的块数由 buf.len() / block_size() 决定
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError>;
+}
+
+//检查一次读写请求是否合法，返回涉及的块数
+pub fn check_request<D: BlockDevice + ?Sized>(dev: &D, lba: u64, len: usize) -> Result<u64, BlockError> {
+    let size = dev.block_size();
+    if !len.is_multiple_of(size) {
+        return Err(BlockError::BadBuffer);
+    }
+    let count = (len / size) as u64;
+    match lba.checked_add(count) {
+        Some(end) if end <= dev.block_count() => Ok(count),
+        _ => Err(BlockError::OutOfRange),
+    }
+}
+
+//可以在多个使用者(文件系统、shell 命令)之间共享的块设备
+pub type SharedDevice = Arc<Mutex<dyn BlockDevice + Send>>;
+
+impl BlockDevice for SharedDevice {
+    fn block_size(&self) -> usize {
+        self.lock().block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.lock().block_count()
+    }
+
+    //大文件的读写可能持续很久，每个请求都向看门狗报告一次
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        crate::watchdog::touch();
+        crate::trace!(Block, lba, buf.len());
+        self.lock().read_blocks(lba, buf)
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        crate::watchdog::touch();
+        crate::trace!(Block, lba, buf.len() as u64 | 1 << 63);
+        self.lock().write_blocks(lba, buf)
+    }
+}
+
+//已注册的块设备，按名字(如 "ata0")索引
+static DEVICES: Mutex<BTreeMap<String, SharedDevice>> = Mutex::new(BTreeMap::new());
+
+pub fn register(name: &str, dev: SharedDevice) {
+    DEVICES.lock().insert(String::from(name), dev);
+}
+
+pub fn unregister(name: &str) -> Option<SharedDevice> {
+    DEVICES.lock().remove(name)
+}
+
+pub fn get(name: &str) -> Option<SharedDevice> {
+    DEVICES.lock().get(name).cloned()
+}
+
+//返回所有设备的名字和设备本身
+pub fn devices() -> Vec<(String, SharedDevice)> {
+    DEVICES.lock().iter().map(|(name, dev)| (name.clone(), dev.clone())).collect()
+}
+
+//设备文件路径(如 /dev/ata0p1)对应的块设备
+pub fn from_path(path: &str) -> Option<SharedDevice> {
+    get(path.strip_prefix("/dev/")?)
+}
+
+//磁盘上的一个分区：把块号加上分区起始位置后转发给整个磁盘
+pub struct Partition {
+    disk: SharedDevice,
+    start: u64,
+    count: u64,
+}
+
+impl BlockDevice for Partition {
+    fn block_size(&self) -> usize {
+        self.disk.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.count
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        self.disk.read_blocks(self.start + lba, buf)
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        self.disk.write_blocks(self.start + lba, buf)
+    }
+}
+
+const MBR_PARTITION_TABLE: usize = 446; //MBR 中分区表的偏移
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+//读取磁盘的 MBR 分区表，把每个分区注册为 <磁盘名>p<序号>(序号从 1 开始)
+pub fn scan_partitions(name: &str) -> Result<usize, BlockError> {
+    let mut disk = get(name).ok_or(BlockError::NoDevice)?;
+    let mut mbr = vec![0u8; disk.block_size()];
+    disk.read_blocks(0, &mut mbr)?;
+    if mbr.len() < 512 || mbr[510] != 0x55 || mbr[511] != 0xAA {
+        return Ok(0);
+    }
+    let mut found = 0;
+    for i in 0..4 {
+        let entry = &mbr[MBR_PARTITION_TABLE + i * 16..MBR_PARTITION_TABLE + (i + 1) * 16];
+        let kind = entry[4];
+        let start = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as u64;
+        let count = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]) as u64;
+        if kind == 0 || kind == MBR_TYPE_GPT_PROTECTIVE || count == 0 || start + count > disk.block_count() {
+            continue;
+        }
+        let partition = Partition { disk: disk.clone(), start, count };
+        register(&format!("{}p{}", name, i + 1), Arc::new(Mutex::new(partition)));
+        found += 1;
+    }
+    Ok(found)
+}
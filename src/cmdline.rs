@@ -0,0 +1,85 @@
+//内核命令行：空格分隔的 key=value 参数或者单独的开关，例如 "loglevel=debug keymap=de noksm"
+//bootloader 0.9 的 BootInfo 里没有命令行，所以在编译时由环境变量 JOAKIM_CMDLINE 给出
+//访问函数每次直接解析这个字符串，不需要初始化，最早的启动代码也能用
+//在 QEMU 里运行时主机还可以通过 fw_cfg 追加参数(见 extend)，追加之后的参数覆盖编译时给出的同名参数
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use spin::Mutex;
+
+const CMDLINE: &str = match option_env!("JOAKIM_CMDLINE") {
+    Some(cmdline) => cmdline,
+    None => "",
+};
+
+static FULL: Mutex<&'static str> = Mutex::new(CMDLINE);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn parse(name: &str) -> Option<LogLevel> {
+        match name {
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            "trace" => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+pub fn raw() -> &'static str {
+    *FULL.lock()
+}
+
+//在命令行末尾追加参数，只在启动时调用几次，旧的字符串不释放
+pub fn extend(extra: &str) {
+    let mut full = FULL.lock();
+    let combined: String = if full.is_empty() { String::from(extra) } else { format!("{} {}", *full, extra) };
+    *full = Box::leak(combined.into_boxed_str());
+}
+
+//全部参数：(key, value)，单独的开关没有 value；同一个 key 出现多次时以最后一次为准
+pub fn params() -> impl Iterator<Item = (&'static str, Option<&'static str>)> {
+    raw().split_whitespace().map(|param| match param.split_once('=') {
+        Some((key, value)) => (key, Some(value)),
+        None => (param, None),
+    })
+}
+
+//key=value 中的 value
+pub fn get(key: &str) -> Option<&'static str> {
+    params().filter(|&(k, _)| k == key).last().and_then(|(_, value)| value)
+}
+
+//开关是否打开：单独出现，或者值为 1/on/yes/true
+pub fn flag(key: &str) -> bool {
+    match params().filter(|&(k, _)| k == key).last() {
+        Some((_, None)) => true,
+        Some((_, Some(value))) => matches!(value, "1" | "on" | "yes" | "true"),
+        None => false,
+    }
+}
+
+//loglevel=error|warn|info|debug|trace，没有给出或不认识时为 info
+pub fn log_level() -> LogLevel {
+    get("loglevel").and_then(LogLevel::parse).unwrap_or(LogLevel::Info)
+}
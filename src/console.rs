@@ -0,0 +1,260 @@
+//控制台滚动缓冲区：记录输出过的每一行以及它的时间戳，可以搜索，也可以在全屏分页器里回看
+use crate::drivers::keyboard;
+use crate::println;
+use crate::sync::Mutex;
+use crate::time;
+use crate::vga_buffer::{self, Color, BUFFER_HEIGHT, BUFFER_WIDTH};
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use pc_keyboard::{DecodedKey, KeyCode};
+
+const SCROLLBACK_LINES: usize = 1000; //最多保留的行数，超出后丢弃最早的行
+const PAGE_LINES: usize = BUFFER_HEIGHT - 1; //分页器中最后一行用作状态栏
+const PROGRESS_WIDTH: usize = 40; //进度条的格数
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub timestamp: u64, //这一行第一个字符输出时的 TSC 计数
+    pub text: String,
+}
+
+struct Scrollback {
+    lines: VecDeque<Line>,
+    current: Option<Line>, //还没有遇到换行符的最后一行
+}
+
+impl Write for Scrollback {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if c == '\n' {
+                let line = self.current.take().unwrap_or(Line { timestamp: timestamp(), text: String::new() });
+                if self.lines.len() == SCROLLBACK_LINES {
+                    self.lines.pop_front();
+                }
+                self.lines.push_back(line);
+            } else {
+                self.current
+                    .get_or_insert_with(|| Line { timestamp: timestamp(), text: String::new() })
+                    .text
+                    .push(c);
+            }
+        }
+        Ok(())
+    }
+}
+
+static SCROLLBACK: Mutex<Scrollback> = Mutex::new("SCROLLBACK", Scrollback { lines: VecDeque::new(), current: None });
+
+//堆初始化之前不能记录(记录需要分配内存)
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+//在 allocator::init 之后调用，开始记录控制台输出
+pub fn init() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+//当前的 TSC 计数，作为每一行的时间戳
+pub fn timestamp() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[doc(hidden)]
+pub fn record(args: fmt::Arguments) {
+    if ENABLED.load(Ordering::SeqCst) {
+        SCROLLBACK.lock().write_fmt(args).unwrap();
+    }
+}
+
+//查找所有包含 pattern 的行，返回它们在滚动缓冲区中的行号(从 0 开始，按时间顺序)
+pub fn find(pattern: &str) -> Vec<usize> {
+    let scrollback = SCROLLBACK.lock();
+    scrollback
+        .lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.text.contains(pattern))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+fn lines() -> Vec<Line> {
+    let scrollback = SCROLLBACK.lock();
+    scrollback.lines.iter().cloned().chain(scrollback.current.clone()).collect()
+}
+
+//在屏幕的一行上显示文字，超出宽度的部分截断，不足的部分用空格补齐
+//highlight 中的区间用黑字黄底显示
+fn draw_row(row: usize, text: &str, highlight: &[(usize, usize)], foreground: Color, background: Color) {
+    let bytes = text.as_bytes();
+    for col in 0..BUFFER_WIDTH {
+        let byte = match bytes.get(col) {
+            Some(&b) if (0x20..=0x7e).contains(&b) => b,
+            Some(_) => 0xfe,
+            None => b' ',
+        };
+        let marked = highlight.iter().any(|&(start, end)| col >= start && col < end);
+        if marked {
+            vga_buffer::put_char(row, col, byte, Color::Black, Color::Yellow);
+        } else {
+            vga_buffer::put_char(row, col, byte, foreground, background);
+        }
+    }
+}
+
+//分页器显示的一行："[启动后的秒数] 内容"
+fn format_line(line: &Line) -> String {
+    let time = time::since_boot(line.timestamp);
+    format!("[{:>5}.{:06}] {}", time.as_secs(), time.subsec_micros(), line.text)
+}
+
+//找出一行中所有匹配的位置(以显示后的列号表示)
+fn match_ranges(text: &str, pattern: &str) -> Vec<(usize, usize)> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+    text.match_indices(pattern).map(|(start, m)| (start, start + m.len())).collect()
+}
+
+//在状态栏上读取搜索关键字，Esc 取消
+fn prompt(label: &str) -> Option<String> {
+    let mut input = String::new();
+    loop {
+        draw_row(BUFFER_HEIGHT - 1, &format!("{}{}", label, input), &[], Color::Black, Color::LightGray);
+        match keyboard::read_key() {
+            DecodedKey::Unicode('\n') => return Some(input),
+            DecodedKey::Unicode('\u{1b}') => return None,
+            DecodedKey::Unicode('\u{8}') => {
+                input.pop();
+            }
+            DecodedKey::Unicode(c) if c.is_ascii() && !c.is_ascii_control() => input.push(c),
+            _ => {}
+        }
+    }
+}
+
+//全屏分页器：方向键/PgUp/PgDn 翻页，Ctrl+F 搜索，n 跳到下一个匹配，q 或 Esc 退出
+pub fn pager() {
+    let saved = vga_buffer::snapshot();
+    let lines = lines();
+    let mut top = lines.len().saturating_sub(PAGE_LINES);
+    let mut pattern = String::new();
+    let mut status = String::new();
+
+    loop {
+        let max_top = lines.len().saturating_sub(PAGE_LINES);
+        top = top.min(max_top);
+        for row in 0..PAGE_LINES {
+            match lines.get(top + row) {
+                Some(line) => {
+                    let text = format_line(line);
+                    draw_row(row, &text, &match_ranges(&text, &pattern), Color::LightGray, Color::Black);
+                }
+                None => draw_row(row, "", &[], Color::LightGray, Color::Black),
+            }
+        }
+        let bar = format!(
+            "lines {}-{}/{}  Up/Down PgUp/PgDn  Ctrl+F search  n next  q quit  {}",
+            if lines.is_empty() { 0 } else { top + 1 },
+            (top + PAGE_LINES).min(lines.len()),
+            lines.len(),
+            status
+        );
+        draw_row(BUFFER_HEIGHT - 1, &bar, &[], Color::Black, Color::LightGray);
+
+        let mut search_from = None;
+        match keyboard::read_key() {
+            DecodedKey::Unicode('q') | DecodedKey::Unicode('\u{1b}') => break,
+            DecodedKey::RawKey(KeyCode::ArrowUp) => top = top.saturating_sub(1),
+            DecodedKey::RawKey(KeyCode::ArrowDown) => top += 1,
+            DecodedKey::RawKey(KeyCode::PageUp) => top = top.saturating_sub(PAGE_LINES),
+            DecodedKey::RawKey(KeyCode::PageDown) => top += PAGE_LINES,
+            DecodedKey::RawKey(KeyCode::Home) => top = 0,
+            DecodedKey::RawKey(KeyCode::End) => top = max_top,
+            DecodedKey::Unicode('\u{6}') => { //Ctrl+F
+                if let Some(input) = prompt("search: ") {
+                    pattern = input;
+                    search_from = Some(top);
+                }
+            }
+            DecodedKey::Unicode('n') => search_from = Some(top + 1),
+            _ => {}
+        }
+
+        if let Some(from) = search_from {
+            if pattern.is_empty() {
+                continue;
+            }
+            match find(&pattern).into_iter().find(|&index| index >= from) {
+                Some(index) => {
+                    top = index;
+                    status = String::new();
+                }
+                None => status = format!("'{}' not found", pattern),
+            }
+        }
+    }
+    vga_buffer::restore(&saved);
+}
+
+//在当前行显示的进度条：中间状态反复重画在同一行上，不记入滚动缓冲区，结束时输出最终状态
+pub struct ProgressBar {
+    label: String,
+    total: u64,
+    drawn: Option<usize>, //上次画出的格数，格数不变时不重画
+}
+
+impl ProgressBar {
+    pub fn new(label: &str, total: u64) -> ProgressBar {
+        //标签太长时截断，保证整个进度条在一行之内
+        let label: String = label.chars().take(BUFFER_WIDTH - PROGRESS_WIDTH - 8).collect();
+        let mut bar = ProgressBar { label, total, drawn: None };
+        bar.update(0);
+        bar
+    }
+
+    fn filled(&self, done: u64) -> usize {
+        if self.total == 0 {
+            return PROGRESS_WIDTH;
+        }
+        (done.min(self.total) * PROGRESS_WIDTH as u64 / self.total) as usize
+    }
+
+    fn render(&self, done: u64) -> String {
+        let filled = self.filled(done);
+        let mut bar = String::new();
+        for i in 0..PROGRESS_WIDTH {
+            bar.push(if i < filled { '#' } else { '.' });
+        }
+        format!("{} [{}] {:>3}%", self.label, bar, filled * 100 / PROGRESS_WIDTH)
+    }
+
+    fn clear_line() {
+        vga_buffer::cursor_left(BUFFER_WIDTH);
+        vga_buffer::clear_to_end();
+    }
+
+    //done 为已完成的量(与 total 的单位相同)
+    pub fn update(&mut self, done: u64) {
+        let filled = self.filled(done);
+        if self.drawn == Some(filled) {
+            return;
+        }
+        self.drawn = Some(filled);
+        ProgressBar::clear_line();
+        vga_buffer::write_unrecorded(&self.render(done));
+    }
+
+    pub fn finish(self) {
+        ProgressBar::clear_line();
+        println!("{}", self.render(self.total));
+    }
+
+    //中途出错时擦掉进度条，让错误信息从行首开始显示
+    pub fn abandon(self) {
+        ProgressBar::clear_line();
+    }
+}
@@ -0,0 +1,121 @@
+//控制台过滤器：输出到屏幕之前按行检查，匹配的行用指定的颜色显示(hl)或者不显示(suppress)
+//模式在一行中的任意位置匹配，* 匹配任意多个字符，? 匹配一个字符，其余字符按原样比较
+//被隐藏的行仍然记入滚动缓冲区，scrollback 里还能看到
+//有过滤器时屏幕输出按行缓冲，没有换行符的部分在换行或者等待按键时才显示
+use crate::vga_buffer::Color;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Highlight(Color),
+    Suppress,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub pattern: String,
+    pub action: Action,
+}
+
+static FILTERS: Mutex<Vec<Filter>> = Mutex::new(Vec::new());
+static ACTIVE: AtomicBool = AtomicBool::new(false); //没有过滤器时输出不需要加锁检查
+
+//同一个模式的同类过滤器只保留一个，新加的替换旧的
+pub fn add(pattern: &str, action: Action) {
+    let mut filters = FILTERS.lock();
+    filters.retain(|f| !(f.pattern == pattern && same_kind(f.action, action)));
+    filters.push(Filter { pattern: String::from(pattern), action });
+    ACTIVE.store(true, Ordering::Relaxed);
+}
+
+//删除模式为 pattern 的高亮(suppress 为 false)或隐藏过滤器，返回是否存在
+pub fn remove(pattern: &str, suppress: bool) -> bool {
+    let mut filters = FILTERS.lock();
+    let before = filters.len();
+    filters.retain(|f| !(f.pattern == pattern && (f.action == Action::Suppress) == suppress));
+    ACTIVE.store(!filters.is_empty(), Ordering::Relaxed);
+    filters.len() != before
+}
+
+pub fn list() -> Vec<Filter> {
+    FILTERS.lock().clone()
+}
+
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+fn same_kind(a: Action, b: Action) -> bool {
+    (a == Action::Suppress) == (b == Action::Suppress)
+}
+
+//一行的处理结果：隐藏优先于高亮，多个高亮匹配时用最后添加的
+pub fn classify(line: &str) -> Option<Action> {
+    let filters = FILTERS.lock();
+    if filters.iter().any(|f| f.action == Action::Suppress && matches(&f.pattern, line)) {
+        return Some(Action::Suppress);
+    }
+    filters.iter().rev().find(|f| matches(&f.pattern, line)).map(|f| f.action)
+}
+
+//pattern 是否匹配 text 的某一段
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let text = text.as_bytes();
+    (0..=text.len()).any(|start| glob_prefix(pattern, &text[start..]))
+}
+
+//pattern 是否匹配 text 的某个前缀；* 回溯时只需要记住最近的一个
+fn glob_prefix(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    loop {
+        if p == pattern.len() {
+            return true;
+        }
+        match pattern[p] {
+            b'*' => {
+                star = Some((p, t));
+                p += 1;
+            }
+            c if t < text.len() && (c == b'?' || c == text[t]) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) if star_t < text.len() => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                _ => return false,
+            },
+        }
+    }
+}
+
+pub fn parse_color(name: &str) -> Option<Color> {
+    let color = match name {
+        "black" => Color::Black,
+        "blue" => Color::Blue,
+        "green" => Color::Green,
+        "cyan" => Color::Cyan,
+        "red" => Color::Red,
+        "magenta" => Color::Magenta,
+        "brown" => Color::Brown,
+        "gray" | "lightgray" => Color::LightGray,
+        "darkgray" => Color::DarkGray,
+        "lightblue" => Color::LightBlue,
+        "lightgreen" => Color::LightGreen,
+        "lightcyan" => Color::LightCyan,
+        "lightred" => Color::LightRed,
+        "pink" => Color::Pink,
+        "yellow" => Color::Yellow,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
@@ -0,0 +1,251 @@
+//core 文件：用户进程因为异常或者默认会产生 core 的信号死亡时，把它的寄存器和可写的内存写到 /cores/<pid>.core
+//格式与 Linux 的 ELF core 相同，可以用 gdb <程序> <core 文件> 离线查看：
+//  PT_NOTE  NT_PRSTATUS(信号、PID 和寄存器)和 NT_PRPSINFO(程序名、身份)
+//  PT_LOAD  每段连续的可写用户页面(包括写时复制的页面)，代码段只读，调试器从程序文件里读取
+//缺页异常中只知道 rip、rsp、rflags，其余通用寄存器记为 0；信号在系统调用返回时处理，寄存器是完整的
+//(rcx、r11 被 syscall 指令占用，分别就是 rip 和 rflags)
+//必须在死亡进程自己的地址空间里调用，页面直接按虚拟地址读取；命令行有 nocoredump 时不写
+use crate::cred;
+use crate::fs::vfs::{self, FileHandle};
+use crate::fs::FsError;
+use crate::gdt;
+use crate::memory::{self, COW};
+use crate::process;
+use crate::usermode::UserContext;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::structures::paging::PageTableFlags;
+
+pub const CORE_DIR: &str = "/cores";
+
+const PAGE_SIZE: u64 = 4096;
+
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const PRSTATUS_SIZE: usize = 336; //struct elf_prstatus
+const PRPSINFO_SIZE: usize = 136; //struct elf_prpsinfo
+const PR_REG_OFFSET: usize = 112; //elf_prstatus 中 pr_reg 的位置
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+//缺页异常时的寄存器：只有异常帧里的几个
+pub fn fault_context(rip: u64, rsp: u64, rflags: u64) -> UserContext {
+    UserContext { rip, rsp, rflags, ..UserContext::default() }
+}
+
+//一段连续的、标志相同的可写用户页面
+struct Segment {
+    start: u64,
+    end: u64,
+    flags: u32,
+}
+
+fn segments() -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+    memory::for_each_user_page(&mut |addr, flags| {
+        if !flags.intersects(PageTableFlags::WRITABLE | COW) {
+            return;
+        }
+        let exec = if flags.contains(PageTableFlags::NO_EXECUTE) { 0 } else { PF_X };
+        let flags = PF_R | PF_W | exec;
+        match segments.last_mut() {
+            Some(last) if last.end == addr && last.flags == flags => last.end += PAGE_SIZE,
+            _ => segments.push(Segment { start: addr, end: addr + PAGE_SIZE, flags }),
+        }
+    });
+    segments
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn header(buf: &mut Vec<u8>, phnum: usize) {
+    buf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]); //64 位，小端，版本 1，System V
+    buf.extend_from_slice(&[0; 8]);
+    put_u16(buf, ET_CORE);
+    put_u16(buf, EM_X86_64);
+    put_u32(buf, 1); //e_version
+    put_u64(buf, 0); //e_entry
+    put_u64(buf, EHDR_SIZE as u64); //e_phoff
+    put_u64(buf, 0); //e_shoff
+    put_u32(buf, 0); //e_flags
+    put_u16(buf, EHDR_SIZE as u16);
+    put_u16(buf, PHDR_SIZE as u16);
+    put_u16(buf, phnum as u16);
+    put_u16(buf, 0); //e_shentsize
+    put_u16(buf, 0); //e_shnum
+    put_u16(buf, 0); //e_shstrndx
+}
+
+fn program_header(buf: &mut Vec<u8>, kind: u32, flags: u32, offset: u64, vaddr: u64, size: u64, align: u64) {
+    put_u32(buf, kind);
+    put_u32(buf, flags);
+    put_u64(buf, offset);
+    put_u64(buf, vaddr);
+    put_u64(buf, 0); //p_paddr
+    put_u64(buf, size); //p_filesz
+    put_u64(buf, size); //p_memsz
+    put_u64(buf, align);
+}
+
+//名字为 "CORE" 的注释，名字和内容都按 4 字节对齐
+fn note(buf: &mut Vec<u8>, kind: u32, desc: &[u8]) {
+    put_u32(buf, 5);
+    put_u32(buf, desc.len() as u32);
+    put_u32(buf, kind);
+    buf.extend_from_slice(b"CORE\0\0\0\0");
+    buf.extend_from_slice(desc);
+    buf.resize((buf.len() + 3) & !3, 0);
+}
+
+//按 user_regs_struct 的顺序排列的寄存器
+fn registers(context: &UserContext, rax: i64) -> [u64; 27] {
+    let selectors = gdt::selectors();
+    let (cs, ss) = (selectors.user_code.0 as u64, selectors.user_data.0 as u64);
+    let c = context;
+    [
+        c.r15, c.r14, c.r13, c.r12, c.rbp, c.rbx, c.rflags, c.r10, c.r9, c.r8, rax as u64, c.rip, c.rdx, c.rsi, c.rdi,
+        u64::MAX, c.rip, cs, c.rflags, c.rsp, ss, 0, 0, 0, 0, 0, 0,
+    ]
+}
+
+fn prstatus(pid: u64, parent: u64, sig: u32, context: &UserContext, rax: i64) -> Vec<u8> {
+    let mut desc = Vec::with_capacity(PRSTATUS_SIZE);
+    put_u32(&mut desc, sig); //si_signo
+    put_u32(&mut desc, 0); //si_code
+    put_u32(&mut desc, 0); //si_errno
+    put_u16(&mut desc, sig as u16); //pr_cursig
+    desc.resize(32, 0); //pr_sigpend、pr_sighold
+    put_u32(&mut desc, pid as u32);
+    put_u32(&mut desc, parent as u32);
+    put_u32(&mut desc, pid as u32); //pr_pgrp
+    put_u32(&mut desc, pid as u32); //pr_sid
+    desc.resize(PR_REG_OFFSET, 0); //四个 timeval 都记为 0
+    for reg in registers(context, rax).iter() {
+        put_u64(&mut desc, *reg);
+    }
+    desc.resize(PRSTATUS_SIZE, 0); //pr_fpvalid = 0：没有保存浮点寄存器
+    desc
+}
+
+fn prpsinfo(pid: u64, parent: u64, name: &str, cred: cred::Credentials) -> Vec<u8> {
+    let mut desc = Vec::with_capacity(PRPSINFO_SIZE);
+    desc.extend_from_slice(&[0, b'R', 0, 0, 0, 0, 0, 0]); //pr_state、pr_sname、pr_zomb、pr_nice
+    put_u64(&mut desc, 0); //pr_flag
+    put_u32(&mut desc, cred.uid);
+    put_u32(&mut desc, cred.gid);
+    put_u32(&mut desc, pid as u32);
+    put_u32(&mut desc, parent as u32);
+    put_u32(&mut desc, pid as u32);
+    put_u32(&mut desc, pid as u32);
+    let base = name.rsplit('/').next().unwrap_or(name).as_bytes();
+    let mut fname = [0u8; 16];
+    let len = base.len().min(15);
+    fname[..len].copy_from_slice(&base[..len]);
+    desc.extend_from_slice(&fname);
+    let mut psargs = [0u8; 80];
+    let len = name.len().min(79);
+    psargs[..len].copy_from_slice(&name.as_bytes()[..len]);
+    desc.extend_from_slice(&psargs);
+    desc
+}
+
+fn write_all(file: &mut dyn FileHandle, mut data: &[u8]) -> Result<(), FsError> {
+    while !data.is_empty() {
+        match file.write(data)? {
+            0 => return Err(FsError::NoSpace),
+            n => data = &data[n..],
+        }
+    }
+    Ok(())
+}
+
+fn save(path: &str, head: &[u8], segments: &[Segment]) -> Result<(), FsError> {
+    match vfs::mkdir(CORE_DIR) {
+        Ok(()) | Err(FsError::AlreadyExists) => {}
+        Err(err) => return Err(err),
+    }
+    if vfs::metadata(path).is_ok() {
+        vfs::remove(path)?;
+    }
+    let mut file = vfs::create(path)?;
+    write_all(file.as_mut(), head)?;
+    for segment in segments {
+        let data = unsafe { core::slice::from_raw_parts(segment.start as *const u8, (segment.end - segment.start) as usize) };
+        write_all(file.as_mut(), data)?;
+    }
+    Ok(())
+}
+
+//写出进程 pid 的 core 文件，context 和 rax 是它死亡时的寄存器；成功时返回文件路径并记一条日志
+pub fn write(pid: u64, sig: u32, context: &UserContext, rax: i64) -> Option<String> {
+    if !enabled() {
+        return None;
+    }
+    let info = process::list().into_iter().find(|info| info.pid == pid)?;
+    let segments = segments();
+
+    let mut notes = Vec::new();
+    note(&mut notes, NT_PRSTATUS, &prstatus(pid, info.parent, sig, context, rax));
+    note(&mut notes, NT_PRPSINFO, &prpsinfo(pid, info.parent, &info.name, info.cred));
+
+    let phnum = 1 + segments.len();
+    let notes_offset = EHDR_SIZE + PHDR_SIZE * phnum;
+    let data_offset = (notes_offset + notes.len() + PAGE_SIZE as usize - 1) & !(PAGE_SIZE as usize - 1);
+    let mut head = Vec::with_capacity(data_offset);
+    header(&mut head, phnum);
+    program_header(&mut head, PT_NOTE, 0, notes_offset as u64, 0, notes.len() as u64, 4);
+    let mut offset = data_offset as u64;
+    for segment in &segments {
+        let size = segment.end - segment.start;
+        program_header(&mut head, PT_LOAD, segment.flags, offset, segment.start, size, PAGE_SIZE);
+        offset += size;
+    }
+    head.extend_from_slice(&notes);
+    head.resize(data_offset, 0);
+
+    //进程自己可能没有权限写 /cores，以 root 身份写
+    let path = format!("{}/{}.core", CORE_DIR, pid);
+    let previous = cred::switch(cred::ROOT);
+    let result = save(&path, &head, &segments);
+    cred::switch(previous);
+    match result {
+        Ok(()) => {
+            crate::log!(Info, "pid {}: core dumped to {} ({} bytes)", pid, path, offset);
+            Some(path)
+        }
+        Err(err) => {
+            crate::log!(Warn, "pid {}: writing {} failed: {:?}", pid, path, err);
+            None
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//进程的身份(用户和组)，文件的权限检查按它进行
+//uid 0 是 root，不受权限位限制；shell 和内核自己以 root 身份访问文件
+//子进程继承父进程的身份，只有 root 可以用 setuid 改变身份
+use core::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+pub const ROOT: Credentials = Credentials { uid: 0, gid: 0 };
+
+impl Credentials {
+    pub const fn new(uid: u32, gid: u32) -> Credentials {
+        Credentials { uid, gid }
+    }
+
+    pub fn is_root(self) -> bool {
+        self.uid == 0
+    }
+
+    //"uid" 或 "uid:gid"，只给出 uid 时 gid 与它相同
+    pub fn parse(text: &str) -> Option<Credentials> {
+        match text.split_once(':') {
+            Some((uid, gid)) => Some(Credentials::new(uid.parse().ok()?, gid.parse().ok()?)),
+            None => text.parse().ok().map(|uid| Credentials::new(uid, uid)),
+        }
+    }
+
+    fn pack(self) -> u64 {
+        (self.uid as u64) << 32 | self.gid as u64
+    }
+
+    fn unpack(packed: u64) -> Credentials {
+        Credentials::new((packed >> 32) as u32, packed as u32)
+    }
+}
+
+//正在运行的进程的身份，和 process 的当前 PID 一起切换；不加锁，文件系统的任何地方都可以读取
+static CURRENT: AtomicU64 = AtomicU64::new(0);
+
+pub fn current() -> Credentials {
+    Credentials::unpack(CURRENT.load(Ordering::SeqCst))
+}
+
+//换成 cred，返回原来的身份
+pub fn switch(cred: Credentials) -> Credentials {
+    Credentials::unpack(CURRENT.swap(cred.pack(), Ordering::SeqCst))
+}
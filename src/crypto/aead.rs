@@ -0,0 +1,43 @@
+//ChaCha20-Poly1305(RFC 8439)：带附加数据的认证加密
+//Poly1305 的一次性密钥取 ChaCha20 计数器 0 的密钥流前 32 字节，数据从计数器 1 开始加密
+//标签覆盖附加数据和密文(各补齐到 16 字节)以及两者的长度；同一个密钥下 nonce 绝不能重复
+use super::chacha20::{ChaCha20, KEY_SIZE, NONCE_SIZE};
+use super::poly1305::{self, Poly1305, TAG_SIZE};
+use alloc::vec::Vec;
+
+fn tag(key: &[u8; KEY_SIZE], nonce: &[u8; NONCE_SIZE], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_SIZE] {
+    let mut one_time = [0u8; poly1305::KEY_SIZE];
+    ChaCha20::new(key, nonce, 0).apply_keystream(&mut one_time);
+    let mut mac = Poly1305::new(&one_time);
+    one_time.fill(0);
+    let zeros = [0u8; 16];
+    mac.update(aad);
+    mac.update(&zeros[..(16 - aad.len() % 16) % 16]);
+    mac.update(ciphertext);
+    mac.update(&zeros[..(16 - ciphertext.len() % 16) % 16]);
+    mac.update(&(aad.len() as u64).to_le_bytes());
+    mac.update(&(ciphertext.len() as u64).to_le_bytes());
+    mac.finish()
+}
+
+//加密 plaintext，返回密文后接 16 字节的标签
+pub fn seal(key: &[u8; KEY_SIZE], nonce: &[u8; NONCE_SIZE], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(plaintext.len() + TAG_SIZE);
+    out.extend_from_slice(plaintext);
+    ChaCha20::new(key, nonce, 1).apply_keystream(&mut out);
+    let tag = tag(key, nonce, aad, &out);
+    out.extend_from_slice(&tag);
+    out
+}
+
+//验证并解密 seal 的输出，标签不对(数据被改动或者密钥不同)时返回 None
+pub fn open(key: &[u8; KEY_SIZE], nonce: &[u8; NONCE_SIZE], aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+    let split = sealed.len().checked_sub(TAG_SIZE)?;
+    let (ciphertext, received) = sealed.split_at(split);
+    if !poly1305::verify(&tag(key, nonce, aad, ciphertext), received.try_into().unwrap()) {
+        return None;
+    }
+    let mut plaintext = Vec::from(ciphertext);
+    ChaCha20::new(key, nonce, 1).apply_keystream(&mut plaintext);
+    Some(plaintext)
+}
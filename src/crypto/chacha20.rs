@@ -0,0 +1,85 @@
+//ChaCha20(RFC 8439)：256 位密钥、96 位 nonce、32 位块计数器的流密码
+//加密和解密是同一个操作：把数据与密钥流逐字节异或
+pub const KEY_SIZE: usize = 32;
+pub const NONCE_SIZE: usize = 12;
+const BLOCK_SIZE: usize = 64;
+
+//常量 "expand 32-byte k"
+const SIGMA: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+fn le_word(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+pub struct ChaCha20 {
+    state: [u32; 16],
+}
+
+impl ChaCha20 {
+    pub fn new(key: &[u8; KEY_SIZE], nonce: &[u8; NONCE_SIZE], counter: u32) -> ChaCha20 {
+        let mut state = [0u32; 16];
+        state[..4].copy_from_slice(&SIGMA);
+        for i in 0..8 {
+            state[4 + i] = le_word(&key[i * 4..]);
+        }
+        state[12] = counter;
+        for i in 0..3 {
+            state[13 + i] = le_word(&nonce[i * 4..]);
+        }
+        ChaCha20 { state }
+    }
+
+    //生成当前计数器对应的 64 字节密钥流，然后计数器加 1
+    fn next_block(&mut self) -> [u8; BLOCK_SIZE] {
+        let mut working = self.state;
+        for _ in 0..10 {
+            //列轮
+            quarter_round(&mut working, 0, 4, 8, 12);
+            quarter_round(&mut working, 1, 5, 9, 13);
+            quarter_round(&mut working, 2, 6, 10, 14);
+            quarter_round(&mut working, 3, 7, 11, 15);
+            //对角线轮
+            quarter_round(&mut working, 0, 5, 10, 15);
+            quarter_round(&mut working, 1, 6, 11, 12);
+            quarter_round(&mut working, 2, 7, 8, 13);
+            quarter_round(&mut working, 3, 4, 9, 14);
+        }
+        let mut block = [0u8; BLOCK_SIZE];
+        for i in 0..16 {
+            let word = working[i].wrapping_add(self.state[i]);
+            block[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        self.state[12] = self.state[12].wrapping_add(1);
+        block
+    }
+
+    //把 data 与密钥流异或；每次调用都从一个新的 64 字节块开始
+    pub fn apply_keystream(&mut self, data: &mut [u8]) {
+        for chunk in data.chunks_mut(BLOCK_SIZE) {
+            let block = self.next_block();
+            for (byte, key) in chunk.iter_mut().zip(block.iter()) {
+                *byte ^= key;
+            }
+        }
+    }
+}
+
+impl Drop for ChaCha20 {
+    //不在堆栈上留下密钥
+    fn drop(&mut self) {
+        for word in self.state.iter_mut() {
+            unsafe { core::ptr::write_volatile(word, 0) };
+        }
+    }
+}
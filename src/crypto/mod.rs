@@ -0,0 +1,17 @@
+//密码学算法，纯软件实现，不依赖 CPU 的加速指令
+use alloc::string::String;
+use core::fmt::Write;
+
+pub mod aead; //ChaCha20-Poly1305 认证加密
+pub mod chacha20; //ChaCha20 流密码
+pub mod poly1305; //Poly1305 消息认证码
+pub mod sha256; //SHA-256 摘要
+
+//把字节序列转换成小写十六进制字符串
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        write!(hex, "{:02x}", b).unwrap();
+    }
+    hex
+}
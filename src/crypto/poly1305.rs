@@ -0,0 +1,164 @@
+//Poly1305(RFC 8439)：一次性的消息认证码，32 字节密钥只能用于一条消息
+//在模 2^130-5 的域上求多项式的值，数用 5 个 26 位的分量表示，乘法不会溢出 u64
+pub const KEY_SIZE: usize = 32;
+pub const TAG_SIZE: usize = 16;
+const BLOCK_SIZE: usize = 16;
+
+fn le_word(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+pub struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+    buffer: [u8; BLOCK_SIZE],
+    buffered: usize,
+}
+
+impl Poly1305 {
+    pub fn new(key: &[u8; KEY_SIZE]) -> Poly1305 {
+        //r 按规定清掉一些位
+        let r = [
+            le_word(&key[0..]) & 0x3ff_ffff,
+            (le_word(&key[3..]) >> 2) & 0x3ff_ff03,
+            (le_word(&key[6..]) >> 4) & 0x3ff_c0ff,
+            (le_word(&key[9..]) >> 6) & 0x3f0_3fff,
+            (le_word(&key[12..]) >> 8) & 0x00f_ffff,
+        ];
+        let pad = [le_word(&key[16..]), le_word(&key[20..]), le_word(&key[24..]), le_word(&key[28..])];
+        Poly1305 { r, h: [0; 5], pad, buffer: [0; BLOCK_SIZE], buffered: 0 }
+    }
+
+    //h = (h + 块) * r，hibit 是块后面补的 1(最后一个不满的块自己补)
+    fn block(&mut self, block: &[u8; BLOCK_SIZE], hibit: u32) {
+        let [r0, r1, r2, r3, r4] = self.r.map(|x| x as u64);
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+        let h = &mut self.h;
+        let h0 = (h[0] + (le_word(&block[0..]) & 0x3ff_ffff)) as u64;
+        let h1 = (h[1] + ((le_word(&block[3..]) >> 2) & 0x3ff_ffff)) as u64;
+        let h2 = (h[2] + ((le_word(&block[6..]) >> 4) & 0x3ff_ffff)) as u64;
+        let h3 = (h[3] + ((le_word(&block[9..]) >> 6) & 0x3ff_ffff)) as u64;
+        let h4 = (h[4] + ((le_word(&block[12..]) >> 8) | hibit)) as u64;
+
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        //进位，超过 2^130 的部分乘 5 加回最低的分量
+        let mut c = d0 >> 26;
+        h[0] = d0 as u32 & 0x3ff_ffff;
+        d1 += c;
+        c = d1 >> 26;
+        h[1] = d1 as u32 & 0x3ff_ffff;
+        d2 += c;
+        c = d2 >> 26;
+        h[2] = d2 as u32 & 0x3ff_ffff;
+        d3 += c;
+        c = d3 >> 26;
+        h[3] = d3 as u32 & 0x3ff_ffff;
+        d4 += c;
+        c = d4 >> 26;
+        h[4] = d4 as u32 & 0x3ff_ffff;
+        h[0] += c as u32 * 5;
+        let c = h[0] >> 26;
+        h[0] &= 0x3ff_ffff;
+        h[1] += c;
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.buffered > 0 {
+            let take = (BLOCK_SIZE - self.buffered).min(data.len());
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+            if self.buffered < BLOCK_SIZE {
+                return;
+            }
+            let block = self.buffer;
+            self.block(&block, 1 << 24);
+            self.buffered = 0;
+        }
+        while data.len() >= BLOCK_SIZE {
+            let block: [u8; BLOCK_SIZE] = data[..BLOCK_SIZE].try_into().unwrap();
+            self.block(&block, 1 << 24);
+            data = &data[BLOCK_SIZE..];
+        }
+        self.buffer[..data.len()].copy_from_slice(data);
+        self.buffered = data.len();
+    }
+
+    pub fn finish(mut self) -> [u8; TAG_SIZE] {
+        if self.buffered > 0 {
+            let mut block = [0u8; BLOCK_SIZE];
+            block[..self.buffered].copy_from_slice(&self.buffer[..self.buffered]);
+            block[self.buffered] = 1;
+            self.block(&block, 0);
+        }
+        //完全进位
+        let h = &mut self.h;
+        let mut c = h[1] >> 26;
+        h[1] &= 0x3ff_ffff;
+        for limb in h[2..].iter_mut() {
+            *limb += c;
+            c = *limb >> 26;
+            *limb &= 0x3ff_ffff;
+        }
+        h[0] += c * 5;
+        c = h[0] >> 26;
+        h[0] &= 0x3ff_ffff;
+        h[1] += c;
+
+        //g = h + 5 - 2^130，不小于 0 时取 g(即 h 模 2^130-5)，按掩码选择，不用分支
+        let mut g = [0u32; 5];
+        g[0] = h[0].wrapping_add(5);
+        c = g[0] >> 26;
+        g[0] &= 0x3ff_ffff;
+        for (g, h) in g[1..4].iter_mut().zip(&h[1..4]) {
+            *g = h + c;
+            c = *g >> 26;
+            *g &= 0x3ff_ffff;
+        }
+        g[4] = (h[4] + c).wrapping_sub(1 << 26);
+        let mask = (g[4] >> 31).wrapping_sub(1); //g 没有借位时全 1
+        for (h, g) in h.iter_mut().zip(g) {
+            *h = (*h & !mask) | (g & mask);
+        }
+
+        //拼成 128 位再加上 pad
+        let words = [
+            h[0] | h[1] << 26,
+            h[1] >> 6 | h[2] << 20,
+            h[2] >> 12 | h[3] << 14,
+            h[3] >> 18 | h[4] << 8,
+        ];
+        let mut tag = [0u8; TAG_SIZE];
+        let mut carry = 0u64;
+        for ((out, word), pad) in tag.chunks_mut(4).zip(words).zip(self.pad) {
+            let sum = word as u64 + pad as u64 + carry;
+            out.copy_from_slice(&(sum as u32).to_le_bytes());
+            carry = sum >> 32;
+        }
+        tag
+    }
+}
+
+impl Drop for Poly1305 {
+    //不在堆栈上留下密钥
+    fn drop(&mut self) {
+        for word in self.r.iter_mut().chain(self.pad.iter_mut()) {
+            unsafe { core::ptr::write_volatile(word, 0) };
+        }
+    }
+}
+
+//逐字节比较两个标签，耗时与内容无关
+pub fn verify(a: &[u8; TAG_SIZE], b: &[u8; TAG_SIZE]) -> bool {
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    diff == 0
+}
@@ -0,0 +1,146 @@
+//驱动框架：驱动用 register_driver! 放进链接段 kernel_drivers，启动时由 init_all 统一初始化
+//链接器为名字是合法标识符的段生成 __start_/__stop_ 符号，两者之间就是全部驱动的列表
+//初始化按依赖排序：一个驱动只有在它依赖的驱动都初始化成功后才会被探测
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DriverError {
+    Failed(String),           //驱动自己报告的初始化错误
+    Dependency(&'static str), //依赖的驱动不存在或初始化失败
+    Cycle,                    //依赖关系成环
+}
+
+pub trait Driver: Sync {
+    fn name(&self) -> &'static str;
+
+    //必须先初始化的驱动的名字
+    fn depends(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    //硬件是否存在，返回 false 时跳过这个驱动(不算失败)
+    fn probe(&self) -> bool;
+
+    //初始化硬件并注册设备，成功时返回一行状态说明
+    fn init(&self) -> Result<String, DriverError>;
+
+    //驱动使用的 IRQ 号
+    fn irq(&self) -> Option<u8> {
+        None
+    }
+
+    fn irq_handler(&self) {}
+}
+
+//把驱动放进驱动列表，$driver 必须是常量表达式
+#[macro_export]
+macro_rules! register_driver {
+    ($name:ident, $driver:expr) => {
+        #[used]
+        #[link_section = "kernel_drivers"]
+        static $name: &'static dyn $crate::driver::Driver = &$driver;
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    Ready(String), //初始化成功
+    Absent,        //没有探测到硬件
+    Failed(DriverError),
+}
+
+#[derive(Debug, Clone)]
+pub struct DriverInfo {
+    pub name: &'static str,
+    pub state: State,
+}
+
+static STATES: Mutex<Vec<DriverInfo>> = Mutex::new(Vec::new());
+
+extern "C" {
+    static __start_kernel_drivers: u8;
+    static __stop_kernel_drivers: u8;
+}
+
+fn drivers() -> &'static [&'static dyn Driver] {
+    unsafe {
+        let start = &__start_kernel_drivers as *const u8 as *const &'static dyn Driver;
+        let stop = &__stop_kernel_drivers as *const u8 as *const &'static dyn Driver;
+        core::slice::from_raw_parts(start, stop.offset_from(start) as usize)
+    }
+}
+
+fn find(name: &str) -> Option<&'static dyn Driver> {
+    drivers().iter().copied().find(|driver| driver.name() == name)
+}
+
+fn state(name: &str) -> Option<State> {
+    STATES.lock().iter().find(|info| info.name == name).map(|info| info.state.clone())
+}
+
+//初始化 driver 和它依赖的全部驱动，visiting 是正在初始化的依赖链，用来发现环
+fn init_one(driver: &'static dyn Driver, visiting: &mut Vec<&'static str>) -> State {
+    if let Some(state) = state(driver.name()) {
+        return state;
+    }
+    if visiting.contains(&driver.name()) {
+        return State::Failed(DriverError::Cycle);
+    }
+    visiting.push(driver.name());
+    let mut result = None;
+    for &dependency in driver.depends() {
+        let ready = match find(dependency) {
+            Some(dep) => matches!(init_one(dep, visiting), State::Ready(_)),
+            None => false,
+        };
+        if !ready {
+            result = Some(State::Failed(DriverError::Dependency(dependency)));
+            break;
+        }
+    }
+    visiting.pop();
+
+    let state = result.unwrap_or_else(|| match driver.probe() {
+        false => State::Absent,
+        true => match driver.init() {
+            Ok(status) => State::Ready(status),
+            Err(err) => State::Failed(err),
+        },
+    });
+    match &state {
+        State::Ready(status) => crate::log!(Info, "driver {}: {}", driver.name(), status),
+        State::Absent => crate::log!(Debug, "driver {}: absent", driver.name()),
+        State::Failed(err) => crate::log!(Error, "driver {}: failed: {:?}", driver.name(), err),
+    }
+    STATES.lock().push(DriverInfo { name: driver.name(), state: state.clone() });
+    state
+}
+
+//启动时调用一次：按依赖顺序初始化全部驱动
+pub fn init_all() {
+    for &driver in drivers() {
+        init_one(driver, &mut Vec::new());
+    }
+}
+
+//按初始化顺序列出全部驱动的状态
+pub fn list() -> Vec<DriverInfo> {
+    STATES.lock().clone()
+}
+
+//把 IRQ 交给使用它的驱动处理，返回是否有驱动处理了它
+//还没有 IDT，等中断处理程序接入后由它调用
+#[allow(dead_code)]
+pub fn dispatch_irq(irq: u8) -> bool {
+    let states = STATES.lock();
+    let mut handled = false;
+    for info in states.iter().filter(|info| matches!(info.state, State::Ready(_))) {
+        if let Some(driver) = find(info.name).filter(|driver| driver.irq() == Some(irq)) {
+            driver.irq_handler();
+            handled = true;
+        }
+    }
+    handled
+}
@@ -0,0 +1,372 @@
+use crate::block::{self, check_request, BlockDevice, BlockError};
+use crate::driver::{Driver, DriverError};
+use crate::{failpoint, msg, println, register_driver};
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use spin::Mutex;
+use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
+
+const SECTOR_SIZE: usize = 512; //ATA 扇区固定为 512 字节
+const MAX_SECTORS_PER_COMMAND: usize = 255; //每条命令最多传输的扇区数，避免计数寄存器为 0 时的特殊含义
+const LBA28_LIMIT: u64 = 1 << 28; //28 位 LBA 能寻址的扇区数
+const POLL_LIMIT: usize = 1_000_000; //轮询状态寄存器的最大次数
+
+//状态寄存器的各个位
+const STATUS_ERR: u8 = 0x01;
+const STATUS_DRQ: u8 = 0x08;
+const STATUS_DF: u8 = 0x20;
+const STATUS_BSY: u8 = 0x80;
+
+//ATA 命令
+const CMD_READ_SECTORS: u8 = 0x20;
+const CMD_READ_SECTORS_EXT: u8 = 0x24;
+const CMD_WRITE_SECTORS: u8 = 0x30;
+const CMD_WRITE_SECTORS_EXT: u8 = 0x34;
+const CMD_CACHE_FLUSH: u8 = 0xE7;
+const CMD_CACHE_FLUSH_EXT: u8 = 0xEA;
+const CMD_IDENTIFY: u8 = 0xEC;
+const CMD_SMART: u8 = 0xB0;
+
+//SMART 命令的子命令(写在特性寄存器里)，LBA mid/high 必须是固定的签名
+const SMART_READ_DATA: u8 = 0xD0;
+const SMART_RETURN_STATUS: u8 = 0xDA;
+const SMART_SIGNATURE: (u8, u8) = (0x4F, 0xC2);
+const SMART_EXCEEDED: (u8, u8) = (0xF4, 0x2C); //RETURN STATUS：有属性超过了厂商设定的阈值
+
+//一条 ATA 总线(主通道 0x1F0 / 次通道 0x170)上的全部 I/O 端口
+struct Bus {
+    data: Port<u16>,
+    error: PortReadOnly<u8>,
+    features: PortWriteOnly<u8>, //与错误寄存器是同一个端口，写入时是特性寄存器
+    sector_count: Port<u8>,
+    lba_low: Port<u8>,
+    lba_mid: Port<u8>,
+    lba_high: Port<u8>,
+    drive_select: Port<u8>,
+    status: PortReadOnly<u8>,
+    command: PortWriteOnly<u8>,
+    alt_status: PortReadOnly<u8>, //控制端口，读取时不会清除中断标志
+}
+
+impl Bus {
+    const fn new(io_base: u16, control_base: u16) -> Bus {
+        Bus {
+            data: Port::new(io_base),
+            error: PortReadOnly::new(io_base + 1),
+            features: PortWriteOnly::new(io_base + 1),
+            sector_count: Port::new(io_base + 2),
+            lba_low: Port::new(io_base + 3),
+            lba_mid: Port::new(io_base + 4),
+            lba_high: Port::new(io_base + 5),
+            drive_select: Port::new(io_base + 6),
+            status: PortReadOnly::new(io_base + 7),
+            command: PortWriteOnly::new(io_base + 7),
+            alt_status: PortReadOnly::new(control_base),
+        }
+    }
+
+    //选择驱动器后需要等待约 400ns，读 4 次备用状态寄存器即可
+    fn delay_400ns(&mut self) {
+        for _ in 0..4 {
+            unsafe {
+                self.alt_status.read();
+            }
+        }
+    }
+
+    fn wait_not_busy(&mut self) -> Result<u8, BlockError> {
+        for _ in 0..POLL_LIMIT {
+            let status = unsafe { self.status.read() };
+            if status & STATUS_BSY == 0 {
+                return Ok(status);
+            }
+        }
+        Err(BlockError::Timeout)
+    }
+
+    //等待设备准备好数据传输(DRQ 置位)，同时检查错误位
+    fn wait_drq(&mut self) -> Result<(), BlockError> {
+        for _ in 0..POLL_LIMIT {
+            let status = unsafe { self.status.read() };
+            if status & STATUS_BSY != 0 {
+                continue;
+            }
+            if status & (STATUS_ERR | STATUS_DF) != 0 {
+                return Err(BlockError::DeviceError);
+            }
+            if status & STATUS_DRQ != 0 {
+                return Ok(());
+            }
+        }
+        Err(BlockError::Timeout)
+    }
+
+    fn read_sector(&mut self, buf: &mut [u8]) {
+        for word in buf.chunks_exact_mut(2) {
+            let value = unsafe { self.data.read() };
+            word.copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn write_sector(&mut self, buf: &[u8]) {
+        for word in buf.chunks_exact(2) {
+            unsafe {
+                self.data.write(u16::from_le_bytes([word[0], word[1]]));
+            }
+        }
+    }
+}
+
+pub struct AtaDrive {
+    bus: Bus,
+    slave: bool,     //是否为从盘
+    lba48: bool,     //是否支持 48 位 LBA
+    sectors: u64,    //可寻址的扇区总数
+    model: [u8; 40], //IDENTIFY 返回的型号字符串
+    smart: bool,     //支持并启用了 SMART
+}
+
+impl AtaDrive {
+    //向驱动器发送 IDENTIFY 命令，驱动器不存在或不是 ATA 设备时返回 None
+    fn identify(io_base: u16, control_base: u16, slave: bool) -> Option<AtaDrive> {
+        let mut bus = Bus::new(io_base, control_base);
+        unsafe {
+            bus.drive_select.write(if slave { 0xB0 } else { 0xA0 });
+            bus.delay_400ns();
+            bus.sector_count.write(0);
+            bus.lba_low.write(0);
+            bus.lba_mid.write(0);
+            bus.lba_high.write(0);
+            bus.command.write(CMD_IDENTIFY);
+            if bus.status.read() == 0 {
+                return None; //状态为 0 说明总线上没有这个驱动器
+            }
+        }
+        bus.wait_not_busy().ok()?;
+        //LBA mid/high 不为 0 说明是 ATAPI 或 SATA 设备，这里不支持
+        if unsafe { bus.lba_mid.read() != 0 || bus.lba_high.read() != 0 } {
+            return None;
+        }
+        bus.wait_drq().ok()?;
+
+        let mut raw = [0u8; SECTOR_SIZE];
+        bus.read_sector(&mut raw);
+        let word = |i: usize| u16::from_le_bytes([raw[i * 2], raw[i * 2 + 1]]);
+
+        let lba48 = word(83) & (1 << 10) != 0;
+        let sectors = if lba48 {
+            (0..4).fold(0u64, |acc, i| acc | (word(100 + i) as u64) << (16 * i))
+        } else {
+            word(60) as u64 | (word(61) as u64) << 16
+        };
+
+        //型号字符串每个字中的两个字节是高低颠倒存放的
+        let mut model = [0u8; 40];
+        for i in 0..20 {
+            let [lo, hi] = word(27 + i).to_le_bytes();
+            model[i * 2] = hi;
+            model[i * 2 + 1] = lo;
+        }
+
+        //第 82 字第 0 位：支持 SMART；第 85 字第 0 位：已启用
+        let smart = word(82) & 1 != 0 && word(85) & 1 != 0;
+
+        Some(AtaDrive { bus, slave, lba48, sectors, model, smart })
+    }
+
+    pub fn model(&self) -> &str {
+        let text = core::str::from_utf8(&self.model).unwrap_or("");
+        text.trim()
+    }
+
+    pub fn is_slave(&self) -> bool {
+        self.slave
+    }
+
+    pub fn supports_lba48(&self) -> bool {
+        self.lba48
+    }
+
+    pub fn supports_smart(&self) -> bool {
+        self.smart
+    }
+
+    //发出一条 SMART 子命令，返回命令完成后的 LBA mid/high
+    fn smart_command(&mut self, feature: u8) -> Result<(u8, u8), BlockError> {
+        if !self.smart {
+            return Err(BlockError::NoDevice);
+        }
+        self.bus.wait_not_busy()?;
+        unsafe {
+            self.bus.drive_select.write(if self.slave { 0xB0 } else { 0xA0 });
+            self.bus.delay_400ns();
+            self.bus.features.write(feature);
+            self.bus.sector_count.write(0);
+            self.bus.lba_low.write(0);
+            self.bus.lba_mid.write(SMART_SIGNATURE.0);
+            self.bus.lba_high.write(SMART_SIGNATURE.1);
+            self.bus.command.write(CMD_SMART);
+        }
+        let status = self.bus.wait_not_busy()?;
+        if status & (STATUS_ERR | STATUS_DF) != 0 {
+            unsafe {
+                self.bus.error.read();
+            }
+            return Err(BlockError::DeviceError);
+        }
+        Ok(unsafe { (self.bus.lba_mid.read(), self.bus.lba_high.read()) })
+    }
+
+    //读取 512 字节的 SMART 数据(属性表)
+    pub fn smart_read_data(&mut self) -> Result<[u8; SECTOR_SIZE], BlockError> {
+        self.smart_command(SMART_READ_DATA)?;
+        self.bus.wait_drq()?;
+        let mut data = [0u8; SECTOR_SIZE];
+        self.bus.read_sector(&mut data);
+        Ok(data)
+    }
+
+    //驱动器自己的健康判断：有属性超过阈值时返回 false
+    pub fn smart_healthy(&mut self) -> Result<bool, BlockError> {
+        Ok(self.smart_command(SMART_RETURN_STATUS)? != SMART_EXCEEDED)
+    }
+
+    //设置起始扇区和扇区数，并发出读写命令
+    fn issue(&mut self, lba: u64, count: usize, cmd28: u8, cmd48: u8) -> Result<(), BlockError> {
+        let slave_bit = if self.slave { 0x10 } else { 0 };
+        let use_lba48 = lba + count as u64 > LBA28_LIMIT;
+        if use_lba48 && !self.lba48 {
+            return Err(BlockError::OutOfRange);
+        }
+        self.bus.wait_not_busy()?;
+        unsafe {
+            if use_lba48 {
+                self.bus.drive_select.write(0x40 | slave_bit);
+                self.bus.delay_400ns();
+                //48 位模式下先写高字节，再写低字节
+                self.bus.sector_count.write((count >> 8) as u8);
+                self.bus.lba_low.write((lba >> 24) as u8);
+                self.bus.lba_mid.write((lba >> 32) as u8);
+                self.bus.lba_high.write((lba >> 40) as u8);
+                self.bus.sector_count.write(count as u8);
+                self.bus.lba_low.write(lba as u8);
+                self.bus.lba_mid.write((lba >> 8) as u8);
+                self.bus.lba_high.write((lba >> 16) as u8);
+                self.bus.command.write(cmd48);
+            } else {
+                self.bus.drive_select.write(0xE0 | slave_bit | ((lba >> 24) as u8 & 0x0F));
+                self.bus.delay_400ns();
+                self.bus.sector_count.write(count as u8);
+                self.bus.lba_low.write(lba as u8);
+                self.bus.lba_mid.write((lba >> 8) as u8);
+                self.bus.lba_high.write((lba >> 16) as u8);
+                self.bus.command.write(cmd28);
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        let cmd = if self.lba48 { CMD_CACHE_FLUSH_EXT } else { CMD_CACHE_FLUSH };
+        self.bus.wait_not_busy()?;
+        unsafe {
+            self.bus.command.write(cmd);
+        }
+        let status = self.bus.wait_not_busy()?;
+        if status & (STATUS_ERR | STATUS_DF) != 0 {
+            unsafe {
+                self.bus.error.read();
+            }
+            return Err(BlockError::DeviceError);
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for AtaDrive {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        failpoint!("ata::read_timeout", BlockError::Timeout);
+        let mut lba = lba;
+        for chunk in buf.chunks_mut(SECTOR_SIZE * MAX_SECTORS_PER_COMMAND) {
+            let count = chunk.len() / SECTOR_SIZE;
+            self.issue(lba, count, CMD_READ_SECTORS, CMD_READ_SECTORS_EXT)?;
+            for sector in chunk.chunks_exact_mut(SECTOR_SIZE) {
+                self.bus.wait_drq()?;
+                self.bus.read_sector(sector);
+            }
+            lba += count as u64;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        failpoint!("ata::write_error", BlockError::DeviceError);
+        let mut lba = lba;
+        for chunk in buf.chunks(SECTOR_SIZE * MAX_SECTORS_PER_COMMAND) {
+            let count = chunk.len() / SECTOR_SIZE;
+            self.issue(lba, count, CMD_WRITE_SECTORS, CMD_WRITE_SECTORS_EXT)?;
+            for sector in chunk.chunks_exact(SECTOR_SIZE) {
+                self.bus.wait_drq()?;
+                self.bus.write_sector(sector);
+            }
+            self.flush()?; //写完后刷新驱动器缓存，保证数据落盘
+            lba += count as u64;
+        }
+        Ok(())
+    }
+}
+
+//主通道主盘、主通道从盘、次通道主盘、次通道从盘
+pub static DRIVES: Mutex<[Option<Arc<Mutex<AtaDrive>>>; 4]> = Mutex::new([None, None, None, None]);
+
+//探测两条总线上的全部驱动器，并以 ata0..ata3 的名字注册为块设备
+pub fn init() {
+    let ports = [(0x1F0, 0x3F6, false), (0x1F0, 0x3F6, true), (0x170, 0x376, false), (0x170, 0x376, true)];
+    let mut drives = DRIVES.lock();
+    for (i, &(io_base, control_base, slave)) in ports.iter().enumerate() {
+        drives[i] = AtaDrive::identify(io_base, control_base, slave).map(|drive| Arc::new(Mutex::new(drive)));
+        if let Some(drive) = &drives[i] {
+            let name = format!("ata{}", i);
+            block::register(&name, drive.clone());
+            let _ = block::scan_partitions(&name); //同时注册 ata0p1 这样的分区设备
+        }
+    }
+}
+
+struct AtaDriver;
+
+impl Driver for AtaDriver {
+    fn name(&self) -> &'static str {
+        "ata"
+    }
+
+    fn probe(&self) -> bool {
+        true //驱动器在 init 中逐个识别
+    }
+
+    fn init(&self) -> Result<String, DriverError> {
+        init();
+        let drives = DRIVES.lock();
+        for (i, drive) in drives.iter().enumerate() {
+            if let Some(drive) = drive {
+                let drive = drive.lock();
+                println!("{} ata{}: {} ({} sectors, lba48: {}, slave: {})", msg!(AtaDetected), i, drive.model(),
+                    drive.block_count(), drive.supports_lba48(), drive.is_slave());
+            }
+        }
+        Ok(format!("{} drive(s)", drives.iter().flatten().count()))
+    }
+}
+
+register_driver!(ATA_DRIVER, AtaDriver);
@@ -0,0 +1,134 @@
+//加密块设备(简化版 dm-crypt)：包装任意一个块设备，写入时用 ChaCha20 加密，读出时解密
+//每个扇区用自己的块号作为 nonce，计数器从 0 开始，所以任意扇区都可以单独读写
+//只做保密，不做完整性校验：篡改密文不会被发现，只会解密出错误的数据
+use crate::block::{self, check_request, BlockDevice, BlockError, SharedDevice};
+use crate::crypto::chacha20::{ChaCha20, KEY_SIZE, NONCE_SIZE};
+use crate::crypto::sha256::Sha256;
+use crate::fs;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+const MAX_CRYPT_DEVICES: usize = 8;
+const KDF_ROUNDS: usize = 4096; //由口令推导密钥时迭代 SHA-256 的次数，让暴力猜口令变慢
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptError {
+    NoDevice,   //底层块设备不存在
+    EmptyKey,   //口令为空
+    TooMany,    //加密设备个数达到上限
+    NotFound,   //没有这个加密设备
+}
+
+pub struct CryptDevice {
+    inner: SharedDevice,
+    key: [u8; KEY_SIZE],
+}
+
+//由口令推导 256 位密钥：key = SHA-256(key || 口令)，重复 KDF_ROUNDS 次
+pub fn derive_key(passphrase: &[u8]) -> [u8; KEY_SIZE] {
+    let mut key = [0u8; KEY_SIZE];
+    for _ in 0..KDF_ROUNDS {
+        let mut hasher = Sha256::new();
+        hasher.update(&key);
+        hasher.update(passphrase);
+        key = hasher.finalize();
+    }
+    key
+}
+
+impl CryptDevice {
+    pub fn new(inner: SharedDevice, key: [u8; KEY_SIZE]) -> CryptDevice {
+        CryptDevice { inner, key }
+    }
+
+    //第 lba 块的 nonce：前 4 字节为 0，后 8 字节是小端序的块号
+    fn sector_cipher(&self, lba: u64) -> ChaCha20 {
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce[4..].copy_from_slice(&lba.to_le_bytes());
+        ChaCha20::new(&self.key, &nonce, 0)
+    }
+
+    //逐块加密或解密(两者是同一个操作)，first 是 buf 中第一块的块号
+    fn apply(&self, first: u64, buf: &mut [u8]) {
+        let size = self.block_size();
+        for (i, sector) in buf.chunks_mut(size).enumerate() {
+            self.sector_cipher(first + i as u64).apply_keystream(sector);
+        }
+    }
+}
+
+impl BlockDevice for CryptDevice {
+    fn block_size(&self) -> usize {
+        self.inner.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.inner.block_count()
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        self.inner.read_blocks(lba, buf)?;
+        self.apply(lba, buf);
+        Ok(())
+    }
+
+    //不能原地加密调用者的缓冲区，先复制一份
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        let mut encrypted = Vec::from(buf);
+        self.apply(lba, &mut encrypted);
+        self.inner.write_blocks(lba, &encrypted)
+    }
+}
+
+impl Drop for CryptDevice {
+    fn drop(&mut self) {
+        for byte in self.key.iter_mut() {
+            unsafe { core::ptr::write_volatile(byte, 0) };
+        }
+    }
+}
+
+//已打开的加密设备：设备名(crypt0)到底层设备名
+static OPENED: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+//用口令打开底层设备 device，注册为下一个空闲的 cryptN 并建立 /dev/cryptN，返回设备名
+//口令错误时不会报错(没有保存校验信息)，只会读出无意义的数据，所以挂载会失败
+pub fn open(device: &str, passphrase: &[u8]) -> Result<String, CryptError> {
+    let device = device.strip_prefix("/dev/").unwrap_or(device);
+    let inner = block::get(device).ok_or(CryptError::NoDevice)?;
+    if passphrase.is_empty() {
+        return Err(CryptError::EmptyKey);
+    }
+    let mut opened = OPENED.lock();
+    let name = (0..MAX_CRYPT_DEVICES)
+        .map(|i| format!("crypt{}", i))
+        .find(|name| !opened.contains_key(name))
+        .ok_or(CryptError::TooMany)?;
+    let crypt: SharedDevice = Arc::new(Mutex::new(CryptDevice::new(inner, derive_key(passphrase))));
+    block::register(&name, crypt.clone());
+    fs::add_device_node(&name, crypt);
+    opened.insert(name.clone(), String::from(device));
+    Ok(name)
+}
+
+//关闭加密设备，已经用它挂载的文件系统仍然持有设备(和密钥)，可以继续使用
+pub fn close(name: &str) -> Result<(), CryptError> {
+    let name = name.strip_prefix("/dev/").unwrap_or(name);
+    if OPENED.lock().remove(name).is_none() {
+        return Err(CryptError::NotFound);
+    }
+    block::unregister(name);
+    fs::remove_device_node(name);
+    Ok(())
+}
+
+//所有加密设备及其底层设备
+pub fn list() -> Vec<(String, String)> {
+    OPENED.lock().iter().map(|(name, device)| (name.clone(), device.clone())).collect()
+}
@@ -0,0 +1,152 @@
+//QEMU 固件配置设备(fw_cfg)：主机用 -fw_cfg name=opt/...,file=... 或 string=... 传进来的数据块，按名字读取
+//选择端口写入项目编号，然后从数据端口逐字节读出内容；目录项(0x19)列出全部文件的名字、大小和编号，数值都是大端序
+//内核用它接收 CI 传入的参数，不用重新生成磁盘映像：
+//  opt/joakimos/cmdline       追加到内核命令行之后(见 cmdline::extend)
+//  opt/joakimos/files/<路径>   启动时复制到 ramfs 的 /<路径>
+use crate::driver::{Driver, DriverError};
+use crate::fs::vfs;
+use crate::fs::FsError;
+use crate::register_driver;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use x86_64::instructions::port::Port;
+
+const SELECTOR: u16 = 0x510;
+const DATA: u16 = 0x511;
+
+const KEY_SIGNATURE: u16 = 0x00;
+const KEY_FILE_DIR: u16 = 0x19;
+
+const NAME_LEN: usize = 56;
+
+pub const CMDLINE_FILE: &str = "opt/joakimos/cmdline";
+pub const FILES_PREFIX: &str = "opt/joakimos/files/";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    pub name: String,
+    pub size: u32,
+    pub select: u16,
+}
+
+fn select(key: u16) {
+    unsafe { Port::<u16>::new(SELECTOR).write(key) };
+}
+
+fn read_bytes(buf: &mut [u8]) {
+    let mut data = Port::<u8>::new(DATA);
+    for byte in buf.iter_mut() {
+        *byte = unsafe { data.read() };
+    }
+}
+
+fn read_be_u32() -> u32 {
+    let mut buf = [0; 4];
+    read_bytes(&mut buf);
+    u32::from_be_bytes(buf)
+}
+
+//不是 QEMU(或者没有这个设备)时数据端口读出的不是 "QEMU"
+pub fn present() -> bool {
+    select(KEY_SIGNATURE);
+    let mut signature = [0; 4];
+    read_bytes(&mut signature);
+    &signature == b"QEMU"
+}
+
+//全部文件，设备不存在时为空
+pub fn files() -> Vec<FileEntry> {
+    if !present() {
+        return Vec::new();
+    }
+    select(KEY_FILE_DIR);
+    let count = read_be_u32();
+    (0..count)
+        .map(|_| {
+            let size = read_be_u32();
+            let mut select = [0; 2];
+            read_bytes(&mut select);
+            let mut reserved = [0; 2];
+            read_bytes(&mut reserved);
+            let mut name = [0; NAME_LEN];
+            read_bytes(&mut name);
+            let len = name.iter().position(|&b| b == 0).unwrap_or(NAME_LEN);
+            FileEntry { name: String::from_utf8_lossy(&name[..len]).into_owned(), size, select: u16::from_be_bytes(select) }
+        })
+        .collect()
+}
+
+pub fn find(name: &str) -> Option<FileEntry> {
+    files().into_iter().find(|entry| entry.name == name)
+}
+
+pub fn read_entry(entry: &FileEntry) -> Vec<u8> {
+    let mut data = vec![0; entry.size as usize];
+    select(entry.select);
+    read_bytes(&mut data);
+    data
+}
+
+//按名字读出整个文件
+pub fn read(name: &str) -> Option<Vec<u8>> {
+    find(name).map(|entry| read_entry(&entry))
+}
+
+//主机追加的内核命令行参数，string= 传入的值末尾可能带 NUL
+pub fn cmdline() -> Option<String> {
+    let data = read(CMDLINE_FILE)?;
+    let text = String::from_utf8_lossy(&data);
+    Some(String::from(text.trim_end_matches(|c: char| c == '\0' || c.is_whitespace())))
+}
+
+//把 opt/joakimos/files/ 下的文件复制到根文件系统，缺少的目录自动创建；返回复制的文件数
+pub fn inject_files() -> Result<usize, FsError> {
+    let mut count = 0;
+    for entry in files() {
+        let relative = match entry.name.strip_prefix(FILES_PREFIX) {
+            Some(relative) if !relative.is_empty() => relative,
+            _ => continue,
+        };
+        let path = format!("/{}", relative);
+        for (end, _) in relative.match_indices('/') {
+            match vfs::mkdir(&format!("/{}", &relative[..end])) {
+                Ok(()) | Err(FsError::AlreadyExists) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if vfs::metadata(&path).is_ok() {
+            vfs::remove(&path)?;
+        }
+        let mut file = vfs::create(&path)?;
+        let data = read_entry(&entry);
+        let mut written = 0;
+        while written < data.len() {
+            match file.write(&data[written..])? {
+                0 => return Err(FsError::NoSpace),
+                n => written += n,
+            }
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+struct FwCfgDriver;
+
+impl Driver for FwCfgDriver {
+    fn name(&self) -> &'static str {
+        "fw_cfg"
+    }
+
+    fn probe(&self) -> bool {
+        present()
+    }
+
+    fn init(&self) -> Result<String, DriverError> {
+        Ok(format!("{} files", files().len()))
+    }
+}
+
+register_driver!(FW_CFG_DRIVER, FwCfgDriver);
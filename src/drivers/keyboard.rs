@@ -0,0 +1,222 @@
+use crate::vga_buffer;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use lazy_static::lazy_static;
+use super::keymap;
+use pc_keyboard::{DecodedKey, HandleControl, KeyCode, KeyEvent, KeyState, Keyboard, ScancodeSet, ScancodeSet1};
+use spin::Mutex;
+use x86_64::instructions::port::PortReadOnly;
+
+const STATUS_PORT: u16 = 0x64; //PS/2 控制器状态端口
+const DATA_PORT: u16 = 0x60;   //PS/2 控制器数据端口
+const STATUS_OUTPUT_FULL: u8 = 0x01; //输出缓冲区中有数据可读
+
+lazy_static! {
+    //pc_keyboard 负责把扫描码序列翻译成按键事件，并记录 Shift、CapsLock 等状态
+    //Ctrl+字母会被映射为 U+0001..U+001A，行编辑器据此识别 Ctrl+A、Ctrl+K 等快捷键
+    //按键翻译成字符时使用 keymap 中当前选择的布局
+    static ref KEYBOARD: Mutex<Keyboard<keymap::Selected, ScancodeSet1>> = Mutex::new(
+        Keyboard::new(ScancodeSet1::new(), keymap::Selected, HandleControl::MapLettersToUnicode)
+    );
+}
+
+static ALT_PRESSED: AtomicBool = AtomicBool::new(false); //左 Alt 是否按下
+static RAW_MODE: AtomicBool = AtomicBool::new(false);
+
+//原始按键模式显示的修饰键状态，pc_keyboard 不公开它自己记录的状态，这里按同样的规则从按键事件中记录
+static MODIFIERS: AtomicU8 = AtomicU8::new(0);
+const MODIFIER_KEYS: [(KeyCode, &str); 8] = [
+    (KeyCode::LShift, "lshift"),
+    (KeyCode::RShift, "rshift"),
+    (KeyCode::LControl, "lctrl"),
+    (KeyCode::RControl, "rctrl"),
+    (KeyCode::LAlt, "lalt"),
+    (KeyCode::RAltGr, "altgr"),
+    (KeyCode::CapsLock, "capslock"), //按下时切换
+    (KeyCode::NumpadLock, "numlock"), //按下时切换
+];
+
+//注入的按键：开发新布局或者 USB HID 驱动时，不用真的键盘也能走一遍完整的解码路径
+enum Injected {
+    Scancode(u8),    //和从控制器读到的字节一样解码
+    Event(KeyEvent), //跳过扫描码解码，USB HID 之类不产生扫描码的来源用这个
+}
+
+static INJECTED: Mutex<VecDeque<Injected>> = Mutex::new(VecDeque::new());
+static PENDING: Mutex<Vec<u8>> = Mutex::new(Vec::new()); //还没有凑成一个按键事件的扫描码(E0 前缀等)
+
+//Alt+F1..F4 切换虚拟终端，Alt+F12 打开或关闭原始按键模式，这些按键由驱动自己处理，不交给读键盘的程序，返回 true 表示已处理
+fn handle_terminal_switch(event: &KeyEvent) -> bool {
+    if event.code == KeyCode::LAlt {
+        ALT_PRESSED.store(event.state == KeyState::Down, Ordering::SeqCst);
+        return false;
+    }
+    if !ALT_PRESSED.load(Ordering::SeqCst) {
+        return false;
+    }
+    if event.code == KeyCode::F12 {
+        if event.state == KeyState::Down {
+            set_raw_mode(!raw_mode());
+        }
+        return true;
+    }
+    let terminal = match event.code {
+        KeyCode::F1 => 0,
+        KeyCode::F2 => 1,
+        KeyCode::F3 => 2,
+        KeyCode::F4 => 3,
+        _ => return false,
+    };
+    if event.state == KeyState::Down {
+        vga_buffer::switch_terminal(terminal);
+    }
+    true
+}
+
+//原始按键模式：每个按键事件显示一行：扫描码、pc_keyboard 的 KeyCode、修饰键状态和按当前布局翻译的结果，按键不交给读键盘的程序
+pub fn set_raw_mode(on: bool) {
+    RAW_MODE.store(on, Ordering::SeqCst);
+    crate::println!("keyboard: raw mode {} (Alt+F12 to toggle)", if on { "on" } else { "off" });
+}
+
+pub fn raw_mode() -> bool {
+    RAW_MODE.load(Ordering::SeqCst)
+}
+
+//注入扫描码(第一套)，和键盘控制器送来的一样处理，包括 Alt+F1..F4 等热键
+pub fn inject_scancodes(scancodes: &[u8]) {
+    INJECTED.lock().extend(scancodes.iter().map(|&scancode| Injected::Scancode(scancode)));
+}
+
+//注入一个已经解码的按键事件，同样经过热键、原始按键模式和布局翻译
+pub fn inject(code: KeyCode, state: KeyState) {
+    INJECTED.lock().push_back(Injected::Event(KeyEvent::new(code, state)));
+}
+
+//按 KeyCode 的名字(不区分大小写，如 "f2"、"LShift"、"ArrowUp")查找按键
+//名字表取自第一套扫描码能产生的全部按键，不用另外维护一份
+pub fn key_code(name: &str) -> Option<KeyCode> {
+    for prefix in [None, Some(0xe0)] {
+        for scancode in 0x01..0x80 {
+            let mut set = ScancodeSet1::new();
+            if let Some(prefix) = prefix {
+                let _ = set.advance_state(prefix);
+            }
+            if let Ok(Some(event)) = set.advance_state(scancode) {
+                if format!("{:?}", event.code).eq_ignore_ascii_case(name) {
+                    return Some(event.code);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn track_modifiers(event: &KeyEvent) {
+    let bit = match MODIFIER_KEYS.iter().position(|(code, _)| *code == event.code) {
+        Some(index) => 1 << index,
+        None => return,
+    };
+    let toggle = matches!(event.code, KeyCode::CapsLock | KeyCode::NumpadLock);
+    match (event.state, toggle) {
+        (KeyState::Down, true) => MODIFIERS.fetch_xor(bit, Ordering::SeqCst),
+        (KeyState::Down, false) => MODIFIERS.fetch_or(bit, Ordering::SeqCst),
+        (KeyState::Up, false) => MODIFIERS.fetch_and(!bit, Ordering::SeqCst),
+        _ => return,
+    };
+}
+
+fn modifier_names() -> String {
+    let bits = MODIFIERS.load(Ordering::SeqCst);
+    let names: Vec<&str> = MODIFIER_KEYS
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| bits & (1 << i) != 0)
+        .map(|(_, (_, name))| *name)
+        .collect();
+    if names.is_empty() { String::from("-") } else { names.join(",") }
+}
+
+fn show_raw(scancodes: &[u8], event: &KeyEvent, modifiers: &str, decoded: Option<DecodedKey>) {
+    let mut line = String::new();
+    if scancodes.is_empty() {
+        line.push_str("(injected) ");
+    }
+    for scancode in scancodes {
+        let _ = write!(line, "{:02x} ", scancode);
+    }
+    let _ = write!(line, "{:?} {:?} [{}]", event.code, event.state, modifiers);
+    match decoded {
+        Some(DecodedKey::Unicode(c)) => {
+            let _ = write!(line, " -> {:?}", c);
+        }
+        Some(DecodedKey::RawKey(code)) => {
+            let _ = write!(line, " -> {:?}", code);
+        }
+        None => {}
+    }
+    crate::println!("{}", line);
+}
+
+//下一个输入：先取注入的，再读键盘控制器
+fn next_input() -> Option<Injected> {
+    if let Some(input) = INJECTED.lock().pop_front() {
+        return Some(input);
+    }
+    let mut status = PortReadOnly::<u8>::new(STATUS_PORT);
+    let mut data = PortReadOnly::<u8>::new(DATA_PORT);
+    if unsafe { status.read() } & STATUS_OUTPUT_FULL == 0 {
+        return None;
+    }
+    Some(Injected::Scancode(unsafe { data.read() }))
+}
+
+//还没有中断处理，所以用轮询的方式读取键盘：如果控制器里有扫描码就取出来解码
+pub fn poll_key() -> Option<DecodedKey> {
+    vga_buffer::flush(); //等待按键前先显示还没有换行的输出(提示符等)
+    let input = next_input()?;
+
+    let mut keyboard = KEYBOARD.lock();
+    let mut pending = PENDING.lock();
+    let (event, scancodes) = match input {
+        Injected::Scancode(scancode) => {
+            pending.push(scancode);
+            match keyboard.add_byte(scancode) {
+                Ok(Some(event)) => (event, core::mem::take(&mut *pending)),
+                Ok(None) => return None,
+                Err(_) => {
+                    pending.clear();
+                    return None;
+                }
+            }
+        }
+        Injected::Event(event) => (event, Vec::new()),
+    };
+    drop(pending);
+    track_modifiers(&event);
+    if handle_terminal_switch(&event) {
+        return None;
+    }
+    if raw_mode() {
+        let decoded = keyboard.process_keyevent(event.clone());
+        let modifiers = modifier_names();
+        drop(keyboard);
+        show_raw(&scancodes, &event, &modifiers, decoded);
+        return None;
+    }
+    keyboard.process_keyevent(event)
+}
+
+//阻塞直到读到一个按键
+pub fn read_key() -> DecodedKey {
+    loop {
+        if let Some(key) = poll_key() {
+            return key;
+        }
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,156 @@
+//键盘布局：把按键(物理位置)翻译成字符，可以在运行时用 loadkeys 切换
+//us 和 de 直接使用 pc_keyboard 自带的布局；dvp(程序员 Dvorak)只换字符键的位置，其余按键与 us 相同
+//启动时的默认布局由 cargo 特性 keymap-de / keymap-dvp 选择，都不选时是 us
+use core::sync::atomic::{AtomicUsize, Ordering};
+use pc_keyboard::{layouts, DecodedKey, HandleControl, KeyCode, KeyboardLayout, Modifiers};
+
+pub trait Keymap: Sync {
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    fn map(&self, code: KeyCode, modifiers: &Modifiers, handle_ctrl: HandleControl) -> DecodedKey;
+}
+
+//pc_keyboard 自带的布局
+struct Builtin<L> {
+    name: &'static str,
+    description: &'static str,
+    layout: L,
+}
+
+impl<L: KeyboardLayout + Sync> Keymap for Builtin<L> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn map(&self, code: KeyCode, modifiers: &Modifiers, handle_ctrl: HandleControl) -> DecodedKey {
+        self.layout.map_keycode(code, modifiers, handle_ctrl)
+    }
+}
+
+//程序员 Dvorak：(按键, 不按 Shift 时的字符, 按 Shift 时的字符)，数字在上档
+const DVP_TABLE: &[(KeyCode, char, char)] = &[
+    (KeyCode::Oem8, '$', '~'),
+    (KeyCode::Key1, '&', '%'),
+    (KeyCode::Key2, '[', '7'),
+    (KeyCode::Key3, '{', '5'),
+    (KeyCode::Key4, '}', '3'),
+    (KeyCode::Key5, '(', '1'),
+    (KeyCode::Key6, '=', '9'),
+    (KeyCode::Key7, '*', '0'),
+    (KeyCode::Key8, ')', '2'),
+    (KeyCode::Key9, '+', '4'),
+    (KeyCode::Key0, ']', '8'),
+    (KeyCode::OemMinus, '!', '6'),
+    (KeyCode::OemPlus, '#', '`'),
+    (KeyCode::Q, ';', ':'),
+    (KeyCode::W, ',', '<'),
+    (KeyCode::E, '.', '>'),
+    (KeyCode::R, 'p', 'P'),
+    (KeyCode::T, 'y', 'Y'),
+    (KeyCode::Y, 'f', 'F'),
+    (KeyCode::U, 'g', 'G'),
+    (KeyCode::I, 'c', 'C'),
+    (KeyCode::O, 'r', 'R'),
+    (KeyCode::P, 'l', 'L'),
+    (KeyCode::Oem4, '/', '?'),
+    (KeyCode::Oem6, '@', '^'),
+    (KeyCode::Oem7, '\\', '|'),
+    (KeyCode::A, 'a', 'A'),
+    (KeyCode::S, 'o', 'O'),
+    (KeyCode::D, 'e', 'E'),
+    (KeyCode::F, 'u', 'U'),
+    (KeyCode::G, 'i', 'I'),
+    (KeyCode::H, 'd', 'D'),
+    (KeyCode::J, 'h', 'H'),
+    (KeyCode::K, 't', 'T'),
+    (KeyCode::L, 'n', 'N'),
+    (KeyCode::Oem1, 's', 'S'),
+    (KeyCode::Oem3, '-', '_'),
+    (KeyCode::Z, '\'', '"'),
+    (KeyCode::X, 'q', 'Q'),
+    (KeyCode::C, 'j', 'J'),
+    (KeyCode::V, 'k', 'K'),
+    (KeyCode::B, 'x', 'X'),
+    (KeyCode::N, 'b', 'B'),
+    (KeyCode::M, 'm', 'M'),
+    (KeyCode::OemComma, 'w', 'W'),
+    (KeyCode::OemPeriod, 'v', 'V'),
+    (KeyCode::Oem2, 'z', 'Z'),
+];
+
+struct ProgrammerDvorak;
+
+impl Keymap for ProgrammerDvorak {
+    fn name(&self) -> &'static str {
+        "dvp"
+    }
+
+    fn description(&self) -> &'static str {
+        "programmer Dvorak"
+    }
+
+    //字母受 CapsLock 影响，Ctrl+字母与 us 一样映射为 U+0001..U+001A
+    fn map(&self, code: KeyCode, modifiers: &Modifiers, handle_ctrl: HandleControl) -> DecodedKey {
+        let (normal, shifted) = match DVP_TABLE.iter().find(|&&(key, _, _)| key == code) {
+            Some(&(_, normal, shifted)) => (normal, shifted),
+            None => return layouts::Us104Key.map_keycode(code, modifiers, handle_ctrl),
+        };
+        if normal.is_ascii_lowercase() {
+            if modifiers.is_ctrl() && handle_ctrl == HandleControl::MapLettersToUnicode {
+                return DecodedKey::Unicode((normal as u8 - b'a' + 1) as char);
+            }
+            let upper = modifiers.is_shifted() != modifiers.capslock;
+            return DecodedKey::Unicode(if upper { shifted } else { normal });
+        }
+        DecodedKey::Unicode(if modifiers.is_shifted() { shifted } else { normal })
+    }
+}
+
+static KEYMAPS: &[&dyn Keymap] = &[
+    &Builtin { name: "us", description: "US QWERTY", layout: layouts::Us104Key },
+    &Builtin { name: "de", description: "German QWERTZ", layout: layouts::De105Key },
+    &ProgrammerDvorak,
+];
+
+#[cfg(feature = "keymap-de")]
+const DEFAULT: usize = 1;
+#[cfg(all(feature = "keymap-dvp", not(feature = "keymap-de")))]
+const DEFAULT: usize = 2;
+#[cfg(not(any(feature = "keymap-de", feature = "keymap-dvp")))]
+const DEFAULT: usize = 0;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(DEFAULT);
+
+pub fn all() -> &'static [&'static dyn Keymap] {
+    KEYMAPS
+}
+
+pub fn current() -> &'static dyn Keymap {
+    KEYMAPS[CURRENT.load(Ordering::Relaxed)]
+}
+
+//切换到名为 name 的布局，没有这个布局时返回 false
+pub fn select(name: &str) -> bool {
+    match KEYMAPS.iter().position(|keymap| keymap.name() == name) {
+        Some(index) => {
+            CURRENT.store(index, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+//交给 pc_keyboard 的布局：按当前选择的 Keymap 翻译
+pub struct Selected;
+
+impl KeyboardLayout for Selected {
+    fn map_keycode(&self, code: KeyCode, modifiers: &Modifiers, handle_ctrl: HandleControl) -> DecodedKey {
+        current().map(code, modifiers, handle_ctrl)
+    }
+}
@@ -0,0 +1,13 @@
+pub mod ata; //ATA PIO 硬盘驱动
+pub mod crypt; //加密块设备
+pub mod fw_cfg; //QEMU 固件配置设备
+pub mod keyboard; //PS/2 键盘驱动
+pub mod keymap; //键盘布局
+pub mod net; //网卡驱动
+pub mod pci; //PCI 配置空间访问和设备枚举
+pub mod ramdisk; //内存盘
+pub mod smart; //硬盘健康信息(SMART)
+pub mod snapshot; //写时复制快照设备
+pub mod uart; //16550 串口
+pub mod virtio; //virtio PCI 传输层和 virtqueue
+pub mod virtio_blk; //virtio 块设备
@@ -0,0 +1,94 @@
+//网卡驱动：网络协议栈只通过 NetworkDevice 收发以太网帧，不关心具体的网卡型号
+use crate::driver::{Driver, DriverError};
+use crate::register_driver;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+pub mod rtl8139; //Realtek RTL8139(QEMU 的 -device rtl8139)
+
+pub const MAX_FRAME_SIZE: usize = 1514; //不含 CRC 的最大以太网帧
+pub const MIN_FRAME_SIZE: usize = 60; //更短的帧发送前要补 0
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetError {
+    NoDevice,      //没有找到支持的网卡
+    FrameTooLarge, //帧超过 MAX_FRAME_SIZE
+    Timeout,       //网卡没有在规定时间内完成发送
+    Dma,           //分配不到网卡能访问的 DMA 内存
+}
+
+pub type MacAddress = [u8; 6];
+
+pub trait NetworkDevice {
+    fn mac(&self) -> MacAddress;
+
+    //发送一个完整的以太网帧(从目的 MAC 开始，不含 CRC)
+    fn send(&mut self, frame: &[u8]) -> Result<(), NetError>;
+
+    //取出一个已收到的帧，没有时返回 None，不阻塞
+    //还没有中断处理，接收靠调用者轮询
+    fn recv(&mut self) -> Option<Vec<u8>>;
+
+    //收发队列数：网卡自己按 RSS 散列分配接收队列时大于 1
+    fn queues(&self) -> usize {
+        1
+    }
+
+    //从接收队列 queue 取出一个帧；只有一个队列的网卡用 recv
+    fn recv_queue(&mut self, queue: usize) -> Option<Vec<u8>> {
+        if queue == 0 {
+            self.recv()
+        } else {
+            None
+        }
+    }
+
+    //从发送队列 queue 发送
+    fn send_queue(&mut self, _queue: usize, frame: &[u8]) -> Result<(), NetError> {
+        self.send(frame)
+    }
+}
+
+//探测到的第一块网卡
+pub static NIC: Mutex<Option<Box<dyn NetworkDevice + Send>>> = Mutex::new(None);
+
+//依次尝试各个驱动，返回驱动名和 MAC 地址
+pub fn init() -> Result<(&'static str, MacAddress), NetError> {
+    let device = rtl8139::Rtl8139::probe()?;
+    let mac = device.mac();
+    *NIC.lock() = Some(Box::new(device));
+    Ok(("rtl8139", mac))
+}
+
+//在网卡上执行 f，没有网卡时返回 NoDevice
+pub fn with_nic<T>(f: impl FnOnce(&mut (dyn NetworkDevice + Send)) -> T) -> Result<T, NetError> {
+    let mut nic = NIC.lock();
+    let nic = nic.as_mut().ok_or(NetError::NoDevice)?;
+    Ok(f(nic.as_mut()))
+}
+
+struct NetDriver;
+
+impl Driver for NetDriver {
+    fn name(&self) -> &'static str {
+        "net"
+    }
+
+    fn depends(&self) -> &'static [&'static str] {
+        &["pci"]
+    }
+
+    fn probe(&self) -> bool {
+        rtl8139::Rtl8139::present()
+    }
+
+    fn init(&self) -> Result<String, DriverError> {
+        let (driver, mac) = init().map_err(|err| DriverError::Failed(format!("{:?}", err)))?;
+        Ok(format!("{} {:02x?}", driver, mac))
+    }
+}
+
+register_driver!(NET_DRIVER, NetDriver);
@@ -0,0 +1,186 @@
+//RTL8139 网卡：寄存器在 BAR0 的 I/O 端口上，收发缓冲区由网卡通过 DMA 直接读写
+//接收缓冲区是一个环：每个帧前面有 4 字节的头(状态、长度)，帧按 4 字节对齐依次存放
+//发送使用 4 个缓冲区轮流：写入起始地址和长度后网卡开始发送，完成后置位 OWN
+use super::{MacAddress, NetError, NetworkDevice, MAX_FRAME_SIZE, MIN_FRAME_SIZE};
+use crate::drivers::pci;
+use crate::memory;
+use alloc::vec::Vec;
+use x86_64::instructions::port::Port;
+use x86_64::{PhysAddr, VirtAddr};
+
+const VENDOR_ID: u16 = 0x10EC;
+const DEVICE_ID: u16 = 0x8139;
+
+//寄存器偏移
+const REG_IDR0: u16 = 0x00; //MAC 地址
+const REG_TSD0: u16 = 0x10; //发送状态，每个缓冲区一个
+const REG_TSAD0: u16 = 0x20; //发送缓冲区的物理地址
+const REG_RBSTART: u16 = 0x30; //接收缓冲区的物理地址
+const REG_CR: u16 = 0x37; //命令
+const REG_CAPR: u16 = 0x38; //驱动已经读到的位置(减 16)
+const REG_IMR: u16 = 0x3C; //中断屏蔽
+const REG_ISR: u16 = 0x3E; //中断状态
+const REG_RCR: u16 = 0x44; //接收配置
+const REG_CONFIG1: u16 = 0x52;
+
+const CR_RESET: u8 = 0x10;
+const CR_RX_ENABLE: u8 = 0x08;
+const CR_TX_ENABLE: u8 = 0x04;
+const CR_BUFFER_EMPTY: u8 = 0x01;
+
+//接收本机地址、广播和多播的帧；WRAP 位让跨过环尾的帧连续存放，不必拆成两段复制
+const RCR_ACCEPT: u32 = 0x0E;
+const RCR_WRAP: u32 = 1 << 7;
+
+const TSD_OWN: u32 = 1 << 13; //网卡已把数据从缓冲区取走
+const TSD_ABORTED: u32 = 1 << 30;
+const ISR_ROK: u16 = 0x01;
+const ISR_TOK: u16 = 0x04;
+const RX_STATUS_OK: u16 = 0x01;
+
+const RX_RING_SIZE: usize = 8192;
+const RX_BUFFER_SIZE: usize = RX_RING_SIZE + 16 + 1500; //WRAP 模式下环尾之后还要留出一个最大帧的空间
+const TX_SLOTS: usize = 4;
+const TX_SLOT_SIZE: usize = 1536;
+const RESET_TIMEOUT: usize = 100_000;
+const TX_TIMEOUT: usize = 1_000_000;
+
+pub struct Rtl8139 {
+    io_base: u16,
+    mac: MacAddress,
+    rx: VirtAddr,
+    rx_offset: usize, //下一个帧在接收环中的位置
+    tx: VirtAddr,
+    tx_phys: PhysAddr,
+    tx_next: usize, //下一个使用的发送缓冲区
+}
+
+fn frames_for(size: usize) -> usize {
+    size.div_ceil(4096)
+}
+
+impl Rtl8139 {
+    fn read8(&self, reg: u16) -> u8 {
+        unsafe { Port::<u8>::new(self.io_base + reg).read() }
+    }
+
+    fn write8(&self, reg: u16, value: u8) {
+        unsafe { Port::<u8>::new(self.io_base + reg).write(value) }
+    }
+
+    fn write16(&self, reg: u16, value: u16) {
+        unsafe { Port::<u16>::new(self.io_base + reg).write(value) }
+    }
+
+    fn read32(&self, reg: u16) -> u32 {
+        unsafe { Port::<u32>::new(self.io_base + reg).read() }
+    }
+
+    fn write32(&self, reg: u16, value: u32) {
+        unsafe { Port::<u32>::new(self.io_base + reg).write(value) }
+    }
+
+    //在 PCI 总线上查找网卡，复位并开始收发
+    pub fn present() -> bool {
+        pci::find(VENDOR_ID, DEVICE_ID).is_some()
+    }
+
+    pub fn probe() -> Result<Rtl8139, NetError> {
+        let dev = pci::find(VENDOR_ID, DEVICE_ID).ok_or(NetError::NoDevice)?;
+        dev.enable_bus_master();
+        let io_base = (dev.bar(0) & !0x3) as u16; //I/O BAR 的低 2 位是标志位
+
+        //网卡只能访问 32 位物理地址
+        let (rx_phys, rx) = memory::alloc_dma(frames_for(RX_BUFFER_SIZE)).map_err(|_| NetError::Dma)?;
+        let (tx_phys, tx) = memory::alloc_dma(frames_for(TX_SLOTS * TX_SLOT_SIZE)).map_err(|_| NetError::Dma)?;
+        if rx_phys.as_u64() + RX_BUFFER_SIZE as u64 > u32::MAX as u64 || tx_phys.as_u64() + (TX_SLOTS * TX_SLOT_SIZE) as u64 > u32::MAX as u64 {
+            return Err(NetError::Dma);
+        }
+
+        let mut nic = Rtl8139 { io_base, mac: [0; 6], rx, rx_offset: 0, tx, tx_phys, tx_next: 0 };
+        nic.write8(REG_CONFIG1, 0); //唤醒网卡
+        nic.write8(REG_CR, CR_RESET);
+        let mut waited = 0;
+        while nic.read8(REG_CR) & CR_RESET != 0 {
+            waited += 1;
+            if waited > RESET_TIMEOUT {
+                return Err(NetError::Timeout);
+            }
+            core::hint::spin_loop();
+        }
+        for (i, byte) in nic.mac.iter_mut().enumerate() {
+            *byte = unsafe { Port::<u8>::new(io_base + REG_IDR0 + i as u16).read() };
+        }
+        nic.write32(REG_RBSTART, rx_phys.as_u64() as u32);
+        nic.write16(REG_IMR, 0); //没有中断处理，全部屏蔽，靠轮询
+        nic.write32(REG_RCR, RCR_ACCEPT | RCR_WRAP);
+        nic.write8(REG_CR, CR_RX_ENABLE | CR_TX_ENABLE);
+        Ok(nic)
+    }
+
+    fn rx_ring(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.rx.as_ptr(), RX_BUFFER_SIZE) }
+    }
+}
+
+impl NetworkDevice for Rtl8139 {
+    fn mac(&self) -> MacAddress {
+        self.mac
+    }
+
+    fn send(&mut self, frame: &[u8]) -> Result<(), NetError> {
+        if frame.len() > MAX_FRAME_SIZE {
+            return Err(NetError::FrameTooLarge);
+        }
+        let slot = self.tx_next;
+        let tsd = REG_TSD0 + slot as u16 * 4;
+        let len = frame.len().max(MIN_FRAME_SIZE);
+        unsafe {
+            let buffer = self.tx.as_mut_ptr::<u8>().add(slot * TX_SLOT_SIZE);
+            core::ptr::copy_nonoverlapping(frame.as_ptr(), buffer, frame.len());
+            core::ptr::write_bytes(buffer.add(frame.len()), 0, len - frame.len());
+        }
+        self.write32(REG_TSAD0 + slot as u16 * 4, (self.tx_phys.as_u64() + (slot * TX_SLOT_SIZE) as u64) as u32);
+        self.write32(tsd, len as u32); //写入长度同时清除 OWN 位，网卡开始发送
+        self.tx_next = (slot + 1) % TX_SLOTS;
+
+        //等网卡取走数据后缓冲区才能再次使用；这里直接等待，发送是同步的
+        for _ in 0..TX_TIMEOUT {
+            let status = self.read32(tsd);
+            if status & TSD_ABORTED != 0 {
+                return Err(NetError::Timeout);
+            }
+            if status & TSD_OWN != 0 {
+                self.write16(REG_ISR, ISR_TOK);
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(NetError::Timeout)
+    }
+
+    fn recv(&mut self) -> Option<Vec<u8>> {
+        loop {
+            if self.read8(REG_CR) & CR_BUFFER_EMPTY != 0 {
+                return None;
+            }
+            let ring = self.rx_ring();
+            let header = &ring[self.rx_offset..self.rx_offset + 4];
+            let status = u16::from_le_bytes([header[0], header[1]]);
+            let length = u16::from_le_bytes([header[2], header[3]]) as usize; //包括 4 字节的 CRC
+            let frame = if status & RX_STATUS_OK != 0 && (MIN_FRAME_SIZE..=MAX_FRAME_SIZE + 4).contains(&length) {
+                Some(Vec::from(&ring[self.rx_offset + 4..self.rx_offset + length])) //去掉 CRC
+            } else {
+                None //出错的帧直接跳过
+            };
+
+            self.rx_offset = (self.rx_offset + length + 4 + 3) & !3;
+            self.rx_offset %= RX_RING_SIZE;
+            self.write16(REG_CAPR, (self.rx_offset as u16).wrapping_sub(16)); //CAPR 的值要比实际位置小 16
+            self.write16(REG_ISR, ISR_ROK);
+            if frame.is_some() {
+                return frame;
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//PCI 配置空间访问：使用配置机制 #1(0xCF8 地址端口，0xCFC 数据端口)
+use crate::driver::{Driver, DriverError};
+use crate::register_driver;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use x86_64::instructions::port::Port;
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+//配置空间中常用字段的偏移
+const OFFSET_VENDOR_ID: u8 = 0x00;
+const OFFSET_COMMAND: u8 = 0x04;
+const OFFSET_CLASS: u8 = 0x08;
+const OFFSET_STATUS: u8 = 0x06;
+const OFFSET_HEADER_TYPE: u8 = 0x0C;
+const OFFSET_BAR0: u8 = 0x10;
+const OFFSET_CAPABILITIES: u8 = 0x34;
+const STATUS_CAPABILITIES: u32 = 1 << 4; //设备有能力(capability)链表
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+}
+
+fn address(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    0x8000_0000 | (bus as u32) << 16 | (device as u32) << 11 | (function as u32) << 8 | (offset as u32 & 0xFC)
+}
+
+//读取配置空间中按 4 字节对齐的一个双字
+pub fn read_config(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    let mut addr = Port::<u32>::new(CONFIG_ADDRESS);
+    let mut data = Port::<u32>::new(CONFIG_DATA);
+    unsafe {
+        addr.write(address(bus, device, function, offset));
+        data.read()
+    }
+}
+
+pub fn write_config(bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    let mut addr = Port::<u32>::new(CONFIG_ADDRESS);
+    let mut data = Port::<u32>::new(CONFIG_DATA);
+    unsafe {
+        addr.write(address(bus, device, function, offset));
+        data.write(value);
+    }
+}
+
+impl PciDevice {
+    pub fn read(&self, offset: u8) -> u32 {
+        read_config(self.bus, self.device, self.function, offset)
+    }
+
+    pub fn write(&self, offset: u8, value: u32) {
+        write_config(self.bus, self.device, self.function, offset, value)
+    }
+
+    //允许设备访问 I/O 端口和内存，并作为总线主控发起 DMA
+    pub fn enable_bus_master(&self) {
+        const IO_SPACE: u32 = 1 << 0;
+        const MEMORY_SPACE: u32 = 1 << 1;
+        const BUS_MASTER: u32 = 1 << 2;
+        let command = self.read(OFFSET_COMMAND) & 0xFFFF; //高 16 位是状态寄存器，写 1 会清除其中的位
+        self.write(OFFSET_COMMAND, command | IO_SPACE | MEMORY_SPACE | BUS_MASTER);
+    }
+
+    //第 index 个基址寄存器的原始值，内存 BAR 的低 4 位是标志位
+    pub fn bar(&self, index: u8) -> u32 {
+        self.read(OFFSET_BAR0 + index * 4)
+    }
+
+    //内存 BAR 指向的物理地址，64 位 BAR 的高 32 位在下一个寄存器里；I/O BAR 返回 None
+    pub fn memory_bar(&self, index: u8) -> Option<u64> {
+        let low = self.bar(index);
+        if low & 0x1 != 0 {
+            return None;
+        }
+        let high = if (low >> 1) & 0x3 == 0x2 { self.bar(index + 1) as u64 } else { 0 };
+        Some(high << 32 | (low & !0xF) as u64)
+    }
+
+    //读取配置空间中的一个字节
+    pub fn read_u8(&self, offset: u8) -> u8 {
+        (self.read(offset) >> ((offset & 0x3) * 8)) as u8
+    }
+
+    //能力链表中的每一项：(能力 ID, 在配置空间中的偏移)
+    pub fn capabilities(&self) -> Vec<(u8, u8)> {
+        let mut found = Vec::new();
+        if (self.read(OFFSET_STATUS & !0x3) >> 16) & STATUS_CAPABILITIES == 0 {
+            return found;
+        }
+        let mut offset = self.read_u8(OFFSET_CAPABILITIES) & !0x3;
+        while offset != 0 && found.len() < 48 { //配置空间最多放下 48 个能力，防止损坏的链表成环
+            found.push((self.read_u8(offset), offset));
+            offset = self.read_u8(offset + 1) & !0x3;
+        }
+        found
+    }
+}
+
+fn probe(bus: u8, device: u8, function: u8) -> Option<PciDevice> {
+    let id = read_config(bus, device, function, OFFSET_VENDOR_ID);
+    let vendor_id = id as u16;
+    if vendor_id == 0xFFFF {
+        return None; //没有这个设备
+    }
+    let class = read_config(bus, device, function, OFFSET_CLASS);
+    Some(PciDevice {
+        bus,
+        device,
+        function,
+        vendor_id,
+        device_id: (id >> 16) as u16,
+        class: (class >> 24) as u8,
+        subclass: (class >> 16) as u8,
+    })
+}
+
+//枚举所有总线上的全部设备(逐个尝试，不依赖桥的配置)
+pub fn devices() -> Vec<PciDevice> {
+    let mut found = Vec::new();
+    for bus in 0..=255u8 {
+        for device in 0..32u8 {
+            let first = match probe(bus, device, 0) {
+                Some(first) => first,
+                None => continue,
+            };
+            found.push(first);
+            //头类型的最高位表示这是一个多功能设备
+            let multifunction = (first.read(OFFSET_HEADER_TYPE) >> 16) & 0x80 != 0;
+            if multifunction {
+                found.extend((1..8u8).filter_map(|function| probe(bus, device, function)));
+            }
+        }
+    }
+    found
+}
+
+pub fn find(vendor_id: u16, device_id: u16) -> Option<PciDevice> {
+    devices().into_iter().find(|dev| dev.vendor_id == vendor_id && dev.device_id == device_id)
+}
+
+//PCI 总线本身：其他 PCI 设备的驱动都依赖它
+struct PciDriver;
+
+impl Driver for PciDriver {
+    fn name(&self) -> &'static str {
+        "pci"
+    }
+
+    fn probe(&self) -> bool {
+        true
+    }
+
+    fn init(&self) -> Result<String, DriverError> {
+        Ok(format!("{} device(s)", devices().len()))
+    }
+}
+
+register_driver!(PCI_DRIVER, PciDriver);
@@ -0,0 +1,82 @@
+//内存盘：数据放在内核堆上的块设备，速度快，可以随意破坏，适合文件系统压力测试
+use crate::block::{self, check_request, BlockDevice, BlockError};
+use crate::fs;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+const BLOCK_SIZE: usize = 512;
+const MAX_RAMDISKS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamdiskError {
+    BadSize,      //大小为 0 或不是块大小的整数倍
+    OutOfMemory,  //内核堆放不下
+    TooMany,      //内存盘个数达到上限
+    NotFound,     //没有这个内存盘
+}
+
+pub struct RamDisk {
+    data: Vec<u8>,
+}
+
+impl BlockDevice for RamDisk {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.data.len() / BLOCK_SIZE) as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        let start = lba as usize * BLOCK_SIZE;
+        buf.copy_from_slice(&self.data[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        let start = lba as usize * BLOCK_SIZE;
+        self.data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+fn is_ramdisk(name: &str) -> bool {
+    (0..MAX_RAMDISKS).any(|i| name == format!("ram{}", i))
+}
+
+//创建一个 size 字节、内容全为 0 的内存盘，注册为下一个空闲的 ramN 并建立 /dev/ramN，返回设备名
+//分配失败时返回 OutOfMemory，而不是让整个内核因为堆耗尽而崩溃
+pub fn create(size: usize) -> Result<String, RamdiskError> {
+    if size == 0 || !size.is_multiple_of(BLOCK_SIZE) {
+        return Err(RamdiskError::BadSize);
+    }
+    let name = (0..MAX_RAMDISKS)
+        .map(|i| format!("ram{}", i))
+        .find(|name| block::get(name).is_none())
+        .ok_or(RamdiskError::TooMany)?;
+    let mut data = Vec::new();
+    data.try_reserve_exact(size).map_err(|_| RamdiskError::OutOfMemory)?;
+    data.resize(size, 0);
+    let device: block::SharedDevice = Arc::new(Mutex::new(RamDisk { data }));
+    block::register(&name, device.clone());
+    fs::add_device_node(&name, device);
+    Ok(name)
+}
+
+//删除内存盘，最后一个使用者(例如挂载在它上面的文件系统)释放后内存才会回收
+pub fn destroy(name: &str) -> Result<(), RamdiskError> {
+    let name = name.strip_prefix("/dev/").unwrap_or(name);
+    if !is_ramdisk(name) {
+        return Err(RamdiskError::NotFound);
+    }
+    let device = block::unregister(name).ok_or(RamdiskError::NotFound)?;
+    fs::cache::discard(&device); //内容随内存盘一起丢弃，不必写回
+    fs::remove_device_node(name);
+    Ok(())
+}
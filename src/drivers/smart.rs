@@ -0,0 +1,132 @@
+//硬盘健康信息(SMART)：解析 ATA 驱动器的属性表，取出温度、重新映射的扇区数和通电时间
+//shell 空闲时定期检查所有驱动器，越过阈值(或者恢复正常)时打印一条警告
+//还没有 NVMe 驱动，只支持 ATA
+use super::ata::{AtaDrive, DRIVES};
+use crate::block::BlockError;
+use crate::println;
+use crate::time::Instant;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+use spin::Mutex;
+
+const ATTRIBUTE_COUNT: usize = 30; //属性表最多 30 项，每项 12 字节，从第 2 字节开始
+const CHECK_INTERVAL: Duration = Duration::from_secs(600);
+const TEMPERATURE_LIMIT: u64 = 55; //摄氏度
+
+//常用的属性 ID
+const ATTR_REALLOCATED: u8 = 5;
+const ATTR_POWER_ON_HOURS: u8 = 9;
+const ATTR_AIRFLOW_TEMPERATURE: u8 = 190;
+const ATTR_TEMPERATURE: u8 = 194;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Attribute {
+    pub id: u8,
+    pub value: u8, //归一化的当前值，越大越好
+    pub worst: u8,
+    pub raw: u64, //6 字节的原始值，含义由属性决定
+}
+
+#[derive(Debug, Clone)]
+pub struct SmartData {
+    pub attributes: Vec<Attribute>,
+    pub healthy: bool, //驱动器自己的判断(RETURN STATUS)
+}
+
+impl SmartData {
+    fn raw(&self, id: u8) -> Option<u64> {
+        self.attributes.iter().find(|attr| attr.id == id).map(|attr| attr.raw)
+    }
+
+    //摄氏度，原始值的最低字节
+    pub fn temperature(&self) -> Option<u64> {
+        self.raw(ATTR_TEMPERATURE).or_else(|| self.raw(ATTR_AIRFLOW_TEMPERATURE)).map(|raw| raw & 0xFF)
+    }
+
+    pub fn reallocated_sectors(&self) -> Option<u64> {
+        self.raw(ATTR_REALLOCATED)
+    }
+
+    pub fn power_on_hours(&self) -> Option<u64> {
+        self.raw(ATTR_POWER_ON_HOURS).map(|raw| raw & 0xFFFF_FFFF)
+    }
+}
+
+pub fn read(drive: &mut AtaDrive) -> Result<SmartData, BlockError> {
+    let data = drive.smart_read_data()?;
+    let attributes = data[2..2 + ATTRIBUTE_COUNT * 12]
+        .chunks_exact(12)
+        .filter(|entry| entry[0] != 0)
+        .map(|entry| Attribute {
+            id: entry[0],
+            value: entry[3],
+            worst: entry[4],
+            raw: entry[5..11].iter().rev().fold(0, |acc, &b| acc << 8 | b as u64),
+        })
+        .collect();
+    Ok(SmartData { attributes, healthy: drive.smart_healthy()? })
+}
+
+//按块设备名(ata0..ata3)读取
+pub fn read_device(name: &str) -> Result<SmartData, BlockError> {
+    let index: usize = name.strip_prefix("ata").and_then(|n| n.parse().ok()).ok_or(BlockError::NoDevice)?;
+    let drive = DRIVES.lock().get(index).cloned().flatten().ok_or(BlockError::NoDevice)?;
+    let mut drive = drive.lock();
+    read(&mut drive)
+}
+
+//上次检查时每个驱动器越过了哪些阈值，只在变化时警告
+static WARNINGS: Mutex<[Vec<String>; 4]> = Mutex::new([Vec::new(), Vec::new(), Vec::new(), Vec::new()]);
+static LAST_CHECK: Mutex<Option<Instant>> = Mutex::new(None);
+
+//越过的阈值，重新映射的扇区数变化时消息也不同，所以每次增加都会再警告一次
+fn problems(data: &SmartData) -> Vec<String> {
+    let mut found = Vec::new();
+    if !data.healthy {
+        found.push(String::from("drive reports SMART failure"));
+    }
+    if let Some(temp) = data.temperature().filter(|&temp| temp >= TEMPERATURE_LIMIT) {
+        found.push(format!("temperature {} C", temp));
+    }
+    if let Some(count) = data.reallocated_sectors().filter(|&count| count > 0) {
+        found.push(format!("{} reallocated sectors", count));
+    }
+    found
+}
+
+//检查全部支持 SMART 的驱动器
+pub fn check() {
+    let drives = DRIVES.lock().clone();
+    for (i, drive) in drives.iter().enumerate() {
+        let data = match drive {
+            Some(drive) if drive.lock().supports_smart() => read(&mut drive.lock()),
+            _ => continue,
+        };
+        let found = match data {
+            Ok(data) => problems(&data),
+            Err(err) => vec![format!("SMART read failed: {:?}", err)],
+        };
+        let mut warnings = WARNINGS.lock();
+        if found != warnings[i] {
+            if found.is_empty() {
+                println!("smart: ata{}: back to normal", i);
+            }
+            for problem in found.iter().filter(|p| !warnings[i].contains(p)) {
+                println!("smart: ata{}: warning: {}", i, problem);
+            }
+            warnings[i] = found;
+        }
+    }
+    *LAST_CHECK.lock() = Some(Instant::now());
+}
+
+//shell 等待输入时调用：每 CHECK_INTERVAL 检查一次
+pub fn idle() {
+    let due = LAST_CHECK.lock().is_none_or(|last| last.elapsed() >= CHECK_INTERVAL);
+    if due {
+        check();
+    }
+}
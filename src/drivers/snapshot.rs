@@ -0,0 +1,194 @@
+//快照(写时复制)块设备：包装一个底层设备，所有写入都记在内存中的稀疏覆盖层里，底层设备保持不变
+//测试结束后可以把覆盖层提交到底层设备，也可以直接丢弃，回到快照建立时的状态
+//还可以模拟断电：设定再写入若干块后 "断电"，之后的写入都被悄悄丢弃，就像数据没来得及落盘
+use crate::block::{self, check_request, BlockDevice, BlockError, SharedDevice};
+use crate::fs;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+const MAX_SNAPSHOTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    NoDevice,        //底层块设备不存在
+    TooMany,         //快照设备个数达到上限
+    NotFound,        //没有这个快照设备
+    Block(BlockError), //提交时写入底层设备失败
+}
+
+impl From<BlockError> for SnapshotError {
+    fn from(err: BlockError) -> SnapshotError {
+        SnapshotError::Block(err)
+    }
+}
+
+pub struct CowDevice {
+    base: SharedDevice,
+    overlay: BTreeMap<u64, Vec<u8>>, //块号到这一块的新内容
+    writes_left: Option<u64>,        //模拟断电：还能写入的块数，None 表示不会断电
+    written: u64,                    //建立快照(或上次丢弃)以来写入的块数，同一块写多次算多次
+    dropped: u64,                    //断电后被丢弃的写入块数
+}
+
+//快照设备的状态，供 shell 显示
+#[derive(Debug, Clone, Copy)]
+pub struct Status {
+    pub dirty_blocks: usize,
+    pub written: u64,
+    pub writes_left: Option<u64>,
+    pub dropped: u64,
+}
+
+impl CowDevice {
+    pub fn new(base: SharedDevice) -> CowDevice {
+        CowDevice { base, overlay: BTreeMap::new(), writes_left: None, written: 0, dropped: 0 }
+    }
+
+    //按块号顺序把覆盖层写回底层设备；中途失败时已写回的块从覆盖层中移除，其余的保留
+    pub fn commit(&mut self) -> Result<(), BlockError> {
+        while let Some((&lba, data)) = self.overlay.iter().next() {
+            self.base.write_blocks(lba, data)?;
+            self.overlay.remove(&lba);
+        }
+        Ok(())
+    }
+
+    //丢弃覆盖层，同时恢复供电
+    pub fn discard(&mut self) {
+        self.overlay.clear();
+        self.writes_left = None;
+        self.written = 0;
+        self.dropped = 0;
+    }
+
+    //再写入 blocks 块后断电；None 恢复供电(之前丢弃的写入不会恢复)
+    pub fn fail_after(&mut self, blocks: Option<u64>) {
+        self.writes_left = blocks;
+    }
+
+    pub fn status(&self) -> Status {
+        Status { dirty_blocks: self.overlay.len(), written: self.written, writes_left: self.writes_left, dropped: self.dropped }
+    }
+}
+
+impl BlockDevice for CowDevice {
+    fn block_size(&self) -> usize {
+        self.base.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.base.block_count()
+    }
+
+    //先整段从底层设备读出，再用覆盖层中的块替换
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        let count = check_request(self, lba, buf.len())?;
+        self.base.read_blocks(lba, buf)?;
+        let size = self.block_size();
+        for (&block, data) in self.overlay.range(lba..lba + count) {
+            let start = (block - lba) as usize * size;
+            buf[start..start + size].copy_from_slice(data);
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        let size = self.block_size();
+        for (i, data) in buf.chunks(size).enumerate() {
+            match self.writes_left {
+                Some(0) => {
+                    self.dropped += 1; //已经断电，调用者以为写成功了
+                    continue;
+                }
+                Some(ref mut left) => *left -= 1,
+                None => {}
+            }
+            self.overlay.insert(lba + i as u64, Vec::from(data));
+            self.written += 1;
+        }
+        Ok(())
+    }
+}
+
+struct Snapshot {
+    base: String, //底层设备名
+    device: Arc<Mutex<CowDevice>>,
+}
+
+static SNAPSHOTS: Mutex<BTreeMap<String, Snapshot>> = Mutex::new(BTreeMap::new());
+
+//在底层设备 device 上建立快照，注册为下一个空闲的 snapN 并建立 /dev/snapN，返回设备名
+//快照存在期间不应再直接写底层设备，否则读到的内容会是两者的混合
+pub fn create(device: &str) -> Result<String, SnapshotError> {
+    let device = device.strip_prefix("/dev/").unwrap_or(device);
+    let base = block::get(device).ok_or(SnapshotError::NoDevice)?;
+    let mut snapshots = SNAPSHOTS.lock();
+    let name = (0..MAX_SNAPSHOTS)
+        .map(|i| format!("snap{}", i))
+        .find(|name| !snapshots.contains_key(name))
+        .ok_or(SnapshotError::TooMany)?;
+    let cow = Arc::new(Mutex::new(CowDevice::new(base)));
+    let shared: SharedDevice = cow.clone();
+    block::register(&name, shared.clone());
+    fs::add_device_node(&name, shared);
+    snapshots.insert(name.clone(), Snapshot { base: String::from(device), device: cow });
+    Ok(name)
+}
+
+//在快照设备 name 上执行 f
+pub fn with_snapshot<T>(name: &str, f: impl FnOnce(&mut CowDevice) -> T) -> Result<T, SnapshotError> {
+    let name = name.strip_prefix("/dev/").unwrap_or(name);
+    let device = SNAPSHOTS.lock().get(name).map(|s| s.device.clone()).ok_or(SnapshotError::NotFound)?;
+    let mut device = device.lock();
+    Ok(f(&mut device))
+}
+
+//经过块缓存写入快照设备、还没有写回的块先写进覆盖层；底层设备缓存的块提交后就过时了，一并写回并丢弃
+pub fn commit(name: &str) -> Result<(), SnapshotError> {
+    let name = name.strip_prefix("/dev/").unwrap_or(name);
+    let base = SNAPSHOTS.lock().get(name).map(|s| s.base.clone()).ok_or(SnapshotError::NotFound)?;
+    if let Some(device) = block::get(name) {
+        fs::cache::sync_device(&device)?;
+    }
+    if let Some(base) = block::get(&base) {
+        fs::cache::invalidate(&base)?;
+    }
+    with_snapshot(name, |cow| cow.commit())?.map_err(SnapshotError::from)
+}
+
+//缓存中快照设备的块也属于要丢弃的写入
+pub fn discard(name: &str) -> Result<(), SnapshotError> {
+    let name = name.strip_prefix("/dev/").unwrap_or(name);
+    let result = with_snapshot(name, |cow| cow.discard());
+    if let (Ok(()), Some(device)) = (&result, block::get(name)) {
+        fs::cache::discard(&device);
+    }
+    result
+}
+
+//删除快照设备，未提交的写入全部丢弃；已经用它挂载的文件系统仍然持有设备
+pub fn destroy(name: &str) -> Result<(), SnapshotError> {
+    let name = name.strip_prefix("/dev/").unwrap_or(name);
+    if SNAPSHOTS.lock().remove(name).is_none() {
+        return Err(SnapshotError::NotFound);
+    }
+    if let Some(device) = block::unregister(name) {
+        fs::cache::discard(&device);
+    }
+    fs::remove_device_node(name);
+    Ok(())
+}
+
+//所有快照设备：设备名、底层设备名和状态
+pub fn list() -> Vec<(String, String, Status)> {
+    SNAPSHOTS
+        .lock()
+        .iter()
+        .map(|(name, s)| (name.clone(), s.base.clone(), s.device.lock().status()))
+        .collect()
+}
@@ -0,0 +1,94 @@
+//16550 串口：只用轮询方式收发，不打开串口中断
+use x86_64::instructions::port::Port;
+
+pub const COM1: u16 = 0x3F8;
+pub const COM2: u16 = 0x2F8;
+
+//寄存器相对于基地址的偏移
+const DATA: u16 = 0; //DLAB=1 时为除数低字节
+const INTERRUPT_ENABLE: u16 = 1; //DLAB=1 时为除数高字节
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+const SCRATCH: u16 = 7;
+
+const LINE_DLAB: u8 = 0x80;
+const LINE_8N1: u8 = 0x03;
+const STATUS_DATA_READY: u8 = 0x01;
+const STATUS_THR_EMPTY: u8 = 0x20;
+
+const DIVISOR_115200: u16 = 1; //时钟 1.8432 MHz / 16 / 除数
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Uart {
+    base: u16,
+}
+
+impl Uart {
+    pub const fn new(base: u16) -> Uart {
+        Uart { base }
+    }
+
+    fn read(&self, reg: u16) -> u8 {
+        unsafe { Port::<u8>::new(self.base + reg).read() }
+    }
+
+    fn write(&self, reg: u16, value: u8) {
+        unsafe { Port::<u8>::new(self.base + reg).write(value) }
+    }
+
+    //串口不存在时读出的是 0xFF，用暂存寄存器能否保存写入的值来判断
+    pub fn present(&self) -> bool {
+        self.write(SCRATCH, 0x5A);
+        self.read(SCRATCH) == 0x5A
+    }
+
+    //115200 波特，8 位数据，无校验，1 位停止位
+    pub fn init(&self) {
+        self.write(INTERRUPT_ENABLE, 0);
+        self.write(LINE_CONTROL, LINE_DLAB);
+        self.write(DATA, DIVISOR_115200 as u8);
+        self.write(INTERRUPT_ENABLE, (DIVISOR_115200 >> 8) as u8);
+        self.write(LINE_CONTROL, LINE_8N1);
+        self.write(FIFO_CONTROL, 0xC7); //启用并清空 FIFO，14 字节触发
+        self.write(MODEM_CONTROL, 0x03); //DTR、RTS
+    }
+
+    pub fn send(&self, byte: u8) {
+        while self.read(LINE_STATUS) & STATUS_THR_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        self.write(DATA, byte);
+    }
+
+    pub fn try_recv(&self) -> Option<u8> {
+        if self.read(LINE_STATUS) & STATUS_DATA_READY == 0 {
+            return None;
+        }
+        Some(self.read(DATA))
+    }
+
+    //等待直到收到一个字节
+    pub fn recv(&self) -> u8 {
+        loop {
+            if let Some(byte) = self.try_recv() {
+                return byte;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+//用于 write!：换行前补上回车，串口终端才会回到行首
+impl core::fmt::Write for Uart {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+//virtio 1.0 的 PCI 传输层和 virtqueue：各个 virtio 设备驱动(块设备、网卡等)共用
+//设备的寄存器分成几块，位置由 PCI 能力链表中的 virtio 能力给出，都在内存 BAR 里
+//还没有中断处理，请求提交后轮询 used 环等待完成
+use super::pci::PciDevice;
+use crate::memory;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::{PhysAddr, VirtAddr};
+
+pub const VENDOR_ID: u16 = 0x1AF4;
+
+const CAP_VENDOR_SPECIFIC: u8 = 0x09;
+const CFG_COMMON: u8 = 1;
+const CFG_NOTIFY: u8 = 2;
+const CFG_DEVICE: u8 = 4;
+
+//通用配置结构中各字段的偏移
+const COMMON_DEVICE_FEATURE_SELECT: usize = 0x00;
+const COMMON_DEVICE_FEATURE: usize = 0x04;
+const COMMON_DRIVER_FEATURE_SELECT: usize = 0x08;
+const COMMON_DRIVER_FEATURE: usize = 0x0C;
+const COMMON_DEVICE_STATUS: usize = 0x14;
+const COMMON_QUEUE_SELECT: usize = 0x16;
+const COMMON_QUEUE_SIZE: usize = 0x18;
+const COMMON_QUEUE_ENABLE: usize = 0x1C;
+const COMMON_QUEUE_NOTIFY_OFF: usize = 0x1E;
+const COMMON_QUEUE_DESC: usize = 0x20;
+const COMMON_QUEUE_DRIVER: usize = 0x28;
+const COMMON_QUEUE_DEVICE: usize = 0x30;
+
+//设备状态位
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FEATURES_OK: u8 = 8;
+const STATUS_FAILED: u8 = 0x80;
+
+pub const FEATURE_VERSION_1: u64 = 1 << 32; //不支持旧版(legacy)接口，必须协商这一位
+
+pub const DESC_F_NEXT: u16 = 1;
+pub const DESC_F_WRITE: u16 = 2; //设备写入的缓冲区
+const AVAIL_F_NO_INTERRUPT: u16 = 1;
+
+const MAX_QUEUE_SIZE: u16 = 16;
+const POLL_TIMEOUT: usize = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VirtioError {
+    NoCapability,     //设备缺少必需的 virtio 能力(可能只支持旧版接口)
+    FeaturesRejected, //设备不接受驱动选择的特性
+    NoQueue,          //队列不存在
+    Memory,           //映射寄存器或分配 DMA 内存失败
+    Timeout,          //设备没有在规定时间内完成请求
+}
+
+//一块 virtio 能力描述的寄存器区域
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    base: VirtAddr,
+}
+
+impl Region {
+    fn read<T: Copy>(&self, offset: usize) -> T {
+        unsafe { read_volatile((self.base.as_u64() as usize + offset) as *const T) }
+    }
+
+    fn write<T: Copy>(&self, offset: usize, value: T) {
+        unsafe { write_volatile((self.base.as_u64() as usize + offset) as *mut T, value) }
+    }
+}
+
+pub struct Transport {
+    common: Region,
+    notify: Region,
+    notify_multiplier: u32,
+    device: Region,
+}
+
+//读取 virtio 能力，映射它所指的寄存器区域；返回区域和能力在配置空间中的偏移
+fn map_capability(pci: &PciDevice, cfg_type: u8) -> Result<(Region, u8), VirtioError> {
+    for (id, offset) in pci.capabilities() {
+        if id != CAP_VENDOR_SPECIFIC || pci.read_u8(offset + 3) != cfg_type {
+            continue;
+        }
+        let bar = pci.read_u8(offset + 4);
+        let region_offset = pci.read(offset + 8) as u64;
+        let length = pci.read(offset + 12) as u64;
+        let bar_base = pci.memory_bar(bar).ok_or(VirtioError::NoCapability)?;
+        let base = memory::map_mmio(PhysAddr::new(bar_base + region_offset), length, PageTableFlags::NO_CACHE)
+            .map_err(|_| VirtioError::Memory)?;
+        return Ok((Region { base }, offset));
+    }
+    Err(VirtioError::NoCapability)
+}
+
+impl Transport {
+    //复位设备并协商特性：wanted 中设备也支持的位会被接受，FEATURE_VERSION_1 必须支持
+    //返回协商好的特性，之后要为每个队列调用 setup_queue，最后调用 finish_init
+    pub fn init(pci: &PciDevice, wanted: u64) -> Result<(Transport, u64), VirtioError> {
+        pci.enable_bus_master();
+        let (common, _) = map_capability(pci, CFG_COMMON)?;
+        let (notify, notify_cap) = map_capability(pci, CFG_NOTIFY)?;
+        let (device, _) = map_capability(pci, CFG_DEVICE)?;
+        let transport = Transport { common, notify, notify_multiplier: pci.read(notify_cap + 16), device };
+
+        transport.common.write::<u8>(COMMON_DEVICE_STATUS, 0); //复位
+        while transport.common.read::<u8>(COMMON_DEVICE_STATUS) != 0 {
+            core::hint::spin_loop();
+        }
+        transport.set_status(STATUS_ACKNOWLEDGE);
+        transport.set_status(STATUS_DRIVER);
+
+        let features = transport.device_features() & (wanted | FEATURE_VERSION_1);
+        if features & FEATURE_VERSION_1 == 0 {
+            transport.set_status(STATUS_FAILED);
+            return Err(VirtioError::FeaturesRejected);
+        }
+        transport.common.write::<u32>(COMMON_DRIVER_FEATURE_SELECT, 0);
+        transport.common.write::<u32>(COMMON_DRIVER_FEATURE, features as u32);
+        transport.common.write::<u32>(COMMON_DRIVER_FEATURE_SELECT, 1);
+        transport.common.write::<u32>(COMMON_DRIVER_FEATURE, (features >> 32) as u32);
+        transport.set_status(STATUS_FEATURES_OK);
+        if transport.common.read::<u8>(COMMON_DEVICE_STATUS) & STATUS_FEATURES_OK == 0 {
+            transport.set_status(STATUS_FAILED);
+            return Err(VirtioError::FeaturesRejected);
+        }
+        Ok((transport, features))
+    }
+
+    fn set_status(&self, bit: u8) {
+        let status = self.common.read::<u8>(COMMON_DEVICE_STATUS);
+        self.common.write::<u8>(COMMON_DEVICE_STATUS, status | bit);
+    }
+
+    fn device_features(&self) -> u64 {
+        self.common.write::<u32>(COMMON_DEVICE_FEATURE_SELECT, 0);
+        let low = self.common.read::<u32>(COMMON_DEVICE_FEATURE) as u64;
+        self.common.write::<u32>(COMMON_DEVICE_FEATURE_SELECT, 1);
+        let high = self.common.read::<u32>(COMMON_DEVICE_FEATURE) as u64;
+        high << 32 | low
+    }
+
+    //设备特有的配置(如块设备的容量)
+    pub fn read_device_config<T: Copy>(&self, offset: usize) -> T {
+        self.device.read(offset)
+    }
+
+    pub fn setup_queue(&self, index: u16) -> Result<Virtqueue, VirtioError> {
+        self.common.write::<u16>(COMMON_QUEUE_SELECT, index);
+        let max = self.common.read::<u16>(COMMON_QUEUE_SIZE);
+        if max == 0 {
+            return Err(VirtioError::NoQueue);
+        }
+        let size = max.min(MAX_QUEUE_SIZE);
+        self.common.write::<u16>(COMMON_QUEUE_SIZE, size);
+
+        let (phys, virt) = memory::alloc_dma(1).map_err(|_| VirtioError::Memory)?;
+        let queue = Virtqueue::new(index, size, virt);
+        self.common.write::<u64>(COMMON_QUEUE_DESC, phys.as_u64() + queue.desc_offset as u64);
+        self.common.write::<u64>(COMMON_QUEUE_DRIVER, phys.as_u64() + queue.avail_offset as u64);
+        self.common.write::<u64>(COMMON_QUEUE_DEVICE, phys.as_u64() + queue.used_offset as u64);
+        let notify_off = self.common.read::<u16>(COMMON_QUEUE_NOTIFY_OFF) as usize;
+        let queue = Virtqueue { notify_offset: notify_off * self.notify_multiplier as usize, ..queue };
+        self.common.write::<u16>(COMMON_QUEUE_ENABLE, 1);
+        Ok(queue)
+    }
+
+    pub fn finish_init(&self) {
+        self.set_status(STATUS_DRIVER_OK);
+    }
+
+    fn notify(&self, queue: &Virtqueue) {
+        self.notify.write::<u16>(queue.notify_offset, queue.index);
+    }
+}
+
+//分离式 virtqueue：描述符表、driver(avail)环和 device(used)环放在同一个 DMA 页里
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    base: VirtAddr,
+    desc_offset: usize,
+    avail_offset: usize,
+    used_offset: usize,
+    notify_offset: usize,
+    last_used: u16, //已经处理到的 used 环位置
+}
+
+//描述符表中的一项
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+//一次请求中的一个缓冲区：物理地址、长度、是否由设备写入
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer {
+    pub addr: PhysAddr,
+    pub len: u32,
+    pub device_writes: bool,
+}
+
+impl Virtqueue {
+    fn new(index: u16, size: u16, base: VirtAddr) -> Virtqueue {
+        let n = size as usize;
+        let desc_offset = 0;
+        let avail_offset = desc_offset + 16 * n;
+        let used_offset = (avail_offset + 6 + 2 * n + 3) & !3; //used 环要求 4 字节对齐
+        let queue = Virtqueue { index, size, base, desc_offset, avail_offset, used_offset, notify_offset: 0, last_used: 0 };
+        queue.write::<u16>(avail_offset, AVAIL_F_NO_INTERRUPT); //轮询完成，不需要中断
+        queue
+    }
+
+    fn write<T: Copy>(&self, offset: usize, value: T) {
+        unsafe { write_volatile((self.base.as_u64() as usize + offset) as *mut T, value) }
+    }
+
+    fn read<T: Copy>(&self, offset: usize) -> T {
+        unsafe { read_volatile((self.base.as_u64() as usize + offset) as *const T) }
+    }
+
+    //提交一组缓冲区组成的请求并等待设备完成，返回设备写入的字节数
+    //一次只有一个请求在队列中，描述符总是从 0 号开始使用
+    pub fn submit(&mut self, transport: &Transport, buffers: &[Buffer]) -> Result<u32, VirtioError> {
+        assert!(!buffers.is_empty() && buffers.len() <= self.size as usize, "virtqueue request too long");
+        for (i, buffer) in buffers.iter().enumerate() {
+            let mut flags = if buffer.device_writes { DESC_F_WRITE } else { 0 };
+            if i + 1 < buffers.len() {
+                flags |= DESC_F_NEXT;
+            }
+            let desc = Descriptor { addr: buffer.addr.as_u64(), len: buffer.len, flags, next: i as u16 + 1 };
+            self.write(self.desc_offset + 16 * i, desc);
+        }
+        let avail_idx: u16 = self.read(self.avail_offset + 2);
+        self.write::<u16>(self.avail_offset + 4 + 2 * (avail_idx % self.size) as usize, 0);
+        fence(Ordering::SeqCst); //描述符和环中的项必须先于 idx 对设备可见
+        self.write::<u16>(self.avail_offset + 2, avail_idx.wrapping_add(1));
+        fence(Ordering::SeqCst);
+        transport.notify(self);
+
+        for _ in 0..POLL_TIMEOUT {
+            let used_idx: u16 = self.read(self.used_offset + 2);
+            if used_idx != self.last_used {
+                fence(Ordering::SeqCst);
+                let slot = self.used_offset + 4 + 8 * (self.last_used % self.size) as usize;
+                let written: u32 = self.read(slot + 4);
+                self.last_used = self.last_used.wrapping_add(1);
+                return Ok(written);
+            }
+            core::hint::spin_loop();
+        }
+        Err(VirtioError::Timeout)
+    }
+}
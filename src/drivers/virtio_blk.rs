@@ -0,0 +1,157 @@
+//virtio 块设备(QEMU 的 -drive if=virtio)：每个请求由请求头、数据缓冲区和状态字节三个缓冲区组成
+//数据先经过一块 DMA 缓冲区中转，大的请求拆成几次提交
+use super::pci;
+use super::virtio::{self, Buffer, Transport, Virtqueue, VirtioError};
+use crate::block::{self, check_request, BlockDevice, BlockError};
+use crate::driver::{Driver, DriverError};
+use crate::{failpoint, register_driver};
+use crate::memory;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::{PhysAddr, VirtAddr};
+
+const DEVICE_IDS: [u16; 2] = [0x1001, 0x1042]; //过渡型和纯 1.0 设备
+const SECTOR_SIZE: usize = 512;
+const FEATURE_RO: u64 = 1 << 5; //设备只读
+
+const REQUEST_IN: u32 = 0; //读
+const REQUEST_OUT: u32 = 1; //写
+const STATUS_OK: u8 = 0;
+
+const BOUNCE_FRAMES: usize = 16; //中转缓冲区 64 KiB
+const BOUNCE_SECTORS: usize = BOUNCE_FRAMES * 4096 / SECTOR_SIZE;
+const HEADER_OFFSET: usize = 0; //请求头和状态字节所在页中的偏移
+const STATUS_OFFSET: usize = 16;
+
+pub struct VirtioBlk {
+    transport: Transport,
+    queue: Virtqueue,
+    capacity: u64, //扇区数
+    read_only: bool,
+    request: (PhysAddr, VirtAddr), //请求头和状态字节
+    bounce: (PhysAddr, VirtAddr),
+}
+
+impl VirtioBlk {
+    fn new(dev: &pci::PciDevice) -> Result<VirtioBlk, VirtioError> {
+        let (transport, features) = Transport::init(dev, FEATURE_RO)?;
+        let queue = transport.setup_queue(0)?;
+        let capacity: u64 = transport.read_device_config(0);
+        let request = memory::alloc_dma(1).map_err(|_| VirtioError::Memory)?;
+        let bounce = memory::alloc_dma(BOUNCE_FRAMES).map_err(|_| VirtioError::Memory)?;
+        transport.finish_init();
+        Ok(VirtioBlk { transport, queue, capacity, read_only: features & FEATURE_RO != 0, request, bounce })
+    }
+
+    //提交一个读写请求，数据在中转缓冲区的前 count 个扇区
+    fn transfer(&mut self, kind: u32, lba: u64, count: usize) -> Result<(), BlockError> {
+        failpoint!("virtio_blk::request_error", BlockError::DeviceError);
+        let header = self.request.1.as_mut_ptr::<u8>();
+        unsafe {
+            core::ptr::write_volatile(header.add(HEADER_OFFSET) as *mut u32, kind);
+            core::ptr::write_volatile(header.add(HEADER_OFFSET + 4) as *mut u32, 0);
+            core::ptr::write_volatile(header.add(HEADER_OFFSET + 8) as *mut u64, lba);
+            core::ptr::write_volatile(header.add(STATUS_OFFSET), 0xFF);
+        }
+        let buffers = [
+            Buffer { addr: self.request.0 + HEADER_OFFSET as u64, len: 16, device_writes: false },
+            Buffer { addr: self.bounce.0, len: (count * SECTOR_SIZE) as u32, device_writes: kind == REQUEST_IN },
+            Buffer { addr: self.request.0 + STATUS_OFFSET as u64, len: 1, device_writes: true },
+        ];
+        self.queue.submit(&self.transport, &buffers).map_err(|_| BlockError::Timeout)?;
+        match unsafe { core::ptr::read_volatile(header.add(STATUS_OFFSET)) } {
+            STATUS_OK => Ok(()),
+            _ => Err(BlockError::DeviceError),
+        }
+    }
+
+    fn bounce(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.bounce.1.as_mut_ptr(), BOUNCE_FRAMES * 4096) }
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.capacity
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        for (i, chunk) in buf.chunks_mut(BOUNCE_SECTORS * SECTOR_SIZE).enumerate() {
+            self.transfer(REQUEST_IN, lba + (i * BOUNCE_SECTORS) as u64, chunk.len() / SECTOR_SIZE)?;
+            chunk.copy_from_slice(&self.bounce()[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        if self.read_only {
+            return Err(BlockError::DeviceError);
+        }
+        for (i, chunk) in buf.chunks(BOUNCE_SECTORS * SECTOR_SIZE).enumerate() {
+            self.bounce()[..chunk.len()].copy_from_slice(chunk);
+            self.transfer(REQUEST_OUT, lba + (i * BOUNCE_SECTORS) as u64, chunk.len() / SECTOR_SIZE)?;
+        }
+        Ok(())
+    }
+}
+
+fn devices() -> Vec<pci::PciDevice> {
+    pci::devices()
+        .into_iter()
+        .filter(|dev| dev.vendor_id == virtio::VENDOR_ID && DEVICE_IDS.contains(&dev.device_id))
+        .collect()
+}
+
+//初始化所有 virtio 块设备，依次注册为 vda、vdb……以及它们的分区，返回设备名和容量(扇区数)
+pub fn init() -> Vec<(String, Result<u64, VirtioError>)> {
+    let mut found = Vec::new();
+    for (i, dev) in devices().into_iter().enumerate() {
+        let name = format!("vd{}", (b'a' + i as u8) as char);
+        let result = VirtioBlk::new(&dev).map(|blk| {
+            let capacity = blk.capacity;
+            block::register(&name, Arc::new(Mutex::new(blk)));
+            let _ = block::scan_partitions(&name);
+            capacity
+        });
+        found.push((name, result));
+    }
+    found
+}
+
+struct VirtioBlkDriver;
+
+impl Driver for VirtioBlkDriver {
+    fn name(&self) -> &'static str {
+        "virtio-blk"
+    }
+
+    fn depends(&self) -> &'static [&'static str] {
+        &["pci"]
+    }
+
+    fn probe(&self) -> bool {
+        !devices().is_empty()
+    }
+
+    fn init(&self) -> Result<String, DriverError> {
+        let mut status = Vec::new();
+        for (name, result) in init() {
+            match result {
+                Ok(sectors) => status.push(format!("{} ({} sectors)", name, sectors)),
+                Err(err) => status.push(format!("{}: {:?}", name, err)),
+            }
+        }
+        Ok(status.join(", "))
+    }
+}
+
+register_driver!(VIRTIO_BLK_DRIVER, VirtioBlkDriver);
@@ -0,0 +1,111 @@
+//故障注入：代码中用 failpoint! 标出可能出错的地方，平时什么也不做
+//从 shell 按名字启用后，按概率让这个地方返回错误、延迟一段时间或者 panic，用来测试驱动和 VFS 的错误处理
+//名字按 "模块::事件" 命名，例如 ata::read_timeout
+use crate::rand;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+use spin::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Error,           //让 failpoint! 所在的地方返回错误
+    Delay(Duration), //等待一段时间后照常执行
+    Panic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub action: Action,
+    pub probability: u8,        //触发的概率(百分比)
+    pub remaining: Option<u64>, //还能触发的次数，用完后自动解除，None 表示不限
+}
+
+//启用的故障点和触发次数
+#[derive(Debug, Clone)]
+pub struct FailpointInfo {
+    pub name: String,
+    pub config: Config,
+    pub hits: u64,
+}
+
+struct Armed {
+    config: Config,
+    hits: u64,
+}
+
+static POINTS: Mutex<BTreeMap<String, Armed>> = Mutex::new(BTreeMap::new());
+static ARMED: AtomicUsize = AtomicUsize::new(0); //没有启用的故障点时 check 不需要加锁
+
+//在代码中标出故障点：failpoint!(name, err) 在触发时 return Err(err.into())
+//failpoint!(name) 返回是否触发，由调用者自己决定怎样出错
+#[macro_export]
+macro_rules! failpoint {
+    ($name:expr) => {
+        $crate::failpoint::check($name)
+    };
+    ($name:expr, $err:expr) => {
+        if $crate::failpoint::check($name) {
+            return Err($err.into());
+        }
+    };
+}
+
+pub fn arm(name: &str, config: Config) {
+    let mut points = POINTS.lock();
+    points.insert(String::from(name), Armed { config, hits: 0 });
+    ARMED.store(points.len(), Ordering::Relaxed);
+}
+
+//解除故障点，返回它是否启用过
+pub fn disarm(name: &str) -> bool {
+    let mut points = POINTS.lock();
+    let removed = points.remove(name).is_some();
+    ARMED.store(points.len(), Ordering::Relaxed);
+    removed
+}
+
+pub fn list() -> Vec<FailpointInfo> {
+    POINTS
+        .lock()
+        .iter()
+        .map(|(name, armed)| FailpointInfo { name: name.clone(), config: armed.config, hits: armed.hits })
+        .collect()
+}
+
+//由 failpoint! 调用：故障点启用且按概率触发时执行它的动作，动作是 Error 时返回 true
+pub fn check(name: &str) -> bool {
+    if ARMED.load(Ordering::Relaxed) == 0 {
+        return false;
+    }
+    let action = {
+        let mut points = POINTS.lock();
+        let armed = match points.get_mut(name) {
+            Some(armed) => armed,
+            None => return false,
+        };
+        if rand::next_u64() % 100 >= armed.config.probability as u64 {
+            return false;
+        }
+        armed.hits += 1;
+        let action = armed.config.action;
+        if let Some(remaining) = armed.config.remaining.as_mut() {
+            *remaining -= 1;
+            if *remaining == 0 {
+                points.remove(name);
+                ARMED.store(points.len(), Ordering::Relaxed);
+            }
+        }
+        action
+    };
+    match action {
+        Action::Error => true,
+        Action::Delay(duration) => {
+            crate::time::sleep(duration);
+            false
+        }
+        Action::Panic => panic!("failpoint {} triggered", name),
+    }
+}
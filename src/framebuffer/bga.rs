@@ -0,0 +1,91 @@
+//Bochs 图形适配器(BGA)：QEMU 的 -vga std 和 Bochs 都提供，通过两个 I/O 端口设置分辨率并打开线性帧缓冲
+use crate::drivers::pci;
+use x86_64::instructions::port::Port;
+use x86_64::PhysAddr;
+
+const INDEX_PORT: u16 = 0x01CE;
+const DATA_PORT: u16 = 0x01CF;
+
+//寄存器编号
+const REG_ID: u16 = 0;
+const REG_XRES: u16 = 1;
+const REG_YRES: u16 = 2;
+const REG_BPP: u16 = 3;
+const REG_ENABLE: u16 = 4;
+const REG_VIRT_WIDTH: u16 = 6;
+const REG_VIRT_HEIGHT: u16 = 7; //只读，由显存大小和虚拟宽度算出
+const REG_X_OFFSET: u16 = 8;
+const REG_Y_OFFSET: u16 = 9;
+const REG_VIDEO_MEMORY_64K: u16 = 10; //0xB0C5 开始提供
+
+const ID_MIN: u16 = 0xB0C0; //各版本的 ID 为 0xB0C0..=0xB0C5
+const ID_MAX: u16 = 0xB0C5;
+const ID_VIDEO_MEMORY: u16 = 0xB0C5; //能读出显存大小的最早版本
+const ENABLED: u16 = 0x01;
+const LFB_ENABLED: u16 = 0x40;
+
+pub const MAX_WIDTH: usize = 1920;
+pub const MAX_HEIGHT: usize = 1200;
+pub const BYTES_PER_PIXEL: usize = 4; //固定使用 32 位色
+
+const QEMU_VGA: (u16, u16) = (0x1234, 0x1111); //QEMU 标准 VGA 的 PCI ID
+const DEFAULT_LFB: u64 = 0xE000_0000; //找不到 PCI 设备时使用 Bochs 的默认地址
+
+fn write(index: u16, value: u16) {
+    unsafe {
+        Port::<u16>::new(INDEX_PORT).write(index);
+        Port::<u16>::new(DATA_PORT).write(value);
+    }
+}
+
+fn read(index: u16) -> u16 {
+    unsafe {
+        Port::<u16>::new(INDEX_PORT).write(index);
+        Port::<u16>::new(DATA_PORT).read()
+    }
+}
+
+pub fn is_present() -> bool {
+    (ID_MIN..=ID_MAX).contains(&read(REG_ID))
+}
+
+//切换到 width x height 的 32 位色模式，返回线性帧缓冲的物理地址
+pub fn set_mode(width: usize, height: usize) -> PhysAddr {
+    write(REG_ENABLE, 0);
+    write(REG_XRES, width as u16);
+    write(REG_YRES, height as u16);
+    write(REG_BPP, (BYTES_PER_PIXEL * 8) as u16);
+    write(REG_VIRT_WIDTH, width as u16);
+    write(REG_X_OFFSET, 0);
+    write(REG_Y_OFFSET, 0);
+    write(REG_ENABLE, ENABLED | LFB_ENABLED);
+    framebuffer_address()
+}
+
+//显存的字节数，老版本读不出来时按最大分辨率的一屏计算
+pub fn memory_size() -> usize {
+    match read(REG_ID) {
+        ID_VIDEO_MEMORY..=ID_MAX => read(REG_VIDEO_MEMORY_64K) as usize * 0x10000,
+        _ => MAX_WIDTH * MAX_HEIGHT * BYTES_PER_PIXEL,
+    }
+}
+
+//当前模式下显存能放下的行数(虚拟高度)，不小于屏幕的高度；超过 height 的部分可以用来平移滚动
+pub fn virtual_height(width: usize, height: usize) -> usize {
+    let fits = memory_size() / (width * BYTES_PER_PIXEL);
+    (read(REG_VIRT_HEIGHT) as usize).min(fits).max(height)
+}
+
+//从显存的第 y 行开始显示
+pub fn set_y_offset(y: usize) {
+    write(REG_Y_OFFSET, y as u16);
+}
+
+//显存的地址由 PCI 的 BAR0 给出
+fn framebuffer_address() -> PhysAddr {
+    let (vendor, device) = QEMU_VGA;
+    match pci::find(vendor, device) {
+        Some(dev) => PhysAddr::new((dev.bar(0) & !0xF) as u64),
+        None => PhysAddr::new(DEFAULT_LFB),
+    }
+}
@@ -0,0 +1,291 @@
+//图形模式：通过 BGA 打开线性帧缓冲，提供画点、填充矩形，以及用点阵字体绘制的文本控制台
+//进入图形模式之后 print!/println! 以及行编辑器、分页器使用的屏幕操作都转到文本控制台上
+use crate::cmdline;
+use crate::memory::{self, MemoryError};
+use crate::vga_buffer::{self, Color};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use spin::Mutex;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::{PhysAddr, VirtAddr};
+
+pub mod bga;  //Bochs 图形适配器
+pub mod psf;  //PSF 点阵字体
+pub mod text; //帧缓冲上的文本控制台
+
+use psf::{Font, FontError};
+use text::TextConsole;
+
+const VGA_FONT_HEIGHT: usize = 16; //VGA 文本模式 80x25 使用 8x16 的字体
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FbError {
+    NoDevice,           //没有 BGA 设备(例如 QEMU 使用了 -vga cirrus)
+    BadResolution,      //分辨率为 0、超过上限或放不下一个字符
+    Font(FontError),    //字体文件不合法
+    NoFont,             //没有 VGA 可以读出内置字体，必须给出字体文件
+    Memory(MemoryError),
+}
+
+impl From<FontError> for FbError {
+    fn from(err: FontError) -> FbError {
+        FbError::Font(err)
+    }
+}
+
+impl From<MemoryError> for FbError {
+    fn from(err: MemoryError) -> FbError {
+        FbError::Memory(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    fn to_pixel(self) -> u32 {
+        (self.0 as u32) << 16 | (self.1 as u32) << 8 | self.2 as u32
+    }
+}
+
+//VGA 16 色调色板，顺序与 Color 的取值一致
+const PALETTE: [Rgb; 16] = [
+    Rgb(0x00, 0x00, 0x00), Rgb(0x00, 0x00, 0xAA), Rgb(0x00, 0xAA, 0x00), Rgb(0x00, 0xAA, 0xAA),
+    Rgb(0xAA, 0x00, 0x00), Rgb(0xAA, 0x00, 0xAA), Rgb(0xAA, 0x55, 0x00), Rgb(0xAA, 0xAA, 0xAA),
+    Rgb(0x55, 0x55, 0x55), Rgb(0x55, 0x55, 0xFF), Rgb(0x55, 0xFF, 0x55), Rgb(0x55, 0xFF, 0xFF),
+    Rgb(0xFF, 0x55, 0x55), Rgb(0xFF, 0x55, 0xFF), Rgb(0xFF, 0xFF, 0x55), Rgb(0xFF, 0xFF, 0xFF),
+];
+
+pub fn color_rgb(color: Color) -> Rgb {
+    PALETTE[color as usize]
+}
+
+//文本控制台换行时整屏上移的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollMode {
+    Pan,    //显存比屏幕高：改变显示的起始行，只画新露出的行；显存用完时才搬一次仍然可见的部分
+    Shadow, //显存放不下多余的行：在内存里的环形副本上滚动，再整屏写入显存，不用读很慢的显存
+}
+
+//线性帧缓冲：每个像素 4 字节(0x00RRGGBB)，一行紧接着一行
+pub struct Framebuffer {
+    base: *mut u32,
+    width: usize,
+    height: usize,
+    virtual_height: usize, //显存能放下的行数
+    origin: usize,         //屏幕的第一行：平移时是显存中的行，否则是 shadow 中的行
+    shadow: Option<Vec<u32>>,
+}
+
+unsafe impl Send for Framebuffer {} //只通过 CONSOLE 的锁访问
+
+impl Framebuffer {
+    fn new(base: *mut u32, width: usize, height: usize, virtual_height: usize, mode: ScrollMode) -> Framebuffer {
+        let shadow = match mode {
+            ScrollMode::Pan => None,
+            ScrollMode::Shadow => Some(vec![0; width * height]),
+        };
+        Framebuffer { base, width, height, virtual_height, origin: 0, shadow }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn scroll_mode(&self) -> ScrollMode {
+        match self.shadow {
+            Some(_) => ScrollMode::Shadow,
+            None => ScrollMode::Pan,
+        }
+    }
+
+    //屏幕第 y 行在显存中的起点
+    fn lfb_row(&self, y: usize) -> *mut u32 {
+        let row = if self.shadow.is_some() { y } else { self.origin + y };
+        unsafe { self.base.add(row * self.width) }
+    }
+
+    //屏幕第 y 行在 shadow 中的起点
+    fn shadow_row(&self, y: usize) -> usize {
+        (self.origin + y) % self.height * self.width
+    }
+
+    pub fn put_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.width && y < self.height {
+            let pixel = color.to_pixel();
+            let index = self.shadow_row(y) + x;
+            if let Some(shadow) = self.shadow.as_mut() {
+                shadow[index] = pixel;
+            }
+            unsafe {
+                self.lfb_row(y).add(x).write_volatile(pixel);
+            }
+        }
+    }
+
+    //超出屏幕的部分被裁掉
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Rgb) {
+        let pixel = color.to_pixel();
+        let x_end = (x + width).min(self.width);
+        let y_end = (y + height).min(self.height);
+        let x = x.min(x_end);
+        for row in y.min(y_end)..y_end {
+            let start = self.shadow_row(row);
+            if let Some(shadow) = self.shadow.as_mut() {
+                shadow[start + x..start + x_end].fill(pixel);
+            }
+            let lfb = self.lfb_row(row);
+            for col in x..x_end {
+                unsafe {
+                    lfb.add(col).write_volatile(pixel);
+                }
+            }
+        }
+    }
+
+    //屏幕第 y 行的像素，有 shadow 时从内存读
+    fn row(&self, y: usize) -> &[u32] {
+        match &self.shadow {
+            Some(shadow) => &shadow[self.shadow_row(y)..self.shadow_row(y) + self.width],
+            None => unsafe { core::slice::from_raw_parts(self.lfb_row(y), self.width) },
+        }
+    }
+
+    //整个画面的像素，按行排列
+    pub fn pixels(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.height).flat_map(move |y| self.row(y).iter().copied())
+    }
+
+    //整个画面向上移动 lines 行像素，底部空出的部分填充 fill
+    pub fn scroll_up(&mut self, lines: usize, fill: Rgb) {
+        let lines = lines.min(self.height);
+        let keep = self.height - lines;
+        match self.shadow {
+            Some(_) => {
+                self.origin = (self.origin + lines) % self.height;
+                let pixel = fill.to_pixel();
+                for y in keep..self.height {
+                    let start = self.shadow_row(y);
+                    if let Some(shadow) = self.shadow.as_mut() {
+                        shadow[start..start + self.width].fill(pixel);
+                    }
+                }
+                for y in 0..self.height {
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(self.row(y).as_ptr(), self.lfb_row(y), self.width);
+                    }
+                }
+            }
+            None => {
+                if self.origin + self.height + lines > self.virtual_height {
+                    //显存用完了：把仍然可见的部分搬到开头，从第 0 行重新开始
+                    unsafe {
+                        core::ptr::copy(self.lfb_row(lines), self.base, keep * self.width);
+                    }
+                    self.origin = 0;
+                } else {
+                    self.origin += lines;
+                }
+                self.fill_rect(0, keep, self.width, lines, fill); //先画好新露出的行再切换显示的起始行
+                bga::set_y_offset(self.origin);
+            }
+        }
+    }
+}
+
+//进入图形模式后的文本控制台，None 表示仍处于 VGA 文本模式
+static CONSOLE: Mutex<Option<TextConsole>> = Mutex::new(None);
+
+//显存映射只做一次，映射全部显存，之后切换分辨率时复用
+static LFB: Mutex<Option<(PhysAddr, VirtAddr)>> = Mutex::new(None);
+
+//在图形模式下对文本控制台执行 f，文本模式下返回 None
+pub fn with_console<T>(f: impl FnOnce(&mut TextConsole) -> T) -> Option<T> {
+    CONSOLE.lock().as_mut().map(f)
+}
+
+//屏幕能否显示 s 中的全部字符：VGA 文本模式只能显示 ASCII，图形模式要看字体里有没有对应的字形
+//控制台正被占用时(例如 panic 时)不等待，按不能显示处理
+pub fn can_display(s: &str) -> bool {
+    if s.is_ascii() {
+        return true;
+    }
+    match CONSOLE.try_lock() {
+        Some(console) => console.as_ref().is_some_and(|console| s.chars().all(|c| c.is_ascii() || console.font().glyph(c).is_some())),
+        None => false,
+    }
+}
+
+//紧急输出(见 vga_buffer::_emergency_print)：强制解锁后写入文本控制台，文本模式下返回 None
+pub fn force_write(args: fmt::Arguments) -> Option<()> {
+    unsafe { CONSOLE.force_unlock() };
+    with_console(|console| {
+        let _ = console.write_fmt(args);
+    })
+}
+
+//切换到 width x height 的图形模式，font 为 None 时沿用当前字体(第一次切换时使用 VGA 内置字体)
+//返回文本控制台的列数和行数
+pub fn init(width: usize, height: usize, font: Option<Font>) -> Result<(usize, usize), FbError> {
+    if !bga::is_present() {
+        return Err(FbError::NoDevice);
+    }
+    if width == 0 || height == 0 || width > bga::MAX_WIDTH || height > bga::MAX_HEIGHT {
+        return Err(FbError::BadResolution);
+    }
+    let mut console = CONSOLE.lock();
+    let font = match (font, console.as_ref()) {
+        (Some(font), _) => font,
+        (None, Some(old)) => old.font().clone(),
+        //VGA 的字体只能在切换模式之前从显存里读出来
+        (None, None) => Font::from_vga(&vga_buffer::read_font().ok_or(FbError::NoFont)?, VGA_FONT_HEIGHT)?,
+    };
+    if width < font.width() || height < font.height() {
+        return Err(FbError::BadResolution);
+    }
+
+    let phys = bga::set_mode(width, height);
+    let mut lfb = LFB.lock();
+    let virt = match *lfb {
+        Some((mapped, virt)) if mapped == phys => virt,
+        _ => {
+            let size = bga::memory_size() as u64;
+            let virt = memory::map_mmio(phys, size, PageTableFlags::NO_CACHE)?;
+            *lfb = Some((phys, virt));
+            virt
+        }
+    };
+    //显存至少能多放一行字时平移滚动，nofbpan 强制使用内存中的副本
+    let virtual_height = bga::virtual_height(width, height);
+    let mode = if virtual_height >= height + font.height() && !cmdline::flag("nofbpan") {
+        ScrollMode::Pan
+    } else {
+        ScrollMode::Shadow
+    };
+    let fb = Framebuffer::new(virt.as_mut_ptr(), width, height, virtual_height, mode);
+    let text = TextConsole::new(fb, font);
+    let size = (text.cols(), text.rows());
+    *console = Some(text);
+    Ok(size)
+}
+
+//以下两个函数直接在图形模式的屏幕上绘制，文本模式下不做任何事
+#[allow(dead_code)]
+pub fn put_pixel(x: usize, y: usize, color: Rgb) {
+    with_console(|console| console.framebuffer().put_pixel(x, y, color));
+}
+
+#[allow(dead_code)]
+pub fn fill_rect(x: usize, y: usize, width: usize, height: usize, color: Rgb) {
+    with_console(|console| console.framebuffer().fill_rect(x, y, width, height, color));
+}
+
+//从文件内容加载字体
+pub fn load_font(data: &[u8]) -> Result<Font, FbError> {
+    Ok(Font::parse(data)?)
+}
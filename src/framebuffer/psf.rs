@@ -0,0 +1,128 @@
+//PC Screen Font(PSF1/PSF2)点阵字体：每个字形按行存放，每行占 (width + 7) / 8 字节，最高位在最左边
+//PSF2 字体带 Unicode 映射表时，字形序号可以按字符查找，宽度合适的字体(例如 16x16 的 unifont)就能显示中文
+use alloc::vec::Vec;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01; //字形数为 512，否则为 256
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xFF; //映射表中每个字形的条目以它结束
+const PSF2_START_SEQ: u8 = 0xFE; //之后是组合字符序列，不使用
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontError {
+    BadMagic,  //不是 PSF 字体
+    Truncated, //文件长度小于头部声明的字形数据
+    BadSize,   //字形宽高为 0 或过大
+}
+
+#[derive(Clone)]
+pub struct Font {
+    width: usize,
+    height: usize,
+    glyph_size: usize, //每个字形占用的字节数
+    glyphs: Vec<u8>,
+    unicode: Vec<(char, u32)>, //按字符排序的 (字符, 字形序号)，没有映射表时为空
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+}
+
+impl Font {
+    //解析 PSF1 或 PSF2 文件；PSF1 的 Unicode 映射表被忽略(字符按编码直接作为字形序号)
+    pub fn parse(data: &[u8]) -> Result<Font, FontError> {
+        if data.len() >= 4 && data[0..2] == PSF1_MAGIC {
+            let count = if data[2] & PSF1_MODE_512 != 0 { 512 } else { 256 };
+            let height = data[3] as usize;
+            return Font::from_glyphs(8, height, height, count, &data[4..]);
+        }
+        if data.len() >= 32 && data[0..4] == PSF2_MAGIC {
+            let header_size = read_u32(data, 8) as usize;
+            let flags = read_u32(data, 12);
+            let count = read_u32(data, 16) as usize;
+            let glyph_size = read_u32(data, 20) as usize;
+            let height = read_u32(data, 24) as usize;
+            let width = read_u32(data, 28) as usize;
+            if header_size > data.len() {
+                return Err(FontError::Truncated);
+            }
+            let mut font = Font::from_glyphs(width, height, glyph_size, count, &data[header_size..])?;
+            if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+                font.unicode = parse_unicode_table(&data[header_size + font.glyphs.len()..], count);
+            }
+            return Ok(font);
+        }
+        Err(FontError::BadMagic)
+    }
+
+    //从 VGA 显存中读出的字体：256 个字形，每个字形在显存中占 32 字节，只用前 height 行
+    pub fn from_vga(raw: &[u8], height: usize) -> Result<Font, FontError> {
+        if height == 0 || height > 32 || raw.len() < 256 * 32 {
+            return Err(FontError::BadSize);
+        }
+        let mut glyphs = Vec::with_capacity(256 * height);
+        for glyph in raw.chunks_exact(32).take(256) {
+            glyphs.extend_from_slice(&glyph[..height]);
+        }
+        Ok(Font { width: 8, height, glyph_size: height, glyphs, unicode: Vec::new() })
+    }
+
+    fn from_glyphs(width: usize, height: usize, glyph_size: usize, count: usize, data: &[u8]) -> Result<Font, FontError> {
+        if width == 0 || height == 0 || width > 32 || height > 64 || glyph_size < width.div_ceil(8) * height {
+            return Err(FontError::BadSize);
+        }
+        let total = glyph_size.checked_mul(count).ok_or(FontError::Truncated)?;
+        if data.len() < total {
+            return Err(FontError::Truncated);
+        }
+        Ok(Font { width, height, glyph_size, glyphs: data[..total].to_vec(), unicode: Vec::new() })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    //字符对应的字形序号：有映射表时查表，否则只认 ASCII 字符
+    pub fn glyph(&self, c: char) -> Option<u32> {
+        if self.unicode.is_empty() {
+            return if c.is_ascii() { Some(c as u32) } else { None };
+        }
+        let found = self.unicode.binary_search_by_key(&c, |&(key, _)| key).ok()?;
+        Some(self.unicode[found].1)
+    }
+
+    //第 glyph 个字形 (x, y) 处的像素是否点亮，超出字体范围的序号用 '?' 显示
+    pub fn pixel(&self, glyph: u32, x: usize, y: usize) -> bool {
+        let count = self.glyphs.len() / self.glyph_size;
+        let index = if (glyph as usize) < count { glyph as usize } else { b'?' as usize };
+        let row = self.width.div_ceil(8);
+        let byte = self.glyphs[index * self.glyph_size + y * row + x / 8];
+        byte & (0x80 >> (x % 8)) != 0
+    }
+}
+
+//PSF2 的 Unicode 映射表：依次是每个字形的条目，条目内是 UTF-8 编码的字符，0xFE 之后是组合序列，0xFF 结束
+//表不完整或者编码不对时只保留已经解析出的部分
+fn parse_unicode_table(mut table: &[u8], count: usize) -> Vec<(char, u32)> {
+    let mut map = Vec::new();
+    for glyph in 0..count as u32 {
+        let end = match table.iter().position(|&b| b == PSF2_SEPARATOR) {
+            Some(end) => end,
+            None => break,
+        };
+        let entry = &table[..end];
+        let single = entry.split(|&b| b == PSF2_START_SEQ).next().unwrap_or(&[]);
+        if let Ok(chars) = core::str::from_utf8(single) {
+            map.extend(chars.chars().map(|c| (c, glyph)));
+        }
+        table = &table[end + 1..];
+    }
+    map.sort_by_key(|&(c, _)| c); //稳定排序，同一个字符出现多次时保留第一个字形
+    map.dedup_by_key(|&mut (c, _)| c);
+    map
+}
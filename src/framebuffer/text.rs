@@ -0,0 +1,188 @@
+//帧缓冲上的文本控制台：行为与 VGA 的 Writer 相同(总是在最后一行输出，换行时整屏上移)
+//每个格子的内容另外保存一份，供全屏界面保存和恢复屏幕
+use super::psf::Font;
+use super::{color_rgb, Framebuffer};
+use crate::bell;
+use crate::vga_buffer::Color;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    glyph: u32, //字形序号
+    foreground: Color,
+    background: Color,
+}
+
+//全屏界面退出时用来恢复屏幕的副本
+pub struct TextSnapshot {
+    cells: Vec<Cell>,
+    column: usize,
+}
+
+pub struct TextConsole {
+    fb: Framebuffer,
+    font: Font,
+    cols: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+    column: usize, //光标在最后一行的位置
+    foreground: Color,
+    background: Color,
+}
+
+impl TextConsole {
+    pub fn new(fb: Framebuffer, font: Font) -> TextConsole {
+        let cols = fb.width() / font.width();
+        let rows = fb.height() / font.height();
+        let blank = Cell { glyph: b' ' as u32, foreground: Color::Yellow, background: Color::Black };
+        let mut console = TextConsole {
+            fb,
+            font,
+            cols,
+            rows,
+            cells: vec![blank; cols * rows],
+            column: 0,
+            foreground: Color::Yellow,
+            background: Color::Black,
+        };
+        let (width, height) = (console.fb.width(), console.fb.height());
+        console.fb.fill_rect(0, 0, width, height, color_rgb(Color::Black));
+        console
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    pub fn framebuffer(&mut self) -> &mut Framebuffer {
+        &mut self.fb
+    }
+
+    fn blank(&self) -> Cell {
+        Cell { glyph: b' ' as u32, foreground: self.foreground, background: self.background }
+    }
+
+    fn draw_cell(&mut self, row: usize, col: usize) {
+        let cell = self.cells[row * self.cols + col];
+        let (width, height) = (self.font.width(), self.font.height());
+        let (fg, bg) = (color_rgb(cell.foreground), color_rgb(cell.background));
+        for y in 0..height {
+            for x in 0..width {
+                let color = if self.font.pixel(cell.glyph, x, y) { fg } else { bg };
+                self.fb.put_pixel(col * width + x, row * height + y, color);
+            }
+        }
+    }
+
+    fn set_cell(&mut self, row: usize, col: usize, cell: Cell) {
+        if self.cells[row * self.cols + col] != cell {
+            self.cells[row * self.cols + col] = cell;
+            self.draw_cell(row, col);
+        }
+    }
+
+    //直接在指定位置写一个字符，不移动光标
+    pub fn put_char(&mut self, row: usize, col: usize, byte: u8, foreground: Color, background: Color) {
+        if row < self.rows && col < self.cols {
+            self.set_cell(row, col, Cell { glyph: byte as u32, foreground, background });
+        }
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            byte => self.write_glyph(byte as u32),
+        }
+    }
+
+    fn write_glyph(&mut self, glyph: u32) {
+        if self.column >= self.cols {
+            self.new_line();
+        }
+        let cell = Cell { glyph, foreground: self.foreground, background: self.background };
+        self.set_cell(self.rows - 1, self.column, cell);
+        self.column += 1;
+    }
+
+    fn new_line(&mut self) {
+        self.cells.drain(..self.cols);
+        let blank = self.blank();
+        self.cells.extend(core::iter::repeat_n(blank, self.cols));
+        self.fb.scroll_up(self.font.height(), color_rgb(self.background));
+        self.column = 0;
+    }
+
+    //所有格子的前景色和背景色对调后重画，视觉响铃用
+    pub fn invert(&mut self) {
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let cell = &mut self.cells[row * self.cols + col];
+                core::mem::swap(&mut cell.foreground, &mut cell.background);
+                self.draw_cell(row, col);
+            }
+        }
+    }
+
+    //换成新的前景色，返回原来的
+    pub fn set_foreground(&mut self, color: Color) -> Color {
+        core::mem::replace(&mut self.foreground, color)
+    }
+
+    pub fn cursor_left(&mut self, n: usize) {
+        self.column = self.column.saturating_sub(n);
+    }
+
+    pub fn cursor_right(&mut self, n: usize) {
+        self.column = (self.column + n).min(self.cols);
+    }
+
+    pub fn clear_to_end(&mut self) {
+        let blank = self.blank();
+        for col in self.column..self.cols {
+            self.set_cell(self.rows - 1, col, blank);
+        }
+    }
+
+    pub fn snapshot(&self) -> TextSnapshot {
+        TextSnapshot { cells: self.cells.clone(), column: self.column }
+    }
+
+    //快照的行列数与当前不同时(中间切换过分辨率)，只恢复光标位置
+    pub fn restore(&mut self, snapshot: &TextSnapshot) {
+        if snapshot.cells.len() == self.cells.len() {
+            for row in 0..self.rows {
+                for col in 0..self.cols {
+                    self.set_cell(row, col, snapshot.cells[row * self.cols + col]);
+                }
+            }
+        }
+        self.column = snapshot.column.min(self.cols);
+    }
+}
+
+impl fmt::Write for TextConsole {
+    //可打印的 ASCII 字符直接显示，BEL 响铃；其他字符在字体的 Unicode 映射表里有字形时显示，否则显示为 0xfe
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                ' '..='~' | '\n' => self.write_byte(c as u8),
+                c if c == bell::BEL as char => bell::ring(|| self.invert()),
+                c => match self.font.glyph(c) {
+                    Some(glyph) => self.write_glyph(glyph),
+                    None => self.write_byte(0xfe),
+                },
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+//块缓存：文件系统和块设备之间按 (设备, 块号) 缓存最近用过的块，FAT32 反复读取同一个目录扇区时不必每次都走 PIO
+//写入先只改缓存(写回)，块被淘汰、调用 sync 或者卸载、关机时才写到设备；满了以后淘汰最久没有用过的块
+//同一个设备的所有 CachedDevice 共用缓存中的块(以底层 SharedDevice 的地址区分设备)，挂载的文件系统和 /dev 下的设备节点看到的内容一致
+//访问设备时不持有缓存的锁：设备本身可能又要经过缓存(建立在缓存文件系统上的回环设备)
+use crate::block::{self, BlockDevice, BlockError, SharedDevice};
+use crate::sync::Mutex;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+const CAPACITY: usize = 128; //缓存的块数，512 字节的块共 64 KiB(内核堆只有 1 MiB)
+
+type Key = (usize, u64); //(设备, 块号)
+
+struct Entry {
+    dev: SharedDevice, //写回时用
+    data: Vec<u8>,
+    dirty: bool,
+    version: u64, //每次写入加一，写回期间又被写过的块不能清除脏标记
+    stamp: u64,   //最近一次使用的时间，越大越新
+}
+
+struct Cache {
+    entries: BTreeMap<Key, Entry>,
+    lru: BTreeMap<u64, Key>, //stamp -> key，第一个就是最久没有用过的
+    clock: u64,
+}
+
+impl Cache {
+    fn touch(&mut self, key: Key) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            self.lru.remove(&entry.stamp);
+            entry.stamp = self.clock;
+            self.lru.insert(self.clock, key);
+        }
+    }
+
+    //放入一个块，返回被淘汰的脏块，由调用者在释放锁之后写回
+    fn insert(&mut self, key: Key, dev: &SharedDevice, data: &[u8], dirty: bool) -> Vec<(Key, Entry)> {
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.data.copy_from_slice(data);
+            if dirty {
+                entry.dirty = true;
+                entry.version += 1;
+            }
+            self.touch(key);
+            return Vec::new();
+        }
+        let mut evicted = Vec::new();
+        while self.entries.len() >= CAPACITY {
+            let (_, oldest) = self.lru.pop_first().expect("lru out of sync with entries");
+            let entry = self.entries.remove(&oldest).expect("lru out of sync with entries");
+            if entry.dirty {
+                evicted.push((oldest, entry));
+            }
+        }
+        self.clock += 1;
+        self.lru.insert(self.clock, key);
+        self.entries.insert(key, Entry { dev: dev.clone(), data: Vec::from(data), dirty, version: 0, stamp: self.clock });
+        evicted
+    }
+
+    //写回失败的淘汰块放回缓存，保留脏标记；这时可能暂时超过 CAPACITY
+    fn restore(&mut self, key: Key, mut entry: Entry) {
+        if self.entries.contains_key(&key) {
+            return; //写回期间又读进来或者写入了更新的内容
+        }
+        self.clock += 1;
+        entry.stamp = self.clock;
+        self.lru.insert(self.clock, key);
+        self.entries.insert(key, entry);
+    }
+
+    fn remove_device(&mut self, id: usize) {
+        let keys: Vec<Key> = self.entries.range((id, 0)..=(id, u64::MAX)).map(|(&key, _)| key).collect();
+        for key in keys {
+            if let Some(entry) = self.entries.remove(&key) {
+                self.lru.remove(&entry.stamp);
+            }
+        }
+    }
+}
+
+static CACHE: Mutex<Cache> = Mutex::new("CACHE", Cache { entries: BTreeMap::new(), lru: BTreeMap::new(), clock: 0 });
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static WRITEBACKS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub writebacks: u64, //写回设备的块数
+    pub blocks: usize,   //当前缓存的块数
+    pub dirty: usize,    //其中还没有写回的块数
+}
+
+pub fn stats() -> CacheStats {
+    let cache = CACHE.lock();
+    CacheStats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        writebacks: WRITEBACKS.load(Ordering::Relaxed),
+        blocks: cache.entries.len(),
+        dirty: cache.entries.values().filter(|entry| entry.dirty).count(),
+    }
+}
+
+fn device_id(dev: &SharedDevice) -> usize {
+    Arc::as_ptr(dev) as *const u8 as usize
+}
+
+//写回被淘汰的脏块，失败的放回缓存
+fn write_back(blocks: Vec<(Key, Entry)>) -> Result<(), BlockError> {
+    let mut result = Ok(());
+    for (key, mut entry) in blocks {
+        WRITEBACKS.fetch_add(1, Ordering::Relaxed);
+        if let Err(err) = entry.dev.write_blocks(key.1, &entry.data) {
+            CACHE.lock().restore(key, entry);
+            result = Err(err);
+        }
+    }
+    result
+}
+
+//把 filter 选中的脏块逐个写回设备，写回成功才清除脏标记，失败的下次 sync 时重试
+//写设备时不持有锁，每次只复制一个块
+fn flush(filter: impl Fn(usize) -> bool) -> Result<(), BlockError> {
+    let keys: Vec<Key> = {
+        let cache = CACHE.lock();
+        cache.entries.iter().filter(|(&(id, _), entry)| entry.dirty && filter(id)).map(|(&key, _)| key).collect()
+    };
+    let mut result = Ok(());
+    for key in keys {
+        let (mut dev, data, version) = match CACHE.lock().entries.get(&key) {
+            Some(entry) if entry.dirty => (entry.dev.clone(), entry.data.clone(), entry.version),
+            _ => continue, //已经被淘汰(淘汰时写回)或者写回过了
+        };
+        WRITEBACKS.fetch_add(1, Ordering::Relaxed);
+        match dev.write_blocks(key.1, &data) {
+            Ok(()) => {
+                if let Some(entry) = CACHE.lock().entries.get_mut(&key) {
+                    if entry.version == version {
+                        entry.dirty = false;
+                    }
+                }
+            }
+            Err(err) => result = Err(err),
+        }
+    }
+    result
+}
+
+//写回全部设备的脏块
+pub fn sync() -> Result<(), BlockError> {
+    flush(|_| true)
+}
+
+//写回一个设备的脏块，缓存的内容保留
+pub fn sync_device(dev: &SharedDevice) -> Result<(), BlockError> {
+    let id = device_id(dev);
+    flush(|other| other == id)
+}
+
+//写回并丢弃一个设备的全部缓存(卸载、设备被移除时)；写回失败时缓存保留，调用者可以再试
+pub fn invalidate(dev: &SharedDevice) -> Result<(), BlockError> {
+    sync_device(dev)?;
+    CACHE.lock().remove_device(device_id(dev));
+    Ok(())
+}
+
+//不写回，直接丢弃一个设备的全部缓存(设备的内容本身被丢弃时，例如删除内存盘、放弃快照)
+pub fn discard(dev: &SharedDevice) {
+    CACHE.lock().remove_device(device_id(dev));
+}
+
+//经过缓存访问的块设备
+pub struct CachedDevice {
+    dev: SharedDevice,
+    id: usize,
+}
+
+//包装成经过缓存的共享设备
+pub fn wrap(dev: SharedDevice) -> SharedDevice {
+    let id = device_id(&dev);
+    Arc::new(spin::Mutex::new(CachedDevice { dev, id }))
+}
+
+impl BlockDevice for CachedDevice {
+    fn block_size(&self) -> usize {
+        self.dev.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.dev.block_count()
+    }
+
+    //命中的块从缓存复制；没有命中的块按连续的段一次读出，再放进缓存
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        block::check_request(self, lba, buf.len())?;
+        let size = self.block_size();
+        let mut missing = Vec::new();
+        {
+            let mut cache = CACHE.lock();
+            for (i, chunk) in buf.chunks_mut(size).enumerate() {
+                let key = (self.id, lba + i as u64);
+                match cache.entries.get(&key) {
+                    Some(entry) => {
+                        chunk.copy_from_slice(&entry.data);
+                        cache.touch(key);
+                    }
+                    None => missing.push(i),
+                }
+            }
+        }
+        HITS.fetch_add((buf.len() / size - missing.len()) as u64, Ordering::Relaxed);
+        MISSES.fetch_add(missing.len() as u64, Ordering::Relaxed);
+
+        let mut evicted = Vec::new();
+        let mut start = 0;
+        while start < missing.len() {
+            let mut end = start + 1;
+            while end < missing.len() && missing[end] == missing[end - 1] + 1 {
+                end += 1;
+            }
+            let (first, count) = (missing[start], end - start);
+            let run = &mut buf[first * size..(first + count) * size];
+            self.dev.read_blocks(lba + first as u64, run)?;
+            let mut cache = CACHE.lock();
+            for (i, chunk) in run.chunks(size).enumerate() {
+                evicted.extend(cache.insert((self.id, lba + (first + i) as u64), &self.dev, chunk, false));
+            }
+            start = end;
+        }
+        write_back(evicted)
+    }
+
+    //只写进缓存，设备上的内容在写回时才更新
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        block::check_request(self, lba, buf.len())?;
+        let size = self.block_size();
+        let mut evicted = Vec::new();
+        {
+            let mut cache = CACHE.lock();
+            for (i, chunk) in buf.chunks(size).enumerate() {
+                evicted.extend(cache.insert((self.id, lba + i as u64), &self.dev, chunk, true));
+            }
+        }
+        write_back(evicted)
+    }
+}
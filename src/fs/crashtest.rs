@@ -0,0 +1,95 @@
+//崩溃一致性测试：在快照设备上反复运行会写盘的操作，每次在随机位置模拟断电，
+//然后 "重启"(重新挂载)并运行一致性检查，统计断电后留下损坏文件系统的次数
+//底层设备只在快照的覆盖层上被修改，测试结束后原样保留
+use super::fat32::{Fat32, Problem};
+use super::FsError;
+use crate::block::SharedDevice;
+use crate::drivers::snapshot::CowDevice;
+use crate::rand;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+//被测试的写盘操作
+pub struct Workload {
+    pub name: &'static str,
+    pub run: fn(&mut SharedDevice) -> Result<(), FsError>,
+}
+
+pub static WORKLOADS: &[Workload] = &[Workload { name: "mkfs", run: mkfs }];
+
+fn mkfs(dev: &mut SharedDevice) -> Result<(), FsError> {
+    super::fat32::format(dev, None)
+}
+
+pub fn find(name: &str) -> Option<&'static Workload> {
+    WORKLOADS.iter().find(|workload| workload.name == name)
+}
+
+//一次断电后留下的损坏
+#[derive(Debug, Clone)]
+pub struct Corruption {
+    pub cut: u64, //在第几个块写入时断电
+    pub problems: Vec<Problem>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CrashReport {
+    pub total_writes: u64, //完整运行一次写入的块数
+    pub iterations: u32,
+    pub unmountable: u32, //断电后不能识别为文件系统(相当于还没有创建，不算损坏)
+    pub clean: u32,
+    pub corrupted: Vec<Corruption>,
+}
+
+//挂载并检查，不能挂载时返回 None
+fn mount_and_check(dev: &SharedDevice) -> Result<Option<Vec<Problem>>, FsError> {
+    let mut fs = match Fat32::mount(dev.clone()) {
+        Ok(fs) => fs,
+        Err(FsError::InvalidFilesystem) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    match fs.check() {
+        Ok(report) => Ok(Some(report.problems)),
+        Err(FsError::InvalidFilesystem) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+//在 base 上运行 iterations 次测试；完整运行一次后文件系统必须是一致的，否则直接报告错误
+pub fn run(base: SharedDevice, workload: &Workload, iterations: u32) -> Result<CrashReport, FsError> {
+    let cow = Arc::new(Mutex::new(CowDevice::new(base)));
+    let mut dev: SharedDevice = cow.clone();
+    let mut report = CrashReport::default();
+
+    //先不断电完整运行一次，得到写入的总块数，并确认操作本身是正确的
+    (workload.run)(&mut dev)?;
+    report.total_writes = cow.lock().status().written;
+    match mount_and_check(&dev)? {
+        Some(problems) if problems.is_empty() => {}
+        _ => return Err(FsError::InvalidFilesystem),
+    }
+    if report.total_writes == 0 {
+        cow.lock().discard();
+        return Ok(report);
+    }
+
+    for _ in 0..iterations {
+        let cut = rand::next_u64() % report.total_writes;
+        {
+            let mut cow = cow.lock();
+            cow.discard();
+            cow.fail_after(Some(cut));
+        }
+        let _ = (workload.run)(&mut dev); //断电后的写入被悄悄丢弃，操作本身仍然 "成功"
+        cow.lock().fail_after(None); //重新上电
+        report.iterations += 1;
+        match mount_and_check(&dev)? {
+            None => report.unmountable += 1,
+            Some(problems) if problems.is_empty() => report.clean += 1,
+            Some(problems) => report.corrupted.push(Corruption { cut, problems }),
+        }
+    }
+    cow.lock().discard();
+    Ok(report)
+}
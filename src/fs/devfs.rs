@@ -0,0 +1,168 @@
+//设备节点：让控制台、空设备和块设备也能像普通文件一样通过路径打开
+use super::vfs::{FileHandle, Inode, InodeKind, Metadata, SeekFrom};
+use super::FsError;
+use crate::block::{BlockDevice, SharedDevice};
+use crate::drivers::keyboard;
+use crate::{print, process, signal};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use pc_keyboard::DecodedKey;
+
+const DEVICE: Metadata = Metadata { kind: InodeKind::Device, size: 0, mtime: 0, mode: 0o666, uid: 0, gid: 0 };
+
+//控制台：写入的内容输出到屏幕，读取时从键盘得到字符
+pub struct Console;
+
+impl Inode for Console {
+    fn metadata(&self) -> Metadata {
+        DEVICE
+    }
+
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError> {
+        Ok(Box::new(ConsoleHandle { nonblocking: false }))
+    }
+}
+
+struct ConsoleHandle {
+    nonblocking: bool,
+}
+
+impl FileHandle for ConsoleHandle {
+    //阻塞直到按下一个可打印的键，每次最多返回一个字符
+    //非阻塞模式下键盘里没有可打印的键时返回 WouldBlock
+    //Ctrl+C 给正在读控制台的进程发送 SIGINT，read 返回 Interrupted
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let key = if self.nonblocking {
+                keyboard::poll_key().ok_or(FsError::WouldBlock)?
+            } else {
+                keyboard::read_key()
+            };
+            if key == DecodedKey::Unicode('\u{3}') && process::current_pid() != 0 {
+                let _ = signal::send(process::current_pid(), signal::SIGINT);
+                return Err(FsError::Interrupted);
+            }
+            if let DecodedKey::Unicode(c) = key {
+                let mut utf8 = [0u8; 4];
+                let encoded = c.encode_utf8(&mut utf8).as_bytes();
+                if encoded.len() <= buf.len() {
+                    buf[..encoded.len()].copy_from_slice(encoded);
+                    return Ok(encoded.len());
+                }
+            }
+        }
+    }
+
+    //屏幕输出从不等待，非阻塞模式下写入也总是完整的
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), FsError> {
+        self.nonblocking = nonblocking;
+        Ok(())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        print!("{}", String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+}
+
+//空设备：读取总是立即返回文件末尾，写入的数据全部丢弃
+pub struct Null;
+
+impl Inode for Null {
+    fn metadata(&self) -> Metadata {
+        DEVICE
+    }
+
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError> {
+        Ok(Box::new(Null))
+    }
+}
+
+impl FileHandle for Null {
+    fn read(&mut self, _buf: &mut [u8]) -> Result<usize, FsError> {
+        Ok(0)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        Ok(buf.len())
+    }
+}
+
+//块设备节点(/dev/ata0、/dev/ata0p1)：按字节偏移读写，不对齐的部分先读出整块再修改
+//只有 root 可以直接读写磁盘
+pub struct Block(pub SharedDevice);
+
+impl Inode for Block {
+    fn metadata(&self) -> Metadata {
+        let size = self.0.block_count() * self.0.block_size() as u64;
+        Metadata { kind: InodeKind::Device, size, mtime: 0, mode: 0o600, uid: 0, gid: 0 }
+    }
+
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError> {
+        Ok(Box::new(BlockHandle { dev: self.0.clone(), pos: 0 }))
+    }
+}
+
+struct BlockHandle {
+    dev: SharedDevice,
+    pos: u64,
+}
+
+impl BlockHandle {
+    fn size(&self) -> u64 {
+        self.dev.block_count() * self.dev.block_size() as u64
+    }
+}
+
+impl FileHandle for BlockHandle {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let block_size = self.dev.block_size() as u64;
+        let mut block = vec![0u8; block_size as usize];
+        let mut done = 0;
+        while done < buf.len() && self.pos < self.size() {
+            self.dev.read_blocks(self.pos / block_size, &mut block)?;
+            let offset = (self.pos % block_size) as usize;
+            let n = (buf.len() - done).min(block.len() - offset);
+            buf[done..done + n].copy_from_slice(&block[offset..offset + n]);
+            done += n;
+            self.pos += n as u64;
+        }
+        Ok(done)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        let block_size = self.dev.block_size() as u64;
+        let mut block = vec![0u8; block_size as usize];
+        let mut done = 0;
+        while done < buf.len() && self.pos < self.size() {
+            let lba = self.pos / block_size;
+            let offset = (self.pos % block_size) as usize;
+            let n = (buf.len() - done).min(block.len() - offset);
+            if n < block.len() {
+                self.dev.read_blocks(lba, &mut block)?;
+            }
+            block[offset..offset + n].copy_from_slice(&buf[done..done + n]);
+            self.dev.write_blocks(lba, &block)?;
+            done += n;
+            self.pos += n as u64;
+        }
+        Ok(done)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, FsError> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+            SeekFrom::End(offset) => self.size() as i64 + offset,
+        };
+        if target < 0 {
+            return Err(FsError::InvalidArgument);
+        }
+        self.pos = target as u64;
+        Ok(self.pos)
+    }
+}
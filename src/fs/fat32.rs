@@ -0,0 +1,697 @@
+use super::vfs::{Directory, FileHandle, Inode, InodeKind, Metadata, SeekFrom};
+use super::{DirEntry, FsError};
+use crate::block::BlockDevice;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+const DIR_ENTRY_SIZE: usize = 32; //每个目录项固定 32 字节
+
+//目录项属性位
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0F; //只读|隐藏|系统|卷标 四位同时置位表示长文件名项
+
+const FAT_ENTRY_MASK: u32 = 0x0FFF_FFFF; //FAT32 表项只使用低 28 位
+const BAD_CLUSTER: u32 = 0x0FFF_FFF7;
+const END_OF_CHAIN: u32 = 0x0FFF_FFF8; //大于等于此值表示簇链结束
+const MAX_CLUSTERS: u64 = 0x0FFF_FFF5; //最大的簇号是 0x0FFFFFF6，再往上是坏簇和链尾标记
+
+//长文件名项中 13 个 UCS-2 字符所在的偏移
+const LFN_OFFSETS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+}
+
+//判断一个扇区是否是 FAT32 的引导扇区(BPB)
+fn is_fat32_bpb(sector: &[u8]) -> bool {
+    if sector.len() < 512 || read_u16(sector, 510) != 0xAA55 {
+        return false;
+    }
+    let bytes_per_sector = read_u16(sector, 11);
+    let sectors_per_cluster = sector[13];
+    matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096)
+        && sectors_per_cluster.is_power_of_two()
+        && sector[16] != 0          //FAT 表个数
+        && read_u16(sector, 22) == 0 //FAT12/16 的 FAT 大小字段，FAT32 中必须为 0
+        && read_u32(sector, 36) != 0 //FAT32 的 FAT 大小
+}
+
+//短文件名的校验和，长文件名项用它和对应的短文件名项关联
+fn short_name_checksum(name: &[u8]) -> u8 {
+    name.iter().fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+}
+
+//把 8.3 格式的短文件名转换为 "NAME.EXT"，并处理 Windows 的小写标志位
+fn short_name(raw: &[u8]) -> String {
+    let lower_base = raw[12] & 0x08 != 0;
+    let lower_ext = raw[12] & 0x10 != 0;
+    let mut name = String::new();
+    for (i, &b) in raw[0..8].iter().enumerate() {
+        let b = if i == 0 && b == 0x05 { 0xE5 } else { b }; //0x05 表示首字节实际是 0xE5
+        if b == b' ' {
+            break;
+        }
+        let b = if lower_base { b.to_ascii_lowercase() } else { b };
+        name.push(b as char);
+    }
+    if raw[8] != b' ' {
+        name.push('.');
+        for &b in raw[8..11].iter().take_while(|&&b| b != b' ') {
+            let b = if lower_ext { b.to_ascii_lowercase() } else { b };
+            name.push(b as char);
+        }
+    }
+    name
+}
+
+fn decode_long_name(units: &[u16]) -> String {
+    let units = units.iter().cloned().take_while(|&c| c != 0x0000 && c != 0xFFFF);
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+fn write_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+//目录中的一项(文件或子目录)
+#[derive(Debug, Clone)]
+struct Node {
+    name: String,
+    attr: u8,
+    cluster: u32,
+    size: u32,
+    mtime: u32, //修改日期(高 16 位)和时间(低 16 位)，按 FAT 的格式原样保存
+}
+
+impl Node {
+    fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+}
+
+pub struct Fat32<D: BlockDevice> {
+    dev: D,
+    bytes_per_sector: usize,
+    sectors_per_cluster: usize,
+    fat_start: u64,              //第一个 FAT 表的扇区号
+    fat_count: u64,              //FAT 表的份数
+    fat_size: u64,               //每份 FAT 表的扇区数
+    data_start: u64,             //数据区的扇区号
+    root_cluster: u32,
+    cluster_count: u32,
+    fat_cache: Option<(u64, Vec<u8>)>, //最近读取的一个 FAT 扇区，顺着簇链走时大多命中
+}
+
+impl<D: BlockDevice> Fat32<D> {
+    //挂载设备：设备的 0 号扇区必须是 FAT32 引导扇区(分区由块设备层拆分成单独的设备)
+    pub fn mount(mut dev: D) -> Result<Fat32<D>, FsError> {
+        let mut sector = vec![0u8; dev.block_size()];
+        dev.read_blocks(0, &mut sector)?;
+        if !is_fat32_bpb(&sector) {
+            return Err(FsError::InvalidFilesystem);
+        }
+        Fat32::from_bpb(dev, &sector)
+    }
+
+    fn from_bpb(dev: D, bpb: &[u8]) -> Result<Fat32<D>, FsError> {
+        let bytes_per_sector = read_u16(bpb, 11) as usize;
+        if bytes_per_sector != dev.block_size() {
+            return Err(FsError::Unsupported); //暂不支持扇区大小与设备块大小不同的情况
+        }
+        let sectors_per_cluster = bpb[13] as usize;
+        let reserved = read_u16(bpb, 14) as u64;
+        let fat_count = bpb[16] as u64;
+        let total_sectors = match read_u16(bpb, 19) {
+            0 => read_u32(bpb, 32) as u64,
+            n => n as u64,
+        };
+        let fat_size = read_u32(bpb, 36) as u64;
+        let data_start = reserved + fat_count * fat_size;
+        if sectors_per_cluster == 0 || total_sectors <= data_start {
+            return Err(FsError::InvalidFilesystem);
+        }
+        //簇号要能用 FAT32 表项表示，FAT 表也要放得下全部簇(加上开头两个保留表项)
+        let cluster_count = (total_sectors - data_start) / sectors_per_cluster as u64;
+        if cluster_count > MAX_CLUSTERS || cluster_count + 2 > fat_size * bytes_per_sector as u64 / 4 {
+            return Err(FsError::InvalidFilesystem);
+        }
+        let cluster_count = cluster_count as u32;
+
+        let fs = Fat32 {
+            dev,
+            bytes_per_sector,
+            sectors_per_cluster,
+            fat_start: reserved,
+            fat_count,
+            fat_size,
+            data_start,
+            root_cluster: read_u32(bpb, 44),
+            cluster_count,
+            fat_cache: None,
+        };
+        fs.check_cluster(fs.root_cluster)?;
+        Ok(fs)
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+
+    //合法的数据簇编号从 2 开始
+    fn check_cluster(&self, cluster: u32) -> Result<(), FsError> {
+        if cluster < 2 || cluster - 2 >= self.cluster_count {
+            return Err(FsError::InvalidFilesystem);
+        }
+        Ok(())
+    }
+
+    fn read_cluster(&mut self, cluster: u32, buf: &mut [u8]) -> Result<(), FsError> {
+        self.check_cluster(cluster)?;
+        let lba = self.data_start + (cluster as u64 - 2) * self.sectors_per_cluster as u64;
+        self.dev.read_blocks(lba, buf)?;
+        Ok(())
+    }
+
+    //读取 cluster 的 FAT 表项(低 28 位)
+    fn fat_entry(&mut self, cluster: u32) -> Result<u32, FsError> {
+        let offset = cluster as u64 * 4;
+        let sector = self.fat_start + offset / self.bytes_per_sector as u64;
+        let in_sector = (offset % self.bytes_per_sector as u64) as usize;
+
+        let cached = matches!(&self.fat_cache, Some((lba, _)) if *lba == sector);
+        if !cached {
+            let mut buf = vec![0u8; self.bytes_per_sector];
+            self.dev.read_blocks(sector, &mut buf)?;
+            self.fat_cache = Some((sector, buf));
+        }
+        match &self.fat_cache {
+            Some((_, buf)) => Ok(read_u32(buf, in_sector) & FAT_ENTRY_MASK),
+            None => unreachable!(),
+        }
+    }
+
+    //查 FAT 表得到簇链中的下一个簇，链结束时返回 None
+    fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>, FsError> {
+        let value = self.fat_entry(cluster)?;
+        if value >= END_OF_CHAIN {
+            return Ok(None);
+        }
+        if value == BAD_CLUSTER {
+            return Err(FsError::InvalidFilesystem);
+        }
+        self.check_cluster(value)?;
+        Ok(Some(value))
+    }
+
+    //读取整条簇链(用于目录)，簇数上限为总簇数，防止损坏的 FAT 形成环
+    fn read_chain(&mut self, first: u32) -> Result<Vec<u8>, FsError> {
+        let cluster_size = self.cluster_size();
+        let mut data = Vec::new();
+        let mut cluster = Some(first);
+        let mut visited = 0u32;
+        while let Some(current) = cluster {
+            visited += 1;
+            if visited > self.cluster_count {
+                return Err(FsError::InvalidFilesystem);
+            }
+            let start = data.len();
+            data.resize(start + cluster_size, 0);
+            self.read_cluster(current, &mut data[start..])?;
+            cluster = self.next_cluster(current)?;
+        }
+        Ok(data)
+    }
+
+    //解析目录内容，把长文件名项和它后面的短文件名项合并成一个 Node
+    fn read_dir_nodes(&mut self, cluster: u32) -> Result<Vec<Node>, FsError> {
+        let data = self.read_chain(cluster)?;
+        let mut nodes = Vec::new();
+        let mut long_name: Vec<u16> = Vec::new();
+        let mut long_checksum = 0u8;
+
+        for raw in data.chunks_exact(DIR_ENTRY_SIZE) {
+            match raw[0] {
+                0x00 => break, //后面不再有目录项
+                0xE5 => {      //已删除的项
+                    long_name.clear();
+                    continue;
+                }
+                _ => {}
+            }
+            let attr = raw[11];
+            if attr & 0x3F == ATTR_LONG_NAME {
+                //长文件名项倒序存放，序号的 0x40 位标记最后(也就是最先出现)的一项
+                let seq = raw[0];
+                let index = (seq & 0x1F) as usize;
+                if index == 0 {
+                    long_name.clear();
+                    continue;
+                }
+                if seq & 0x40 != 0 {
+                    long_name = vec![0xFFFF; index * 13];
+                    long_checksum = raw[13];
+                } else if raw[13] != long_checksum || long_name.len() < index * 13 {
+                    long_name.clear();
+                    continue;
+                }
+                let base = (index - 1) * 13;
+                for (i, &offset) in LFN_OFFSETS.iter().enumerate() {
+                    long_name[base + i] = read_u16(raw, offset);
+                }
+                continue;
+            }
+            if attr & ATTR_VOLUME_ID != 0 {
+                long_name.clear();
+                continue;
+            }
+
+            let name = if !long_name.is_empty() && short_name_checksum(&raw[0..11]) == long_checksum {
+                decode_long_name(&long_name)
+            } else {
+                short_name(raw)
+            };
+            long_name.clear();
+
+            let cluster = (read_u16(raw, 20) as u32) << 16 | read_u16(raw, 26) as u32;
+            let mtime = (read_u16(raw, 24) as u32) << 16 | read_u16(raw, 22) as u32;
+            nodes.push(Node { name, attr, cluster, size: read_u32(raw, 28), mtime });
+        }
+        Ok(nodes)
+    }
+
+    //在目录中查找一项，文件名比较不区分大小写
+    fn find_child(&mut self, dir_cluster: u32, name: &str) -> Result<Node, FsError> {
+        let mut node = self
+            .read_dir_nodes(dir_cluster)?
+            .into_iter()
+            .find(|child| child.name.eq_ignore_ascii_case(name))
+            .ok_or(FsError::NotFound)?;
+        //".." 指向根目录时簇号记为 0
+        if node.is_dir() && node.cluster == 0 {
+            node.cluster = self.root_cluster;
+        }
+        Ok(node)
+    }
+
+    //从游标位置读取文件数据
+    fn read_at(&mut self, cursor: &mut Cursor, buf: &mut [u8]) -> Result<usize, FsError> {
+        if cursor.pos >= cursor.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let cluster_size = self.cluster_size() as u64;
+        let index = cursor.pos / cluster_size;
+        if index < cursor.cluster_index { //向回定位时只能从第一个簇重新走
+            cursor.cluster = cursor.first_cluster;
+            cursor.cluster_index = 0;
+        }
+        while cursor.cluster_index < index {
+            cursor.cluster = self.next_cluster(cursor.cluster)?.ok_or(FsError::InvalidFilesystem)?;
+            cursor.cluster_index += 1;
+        }
+        if cursor.loaded != Some(cursor.cluster) {
+            cursor.buffer.resize(cluster_size as usize, 0);
+            self.read_cluster(cursor.cluster, &mut cursor.buffer)?;
+            cursor.loaded = Some(cursor.cluster);
+        }
+
+        let offset = (cursor.pos % cluster_size) as usize;
+        let n = buf
+            .len()
+            .min(cursor.buffer.len() - offset)
+            .min((cursor.size - cursor.pos) as usize);
+        buf[..n].copy_from_slice(&cursor.buffer[offset..offset + n]);
+        cursor.pos += n as u64;
+        Ok(n)
+    }
+
+    //一致性检查(fsck)：从根目录遍历整棵目录树，检查簇链、文件大小和各份 FAT 表，不修改磁盘
+    //磁盘数据结构的问题记在报告里；只有读设备失败时才返回错误
+    pub fn check(&mut self) -> Result<CheckReport, FsError> {
+        let mut report = CheckReport::default();
+        self.check_fat_copies(&mut report)?;
+
+        let mut used = vec![false; self.cluster_count as usize + 2];
+        let mut pending = vec![(String::from("/"), self.root_cluster)];
+        while let Some((path, cluster)) = pending.pop() {
+            report.directories += 1;
+            if self.check_chain(&path, cluster, &mut used, &mut report)?.is_none() {
+                continue;
+            }
+            let nodes = match self.read_dir_nodes(cluster) {
+                Ok(nodes) => nodes,
+                Err(FsError::InvalidFilesystem) => {
+                    report.problems.push(Problem::BadDirectory(path));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            for node in nodes.into_iter().filter(|node| node.name != "." && node.name != "..") {
+                let child = if path == "/" { format!("/{}", node.name) } else { format!("{}/{}", path, node.name) };
+                if node.is_dir() {
+                    pending.push((child, node.cluster));
+                    continue;
+                }
+                report.files += 1;
+                if node.cluster == 0 {
+                    if node.size != 0 {
+                        report.problems.push(Problem::SizeMismatch(child)); //有大小却没有分配簇
+                    }
+                    continue;
+                }
+                if let Some(clusters) = self.check_chain(&child, node.cluster, &mut used, &mut report)? {
+                    let needed = (node.size as u64).div_ceil(self.cluster_size() as u64);
+                    if clusters != needed.max(1) {
+                        report.problems.push(Problem::SizeMismatch(child));
+                    }
+                }
+            }
+        }
+
+        //FAT 表中已分配、但不属于任何文件或目录的簇
+        for cluster in (2..).take(self.cluster_count as usize) {
+            if !used[cluster as usize] && self.fat_entry(cluster)? != 0 {
+                report.lost_clusters += 1;
+            }
+        }
+        report.used_clusters = used.iter().filter(|&&u| u).count() as u32;
+        Ok(report)
+    }
+
+    //沿簇链标记已使用的簇，返回链的长度；链损坏或与别的链交叉时记录问题并返回 None
+    fn check_chain(&mut self, path: &str, first: u32, used: &mut [bool], report: &mut CheckReport) -> Result<Option<u64>, FsError> {
+        let mut cluster = first;
+        let mut length = 0u64;
+        loop {
+            if self.check_cluster(cluster).is_err() {
+                report.problems.push(Problem::BadChain(String::from(path)));
+                return Ok(None);
+            }
+            if used[cluster as usize] {
+                report.problems.push(Problem::CrossLinked(String::from(path), cluster)); //也包括链中的环
+                return Ok(None);
+            }
+            used[cluster as usize] = true;
+            length += 1;
+            match self.next_cluster(cluster) {
+                Ok(Some(next)) => cluster = next,
+                Ok(None) => return Ok(Some(length)),
+                Err(FsError::InvalidFilesystem) => {
+                    report.problems.push(Problem::BadChain(String::from(path)));
+                    return Ok(None);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    //各份 FAT 表应当完全相同
+    fn check_fat_copies(&mut self, report: &mut CheckReport) -> Result<(), FsError> {
+        let mut first = vec![0u8; self.bytes_per_sector];
+        let mut other = vec![0u8; self.bytes_per_sector];
+        for sector in 0..self.fat_size {
+            self.dev.read_blocks(self.fat_start + sector, &mut first)?;
+            for copy in 1..self.fat_count {
+                self.dev.read_blocks(self.fat_start + copy * self.fat_size + sector, &mut other)?;
+                if first != other {
+                    report.problems.push(Problem::FatMismatch(sector));
+                    return Ok(()); //只报告第一处不同
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+//一致性检查发现的问题
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    FatMismatch(u64),         //各份 FAT 表在这个扇区(相对表头)不一致
+    BadDirectory(String),     //目录内容无法解析
+    BadChain(String),         //簇链指向非法的簇
+    CrossLinked(String, u32), //簇链经过一个已经属于别处的簇
+    SizeMismatch(String),     //文件大小与簇链长度不符
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    pub directories: u32,
+    pub files: u32,
+    pub used_clusters: u32,
+    pub lost_clusters: u32, //已分配但没有被引用的簇，只浪费空间，不算损坏
+    pub problems: Vec<Problem>,
+}
+
+impl CheckReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl<D: BlockDevice + Send + 'static> Fat32<D> {
+    //把整个卷接入 VFS，返回根目录节点，之后可以用 vfs::mount 挂载
+    pub fn into_root(self) -> Arc<dyn Inode> {
+        let node = Node {
+            name: String::from("/"),
+            attr: ATTR_DIRECTORY,
+            cluster: self.root_cluster,
+            size: 0,
+            mtime: 0,
+        };
+        Arc::new(FatInode { volume: Arc::new(Mutex::new(self)), node })
+    }
+}
+
+//文件中的读取位置，记住当前所在的簇，顺序读取时不必每次从头遍历簇链
+struct Cursor {
+    first_cluster: u32,
+    size: u64,
+    pos: u64,
+    cluster: u32,        //pos 所在的簇
+    cluster_index: u64,  //该簇是文件的第几个簇
+    buffer: Vec<u8>,     //缓存一个簇的数据
+    loaded: Option<u32>, //buffer 中缓存的是哪个簇
+}
+
+//卷中的一个文件或目录，所有节点共享同一个卷
+struct FatInode<D: BlockDevice> {
+    volume: Arc<Mutex<Fat32<D>>>,
+    node: Node,
+}
+
+impl<D: BlockDevice + Send + 'static> Inode for FatInode<D> {
+    //FAT 没有所有者和权限位：全部属于 root，所有人可读可执行，带只读属性的不可写
+    fn metadata(&self) -> Metadata {
+        let kind = if self.node.is_dir() { InodeKind::Directory } else { InodeKind::File };
+        let mode = if self.node.attr & ATTR_READ_ONLY != 0 { 0o555 } else { 0o755 };
+        Metadata { kind, size: self.node.size as u64, mtime: self.node.mtime as u64, mode, uid: 0, gid: 0 }
+    }
+
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError> {
+        if self.node.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+        let cursor = Cursor {
+            first_cluster: self.node.cluster,
+            size: self.node.size as u64,
+            pos: 0,
+            cluster: self.node.cluster,
+            cluster_index: 0,
+            buffer: Vec::new(),
+            loaded: None,
+        };
+        Ok(Box::new(FatFile { volume: self.volume.clone(), cursor }))
+    }
+
+    fn as_directory(&self) -> Option<&dyn Directory> {
+        if self.node.is_dir() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+impl<D: BlockDevice + Send + 'static> Directory for FatInode<D> {
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        let node = self.volume.lock().find_child(self.node.cluster, name)?;
+        Ok(Arc::new(FatInode { volume: self.volume.clone(), node }))
+    }
+
+    fn entries(&self) -> Result<Vec<DirEntry>, FsError> {
+        let nodes = self.volume.lock().read_dir_nodes(self.node.cluster)?;
+        Ok(nodes
+            .into_iter()
+            .filter(|child| child.name != "." && child.name != "..")
+            .map(|child| DirEntry {
+                is_dir: child.is_dir(),
+                size: child.size as u64,
+                name: child.name,
+            })
+            .collect())
+    }
+}
+
+struct FatFile<D: BlockDevice> {
+    volume: Arc<Mutex<Fat32<D>>>,
+    cursor: Cursor,
+}
+
+impl<D: BlockDevice + Send + 'static> FileHandle for FatFile<D> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        self.volume.lock().read_at(&mut self.cursor, buf)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, FsError> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.cursor.pos as i64 + offset,
+            SeekFrom::End(offset) => self.cursor.size as i64 + offset,
+        };
+        if target < 0 {
+            return Err(FsError::InvalidArgument);
+        }
+        self.cursor.pos = target as u64;
+        Ok(self.cursor.pos)
+    }
+}
+
+//格式化参数(与 Microsoft FAT 规范中推荐的取值一致)
+const FORMAT_RESERVED_SECTORS: u32 = 32;
+const FORMAT_FAT_COUNT: u32 = 2;
+const FORMAT_FSINFO_SECTOR: u16 = 1;
+const FORMAT_BACKUP_BOOT_SECTOR: u16 = 6;
+const FORMAT_MEDIA: u8 = 0xF8; //固定磁盘
+const MIN_CLUSTERS: u32 = 65525; //簇数少于这个值的卷会被识别为 FAT16
+const ZERO_CHUNK_SECTORS: u64 = 64; //清零 FAT 表时每次写入的扇区数
+
+//按卷大小选择每簇扇区数(单位为 512 字节扇区)
+fn sectors_per_cluster_for(total_sectors: u64) -> u32 {
+    match total_sectors {
+        0..=532_480 => 1,               //260 MiB 以下
+        532_481..=16_777_216 => 8,      //8 GiB 以下，4 KiB 簇
+        16_777_217..=33_554_432 => 16,  //16 GiB 以下
+        33_554_433..=67_108_864 => 32,  //32 GiB 以下
+        _ => 64,
+    }
+}
+
+//卷标：转成大写，不足 11 个字符用空格补齐
+fn volume_label(label: Option<&str>) -> Result<[u8; 11], FsError> {
+    let mut raw = *b"NO NAME    ";
+    if let Some(label) = label {
+        if label.is_empty() || label.len() > 11 || !label.bytes().all(|b| b.is_ascii_graphic() || b == b' ') {
+            return Err(FsError::InvalidArgument);
+        }
+        raw = [b' '; 11];
+        for (dst, b) in raw.iter_mut().zip(label.bytes()) {
+            *dst = b.to_ascii_uppercase();
+        }
+    }
+    Ok(raw)
+}
+
+//在整个设备上建立一个空的 FAT32 卷：引导扇区及其备份、FSInfo、两份 FAT 表和空的根目录
+//设备原有的数据会丢失；设备太小(簇数不足 65525)时返回 Unsupported
+pub fn format<D: BlockDevice + ?Sized>(dev: &mut D, label: Option<&str>) -> Result<(), FsError> {
+    if dev.block_size() != 512 {
+        return Err(FsError::Unsupported);
+    }
+    let label = volume_label(label)?;
+    let total_sectors = dev.block_count().min(u32::MAX as u64);
+    let sectors_per_cluster = sectors_per_cluster_for(total_sectors);
+
+    //FAT 大小的计算方法来自 FAT 规范，结果可能略大于实际需要，但不会偏小
+    let usable = total_sectors.saturating_sub(FORMAT_RESERVED_SECTORS as u64);
+    let divisor = (256 * sectors_per_cluster as u64 + FORMAT_FAT_COUNT as u64) / 2;
+    let fat_size = usable.div_ceil(divisor);
+    let data_start = FORMAT_RESERVED_SECTORS as u64 + FORMAT_FAT_COUNT as u64 * fat_size;
+    if total_sectors <= data_start {
+        return Err(FsError::Unsupported);
+    }
+    let cluster_count = (total_sectors - data_start) / sectors_per_cluster as u64;
+    if cluster_count < MIN_CLUSTERS as u64 {
+        return Err(FsError::Unsupported);
+    }
+
+    //引导扇区
+    let mut boot = vec![0u8; 512];
+    boot[0..3].copy_from_slice(&[0xEB, 0x58, 0x90]); //jmp short + nop
+    boot[3..11].copy_from_slice(b"JOAKIMOS");
+    write_u16(&mut boot, 11, 512);
+    boot[13] = sectors_per_cluster as u8;
+    write_u16(&mut boot, 14, FORMAT_RESERVED_SECTORS as u16);
+    boot[16] = FORMAT_FAT_COUNT as u8;
+    boot[21] = FORMAT_MEDIA;
+    write_u16(&mut boot, 24, 63);  //每磁道扇区数(只用于 CHS，LBA 下无意义)
+    write_u16(&mut boot, 26, 255); //磁头数
+    write_u32(&mut boot, 32, total_sectors as u32);
+    write_u32(&mut boot, 36, fat_size as u32);
+    write_u32(&mut boot, 44, 2); //根目录从 2 号簇开始
+    write_u16(&mut boot, 48, FORMAT_FSINFO_SECTOR);
+    write_u16(&mut boot, 50, FORMAT_BACKUP_BOOT_SECTOR);
+    boot[64] = 0x80; //驱动器号
+    boot[66] = 0x29; //扩展引导签名，表示后面的卷序列号、卷标和类型字段有效
+    write_u32(&mut boot, 67, crate::console::timestamp() as u32); //卷序列号
+    boot[71..82].copy_from_slice(&label);
+    boot[82..90].copy_from_slice(b"FAT32   ");
+    write_u16(&mut boot, 510, 0xAA55);
+
+    //FSInfo 扇区：记录空闲簇数和下一个可分配的簇，根目录已占用 2 号簇
+    let mut fsinfo = vec![0u8; 512];
+    write_u32(&mut fsinfo, 0, 0x4161_5252);
+    write_u32(&mut fsinfo, 484, 0x6141_7272);
+    write_u32(&mut fsinfo, 488, cluster_count as u32 - 1);
+    write_u32(&mut fsinfo, 492, 3);
+    write_u32(&mut fsinfo, 508, 0xAA55_0000);
+
+    //保留区先全部清零，再写入主引导扇区和备份
+    let backup = FORMAT_BACKUP_BOOT_SECTOR as u64;
+    dev.write_blocks(0, &vec![0u8; FORMAT_RESERVED_SECTORS as usize * 512])?;
+    dev.write_blocks(0, &boot)?;
+    dev.write_blocks(FORMAT_FSINFO_SECTOR as u64, &fsinfo)?;
+    dev.write_blocks(backup, &boot)?;
+    dev.write_blocks(backup + FORMAT_FSINFO_SECTOR as u64, &fsinfo)?;
+
+    //两份 FAT 表：0、1 号表项是保留值，2 号表项标记根目录的簇链结束
+    let zeros = vec![0u8; ZERO_CHUNK_SECTORS as usize * 512];
+    let mut first = vec![0u8; 512];
+    write_u32(&mut first, 0, 0x0FFF_FF00 | FORMAT_MEDIA as u32);
+    write_u32(&mut first, 4, FAT_ENTRY_MASK);
+    write_u32(&mut first, 8, FAT_ENTRY_MASK);
+    for copy in 0..FORMAT_FAT_COUNT as u64 {
+        let start = FORMAT_RESERVED_SECTORS as u64 + copy * fat_size;
+        let mut sector = 0;
+        while sector < fat_size {
+            let count = ZERO_CHUNK_SECTORS.min(fat_size - sector);
+            dev.write_blocks(start + sector, &zeros[..count as usize * 512])?;
+            sector += count;
+        }
+        dev.write_blocks(start, &first)?;
+    }
+
+    //根目录：一个空簇，第一项是卷标
+    let mut root = vec![0u8; sectors_per_cluster as usize * 512];
+    if label != *b"NO NAME    " {
+        root[0..11].copy_from_slice(&label);
+        root[11] = ATTR_VOLUME_ID;
+    }
+    dev.write_blocks(data_start, &root)?;
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//回环设备：把某个已挂载文件系统上的文件当作块设备，文件中的第 n 个 512 字节就是第 n 块
+//可以直接用磁盘镜像文件测试文件系统驱动，不需要给 QEMU 额外配置硬盘
+use super::vfs::{self, FileHandle, InodeKind, SeekFrom};
+use super::FsError;
+use crate::block::{self, check_request, BlockDevice, BlockError};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+const BLOCK_SIZE: usize = 512;
+const MAX_LOOP_DEVICES: usize = 8;
+
+pub struct Loopback {
+    file: Box<dyn FileHandle>,
+    blocks: u64, //文件末尾不足一块的部分不使用
+}
+
+impl Loopback {
+    fn seek_to(&mut self, lba: u64) -> Result<(), BlockError> {
+        self.file
+            .seek(SeekFrom::Start(lba * BLOCK_SIZE as u64))
+            .map(|_| ())
+            .map_err(|_| BlockError::Backing)
+    }
+}
+
+impl BlockDevice for Loopback {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.blocks
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        self.seek_to(lba)?;
+        let mut done = 0;
+        while done < buf.len() {
+            match self.file.read(&mut buf[done..]) {
+                Ok(0) | Err(_) => return Err(BlockError::Backing), //文件在挂接之后被截短了
+                Ok(n) => done += n,
+            }
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        self.seek_to(lba)?;
+        let mut done = 0;
+        while done < buf.len() {
+            match self.file.write(&buf[done..]) {
+                Ok(0) | Err(_) => return Err(BlockError::Backing),
+                Ok(n) => done += n,
+            }
+        }
+        Ok(())
+    }
+}
+
+//已挂接的回环设备：设备名(loop0)到文件路径
+static ATTACHED: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+//把文件挂接为下一个空闲的 loopN 设备，同时建立 /dev/loopN，返回设备名
+//底层文件系统只读时设备也只能读，写入会返回 BlockError::Backing
+pub fn attach(path: &str) -> Result<String, FsError> {
+    let path = vfs::normalize(path)?;
+    let meta = vfs::metadata(&path)?;
+    if meta.kind != InodeKind::File {
+        return Err(FsError::InvalidArgument);
+    }
+    let blocks = meta.size / BLOCK_SIZE as u64;
+    if blocks == 0 {
+        return Err(FsError::InvalidArgument);
+    }
+    let file = vfs::open(&path)?;
+
+    let mut attached = ATTACHED.lock();
+    let name = (0..MAX_LOOP_DEVICES)
+        .map(|i| format!("loop{}", i))
+        .find(|name| !attached.contains_key(name))
+        .ok_or(FsError::Unsupported)?;
+    let device: block::SharedDevice = Arc::new(Mutex::new(Loopback { file, blocks }));
+    block::register(&name, device.clone());
+    super::add_device_node(&name, device);
+    attached.insert(name.clone(), path);
+    Ok(name)
+}
+
+//拆除回环设备，已经用它挂载的文件系统仍然持有设备，可以继续使用
+pub fn detach(name: &str) -> Result<(), FsError> {
+    let name = name.strip_prefix("/dev/").unwrap_or(name);
+    if !ATTACHED.lock().contains_key(name) {
+        return Err(FsError::NotFound);
+    }
+    //缓存中还没有写回的块写到文件里，之后不再有块属于这个设备
+    if let Some(dev) = block::get(name) {
+        super::cache::invalidate(&dev)?;
+    }
+    ATTACHED.lock().remove(name);
+    block::unregister(name);
+    super::remove_device_node(name);
+    Ok(())
+}
+
+//所有回环设备及其背后的文件
+pub fn list() -> Vec<(String, String)> {
+    ATTACHED.lock().iter().map(|(name, path)| (name.clone(), path.clone())).collect()
+}
@@ -0,0 +1,93 @@
+//文件系统：vfs 提供统一的路径命名空间，各个具体文件系统以挂载点的形式接入
+use crate::block::{self, BlockError, SharedDevice};
+use alloc::string::String;
+use alloc::sync::Arc;
+use spin::Mutex;
+use vfs::{Directory, Inode};
+
+pub mod cache; //块缓存(写回，LRU)
+pub mod crashtest; //断电崩溃一致性测试
+pub mod devfs; //设备节点(/dev/console、/dev/null、块设备)
+pub mod fat32; //FAT32 文件系统(只读挂载，支持格式化)
+pub mod loopback; //把文件当作块设备使用
+pub mod pipe; //进程间的单向字节流
+pub mod procfs; //进程信息(/proc)
+pub mod ramfs; //内存文件系统
+pub mod socket; //本地套接字
+pub mod vfs;   //虚拟文件系统层
+pub mod watch; //路径变化通知
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    NotFound,          //路径不存在
+    NotADirectory,     //路径中间某一级不是目录
+    IsADirectory,      //试图把目录当作文件打开
+    AlreadyExists,     //创建的文件或目录已经存在
+    NotEmpty,          //删除非空目录
+    InvalidPath,       //路径不是以 / 开头的绝对路径
+    InvalidArgument,   //参数不合法，例如定位到负数偏移
+    ReadOnly,          //文件系统或文件不支持写入
+    InvalidFilesystem, //磁盘上的数据结构损坏或不是支持的文件系统
+    Unsupported,       //文件系统合法，但使用了尚未支持的特性
+    Io(BlockError),    //底层块设备出错
+    WouldBlock,        //非阻塞模式下暂时没有数据可读或没有空间可写
+    BrokenPipe,        //管道的读端已全部关闭
+    Network,           //网络套接字收发失败(地址不可达、网卡出错)
+    ConnectionRefused, //本地套接字的路径上没有在监听的一端
+    PermissionDenied,  //没有访问权限
+    NoSpace,           //存储空间或内存不足
+    Interrupted,       //等待期间收到信号(控制台上按了 Ctrl+C)
+    TooManyLinks,      //路径中的符号链接太多，通常是链接成环
+    CrossDevice,       //硬链接或改名跨越了不同的文件系统
+}
+
+impl From<BlockError> for FsError {
+    fn from(err: BlockError) -> FsError {
+        FsError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+//根文件系统中的 /dev 目录，之后新增的块设备也放在这里
+static DEV_DIR: Mutex<Option<Arc<ramfs::RamDir>>> = Mutex::new(None);
+
+//建立根文件系统：/ 是一个 ramfs，其中预先建好 /dev、/boot、/mnt、/run、/proc 五个目录，/proc 上挂载 procfs
+//需要在块设备驱动初始化之后调用，已注册的块设备会出现在 /dev 下
+pub fn init() {
+    let root = ramfs::RamDir::new();
+    let dev = ramfs::RamDir::new();
+    dev.insert("console", Arc::new(devfs::Console));
+    dev.insert("null", Arc::new(devfs::Null));
+    for (name, device) in block::devices() {
+        dev.insert(&name, Arc::new(devfs::Block(cache::wrap(device))));
+    }
+    root.insert("dev", dev.clone());
+    *DEV_DIR.lock() = Some(dev);
+    root.insert("boot", ramfs::RamDir::new());
+    root.insert("mnt", ramfs::RamDir::new());
+    let run = ramfs::RamDir::new();
+    let _ = run.set_mode(0o777); //本地套接字的监听点，所有用户都可以在这里 bind
+    root.insert("run", run);
+    root.insert("proc", ramfs::RamDir::new());
+    vfs::mount("/", root).unwrap();
+    vfs::mount("/proc", Arc::new(procfs::ProcRoot)).unwrap();
+}
+
+//为 init 之后才注册的块设备(回环设备、内存盘)建立 /dev 节点
+pub fn add_device_node(name: &str, device: SharedDevice) {
+    if let Some(dev) = DEV_DIR.lock().as_ref() {
+        dev.insert(name, Arc::new(devfs::Block(cache::wrap(device))));
+    }
+}
+
+pub fn remove_device_node(name: &str) {
+    if let Some(dev) = DEV_DIR.lock().as_ref() {
+        let _ = dev.remove(name);
+    }
+}
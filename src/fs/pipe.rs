@@ -0,0 +1,181 @@
+//管道：容量有限的单向字节流，写端写入的数据按顺序从读端读出
+//内核还不能在进程之间切换，阻塞模式下的等待只有在另一端由别的执行流操作时才会结束
+use super::vfs::FileHandle;
+use super::FsError;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use spin::Mutex;
+
+pub const CAPACITY: usize = 4096;
+
+struct Pipe {
+    buffer: VecDeque<u8>,
+    readers: usize,
+    writers: usize,
+}
+
+//建立一个管道，返回读端和写端
+pub fn pipe() -> (Box<dyn FileHandle>, Box<dyn FileHandle>) {
+    let pipe = Arc::new(Mutex::new(Pipe { buffer: VecDeque::with_capacity(CAPACITY), readers: 1, writers: 1 }));
+    let reader = PipeReader { pipe: pipe.clone(), nonblocking: false };
+    let writer = PipeWriter { pipe, nonblocking: false };
+    (Box::new(reader), Box::new(writer))
+}
+
+struct PipeReader {
+    pipe: Arc<Mutex<Pipe>>,
+    nonblocking: bool,
+}
+
+struct PipeWriter {
+    pipe: Arc<Mutex<Pipe>>,
+    nonblocking: bool,
+}
+
+impl FileHandle for PipeReader {
+    //有数据时立即返回已有的部分(可能少于 buf.len())；写端全部关闭且没有数据时返回 0
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            {
+                let mut pipe = self.pipe.lock();
+                if !pipe.buffer.is_empty() {
+                    let n = buf.len().min(pipe.buffer.len());
+                    for (dst, src) in buf.iter_mut().zip(pipe.buffer.drain(..n)) {
+                        *dst = src;
+                    }
+                    return Ok(n);
+                }
+                if pipe.writers == 0 {
+                    return Ok(0);
+                }
+                if self.nonblocking {
+                    return Err(FsError::WouldBlock);
+                }
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, FsError> {
+        Err(FsError::InvalidArgument) //读端不能写
+    }
+
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), FsError> {
+        self.nonblocking = nonblocking;
+        Ok(())
+    }
+}
+
+impl FileHandle for PipeWriter {
+    fn read(&mut self, _buf: &mut [u8]) -> Result<usize, FsError> {
+        Err(FsError::InvalidArgument) //写端不能读
+    }
+
+    //有空间时写入能放下的部分并返回写入的字节数(可能少于 buf.len())；读端全部关闭时返回 BrokenPipe
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            {
+                let mut pipe = self.pipe.lock();
+                if pipe.readers == 0 {
+                    return Err(FsError::BrokenPipe);
+                }
+                let free = CAPACITY - pipe.buffer.len();
+                if free > 0 {
+                    let n = buf.len().min(free);
+                    pipe.buffer.extend(&buf[..n]);
+                    return Ok(n);
+                }
+                if self.nonblocking {
+                    return Err(FsError::WouldBlock);
+                }
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), FsError> {
+        self.nonblocking = nonblocking;
+        Ok(())
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        self.pipe.lock().readers -= 1;
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        self.pipe.lock().writers -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nonblocking_pipe() -> (Box<dyn FileHandle>, Box<dyn FileHandle>) {
+        let (mut reader, mut writer) = pipe();
+        reader.set_nonblocking(true).unwrap();
+        writer.set_nonblocking(true).unwrap();
+        (reader, writer)
+    }
+
+    #[test]
+    fn empty_pipe_would_block() {
+        let (mut reader, _writer) = nonblocking_pipe();
+        assert_eq!(reader.read(&mut [0u8; 16]), Err(FsError::WouldBlock));
+    }
+
+    #[test]
+    fn partial_read() {
+        let (mut reader, mut writer) = nonblocking_pipe();
+        assert_eq!(writer.write(b"hello"), Ok(5));
+        let mut buf = [0u8; 3];
+        assert_eq!(reader.read(&mut buf), Ok(3));
+        assert_eq!(&buf, b"hel");
+        let mut buf = [0u8; 16];
+        assert_eq!(reader.read(&mut buf), Ok(2));
+        assert_eq!(&buf[..2], b"lo");
+        assert_eq!(reader.read(&mut buf), Err(FsError::WouldBlock));
+    }
+
+    #[test]
+    fn partial_write_then_full() {
+        let (mut reader, mut writer) = nonblocking_pipe();
+        let data = [7u8; CAPACITY + 100];
+        assert_eq!(writer.write(&data), Ok(CAPACITY));
+        assert_eq!(writer.write(&data), Err(FsError::WouldBlock));
+        assert_eq!(reader.read(&mut [0u8; 10]), Ok(10));
+        assert_eq!(writer.write(&data), Ok(10));
+    }
+
+    #[test]
+    fn closed_ends() {
+        let (mut reader, mut writer) = nonblocking_pipe();
+        writer.write(b"x").unwrap();
+        drop(writer);
+        //写端关闭后先读完剩下的数据，然后是文件结尾而不是 WouldBlock
+        assert_eq!(reader.read(&mut [0u8; 4]), Ok(1));
+        assert_eq!(reader.read(&mut [0u8; 4]), Ok(0));
+
+        let (reader, mut writer) = nonblocking_pipe();
+        drop(reader);
+        assert_eq!(writer.write(b"x"), Err(FsError::BrokenPipe));
+    }
+
+    #[test]
+    fn wrong_direction() {
+        let (mut reader, mut writer) = pipe();
+        assert_eq!(reader.write(b"x"), Err(FsError::InvalidArgument));
+        assert_eq!(writer.read(&mut [0u8; 1]), Err(FsError::InvalidArgument));
+    }
+}
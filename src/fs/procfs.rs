@@ -0,0 +1,247 @@
+//进程信息文件系统(/proc)：内容在打开时由内核当场生成，只能读
+//每个进程一个以 PID 命名的目录，其中的 stat 是一行状态
+//全局的文件：vmstat 是缺页异常统计，meminfo 是内存用量，interrupts 是各中断的次数，uptime 是启动以来的秒数
+//groups 是各进程组的限制和用量，cmdline 是内核命令行
+use super::vfs::{Directory, FileHandle, Inode, InodeKind, Metadata, SeekFrom};
+use super::{DirEntry, FsError};
+use crate::process::{self, ProcessInfo, State};
+use crate::{cmdline, group, oom, stats, time};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+const DIRECTORY: Metadata = Metadata { kind: InodeKind::Directory, size: 0, mtime: 0, mode: 0o555, uid: 0, gid: 0 };
+const FILE: Metadata = Metadata { kind: InodeKind::File, size: 0, mtime: 0, mode: 0o444, uid: 0, gid: 0 }; //大小要读了才知道
+
+//打开文件时生成全部内容
+type Generator = fn() -> String;
+
+//根目录下不属于某个进程的文件
+const GLOBAL_FILES: &[(&str, Generator)] = &[
+    ("vmstat", vmstat),
+    ("meminfo", meminfo),
+    ("interrupts", interrupts),
+    ("uptime", uptime),
+    ("groups", groups),
+    ("cmdline", cmdline),
+];
+
+fn find_process(pid: u64) -> Option<ProcessInfo> {
+    process::list().into_iter().find(|info| info.pid == pid)
+}
+
+//根目录
+pub struct ProcRoot;
+
+impl Inode for ProcRoot {
+    fn metadata(&self) -> Metadata {
+        DIRECTORY
+    }
+
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError> {
+        Err(FsError::IsADirectory)
+    }
+
+    fn as_directory(&self) -> Option<&dyn Directory> {
+        Some(self)
+    }
+}
+
+impl Directory for ProcRoot {
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        if let Some(&(_, generate)) = GLOBAL_FILES.iter().find(|(file, _)| *file == name) {
+            return Ok(Arc::new(Generated(generate)));
+        }
+        let pid = name.parse().map_err(|_| FsError::NotFound)?;
+        find_process(pid).ok_or(FsError::NotFound)?;
+        Ok(Arc::new(ProcessDir(pid)))
+    }
+
+    fn entries(&self) -> Result<Vec<DirEntry>, FsError> {
+        let mut entries: Vec<DirEntry> = process::list()
+            .into_iter()
+            .map(|info| DirEntry { name: info.pid.to_string(), size: 0, is_dir: true })
+            .collect();
+        entries.extend(GLOBAL_FILES.iter().map(|(name, _)| DirEntry { name: String::from(*name), size: 0, is_dir: false }));
+        Ok(entries)
+    }
+}
+
+//一个进程的目录
+struct ProcessDir(u64);
+
+impl Inode for ProcessDir {
+    fn metadata(&self) -> Metadata {
+        DIRECTORY
+    }
+
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError> {
+        Err(FsError::IsADirectory)
+    }
+
+    fn as_directory(&self) -> Option<&dyn Directory> {
+        Some(self)
+    }
+}
+
+impl Directory for ProcessDir {
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        match name {
+            "stat" => Ok(Arc::new(ProcessStat(self.0))),
+            _ => Err(FsError::NotFound),
+        }
+    }
+
+    fn entries(&self) -> Result<Vec<DirEntry>, FsError> {
+        Ok(alloc::vec![DirEntry { name: String::from("stat"), size: 0, is_dir: false }])
+    }
+}
+
+//格式与 Linux 的 /proc/<pid>/stat 类似但字段少得多：
+//pid (name) state ppid minflt majflt cowflt
+struct ProcessStat(u64);
+
+impl Inode for ProcessStat {
+    fn metadata(&self) -> Metadata {
+        FILE
+    }
+
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError> {
+        let info = find_process(self.0).ok_or(FsError::NotFound)?;
+        let state = match info.state {
+            State::Running | State::Ready => 'R',
+            State::Blocked => 'S',
+            State::Zombie => 'Z',
+        };
+        let text = format!(
+            "{} ({}) {} {} {} {} {}\n",
+            info.pid, info.name, state, info.parent, info.faults.minor, info.faults.major, info.faults.cow
+        );
+        Ok(Box::new(TextHandle { data: text.into_bytes(), pos: 0 }))
+    }
+}
+
+//内容由函数生成的文件
+struct Generated(Generator);
+
+impl Inode for Generated {
+    fn metadata(&self) -> Metadata {
+        FILE
+    }
+
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError> {
+        Ok(Box::new(TextHandle { data: (self.0)().into_bytes(), pos: 0 }))
+    }
+}
+
+//每行一个 "名字 值"，名字与 Linux 的 /proc/vmstat 相同(pgcowfault 和 bcache_* 是这里新加的)
+fn vmstat() -> String {
+    let snapshot = stats::snapshot();
+    let (faults, cache) = (snapshot.faults, snapshot.block_cache);
+    format!(
+        "pgfault {}\npgmajfault {}\npgcowfault {}\noom_kill {}\nbcache_hit {}\nbcache_miss {}\nbcache_writeback {}\n",
+        faults.total(),
+        faults.major,
+        faults.cow,
+        oom::kills(),
+        cache.hits,
+        cache.misses,
+        cache.writebacks
+    )
+}
+
+//数值以 kB 为单位，格式与 Linux 相同
+fn meminfo() -> String {
+    let snapshot = stats::snapshot();
+    format!(
+        "MemTotal: {:>10} kB\nMemFree: {:>11} kB\nHeapTotal: {:>9} kB\nHeapUsed: {:>10} kB\nBlockCache: {:>8}\nBlockDirty: {:>8}\n",
+        snapshot.frames_total * 4,
+        snapshot.frames_free * 4,
+        snapshot.heap_size / 1024,
+        snapshot.heap_used / 1024,
+        snapshot.block_cache.blocks,
+        snapshot.block_cache.dirty
+    )
+}
+
+//每个发生过的中断一行：向量号、每个 CPU 上的次数、名字；最后一行是上下文切换次数
+fn interrupts() -> String {
+    let snapshot = stats::snapshot();
+    let mut text = String::from("    ");
+    for cpu in 0..snapshot.cpus.len() {
+        text += &format!(" {:>10}", format!("CPU{}", cpu));
+    }
+    text.push('\n');
+    let mut vectors: Vec<u8> = snapshot.cpus.iter().flat_map(|cpu| cpu.interrupts.iter().map(|&(v, _)| v)).collect();
+    vectors.sort_unstable();
+    vectors.dedup();
+    for vector in vectors {
+        text += &format!("{:>3}:", vector);
+        for cpu in &snapshot.cpus {
+            let count = cpu.interrupts.iter().find(|&&(v, _)| v == vector).map_or(0, |&(_, count)| count);
+            text += &format!(" {:>10}", count);
+        }
+        text += &format!("  {}\n", stats::vector_name(vector));
+    }
+    text += "CSW:";
+    for cpu in &snapshot.cpus {
+        text += &format!(" {:>10}", cpu.context_switches);
+    }
+    text += "  Context switches\n";
+    text
+}
+
+//启动以来的秒数
+fn uptime() -> String {
+    let uptime = time::uptime();
+    format!("{}.{:02}\n", uptime.as_secs(), uptime.subsec_millis() / 10)
+}
+
+//每个组一行：名字 内存上限(kB，- 表示不限) 内存用量(kB) CPU 份额 进程列表
+fn groups() -> String {
+    let mut text = String::new();
+    for info in group::list() {
+        let limit = info.limits.memory_pages.map_or(String::from("-"), |pages| (pages * 4).to_string());
+        let pids: Vec<String> = info.pids.iter().map(|pid| pid.to_string()).collect();
+        text += &format!("{} {} {} {} {}\n", info.name, limit, info.memory_pages * 4, info.limits.cpu_shares, pids.join(","));
+    }
+    text
+}
+
+fn cmdline() -> String {
+    format!("{}\n", cmdline::raw())
+}
+
+//打开时生成的只读内容
+struct TextHandle {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl FileHandle for TextHandle {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let n = buf.len().min(self.data.len().saturating_sub(self.pos));
+        buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, FsError> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+            SeekFrom::End(offset) => self.data.len() as i64 + offset,
+        };
+        if target < 0 {
+            return Err(FsError::InvalidArgument);
+        }
+        self.pos = target as usize;
+        Ok(target as u64)
+    }
+}
@@ -0,0 +1,291 @@
+//完全放在内核堆上的文件系统，用作根目录以及临时文件
+//新建的文件和目录属于创建它的进程的用户和组，文件的权限是 0o644，目录是 0o755
+use super::vfs::{Directory, FileHandle, Inode, InodeKind, Metadata, SeekFrom};
+use super::{DirEntry, FsError};
+use crate::cred;
+use crate::failpoint;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+//权限位和所有者
+#[derive(Debug, Clone, Copy)]
+struct Attributes {
+    mode: u16,
+    uid: u32,
+    gid: u32,
+}
+
+impl Attributes {
+    fn new(mode: u16) -> Mutex<Attributes> {
+        let cred = cred::current();
+        Mutex::new(Attributes { mode, uid: cred.uid, gid: cred.gid })
+    }
+
+    fn metadata(&self, kind: InodeKind, size: u64, mtime: u64) -> Metadata {
+        Metadata { kind, size, mtime, mode: self.mode, uid: self.uid, gid: self.gid }
+    }
+}
+
+pub struct RamDir {
+    entries: Mutex<BTreeMap<String, Arc<dyn Inode>>>,
+    attr: Mutex<Attributes>,
+}
+
+impl RamDir {
+    pub fn new() -> Arc<RamDir> {
+        Arc::new(RamDir { entries: Mutex::new(BTreeMap::new()), attr: Attributes::new(0o755) })
+    }
+
+    //直接放入一个节点(可以是其他文件系统的节点，例如设备)
+    pub fn insert(&self, name: &str, inode: Arc<dyn Inode>) {
+        self.entries.lock().insert(String::from(name), inode);
+    }
+
+    fn create(&self, name: &str, inode: Arc<dyn Inode>) -> Result<Arc<dyn Inode>, FsError> {
+        let mut entries = self.entries.lock();
+        if entries.contains_key(name) {
+            return Err(FsError::AlreadyExists);
+        }
+        entries.insert(String::from(name), inode.clone());
+        Ok(inode)
+    }
+}
+
+impl Inode for RamDir {
+    fn metadata(&self) -> Metadata {
+        self.attr.lock().metadata(InodeKind::Directory, self.entries.lock().len() as u64, 0)
+    }
+
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError> {
+        Err(FsError::IsADirectory)
+    }
+
+    fn as_directory(&self) -> Option<&dyn Directory> {
+        Some(self)
+    }
+
+    fn set_mode(&self, mode: u16) -> Result<(), FsError> {
+        self.attr.lock().mode = mode;
+        Ok(())
+    }
+
+    fn set_owner(&self, uid: u32, gid: u32) -> Result<(), FsError> {
+        let mut attr = self.attr.lock();
+        (attr.uid, attr.gid) = (uid, gid);
+        Ok(())
+    }
+}
+
+impl Directory for RamDir {
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        self.entries.lock().get(name).cloned().ok_or(FsError::NotFound)
+    }
+
+    fn entries(&self) -> Result<Vec<DirEntry>, FsError> {
+        let entries = self.entries.lock();
+        Ok(entries
+            .iter()
+            .map(|(name, inode)| {
+                let meta = inode.metadata();
+                DirEntry { name: name.clone(), size: meta.size, is_dir: meta.kind == InodeKind::Directory }
+            })
+            .collect())
+    }
+
+    fn create_file(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        self.create(name, Arc::new(RamFile::new(Vec::new())))
+    }
+
+    fn create_dir(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        self.create(name, RamDir::new())
+    }
+
+    fn remove(&self, name: &str) -> Result<(), FsError> {
+        let mut entries = self.entries.lock();
+        let inode = entries.get(name).ok_or(FsError::NotFound)?;
+        if let Some(dir) = inode.as_directory() {
+            if !dir.entries()?.is_empty() {
+                return Err(FsError::NotEmpty);
+            }
+        }
+        entries.remove(name);
+        Ok(())
+    }
+
+    fn bind(&self, name: &str, inode: Arc<dyn Inode>) -> Result<(), FsError> {
+        self.create(name, inode).map(|_| ())
+    }
+
+    fn link(&self, name: &str, inode: Arc<dyn Inode>) -> Result<(), FsError> {
+        self.create(name, inode).map(|_| ())
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Result<(), FsError> {
+        self.create(name, Arc::new(RamSymlink { target: String::from(target), attr: Attributes::new(0o777) })).map(|_| ())
+    }
+
+    //两个目录的表都锁住之后再改，中间状态不会被看到
+    fn rename(&self, from: &str, to_dir: &dyn Directory, to: &str) -> Result<(), FsError> {
+        let target = to_dir.as_any().and_then(|any| any.downcast_ref::<RamDir>()).ok_or(FsError::CrossDevice)?;
+        if core::ptr::eq(self, target) {
+            let mut entries = self.entries.lock();
+            let inode = entries.get(from).ok_or(FsError::NotFound)?.clone();
+            if !replaceable(&inode, entries.get(to), self)? {
+                return Ok(());
+            }
+            entries.remove(from);
+            entries.insert(String::from(to), inode);
+            return Ok(());
+        }
+        let mut source = self.entries.lock();
+        let mut dest = target.entries.lock();
+        let inode = source.get(from).ok_or(FsError::NotFound)?.clone();
+        if !replaceable(&inode, dest.get(to), self)? {
+            return Ok(());
+        }
+        source.remove(from);
+        dest.insert(String::from(to), inode);
+        Ok(())
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+fn is(inode: &Arc<dyn Inode>, dir: &RamDir) -> bool {
+    Arc::as_ptr(inode) as *const u8 == dir as *const RamDir as *const u8
+}
+
+//inode 能否替换掉 existing；两者是同一个节点(硬链接)时返回 false，什么也不用做
+//locked 的表已经被锁住，existing 就是它时不能再去读它的内容(它至少包含要移走的那一项，不是空目录)
+fn replaceable(inode: &Arc<dyn Inode>, existing: Option<&Arc<dyn Inode>>, locked: &RamDir) -> Result<bool, FsError> {
+    let existing = match existing {
+        Some(existing) if Arc::ptr_eq(inode, existing) => return Ok(false),
+        Some(existing) => existing,
+        None => return Ok(true),
+    };
+    match (inode.as_directory().is_some(), existing.as_directory()) {
+        (true, None) => Err(FsError::NotADirectory),
+        (false, Some(_)) => Err(FsError::IsADirectory),
+        (true, Some(_)) if is(existing, locked) => Err(FsError::NotEmpty),
+        (true, Some(dir)) if !dir.entries()?.is_empty() => Err(FsError::NotEmpty),
+        _ => Ok(true),
+    }
+}
+
+//符号链接：只保存目标路径，查找时由 VFS 跟随
+struct RamSymlink {
+    target: String,
+    attr: Mutex<Attributes>,
+}
+
+impl Inode for RamSymlink {
+    fn metadata(&self) -> Metadata {
+        self.attr.lock().metadata(InodeKind::Symlink, self.target.len() as u64, 0)
+    }
+
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError> {
+        Err(FsError::InvalidArgument)
+    }
+
+    fn set_owner(&self, uid: u32, gid: u32) -> Result<(), FsError> {
+        let mut attr = self.attr.lock();
+        (attr.uid, attr.gid) = (uid, gid);
+        Ok(())
+    }
+
+    fn read_link(&self) -> Result<String, FsError> {
+        Ok(self.target.clone())
+    }
+}
+
+pub struct RamFile {
+    data: Arc<Mutex<Vec<u8>>>, //所有打开的句柄共享同一份数据
+    mtime: Arc<AtomicU64>,     //最后一次写入时的 TSC 计数
+    attr: Mutex<Attributes>,
+}
+
+impl RamFile {
+    pub fn new(data: Vec<u8>) -> RamFile {
+        RamFile {
+            data: Arc::new(Mutex::new(data)),
+            mtime: Arc::new(AtomicU64::new(crate::console::timestamp())),
+            attr: Attributes::new(0o644),
+        }
+    }
+}
+
+impl Inode for RamFile {
+    fn metadata(&self) -> Metadata {
+        self.attr.lock().metadata(InodeKind::File, self.data.lock().len() as u64, self.mtime.load(Ordering::SeqCst))
+    }
+
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError> {
+        Ok(Box::new(RamFileHandle { data: self.data.clone(), mtime: self.mtime.clone(), pos: 0 }))
+    }
+
+    fn set_mode(&self, mode: u16) -> Result<(), FsError> {
+        self.attr.lock().mode = mode;
+        Ok(())
+    }
+
+    fn set_owner(&self, uid: u32, gid: u32) -> Result<(), FsError> {
+        let mut attr = self.attr.lock();
+        (attr.uid, attr.gid) = (uid, gid);
+        Ok(())
+    }
+}
+
+struct RamFileHandle {
+    data: Arc<Mutex<Vec<u8>>>,
+    mtime: Arc<AtomicU64>,
+    pos: usize,
+}
+
+impl FileHandle for RamFileHandle {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let data = self.data.lock();
+        if self.pos >= data.len() {
+            return Ok(0);
+        }
+        let n = buf.len().min(data.len() - self.pos);
+        buf[..n].copy_from_slice(&data[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+
+    //写入位置超过文件末尾时，中间的空洞用 0 填充
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        failpoint!("ramfs::write_nospace", FsError::NoSpace);
+        let mut data = self.data.lock();
+        let end = self.pos + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[self.pos..end].copy_from_slice(buf);
+        self.pos = end;
+        self.mtime.store(crate::console::timestamp(), Ordering::SeqCst);
+        Ok(buf.len())
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, FsError> {
+        let len = self.data.lock().len() as i64;
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+            SeekFrom::End(offset) => len + offset,
+        };
+        if target < 0 {
+            return Err(FsError::InvalidArgument);
+        }
+        self.pos = target as usize;
+        Ok(self.pos as u64)
+    }
+}
@@ -0,0 +1,247 @@
+//本地套接字：同一台机器上的双向 IPC，比管道多了两个方向、消息边界和描述符传递
+//socketpair 直接建立一对互相连接的端点；bind 在 VFS 中(一般是 /run 下)建立有名字的监听点，connect 按路径连接
+//流模式像管道一样按字节读取；数据报模式保留消息边界，每次读取一条消息，放不下的部分被丢弃
+//传递的描述符从发送方移到接收方(还没有 dup，不能让两个进程共享同一个打开的文件)
+use super::vfs::{self, FileHandle, Inode, InodeKind, Metadata};
+use super::FsError;
+use crate::cred::{self, Credentials};
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+pub const CAPACITY: usize = 16 * 1024; //每个方向最多缓存的字节数
+pub const MAX_FDS: usize = 8; //一条消息最多携带的描述符数
+const BACKLOG: usize = 16; //还没有 accept 的连接数上限
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Stream,
+    Datagram,
+}
+
+struct Message {
+    data: Vec<u8>,
+    offset: usize, //流模式下已经读走的部分
+    fds: Vec<Box<dyn FileHandle>>,
+}
+
+//一个方向上的消息队列
+struct Channel {
+    messages: VecDeque<Message>,
+    bytes: usize,
+    reader_closed: bool,
+    writer_closed: bool,
+}
+
+impl Channel {
+    fn new() -> Arc<Mutex<Channel>> {
+        Arc::new(Mutex::new(Channel { messages: VecDeque::new(), bytes: 0, reader_closed: false, writer_closed: false }))
+    }
+}
+
+pub struct Socket {
+    kind: Kind,
+    rx: Arc<Mutex<Channel>>,
+    tx: Arc<Mutex<Channel>>,
+    nonblocking: bool,
+}
+
+//建立一对互相连接的套接字
+pub fn pair(kind: Kind) -> (Socket, Socket) {
+    let (a, b) = (Channel::new(), Channel::new());
+    let first = Socket { kind, rx: a.clone(), tx: b.clone(), nonblocking: false };
+    let second = Socket { kind, rx: b, tx: a, nonblocking: false };
+    (first, second)
+}
+
+impl Socket {
+    //等待对方的 Channel 满足 ready，非阻塞模式下不满足时返回 WouldBlock
+    fn wait<T>(&self, channel: &Mutex<Channel>, mut ready: impl FnMut(&mut Channel) -> Option<T>) -> Result<T, FsError> {
+        loop {
+            if let Some(result) = ready(&mut channel.lock()) {
+                return Ok(result);
+            }
+            if self.nonblocking {
+                return Err(FsError::WouldBlock);
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl FileHandle for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        self.recv_fds(buf, &mut Vec::new()) //随消息传来的描述符被关闭
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        self.send_fds(buf, &mut Vec::new())
+    }
+
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), FsError> {
+        self.nonblocking = nonblocking;
+        Ok(())
+    }
+
+    //流模式下写入能放下的部分；数据报模式下整条消息放不下时等待，超过 CAPACITY 的消息返回 InvalidArgument
+    fn send_fds(&mut self, buf: &[u8], fds: &mut Vec<Box<dyn FileHandle>>) -> Result<usize, FsError> {
+        if fds.len() > MAX_FDS || (self.kind == Kind::Datagram && buf.len() > CAPACITY) {
+            return Err(FsError::InvalidArgument);
+        }
+        if buf.is_empty() && fds.is_empty() && self.kind == Kind::Stream {
+            return Ok(0);
+        }
+        let kind = self.kind;
+        let tx = self.tx.clone();
+        self.wait(&tx, |channel| {
+            if channel.reader_closed {
+                return Some(Err(FsError::BrokenPipe));
+            }
+            let free = CAPACITY - channel.bytes;
+            let n = match kind {
+                Kind::Stream if free > 0 => buf.len().min(free),
+                Kind::Datagram if free >= buf.len() => buf.len(),
+                _ => return None,
+            };
+            channel.bytes += n;
+            channel.messages.push_back(Message { data: buf[..n].to_vec(), offset: 0, fds: core::mem::take(fds) });
+            Some(Ok(n))
+        })?
+    }
+
+    //对端关闭且没有数据时返回 0；收到的描述符追加到 fds
+    fn recv_fds(&mut self, buf: &mut [u8], fds: &mut Vec<Box<dyn FileHandle>>) -> Result<usize, FsError> {
+        let kind = self.kind;
+        let rx = self.rx.clone();
+        self.wait(&rx, |channel| {
+            if channel.messages.is_empty() {
+                return if channel.writer_closed { Some(0) } else { None };
+            }
+            let mut n = 0;
+            while let Some(message) = channel.messages.front_mut() {
+                fds.append(&mut message.fds);
+                let count = (buf.len() - n).min(message.data.len() - message.offset);
+                buf[n..n + count].copy_from_slice(&message.data[message.offset..message.offset + count]);
+                message.offset += count;
+                n += count;
+                channel.bytes -= count;
+                if kind == Kind::Datagram || message.offset == message.data.len() {
+                    let message = channel.messages.pop_front().unwrap();
+                    channel.bytes -= message.data.len() - message.offset; //数据报中放不下的部分被丢弃
+                }
+                if kind == Kind::Datagram || n == buf.len() {
+                    break;
+                }
+            }
+            Some(n)
+        })
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        self.rx.lock().reader_closed = true;
+        self.tx.lock().writer_closed = true;
+    }
+}
+
+//有名字的监听点，connect 建立的连接排队等待 accept
+struct Listener {
+    kind: Kind,
+    pending: VecDeque<Socket>,
+    closed: bool,
+}
+
+//VFS 中表示监听点的节点，不能 open，只能用 connect 连接；属于建立它的进程的用户
+struct SocketNode {
+    owner: Credentials,
+}
+
+impl Inode for SocketNode {
+    fn metadata(&self) -> Metadata {
+        Metadata { kind: InodeKind::Socket, size: 0, mtime: 0, mode: 0o777, uid: self.owner.uid, gid: self.owner.gid }
+    }
+
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError> {
+        Err(FsError::Unsupported)
+    }
+}
+
+//按规范化路径索引的全部监听点
+static LISTENERS: Mutex<BTreeMap<String, Arc<Mutex<Listener>>>> = Mutex::new(BTreeMap::new());
+
+pub struct ListenerHandle {
+    path: String,
+    listener: Arc<Mutex<Listener>>,
+    nonblocking: bool,
+}
+
+//在 path 上建立监听点，path 所在的目录必须支持 bind(ramfs)
+pub fn bind(path: &str, kind: Kind) -> Result<ListenerHandle, FsError> {
+    let path = vfs::normalize(path)?;
+    vfs::bind(&path, Arc::new(SocketNode { owner: cred::current() }))?;
+    let listener = Arc::new(Mutex::new(Listener { kind, pending: VecDeque::new(), closed: false }));
+    LISTENERS.lock().insert(path.clone(), listener.clone());
+    Ok(ListenerHandle { path, listener, nonblocking: false })
+}
+
+//连接 path 上的监听点；节点已被删除或监听的一端已经关闭时返回 ConnectionRefused
+pub fn connect(path: &str) -> Result<Socket, FsError> {
+    let path = vfs::normalize(path)?;
+    if vfs::metadata(&path)?.kind != InodeKind::Socket {
+        return Err(FsError::ConnectionRefused);
+    }
+    let listener = LISTENERS.lock().get(&path).cloned().ok_or(FsError::ConnectionRefused)?;
+    let mut listener = listener.lock();
+    if listener.closed || listener.pending.len() >= BACKLOG {
+        return Err(FsError::ConnectionRefused);
+    }
+    let (client, server) = pair(listener.kind);
+    listener.pending.push_back(server);
+    Ok(client)
+}
+
+impl FileHandle for ListenerHandle {
+    fn read(&mut self, _buf: &mut [u8]) -> Result<usize, FsError> {
+        Err(FsError::InvalidArgument) //监听点只能 accept
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, FsError> {
+        Err(FsError::InvalidArgument)
+    }
+
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), FsError> {
+        self.nonblocking = nonblocking;
+        Ok(())
+    }
+
+    fn accept(&mut self) -> Result<Box<dyn FileHandle>, FsError> {
+        loop {
+            if let Some(socket) = self.listener.lock().pending.pop_front() {
+                return Ok(Box::new(socket));
+            }
+            if self.nonblocking {
+                return Err(FsError::WouldBlock);
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+//关闭监听点时删除 VFS 中的节点，还没有 accept 的连接被关闭
+impl Drop for ListenerHandle {
+    fn drop(&mut self) {
+        let mut listener = self.listener.lock();
+        listener.closed = true;
+        listener.pending.clear();
+        drop(listener);
+        let mut listeners = LISTENERS.lock();
+        if listeners.get(&self.path).is_some_and(|l| Arc::ptr_eq(l, &self.listener)) {
+            listeners.remove(&self.path);
+            let _ = vfs::remove(&self.path);
+        }
+    }
+}
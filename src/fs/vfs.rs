@@ -0,0 +1,509 @@
+use super::{watch, DirEntry, FsError};
+use crate::audit::{self, Operation};
+use crate::block::{BlockError, SharedDevice};
+use crate::cred::{self, Credentials};
+use crate::failpoint;
+use crate::sync::Mutex;
+use crate::vm::Backing;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InodeKind {
+    File,
+    Directory,
+    Device,
+    Socket,  //本地套接字的监听点
+    Symlink, //符号链接，内容是另一个路径
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub kind: InodeKind,
+    pub size: u64,
+    pub mtime: u64, //最后修改时间，只用来判断文件是否变过，不同文件系统的单位不同(0 表示未知)
+    pub mode: u16,  //权限位，与 Unix 相同(0o755 等)，不含文件类型
+    pub uid: u32,
+    pub gid: u32,
+}
+
+//权限检查要求的访问方式，可以组合；数值与 mode 中每组的三位对应
+pub const MAY_READ: u16 = 4;
+pub const MAY_WRITE: u16 = 2;
+pub const MAY_EXEC: u16 = 1; //目录表示可以查找其中的名字
+
+const MAX_SYMLINKS: usize = 40; //查找一个路径时最多跟随的符号链接数，超过时认为链接成环
+
+impl Metadata {
+    //cred 能否以 want 的方式访问：所有者看所有者位，同组看组位，其他人看其他人位；root 总是可以
+    pub fn permits(&self, cred: Credentials, want: u16) -> bool {
+        if cred.is_root() {
+            return true;
+        }
+        let bits = if cred.uid == self.uid {
+            self.mode >> 6
+        } else if cred.gid == self.gid {
+            self.mode >> 3
+        } else {
+            self.mode
+        };
+        bits & want & 0o7 == want
+    }
+
+    //ls -l 显示的 "rwxr-xr-x"
+    pub fn mode_string(&self) -> String {
+        (0..9)
+            .map(|i| match self.mode & (0o400 >> i) != 0 {
+                true => ['r', 'w', 'x'][i % 3],
+                false => '-',
+            })
+            .collect()
+    }
+}
+
+fn check(meta: &Metadata, cred: Credentials, want: u16) -> Result<(), FsError> {
+    match meta.permits(cred, want) {
+        true => Ok(()),
+        false => Err(FsError::PermissionDenied),
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+//打开后的文件，每个句柄有自己的读写位置
+pub trait FileHandle: Send {
+    //从当前位置读取，返回读取的字节数，到达末尾时返回 0
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError>;
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn seek(&mut self, _pos: SeekFrom) -> Result<u64, FsError> {
+        Err(FsError::Unsupported)
+    }
+
+    //设置非阻塞模式：读写不能立即完成时返回 WouldBlock，而不是等待
+    //普通文件和块设备的读写从不等待，默认实现什么也不做
+    fn set_nonblocking(&mut self, _nonblocking: bool) -> Result<(), FsError> {
+        Ok(())
+    }
+
+    //本地套接字：发送数据并把 fds 中的描述符交给对端，成功时 fds 被取空
+    fn send_fds(&mut self, _buf: &[u8], _fds: &mut Vec<Box<dyn FileHandle>>) -> Result<usize, FsError> {
+        Err(FsError::Unsupported)
+    }
+
+    //本地套接字：接收数据，随数据传来的描述符追加到 fds
+    fn recv_fds(&mut self, _buf: &mut [u8], _fds: &mut Vec<Box<dyn FileHandle>>) -> Result<usize, FsError> {
+        Err(FsError::Unsupported)
+    }
+
+    //本地套接字的监听点：取出一个已经建立的连接
+    fn accept(&mut self) -> Result<Box<dyn FileHandle>, FsError> {
+        Err(FsError::Unsupported)
+    }
+
+    //共享内存：返回映射前 len 字节用的后备对象，映射后各进程看到同一批物理页面
+    fn map_shared(&mut self, _len: u64, _write: bool) -> Result<Backing, FsError> {
+        Err(FsError::Unsupported)
+    }
+
+    //把剩余内容全部读入堆上的缓冲区
+    fn read_to_end(&mut self) -> Result<Vec<u8>, FsError> {
+        let mut data = Vec::new();
+        let mut chunk = vec![0u8; 4096];
+        loop {
+            let n = self.read(&mut chunk)?;
+            if n == 0 {
+                return Ok(data);
+            }
+            data.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+//目录操作，只读文件系统不需要实现创建和删除
+pub trait Directory: Send + Sync {
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError>;
+
+    fn entries(&self) -> Result<Vec<DirEntry>, FsError>;
+
+    fn create_file(&self, _name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn create_dir(&self, _name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn remove(&self, _name: &str) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    //放入一个由内核创建的特殊节点(如套接字的监听点)，只有内存文件系统支持
+    fn bind(&self, _name: &str, _inode: Arc<dyn Inode>) -> Result<(), FsError> {
+        Err(FsError::Unsupported)
+    }
+
+    //硬链接：给同一个文件系统中已有的节点再加一个名字
+    fn link(&self, _name: &str, _inode: Arc<dyn Inode>) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn symlink(&self, _name: &str, _target: &str) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    //把名字 from 改成 to_dir 中的 to，要么完整地完成，要么什么也不改
+    //to 已经存在时被替换：目录只能替换空目录，其他节点不能替换目录
+    fn rename(&self, _from: &str, _to_dir: &dyn Directory, _to: &str) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    //rename 用来确认 to_dir 和自己属于同一种文件系统
+    fn as_any(&self) -> Option<&dyn Any> {
+        None
+    }
+}
+
+//文件系统中的一个对象：普通文件、目录、设备或符号链接
+pub trait Inode: Send + Sync {
+    fn metadata(&self) -> Metadata;
+
+    //打开文件或设备，目录返回 IsADirectory
+    fn open(&self) -> Result<Box<dyn FileHandle>, FsError>;
+
+    fn as_directory(&self) -> Option<&dyn Directory> {
+        None
+    }
+
+    //修改权限位和所有者，不能记录它们的文件系统返回 Unsupported
+    fn set_mode(&self, _mode: u16) -> Result<(), FsError> {
+        Err(FsError::Unsupported)
+    }
+
+    fn set_owner(&self, _uid: u32, _gid: u32) -> Result<(), FsError> {
+        Err(FsError::Unsupported)
+    }
+
+    //符号链接的内容，其他节点返回 InvalidArgument
+    fn read_link(&self) -> Result<String, FsError> {
+        Err(FsError::InvalidArgument)
+    }
+}
+
+struct Mount {
+    path: String, //规范化之后的挂载点路径
+    root: Arc<dyn Inode>,
+    device: Option<SharedDevice>, //文件系统所在的块设备，卸载时交给调用者写回缓存
+}
+
+static MOUNTS: Mutex<Vec<Mount>> = Mutex::new("MOUNTS", Vec::new());
+
+//把路径规范化为 "/a/b" 的形式，处理多余的 /、"." 和 ".."
+pub fn normalize(path: &str) -> Result<String, FsError> {
+    if !path.starts_with('/') {
+        return Err(FsError::InvalidPath);
+    }
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    let mut normalized = String::new();
+    for part in parts {
+        normalized.push('/');
+        normalized.push_str(part);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    Ok(normalized)
+}
+
+//挂载点 mount 是否覆盖了路径 path(按路径的每一级比较，/dev 不覆盖 /device)
+fn covers(mount: &str, path: &str) -> bool {
+    mount == "/" || path == mount || (path.starts_with(mount) && path.as_bytes()[mount.len()] == b'/')
+}
+
+//挂载和卸载都记入审计记录
+pub fn mount(path: &str, root: Arc<dyn Inode>) -> Result<(), FsError> {
+    let result = add_mount(path, root, None);
+    audit::record(Operation::Mount, path, result.is_ok());
+    result
+}
+
+//挂载块设备 device 上的文件系统
+pub fn mount_device(path: &str, root: Arc<dyn Inode>, device: SharedDevice) -> Result<(), FsError> {
+    let result = add_mount(path, root, Some(device));
+    audit::record(Operation::Mount, path, result.is_ok());
+    result
+}
+
+fn add_mount(path: &str, root: Arc<dyn Inode>, device: Option<SharedDevice>) -> Result<(), FsError> {
+    let path = normalize(path)?;
+    if root.as_directory().is_none() {
+        return Err(FsError::NotADirectory);
+    }
+    let mut mounts = MOUNTS.lock();
+    if mounts.iter().any(|m| m.path == path) {
+        return Err(FsError::AlreadyExists);
+    }
+    mounts.push(Mount { path, root, device });
+    Ok(())
+}
+
+//返回文件系统所在的块设备(用 mount_device 挂载时)
+pub fn unmount(path: &str) -> Result<Option<SharedDevice>, FsError> {
+    let result = remove_mount(path);
+    audit::record(Operation::Unmount, path, result.is_ok());
+    result
+}
+
+fn remove_mount(path: &str) -> Result<Option<SharedDevice>, FsError> {
+    let path = normalize(path)?;
+    let mut mounts = MOUNTS.lock();
+    let index = mounts.iter().position(|m| m.path == path).ok_or(FsError::NotFound)?;
+    Ok(mounts.remove(index).device)
+}
+
+//按最长匹配找到路径所在的挂载点，再从挂载点的根目录逐级查找，经过的每一级目录都要有查找权限
+//路径中的符号链接都会被跟随，包括最后一级
+pub fn resolve(path: &str) -> Result<Arc<dyn Inode>, FsError> {
+    resolve_as(path, cred::current())
+}
+
+fn resolve_as(path: &str, cred: Credentials) -> Result<Arc<dyn Inode>, FsError> {
+    walk(path, cred, true).map(|(inode, _)| inode)
+}
+
+fn find_mount(path: &str) -> Result<(String, Arc<dyn Inode>), FsError> {
+    let mounts = MOUNTS.lock();
+    let mount = mounts.iter().filter(|m| covers(&m.path, path)).max_by_key(|m| m.path.len()).ok_or(FsError::NotFound)?;
+    Ok((mount.path.clone(), mount.root.clone()))
+}
+
+//逐级查找 path，follow 为 false 时最后一级是符号链接也不跟随(lstat、readlink、删除和改名用)
+//遇到符号链接时把链接内容和剩余的部分拼成新路径从头查找，相对的链接相对于链接所在的目录
+//".." 在规范化时按字面处理，不会回到符号链接之前的目录
+//返回节点和不含符号链接的规范路径
+fn walk(path: &str, cred: Credentials, follow: bool) -> Result<(Arc<dyn Inode>, String), FsError> {
+    let mut path = normalize(path)?;
+    let mut links = 0;
+    'restart: loop {
+        let (mut walked, mut inode) = find_mount(&path)?;
+        let rest: Vec<String> = path[walked.len()..].split('/').filter(|p| !p.is_empty()).map(String::from).collect();
+        for (i, part) in rest.iter().enumerate() {
+            if !cred.is_root() {
+                check(&inode.metadata(), cred, MAY_EXEC)?;
+            }
+            let next = inode.as_directory().ok_or(FsError::NotADirectory)?.lookup(part)?;
+            if next.metadata().kind == InodeKind::Symlink && (follow || i + 1 < rest.len()) {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(FsError::TooManyLinks);
+                }
+                let target = next.read_link()?;
+                let base = if target.starts_with('/') { target } else { join(&walked, &target) };
+                path = normalize(&join(&base, &rest[i + 1..].join("/")))?;
+                continue 'restart;
+            }
+            walked = join(&walked, part);
+            inode = next;
+        }
+        return Ok((inode, walked));
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    match (dir.ends_with('/'), name.is_empty()) {
+        (_, true) => String::from(dir),
+        (true, false) => format!("{}{}", dir, name),
+        (false, false) => format!("{}/{}", dir, name),
+    }
+}
+
+//解析掉全部符号链接之后的路径
+pub fn canonicalize(path: &str) -> Result<String, FsError> {
+    walk(path, cred::current(), true).map(|(_, path)| path)
+}
+
+//把路径拆成父目录和最后一级的名字，在父目录中创建或删除名字需要写和查找权限
+//同时返回父目录的规范路径
+fn split_parent(path: &str) -> Result<(Arc<dyn Inode>, String, String), FsError> {
+    let path = normalize(path)?;
+    let index = path.rfind('/').unwrap();
+    let name = &path[index + 1..];
+    if name.is_empty() {
+        return Err(FsError::InvalidPath); //不能对根目录本身操作
+    }
+    let parent = if index == 0 { "/" } else { &path[..index] };
+    let cred = cred::current();
+    let (parent, parent_path) = walk(parent, cred, true)?;
+    check(&parent.metadata(), cred, MAY_WRITE | MAY_EXEC)?;
+    Ok((parent, String::from(name), parent_path))
+}
+
+pub fn metadata(path: &str) -> Result<Metadata, FsError> {
+    Ok(resolve(path)?.metadata())
+}
+
+//与 metadata 相同，但最后一级是符号链接时返回链接本身的信息
+pub fn link_metadata(path: &str) -> Result<Metadata, FsError> {
+    Ok(walk(path, cred::current(), false)?.0.metadata())
+}
+
+//打开需要读权限(句柄也可以写，目前只有以 root 身份运行的 shell 写文件)
+pub fn open(path: &str) -> Result<Box<dyn FileHandle>, FsError> {
+    failpoint!("vfs::open_io_error", FsError::Io(BlockError::DeviceError));
+    let inode = resolve(path)?;
+    check(&inode.metadata(), cred::current(), MAY_READ)?;
+    inode.open()
+}
+
+//cred 能否以 want 的方式访问 path，exec 用它检查程序文件
+pub fn access(path: &str, cred: Credentials, want: u16) -> Result<(), FsError> {
+    check(&resolve_as(path, cred)?.metadata(), cred, want)
+}
+
+//修改权限位，只有所有者和 root 可以
+pub fn chmod(path: &str, mode: u16) -> Result<(), FsError> {
+    let inode = resolve(path)?;
+    let cred = cred::current();
+    if !cred.is_root() && inode.metadata().uid != cred.uid {
+        return Err(FsError::PermissionDenied);
+    }
+    inode.set_mode(mode & 0o7777)?;
+    watch::notify(path);
+    Ok(())
+}
+
+//修改所有者，只有 root 可以
+pub fn chown(path: &str, uid: u32, gid: u32) -> Result<(), FsError> {
+    let result = if cred::current().is_root() {
+        resolve(path).and_then(|inode| inode.set_owner(uid, gid))
+    } else {
+        Err(FsError::PermissionDenied)
+    };
+    audit::record(Operation::Chown, &format!("{} {}:{}", path, uid, gid), result.is_ok());
+    result?;
+    watch::notify(path);
+    Ok(())
+}
+
+pub fn read_dir(path: &str) -> Result<Vec<DirEntry>, FsError> {
+    let inode = resolve(path)?;
+    let dir = inode.as_directory().ok_or(FsError::NotADirectory)?;
+    dir.entries()
+}
+
+//创建一个空文件并打开它
+pub fn create(path: &str) -> Result<Box<dyn FileHandle>, FsError> {
+    let (parent, name, _) = split_parent(path)?;
+    let dir = parent.as_directory().ok_or(FsError::NotADirectory)?;
+    let handle = dir.create_file(&name)?.open()?;
+    watch::notify(path);
+    Ok(handle)
+}
+
+pub fn mkdir(path: &str) -> Result<(), FsError> {
+    let (parent, name, _) = split_parent(path)?;
+    let dir = parent.as_directory().ok_or(FsError::NotADirectory)?;
+    dir.create_dir(&name)?;
+    watch::notify(path);
+    Ok(())
+}
+
+//删除文件或空目录
+pub fn remove(path: &str) -> Result<(), FsError> {
+    let (parent, name, _) = split_parent(path)?;
+    let dir = parent.as_directory().ok_or(FsError::NotADirectory)?;
+    dir.remove(&name)?;
+    watch::notify(path);
+    Ok(())
+}
+
+pub fn bind(path: &str, inode: Arc<dyn Inode>) -> Result<(), FsError> {
+    let (parent, name, _) = split_parent(path)?;
+    let dir = parent.as_directory().ok_or(FsError::NotADirectory)?;
+    dir.bind(&name, inode)?;
+    watch::notify(path);
+    Ok(())
+}
+
+//readlink：返回符号链接的内容
+pub fn read_link(path: &str) -> Result<String, FsError> {
+    walk(path, cred::current(), false)?.0.read_link()
+}
+
+pub fn symlink(target: &str, path: &str) -> Result<(), FsError> {
+    if target.is_empty() {
+        return Err(FsError::InvalidPath);
+    }
+    let (parent, name, _) = split_parent(path)?;
+    parent.as_directory().ok_or(FsError::NotADirectory)?.symlink(&name, target)?;
+    watch::notify(path);
+    Ok(())
+}
+
+//路径所在的挂载点，path 必须是规范路径
+fn mount_point(path: &str) -> Result<String, FsError> {
+    find_mount(path).map(|(mount, _)| mount)
+}
+
+//给已有的文件加一个名字 path，两者必须在同一个文件系统中；不能给目录建立硬链接
+//existing 是符号链接时链接的是符号链接本身
+pub fn link(existing: &str, path: &str) -> Result<(), FsError> {
+    let (inode, existing_path) = walk(existing, cred::current(), false)?;
+    if inode.metadata().kind == InodeKind::Directory {
+        return Err(FsError::PermissionDenied);
+    }
+    let (parent, name, parent_path) = split_parent(path)?;
+    if mount_point(&existing_path)? != mount_point(&join(&parent_path, &name))? {
+        return Err(FsError::CrossDevice);
+    }
+    parent.as_directory().ok_or(FsError::NotADirectory)?.link(&name, inode)?;
+    watch::notify(path);
+    Ok(())
+}
+
+//改名或移动，to 已经存在时被替换；不能跨文件系统，也不能把目录移到它自己里面
+pub fn rename(from: &str, to: &str) -> Result<(), FsError> {
+    let (from_parent, from_name, from_parent_path) = split_parent(from)?;
+    let (to_parent, to_name, to_parent_path) = split_parent(to)?;
+    let (from_path, to_path) = (join(&from_parent_path, &from_name), join(&to_parent_path, &to_name));
+    //挂载点本身不能改名
+    if mount_point(&from_path)? == from_path || mount_point(&from_path)? != mount_point(&to_path)? {
+        return Err(FsError::CrossDevice);
+    }
+    if from_path == to_path {
+        return Ok(());
+    }
+    let inside = format!("{}/", from_path);
+    if to_path.starts_with(&inside) || MOUNTS.lock().iter().any(|m| m.path.starts_with(&inside)) {
+        return Err(FsError::InvalidArgument); //移到自己里面，或者下面还有挂载点
+    }
+    let from_dir = from_parent.as_directory().ok_or(FsError::NotADirectory)?;
+    let to_dir = to_parent.as_directory().ok_or(FsError::NotADirectory)?;
+    from_dir.rename(&from_name, to_dir, &to_name)?;
+    watch::notify(from);
+    watch::notify(to);
+    Ok(())
+}
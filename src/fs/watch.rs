@@ -0,0 +1,18 @@
+//路径变化通知：通过 vfs 创建或删除文件、目录时，通知所有订阅者
+//只能发现经过路径的操作；通过已打开的句柄写入文件不会通知，需要时再比较 mtime
+use alloc::vec::Vec;
+use spin::Mutex;
+
+static WATCHERS: Mutex<Vec<fn(&str)>> = Mutex::new(Vec::new());
+
+//订阅变化，回调的参数是发生变化的路径(调用 vfs 时给出的原样)
+pub fn subscribe(callback: fn(&str)) {
+    WATCHERS.lock().push(callback);
+}
+
+pub fn notify(path: &str) {
+    let watchers = WATCHERS.lock().clone(); //回调里可能再次操作文件系统
+    for watcher in watchers {
+        watcher(path);
+    }
+}
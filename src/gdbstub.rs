@@ -0,0 +1,499 @@
+//GDB 远程调试：在第二个串口(COM2)上实现 GDB 远程串行协议，不依赖 QEMU 自带的 gdbstub 就能用 gdb 连接运行中的内核
+//  qemu ... -serial stdio -serial tcp::1234,server,nowait   然后   gdb kernel -ex "target remote :1234"
+//断点(int3)和单步(调试异常)进入 stub，在异常处理中轮询串口处理 gdb 的请求，直到 gdb 让内核继续运行
+//shell 空闲时检查串口，收到 gdb 的 Ctrl-C 时主动进入 stub；命令行参数 gdb 让内核启动后等待 gdb 连接
+//stub 运行时整个内核都停着，不要在 stub 自己和串口驱动里设断点
+//gdb 的 monitor 命令(qRcmd)由 stub 自己执行，见 monitor
+use crate::driver::{Driver, DriverError};
+use crate::drivers::uart::{self, Uart};
+use crate::interrupts::{TrapFrame, VECTOR_BREAKPOINT, VECTOR_DEBUG};
+use crate::line_editor::{Key, KeyDecoder, LineEditor, LineOutput};
+use crate::{memory, println, register_driver};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use x86_64::VirtAddr;
+
+const INT3: u8 = 0xCC;
+const TRAP_FLAG: u64 = 1 << 8;
+const SIGTRAP: u8 = 5;
+const PACKET_SIZE: usize = 0x1000;
+const MAX_BREAKPOINTS: usize = 64;
+const MONITOR_HISTORY: usize = 16;
+
+static PORT: Mutex<Option<Uart>> = Mutex::new(None);
+static CONNECTED: AtomicBool = AtomicBool::new(false); //收到过 gdb 的请求，之后每次停下都要报告
+static BREAKPOINTS: Mutex<Vec<(u64, u8)>> = Mutex::new(Vec::new()); //(地址, 被 int3 覆盖的原字节)
+static MONITOR: Mutex<Option<(LineEditor, KeyDecoder)>> = Mutex::new(None); //第一次使用 monitor 时创建
+
+//gdb 的 x86-64 寄存器编号：rax rbx rcx rdx rsi rdi rbp rsp r8..r15 rip 各 8 字节，eflags cs ss ds es fs gs 各 4 字节
+const REGISTER_COUNT: usize = 24;
+
+//编号为 n 的寄存器和它在协议中的字节数，ds..gs 在长模式下不用，读出 0，写入被忽略
+fn register(frame: &mut TrapFrame, n: usize) -> Option<(Option<&mut u64>, usize)> {
+    let reg = match n {
+        0 => &mut frame.rax,
+        1 => &mut frame.rbx,
+        2 => &mut frame.rcx,
+        3 => &mut frame.rdx,
+        4 => &mut frame.rsi,
+        5 => &mut frame.rdi,
+        6 => &mut frame.rbp,
+        7 => &mut frame.rsp,
+        8 => &mut frame.r8,
+        9 => &mut frame.r9,
+        10 => &mut frame.r10,
+        11 => &mut frame.r11,
+        12 => &mut frame.r12,
+        13 => &mut frame.r13,
+        14 => &mut frame.r14,
+        15 => &mut frame.r15,
+        16 => &mut frame.rip,
+        17 => return Some((Some(&mut frame.rflags), 4)),
+        18 => return Some((Some(&mut frame.cs), 4)),
+        19 => return Some((Some(&mut frame.ss), 4)),
+        20..=23 => return Some((None, 4)),
+        _ => return None,
+    };
+    Some((Some(reg), 8))
+}
+
+//shell 空闲时调用：gdb 发来 Ctrl-C(或者在内核运行时发来请求)时进入 stub
+//请求的开头已经被读走，gdb 收不到确认会重发
+pub fn idle() {
+    let port = match *PORT.lock() {
+        Some(port) => port,
+        None => return,
+    };
+    if let Some(0x03) | Some(b'$') = port.try_recv() {
+        breakpoint();
+    }
+}
+
+//在这里停下，等待 gdb
+pub fn breakpoint() {
+    x86_64::instructions::interrupts::int3();
+}
+
+pub fn available() -> bool {
+    PORT.lock().is_some()
+}
+
+//断点异常和调试异常
+pub fn handle_trap(frame: &mut TrapFrame) {
+    frame.rflags &= !TRAP_FLAG;
+    let port = match *PORT.lock() {
+        Some(port) if frame.cs & 3 == 0 => port,
+        _ => {
+            if frame.vector == VECTOR_BREAKPOINT {
+                println!("breakpoint at {:#x}, no debugger attached", frame.rip - 1);
+            }
+            return;
+        }
+    };
+    //停在 gdb 设置的断点上时，把 rip 退回到断点地址
+    let swbreak = frame.vector == VECTOR_BREAKPOINT && BREAKPOINTS.lock().iter().any(|&(addr, _)| addr == frame.rip - 1);
+    if swbreak {
+        frame.rip -= 1;
+    }
+    if CONNECTED.load(Ordering::Relaxed) {
+        send_packet(port, &stop_reply(swbreak));
+    } else if frame.vector == VECTOR_DEBUG {
+        return; //gdb 断开后残留的单步
+    } else {
+        println!("gdb: waiting for debugger on COM2");
+    }
+    Stub { port, frame }.serve(swbreak);
+}
+
+fn stop_reply(swbreak: bool) -> Vec<u8> {
+    if swbreak {
+        format!("T{:02x}swbreak:;", SIGTRAP).into_bytes()
+    } else {
+        format!("S{:02x}", SIGTRAP).into_bytes()
+    }
+}
+
+struct Stub<'a> {
+    port: Uart,
+    frame: &'a mut TrapFrame,
+}
+
+impl Stub<'_> {
+    //处理 gdb 的请求，直到 gdb 让内核继续运行
+    fn serve(&mut self, swbreak: bool) {
+        loop {
+            let packet = self.read_packet();
+            CONNECTED.store(true, Ordering::Relaxed);
+            let (command, args) = match packet.split_first() {
+                Some((&command, args)) => (command, args),
+                None => continue,
+            };
+            let reply = match command {
+                b'?' => stop_reply(swbreak),
+                b'g' => self.read_registers(),
+                b'G' => self.write_registers(args),
+                b'p' => self.read_register(args),
+                b'P' => self.write_register(args),
+                b'm' => read_memory(args),
+                b'M' => write_memory(args),
+                b'Z' => insert_breakpoint(args),
+                b'z' => remove_breakpoint(args),
+                b'c' | b's' => {
+                    if let Some(addr) = parse_hex(args) {
+                        self.frame.rip = addr;
+                    }
+                    if command == b's' {
+                        self.frame.rflags |= TRAP_FLAG;
+                    }
+                    return;
+                }
+                b'D' | b'k' => {
+                    remove_all_breakpoints();
+                    CONNECTED.store(false, Ordering::Relaxed);
+                    if command == b'D' {
+                        send_packet(self.port, b"OK");
+                    }
+                    return;
+                }
+                b'q' => match args.strip_prefix(b"Rcmd,") {
+                    Some(command) => monitor(self.port, command),
+                    None => query(args),
+                },
+                b'H' | b'T' => b"OK".to_vec(),
+                _ => Vec::new(), //空回复表示不支持
+            };
+            send_packet(self.port, &reply);
+        }
+    }
+
+    fn read_registers(&mut self) -> Vec<u8> {
+        let mut reply = Vec::new();
+        for n in 0..REGISTER_COUNT {
+            if let Some((reg, size)) = register(self.frame, n) {
+                push_hex(&mut reply, &reg.map_or(0, |reg| *reg).to_le_bytes()[..size]);
+            }
+        }
+        reply
+    }
+
+    fn write_registers(&mut self, args: &[u8]) -> Vec<u8> {
+        let bytes = match decode_hex(args) {
+            Some(bytes) => bytes,
+            None => return error(EINVAL),
+        };
+        let mut offset = 0;
+        for n in 0..REGISTER_COUNT {
+            let (reg, size) = match register(self.frame, n) {
+                Some(register) => register,
+                None => break,
+            };
+            let value = match bytes.get(offset..offset + size) {
+                Some(value) => value,
+                None => break,
+            };
+            if let Some(reg) = reg {
+                *reg = value.iter().rev().fold(0, |acc, &b| acc << 8 | b as u64);
+            }
+            offset += size;
+        }
+        b"OK".to_vec()
+    }
+
+    fn read_register(&mut self, args: &[u8]) -> Vec<u8> {
+        match parse_hex(args).and_then(|n| register(self.frame, n as usize)) {
+            Some((reg, size)) => {
+                let mut reply = Vec::new();
+                push_hex(&mut reply, &reg.map_or(0, |reg| *reg).to_le_bytes()[..size]);
+                reply
+            }
+            None => error(EINVAL),
+        }
+    }
+
+    //P n=value，value 是小端的十六进制
+    fn write_register(&mut self, args: &[u8]) -> Vec<u8> {
+        let (n, value) = match split(args, b'=') {
+            Some((n, value)) => (n, value),
+            None => return error(EINVAL),
+        };
+        let (register, value) = match (parse_hex(n), decode_hex(value)) {
+            (Some(n), Some(value)) => (register(self.frame, n as usize), value),
+            _ => return error(EINVAL),
+        };
+        match register {
+            Some((reg, size)) if value.len() == size => {
+                if let Some(reg) = reg {
+                    *reg = value.iter().rev().fold(0, |acc, &b| acc << 8 | b as u64);
+                }
+                b"OK".to_vec()
+            }
+            _ => error(EINVAL),
+        }
+    }
+
+    //$<数据>#<校验和>，校验和正确时回复 +，否则回复 - 让 gdb 重发
+    fn read_packet(&self) -> Vec<u8> {
+        loop {
+            while self.port.recv() != b'$' {}
+            let mut data = Vec::new();
+            let mut sum: u8 = 0;
+            loop {
+                match self.port.recv() {
+                    b'#' => break,
+                    byte => {
+                        sum = sum.wrapping_add(byte);
+                        if data.len() < PACKET_SIZE {
+                            data.push(byte);
+                        }
+                    }
+                }
+            }
+            let checksum = [self.port.recv(), self.port.recv()];
+            if parse_hex(&checksum) == Some(sum as u64) {
+                self.port.send(b'+');
+                return data;
+            }
+            self.port.send(b'-');
+        }
+    }
+}
+
+//发送后等待 gdb 确认，收到 - 时重发
+fn send_packet(port: Uart, data: &[u8]) {
+    let sum = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    loop {
+        port.send(b'$');
+        data.iter().for_each(|&b| port.send(b));
+        port.send(b'#');
+        format!("{:02x}", sum).bytes().for_each(|b| port.send(b));
+        loop {
+            match port.recv() {
+                b'+' => return,
+                b'-' => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+//错误码和 syscall 的一样，回复时取正数
+const EFAULT: u8 = 14;
+const EINVAL: u8 = 22;
+const ENOSPC: u8 = 28;
+
+fn error(code: u8) -> Vec<u8> {
+    format!("E{:02x}", code).into_bytes()
+}
+
+fn query(args: &[u8]) -> Vec<u8> {
+    if args.starts_with(b"Supported") {
+        return format!("PacketSize={:x};swbreak+", PACKET_SIZE).into_bytes();
+    }
+    match args {
+        b"Attached" => b"1".to_vec(),   //连到的是已经在运行的内核，gdb 断开时不要结束它
+        b"C" => b"QC1".to_vec(),        //只有一个线程
+        b"fThreadInfo" => b"m1".to_vec(),
+        b"sThreadInfo" => b"l".to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+//qRcmd,<十六进制的命令行>：命令行按终端字节交给行编辑器，和远程控制台一样可以用 Ctrl+U、Ctrl+W 等编辑，
+//执行过的命令记入历史；输出用 O 包发回，最后回复 OK
+fn monitor(port: Uart, args: &[u8]) -> Vec<u8> {
+    let bytes = match decode_hex(args) {
+        Some(bytes) => bytes,
+        None => return error(EINVAL),
+    };
+    let mut monitor = MONITOR.lock();
+    let (editor, decoder) = monitor.get_or_insert_with(|| (LineEditor::new(MONITOR_HISTORY), KeyDecoder::default()));
+    let mut line = editor.begin();
+    let mut command = String::new();
+    for key in bytes.iter().filter_map(|&b| decoder.decode(b)).chain(Some(Key::Enter)) {
+        if let Some(text) = editor.feed(&mut line, key, &mut Discard) {
+            command = text;
+            break;
+        }
+    }
+    let output = match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] | ["help"] => String::from("monitor commands: help, breakpoints, history\n"),
+        ["breakpoints"] => {
+            let breakpoints = BREAKPOINTS.lock();
+            if breakpoints.is_empty() {
+                String::from("no breakpoints\n")
+            } else {
+                breakpoints.iter().map(|&(addr, _)| format!("breakpoint at {:#x}\n", addr)).collect()
+            }
+        }
+        ["history"] => editor.history().iter().enumerate().map(|(i, entry)| format!("{:>3}  {}\n", i + 1, entry)).collect(),
+        [name, ..] => format!("unknown monitor command: {}\n", name),
+    };
+    for chunk in output.as_bytes().chunks((PACKET_SIZE - 1) / 2) {
+        let mut packet = vec![b'O'];
+        push_hex(&mut packet, chunk);
+        send_packet(port, &packet);
+    }
+    b"OK".to_vec()
+}
+
+//monitor 的回显由 gdb 自己处理，编辑器的输出直接丢掉
+struct Discard;
+
+impl LineOutput for Discard {
+    fn write_str(&mut self, _s: &str) {}
+    fn cursor_left(&mut self, _n: usize) {}
+    fn cursor_right(&mut self, _n: usize) {}
+    fn clear_to_end(&mut self) {}
+}
+
+//m addr,length
+fn read_memory(args: &[u8]) -> Vec<u8> {
+    let (addr, len) = match split(args, b',').and_then(|(addr, len)| Some((parse_hex(addr)?, parse_hex(len)?))) {
+        Some(range) => range,
+        None => return error(EINVAL),
+    };
+    let mut bytes = Vec::new();
+    for addr in addr..addr.saturating_add(len.min(PACKET_SIZE as u64 / 2)) {
+        match peek(addr) {
+            Some(byte) => bytes.push(byte),
+            None if bytes.is_empty() => return error(EFAULT),
+            None => break, //只能读出一部分时返回读到的部分
+        }
+    }
+    let mut reply = Vec::new();
+    push_hex(&mut reply, &bytes);
+    reply
+}
+
+//M addr,length:data
+fn write_memory(args: &[u8]) -> Vec<u8> {
+    let parsed = split(args, b',').and_then(|(addr, rest)| {
+        let (len, data) = split(rest, b':')?;
+        Some((parse_hex(addr)?, parse_hex(len)?, decode_hex(data)?))
+    });
+    let (addr, data) = match parsed {
+        Some((addr, len, data)) if data.len() as u64 == len => (addr, data),
+        _ => return error(EINVAL),
+    };
+    for (i, &byte) in data.iter().enumerate() {
+        if !poke(addr + i as u64, byte) {
+            return error(EFAULT);
+        }
+    }
+    b"OK".to_vec()
+}
+
+//Z0,addr,kind：只支持软件断点
+fn insert_breakpoint(args: &[u8]) -> Vec<u8> {
+    let addr = match breakpoint_addr(args) {
+        Some(addr) => addr,
+        None => return Vec::new(),
+    };
+    let mut breakpoints = BREAKPOINTS.lock();
+    if breakpoints.iter().any(|&(a, _)| a == addr) {
+        return b"OK".to_vec();
+    }
+    if breakpoints.len() >= MAX_BREAKPOINTS {
+        return error(ENOSPC);
+    }
+    match peek(addr) {
+        Some(original) if poke(addr, INT3) => {
+            breakpoints.push((addr, original));
+            b"OK".to_vec()
+        }
+        _ => error(EFAULT),
+    }
+}
+
+fn remove_breakpoint(args: &[u8]) -> Vec<u8> {
+    let addr = match breakpoint_addr(args) {
+        Some(addr) => addr,
+        None => return Vec::new(),
+    };
+    let mut breakpoints = BREAKPOINTS.lock();
+    if let Some(index) = breakpoints.iter().position(|&(a, _)| a == addr) {
+        let (addr, original) = breakpoints.remove(index);
+        poke(addr, original);
+    }
+    b"OK".to_vec()
+}
+
+fn remove_all_breakpoints() {
+    for (addr, original) in BREAKPOINTS.lock().drain(..) {
+        poke(addr, original);
+    }
+}
+
+fn breakpoint_addr(args: &[u8]) -> Option<u64> {
+    let rest = args.strip_prefix(b"0,")?;
+    let (addr, _kind) = split(rest, b',')?;
+    parse_hex(addr)
+}
+
+//通过物理内存的线性映射访问，没有映射的地址返回 None 而不是触发缺页异常
+fn peek(addr: u64) -> Option<u8> {
+    let alias = memory::linear_alias(VirtAddr::try_new(addr).ok()?)?;
+    Some(unsafe { *alias.as_ptr::<u8>() })
+}
+
+//代码页面是只读的，通过线性映射写入不受影响
+fn poke(addr: u64, byte: u8) -> bool {
+    let alias = match VirtAddr::try_new(addr).ok().and_then(memory::linear_alias) {
+        Some(alias) => alias,
+        None => return false,
+    };
+    unsafe { *alias.as_mut_ptr::<u8>() = byte };
+    true
+}
+
+fn split(bytes: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let index = bytes.iter().position(|&b| b == separator)?;
+    Some((&bytes[..index], &bytes[index + 1..]))
+}
+
+fn parse_hex(bytes: &[u8]) -> Option<u64> {
+    if bytes.is_empty() || bytes.len() > 16 {
+        return None;
+    }
+    bytes.iter().try_fold(0u64, |acc, &b| Some(acc << 4 | (b as char).to_digit(16)? as u64))
+}
+
+fn decode_hex(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    bytes.chunks_exact(2).map(|pair| parse_hex(pair).map(|b| b as u8)).collect()
+}
+
+fn push_hex(out: &mut Vec<u8>, bytes: &[u8]) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    for &b in bytes {
+        out.push(DIGITS[(b >> 4) as usize]);
+        out.push(DIGITS[(b & 0xF) as usize]);
+    }
+}
+
+struct GdbStubDriver;
+
+impl Driver for GdbStubDriver {
+    fn name(&self) -> &'static str {
+        "gdbstub"
+    }
+
+    fn probe(&self) -> bool {
+        Uart::new(uart::COM2).present()
+    }
+
+    fn init(&self) -> Result<String, DriverError> {
+        let port = Uart::new(uart::COM2);
+        port.init();
+        *PORT.lock() = Some(port);
+        Ok(String::from("COM2, 115200 baud"))
+    }
+}
+
+register_driver!(GDBSTUB_DRIVER, GdbStubDriver);
@@ -0,0 +1,120 @@
+//进程组(简化的 cgroup)：把进程分组，限制一组进程合计的常驻内存
+//组内的用量达到上限时，组里的进程再分配页面就失败，由 OOM 在组内选一个进程结束，不影响组外的进程
+//进程创建时加入父进程所在的组，由 shell 启动的进程在 root 组
+//CPU 份额只是记录下来：还没有抢占式调度器，进程总是运行到结束
+use crate::process;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+pub const ROOT: &str = "root";
+const DEFAULT_SHARES: u32 = 1024;
+const NAME_MAX: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupError {
+    NotFound,
+    AlreadyExists,
+    InvalidName,
+    Busy, //组里还有进程，或者试图删除 root 组
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub memory_pages: Option<u64>, //组内进程合计的常驻页面数上限，None 表示不限制
+    pub cpu_shares: u32,
+}
+
+//组的状态，/proc/groups 和 shell 显示用
+#[derive(Debug, Clone)]
+pub struct GroupInfo {
+    pub name: String,
+    pub limits: Limits,
+    pub memory_pages: u64, //组内进程当前合计的常驻页面数
+    pub pids: Vec<u64>,
+}
+
+//root 组不在表里，总是存在且没有限制
+static GROUPS: Mutex<BTreeMap<String, Limits>> = Mutex::new(BTreeMap::new());
+
+pub fn create(name: &str) -> Result<(), GroupError> {
+    if name.is_empty() || name.len() > NAME_MAX || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(GroupError::InvalidName);
+    }
+    let mut groups = GROUPS.lock();
+    if name == ROOT || groups.contains_key(name) {
+        return Err(GroupError::AlreadyExists);
+    }
+    groups.insert(String::from(name), Limits { memory_pages: None, cpu_shares: DEFAULT_SHARES });
+    Ok(())
+}
+
+//删除一个没有进程的组
+pub fn remove(name: &str) -> Result<(), GroupError> {
+    if name == ROOT {
+        return Err(GroupError::Busy);
+    }
+    if !GROUPS.lock().contains_key(name) {
+        return Err(GroupError::NotFound);
+    }
+    if process::list().iter().any(|info| info.group == name && info.state != process::State::Zombie) {
+        return Err(GroupError::Busy);
+    }
+    GROUPS.lock().remove(name);
+    Ok(())
+}
+
+pub fn exists(name: &str) -> bool {
+    name == ROOT || GROUPS.lock().contains_key(name)
+}
+
+pub fn set_limits(name: &str, limits: Limits) -> Result<(), GroupError> {
+    *GROUPS.lock().get_mut(name).ok_or(GroupError::NotFound)? = limits;
+    Ok(())
+}
+
+pub fn limits(name: &str) -> Option<Limits> {
+    if name == ROOT {
+        return Some(Limits { memory_pages: None, cpu_shares: DEFAULT_SHARES });
+    }
+    GROUPS.lock().get(name).copied()
+}
+
+//把进程移到另一个组
+pub fn attach(name: &str, pid: u64) -> Result<(), GroupError> {
+    if !exists(name) {
+        return Err(GroupError::NotFound);
+    }
+    process::set_group(pid, name).ok_or(GroupError::NotFound)
+}
+
+pub fn list() -> Vec<GroupInfo> {
+    let processes = process::list();
+    let mut names: Vec<String> = Vec::from([String::from(ROOT)]);
+    names.extend(GROUPS.lock().keys().cloned());
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let limits = limits(&name)?;
+            let members = processes.iter().filter(|info| info.group == name && info.state != process::State::Zombie);
+            let memory_pages = members.clone().map(|info| info.rss).sum();
+            let pids = members.map(|info| info.pid).collect();
+            Some(GroupInfo { name, limits, memory_pages, pids })
+        })
+        .collect()
+}
+
+//当前进程所在的组已经用到内存上限时返回组名，由缺页异常处理程序在分配页面前调用
+//进程表被锁住时无法统计，当作没有超限
+pub fn memory_exhausted() -> Option<String> {
+    let processes = process::try_list()?;
+    let group = processes.iter().find(|info| info.pid == process::current_pid())?.group.clone();
+    let limit = limits(&group)?.memory_pages?;
+    let used: u64 = processes.iter().filter(|info| info.group == group).map(|info| info.rss).sum();
+    if used >= limit {
+        Some(group)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,153 @@
+use crate::framebuffer;
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+//简单的消息目录：每条界面文字用 MsgId 标识，按当前语言查表得到字符串
+//VGA 文本模式只能显示 ASCII；屏幕显示不了的译文(文本模式，或者图形模式的字体里没有这些字形)退回英文
+//消息里的 {} 依次替换为 msg! 的参数，译文可以调整参数出现的位置，但个数和顺序要与英文一致
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Language {
+    English = 0,
+    Chinese = 1,
+}
+
+impl Language {
+    //根据启动参数中的语言名(如 lang=zh)查找对应语言
+    pub fn from_name(name: &str) -> Option<Language> {
+        match name {
+            "en" | "en_US" | "english" => Some(Language::English),
+            "zh" | "zh_CN" | "chinese" => Some(Language::Chinese),
+            _ => None,
+        }
+    }
+}
+
+//MsgId 和两个目录由同一张表生成，顺序和个数不会不一致
+macro_rules! messages {
+    ($($id:ident: $english:expr, $chinese:expr;)*) => {
+        #[allow(dead_code)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[repr(usize)]
+        pub enum MsgId {
+            $($id,)*
+        }
+
+        const MSG_COUNT: usize = [$(MsgId::$id),*].len();
+
+        static ENGLISH: [&str; MSG_COUNT] = [$($english),*];
+        static CHINESE: [&str; MSG_COUNT] = [$($chinese),*];
+    };
+}
+
+messages! {
+    Welcome: "Hello Joakim", "你好 Joakim";
+    KernelPanic: "KERNEL PANIC", "内核崩溃";
+    AtaDetected: "ATA drive detected", "检测到 ATA 硬盘";
+    ShellHelp: "Available commands:", "可用命令：";
+    //启动过程的状态行
+    TscFrequency: "tsc: {} MHz", "tsc：{} MHz";
+    TscInvariant: "tsc: {} MHz (invariant)", "tsc：{} MHz(频率恒定)";
+    RngSeeded: "rng: seeded from {}", "rng：种子来自 {}";
+    AcpiS5Found: "acpi: revision {}, S5 found", "acpi：版本 {}，找到 S5";
+    AcpiS5Missing: "acpi: revision {}, S5 missing", "acpi：版本 {}，没有 S5";
+    AcpiNone: "acpi: no tables, using emulator power-off ports", "acpi：没有 ACPI 表，使用模拟器的关机端口";
+    Cmdline: "cmdline: {}", "命令行：{}";
+    UnknownKeymap: "cmdline: unknown keymap {}", "命令行：未知的键盘布局 {}";
+    UnknownLanguage: "cmdline: unknown language {}", "命令行：未知的语言 {}";
+    UnknownBellMode: "cmdline: unknown bell mode {}", "命令行：未知的响铃方式 {}";
+    InvalidIsolcpus: "cmdline: invalid isolcpus {}", "命令行：isolcpus 不合法：{}";
+    InvalidNetqueues: "cmdline: invalid netqueues {}", "命令行：netqueues 不合法：{}";
+    ResourcesInstalled: "resources: installed {} embedded files", "resources：安装了 {} 个内嵌文件";
+    ResourcesFailed: "resources: installing files failed: {}", "resources：安装文件失败：{}";
+    FwCfgCopied: "fw_cfg: copied {} files into /", "fw_cfg：复制了 {} 个文件到 /";
+    FwCfgFailed: "fw_cfg: copying files failed: {}", "fw_cfg：复制文件失败：{}";
+    Mounted: "mounted {} (fat32) on {}", "已挂载 {}(fat32)到 {}";
+    RconsoleFailed: "rconsole: {}", "rconsole：{}";
+}
+
+static LANGUAGE: AtomicU8 = AtomicU8::new(Language::English as u8);
+
+pub fn set_language(lang: Language) {
+    LANGUAGE.store(lang as u8, Ordering::Relaxed);
+}
+
+pub fn language() -> Language {
+    match LANGUAGE.load(Ordering::Relaxed) {
+        1 => Language::Chinese,
+        _ => Language::English,
+    }
+}
+
+pub fn lookup(id: MsgId) -> &'static str {
+    let text = match language() {
+        Language::English => ENGLISH[id as usize],
+        Language::Chinese => CHINESE[id as usize],
+    };
+    if framebuffer::can_display(text) {
+        text
+    } else {
+        ENGLISH[id as usize]
+    }
+}
+
+//带参数的消息，显示时把模板里的 {} 依次替换为参数；参数不够时原样保留 {}
+pub struct Formatted<'a> {
+    template: &'static str,
+    args: &'a [&'a dyn fmt::Display],
+}
+
+impl<'a> Formatted<'a> {
+    pub fn new(template: &'static str, args: &'a [&'a dyn fmt::Display]) -> Formatted<'a> {
+        Formatted { template, args }
+    }
+}
+
+impl fmt::Display for Formatted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut args = self.args.iter();
+        let mut parts = self.template.split("{}");
+        f.write_str(parts.next().unwrap_or(""))?;
+        for part in parts {
+            match args.next() {
+                Some(arg) => arg.fmt(f)?,
+                None => f.write_str("{}")?,
+            }
+            f.write_str(part)?;
+        }
+        Ok(())
+    }
+}
+
+#[macro_export]
+macro_rules! msg { //msg!(Welcome) 返回当前语言下的消息文字，msg!(Mounted, name, path) 返回填好参数的消息
+    ($id:ident) => ($crate::i18n::lookup($crate::i18n::MsgId::$id));
+    ($id:ident, $($arg:expr),+ $(,)?) => (
+        $crate::i18n::Formatted::new($crate::i18n::lookup($crate::i18n::MsgId::$id), &[$(&$arg as &dyn core::fmt::Display),+])
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn fills_placeholders_in_order() {
+        let text = format!("{}", Formatted::new("mounted {} (fat32) on {}", &[&"hda", &"/boot"]));
+        assert_eq!(text, "mounted hda (fat32) on /boot");
+    }
+
+    #[test]
+    fn keeps_extra_placeholders() {
+        assert_eq!(format!("{}", Formatted::new("{} and {}", &[&1])), "1 and {}");
+    }
+
+    #[test]
+    fn translations_have_same_placeholders() {
+        for (english, chinese) in ENGLISH.iter().zip(CHINESE.iter()) {
+            assert_eq!(english.matches("{}").count(), chinese.matches("{}").count(), "{}", english);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//中断描述符表：处理缺页异常(用来实现按需分页)，断点和调试异常交给 gdbstub
+//外部中断只打开了 PIT 的时钟中断(IRQ 0)，用于看门狗；其余 IRQ 都被屏蔽，设备仍然轮询
+//用户程序和系统调用运行时中断是关闭的，其他异常仍然会导致三重错误
+use crate::{bell, coredump, gdbstub, latency, println, process, signal, stats, usermode, vm, watchdog};
+use core::arch::global_asm;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+use x86_64::instructions::port::Port;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::VirtAddr;
+
+pub const VECTOR_DEBUG: u64 = 1;
+pub const VECTOR_BREAKPOINT: u64 = 3;
+pub const VECTOR_TIMER: u64 = PIC_OFFSET as u64;
+
+//两片 8259 PIC 的 IRQ 0..15 映射到向量 32..47，避开 CPU 异常
+const PIC_OFFSET: u8 = 32;
+const PIC1_COMMAND: u16 = 0x20;
+const PIC1_DATA: u16 = 0x21;
+const PIC2_COMMAND: u16 = 0xA0;
+const PIC2_DATA: u16 = 0xA1;
+const PIC_EOI: u8 = 0x20;
+
+const PIT_HZ: u32 = 1_193_182;
+const PIT_CHANNEL0: u16 = 0x40;
+const PIT_COMMAND: u16 = 0x43;
+pub const TIMER_HZ: u32 = 100;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+//正在执行的中断/异常处理函数的嵌套层数，service 据此判断 panic 是否发生在中断上下文
+static HANDLER_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+global_asm!(
+    //需要读写全部通用寄存器的入口(gdbstub、看门狗)不能用 x86-interrupt 函数
+    //CPU 已经压入 ss、rsp、rflags、cs、rip(这几个向量都没有错误码)，再压入向量号和通用寄存器，布局就是 TrapFrame
+    "trap_debug:",
+    "push 1",
+    "jmp trap_common",
+    "trap_breakpoint:",
+    "push 3",
+    "jmp trap_common",
+    "trap_timer:",
+    "push 32",
+    "jmp trap_common",
+    "trap_common:",
+    "push rax",
+    "push rbx",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push rbp",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov rdi, rsp",
+    "sub rsp, 8", //21 个 8 字节之后补齐 16 字节对齐
+    "call trap_dispatch",
+    "add rsp, 8",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rbp",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rbx",
+    "pop rax",
+    "add rsp, 8", //向量号
+    "iretq",
+);
+
+extern "C" {
+    fn trap_debug();
+    fn trap_breakpoint();
+    fn trap_timer();
+}
+
+//trap_common 保存在栈上的寄存器，处理函数可以修改，返回时写回
+#[repr(C)]
+#[derive(Debug)]
+pub struct TrapFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub vector: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+fn entry(handler: unsafe extern "C" fn()) -> VirtAddr {
+    VirtAddr::new(handler as usize as u64)
+}
+
+lazy_static! {
+    static ref IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(page_fault_handler);
+        unsafe {
+            idt.debug.set_handler_addr(entry(trap_debug));
+            idt.breakpoint.set_handler_addr(entry(trap_breakpoint));
+            idt[PIC_OFFSET as usize].set_handler_addr(entry(trap_timer));
+        }
+        //其余 IRQ 都被屏蔽，只可能以伪中断(IRQ 7/15)的形式出现
+        for vector in PIC_OFFSET as usize + 1..PIC_OFFSET as usize + 16 {
+            idt[vector].set_handler_fn(spurious_irq_handler);
+        }
+        idt[PIC_OFFSET as usize + 15].set_handler_fn(spurious_slave_irq_handler);
+        idt
+    };
+}
+
+pub fn init() {
+    IDT.load();
+}
+
+//当前是否在中断/异常处理函数里
+pub fn in_handler() -> bool {
+    HANDLER_DEPTH.load(Ordering::SeqCst) != 0
+}
+
+//进入处理函数时创建，离开时(包括提前返回)减少嵌套层数
+struct HandlerGuard;
+
+impl HandlerGuard {
+    fn enter() -> Self {
+        HANDLER_DEPTH.fetch_add(1, Ordering::SeqCst);
+        HandlerGuard
+    }
+}
+
+impl Drop for HandlerGuard {
+    fn drop(&mut self) {
+        HANDLER_DEPTH.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[no_mangle]
+extern "C" fn trap_dispatch(frame: &mut TrapFrame) {
+    let _handler = HandlerGuard::enter();
+    stats::count_interrupt(frame.vector as u8);
+    crate::trace!(Irq, frame.vector, frame.rip);
+    match frame.vector {
+        VECTOR_TIMER => timer_interrupt(frame),
+        _ => gdbstub::handle_trap(frame),
+    }
+}
+
+//重新映射 PIC，只打开 IRQ 0；PIT 通道 0 以 TIMER_HZ 产生时钟中断，然后打开中断
+pub fn init_timer() {
+    unsafe {
+        let mut pic1_command = Port::<u8>::new(PIC1_COMMAND);
+        let mut pic1_data = Port::<u8>::new(PIC1_DATA);
+        let mut pic2_command = Port::<u8>::new(PIC2_COMMAND);
+        let mut pic2_data = Port::<u8>::new(PIC2_DATA);
+        pic1_command.write(0x11); //ICW1：边沿触发、级联、需要 ICW4
+        pic2_command.write(0x11);
+        pic1_data.write(PIC_OFFSET); //ICW2：起始向量号
+        pic2_data.write(PIC_OFFSET + 8);
+        pic1_data.write(4); //ICW3：从片接在主片的 IRQ 2
+        pic2_data.write(2);
+        pic1_data.write(0x01); //ICW4：8086 模式
+        pic2_data.write(0x01);
+        pic1_data.write(0xFE); //只打开 IRQ 0
+        pic2_data.write(0xFF);
+
+        let divisor = (PIT_HZ / TIMER_HZ) as u16;
+        Port::<u8>::new(PIT_COMMAND).write(0x36); //通道 0，先低后高字节，模式 3(方波)
+        Port::<u8>::new(PIT_CHANNEL0).write(divisor as u8);
+        Port::<u8>::new(PIT_CHANNEL0).write((divisor >> 8) as u8);
+    }
+    x86_64::instructions::interrupts::enable();
+}
+
+//启动以来的时钟中断次数
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+//时钟中断里只用原子变量，不加锁：被打断的代码可能正持有任何一把锁
+fn timer_interrupt(frame: &mut TrapFrame) {
+    let _timer = latency::IrqTimer::start();
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    bell::tick(now);
+    watchdog::tick(frame);
+    unsafe { Port::<u8>::new(PIC1_COMMAND).write(PIC_EOI) };
+}
+
+//主片的伪中断不发送 EOI
+extern "x86-interrupt" fn spurious_irq_handler(_frame: InterruptStackFrame) {
+    stats::count_interrupt(PIC_OFFSET + 7);
+}
+
+//从片的伪中断：从片不发送 EOI，但主片以为 IRQ 2 是真的中断，要给主片发送
+extern "x86-interrupt" fn spurious_slave_irq_handler(_frame: InterruptStackFrame) {
+    stats::count_interrupt(PIC_OFFSET + 15);
+    unsafe { Port::<u8>::new(PIC1_COMMAND).write(PIC_EOI) };
+}
+
+//缺页异常：访问的是 mmap 建立的区域时分配页面后返回，重新执行出错的指令
+//否则用户程序被结束，内核自己出错时 panic
+extern "x86-interrupt" fn page_fault_handler(frame: InterruptStackFrame, code: PageFaultErrorCode) {
+    stats::count_interrupt(14); //缺页异常的向量号
+    let handler = HandlerGuard::enter();
+    let _timer = latency::IrqTimer::start();
+    let addr = Cr2::read();
+    crate::trace!(Fault, addr.as_u64(), code.bits());
+    if vm::handle_fault(addr, code.contains(PageFaultErrorCode::CAUSED_BY_WRITE)) {
+        return;
+    }
+    if code.contains(PageFaultErrorCode::USER_MODE) {
+        let pid = process::current_pid();
+        println!("pid {}: page fault at {:#x} ({:?}), killed", pid, addr.as_u64(), code);
+        let context = coredump::fault_context(frame.instruction_pointer.as_u64(), frame.stack_pointer.as_u64(), frame.cpu_flags);
+        coredump::write(pid, signal::SIGSEGV, &context, 0);
+        drop(handler); //exit 不会返回，回到内核之前就不算在处理函数里了
+        usermode::exit(process::SEGFAULT_EXIT_CODE);
+    }
+    panic!("page fault at {:#x} ({:?})\n{:#?}", addr.as_u64(), code, frame);
+}
@@ -0,0 +1,115 @@
+//相同页面合并：空闲时扫描各进程的私有页面，内容完全相同的页面改成共享同一个帧(写时复制)，多余的帧被回收
+//主要的收获是全零的页面(bss、刚 mmap 还没写过的匿名内存)和分别读进来的同一个程序的代码
+//页面先按内容的散列值分组，散列相同时再逐字节比较，确认相同才合并
+use crate::memory;
+use crate::process;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use crate::time::Instant;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use spin::Mutex;
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::PhysFrame;
+
+const PAGE_SIZE: u64 = 4096;
+const SCAN_INTERVAL: Duration = Duration::from_secs(1); //两次空闲扫描之间至少间隔的时间
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static LAST_SCAN: Mutex<Option<Instant>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub scans: u64,   //完成的扫描次数
+    pub merged: u64,  //累计合并的页面数
+    pub scanned: u64, //上一次扫描检查的私有页面数
+    pub frames: u64,  //上一次扫描时这些页面实际占用的帧数
+    pub zero: u64,    //上一次扫描时映射全零帧的页面数
+}
+
+impl Stats {
+    //共享帧省下的内存(包括 fork 共享的页面)
+    pub fn saved_bytes(&self) -> u64 {
+        (self.scanned - self.frames) * PAGE_SIZE
+    }
+}
+
+static STATS: Mutex<Stats> = Mutex::new(Stats { scans: 0, merged: 0, scanned: 0, frames: 0, zero: 0 });
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn stats() -> Stats {
+    *STATS.lock()
+}
+
+//shell 等待输入时调用：启用且距上次扫描足够久时扫描一次
+pub fn idle() {
+    let due = LAST_SCAN.lock().is_none_or(|last| last.elapsed() >= SCAN_INTERVAL);
+    if enabled() && due {
+        scan();
+    }
+}
+
+fn page(frame: PhysFrame) -> &'static [u64] {
+    let ptr = memory::phys_to_virt(frame.start_address()).as_ptr::<u64>();
+    unsafe { core::slice::from_raw_parts(ptr, PAGE_SIZE as usize / 8) }
+}
+
+//FNV-1a，按 8 字节一组计算
+fn hash(frame: PhysFrame) -> u64 {
+    page(frame).iter().fold(0xcbf2_9ce4_8422_2325, |h, &word| (h ^ word).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+//已经见过的一个帧和第一个映射它的页表项，之后的相同页面都合并到它上面
+struct Canonical {
+    frame: PhysFrame,
+    entry: *mut PageTableEntry,
+    shared: bool, //已经有页面合并进来，entry 已改成写时复制
+}
+
+//扫描所有空闲进程的地址空间并合并相同的页面，返回这次合并的页面数
+//只能在没有用户程序运行时调用(地址空间都不是当前的，页表不会在扫描中途改变)
+pub fn scan() -> u64 {
+    let mut groups: BTreeMap<u64, Vec<Canonical>> = BTreeMap::new();
+    let mut frames = BTreeSet::<PhysFrame>::new();
+    let (mut merged, mut scanned, mut zero) = (0, 0, 0);
+    for space in process::idle_address_spaces() {
+        memory::for_each_private_page(space, &mut |entry| {
+            scanned += 1;
+            let frame = PhysFrame::containing_address(entry.addr());
+            if page(frame).iter().all(|&word| word == 0) {
+                zero += 1;
+            }
+            let hash = hash(frame);
+            let group = groups.entry(hash).or_default();
+            match group.iter_mut().find(|c| c.frame == frame || page(c.frame) == page(frame)) {
+                Some(c) if c.frame == frame => {} //fork 之后已经共享的页面
+                Some(c) => {
+                    if !c.shared {
+                        memory::make_cow(unsafe { &mut *c.entry });
+                        c.shared = true;
+                    }
+                    memory::remap_shared(entry, c.frame);
+                    merged += 1;
+                }
+                None => group.push(Canonical { frame, entry: entry as *mut PageTableEntry, shared: false }),
+            }
+            frames.insert(PhysFrame::containing_address(entry.addr()));
+        });
+    }
+    x86_64::instructions::tlb::flush_all();
+    *LAST_SCAN.lock() = Some(Instant::now());
+    let mut stats = STATS.lock();
+    stats.scans += 1;
+    stats.merged += merged;
+    stats.scanned = scanned;
+    stats.frames = frames.len() as u64;
+    stats.zero = zero;
+    merged
+}
@@ -0,0 +1,101 @@
+//延迟直方图：进程从就绪到开始运行要等多久(运行队列延迟)，异常处理函数从进入到处理完要多久
+//用来比较调度和控制台的改动对尾延迟的影响，shell 的 latency report 打印各个百分位
+//按 HDR 直方图的方式分桶：每个 2 的幂区间再等分成 SUB_BUCKETS 份，相对误差不超过 1/SUB_BUCKETS，记录时不需要分配内存也不用加锁
+use crate::time::Instant;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+const SUB_BITS: u32 = 3;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+//纳秒数的直方图
+pub struct Histogram {
+    counts: [AtomicU64; BUCKETS],
+    count: AtomicU64,
+    max: AtomicU64,
+}
+
+//小于 SUB_BUCKETS 的值各占一个桶，更大的值按最高位所在的 2 的幂区间和其后 SUB_BITS 位分桶
+fn bucket(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let exponent = 63 - value.leading_zeros();
+    let sub = (value >> (exponent - SUB_BITS)) as usize & (SUB_BUCKETS - 1);
+    (exponent - SUB_BITS + 1) as usize * SUB_BUCKETS + sub
+}
+
+//桶中最大的值，报告百分位时用它(偏保守)
+fn bucket_high(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let exponent = (index / SUB_BUCKETS) as u32 + SUB_BITS - 1;
+    let sub = (index % SUB_BUCKETS) as u64;
+    let width = 1u64 << (exponent - SUB_BITS);
+    ((SUB_BUCKETS as u64 + sub) * width).saturating_add(width - 1)
+}
+
+impl Histogram {
+    pub const fn new() -> Histogram {
+        Histogram { counts: [const { AtomicU64::new(0) }; BUCKETS], count: AtomicU64::new(0), max: AtomicU64::new(0) }
+    }
+
+    pub fn record(&self, duration: Duration) {
+        let nanos = duration.as_nanos().min(u64::MAX as u128) as u64;
+        self.counts[bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.max.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.counts.iter().for_each(|count| count.store(0, Ordering::Relaxed));
+        self.count.store(0, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max.load(Ordering::Relaxed))
+    }
+
+    //至少 percent% 的记录不超过的值，没有记录时为 0
+    pub fn percentile(&self, percent: f64) -> Duration {
+        let total = self.count();
+        let target = ((total as f64 * percent / 100.0) as u64).clamp(1, total.max(1));
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count.load(Ordering::Relaxed);
+            if seen >= target && total > 0 {
+                return Duration::from_nanos(bucket_high(index).min(self.max.load(Ordering::Relaxed)));
+            }
+        }
+        Duration::ZERO
+    }
+}
+
+//进程变成就绪到开始运行
+pub static RUNQUEUE: Histogram = Histogram::new();
+//异常处理函数从入口到返回(不包括结束进程或 panic 的情况)
+pub static IRQ: Histogram = Histogram::new();
+
+pub static HISTOGRAMS: &[(&str, &Histogram)] = &[("runqueue", &RUNQUEUE), ("irq", &IRQ)];
+
+//在处理函数开头创建，离开作用域时把经过的时间记入 IRQ
+pub struct IrqTimer(Instant);
+
+impl IrqTimer {
+    pub fn start() -> IrqTimer {
+        IrqTimer(Instant::now())
+    }
+}
+
+impl Drop for IrqTimer {
+    fn drop(&mut self) {
+        IRQ.record(self.0.elapsed());
+    }
+}
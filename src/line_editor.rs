@@ -0,0 +1,422 @@
+//可复用的行编辑器：光标移动、历史记录、剪切环(kill ring)
+//输入和输出都通过 trait 注入，键盘+VGA、串口终端或测试桩都可以驱动同一份编辑逻辑
+//终端发来的原始字节(远程控制台、gdb 的 monitor 命令)先经过 KeyDecoder 翻译成按键
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,        //Ctrl+A
+    End,         //Ctrl+E
+    Up,          //上一条历史
+    Down,        //下一条历史
+    KillToEnd,   //Ctrl+K 剪切光标到行尾
+    KillToStart, //Ctrl+U 剪切行首到光标
+    KillWord,    //Ctrl+W 剪切光标前的一个单词
+    Yank,        //Ctrl+Y 粘贴最近一次剪切的内容
+    Other,
+}
+
+pub trait KeySource {
+    fn read_key(&mut self) -> Key; //阻塞直到得到一个按键
+}
+
+//编辑器只要求输出端能写字符、左右移动光标、清除光标之后的内容
+pub trait LineOutput {
+    fn write_str(&mut self, s: &str);
+    fn cursor_left(&mut self, n: usize);
+    fn cursor_right(&mut self, n: usize);
+    fn clear_to_end(&mut self);
+}
+
+const KILL_RING_SIZE: usize = 8;
+
+pub struct LineEditor {
+    history: Vec<String>,
+    history_limit: usize,
+    kill_ring: Vec<String>, //最新剪切的内容在末尾
+}
+
+//正在编辑的一行；read_line 内部使用，输入不能阻塞等待时(如远程控制台)由调用者保存，每来一个按键调用一次 feed
+pub struct LineState {
+    chars: Vec<char>,
+    cursor: usize,
+    history_index: usize, //等于历史记录条数表示正在编辑新的一行
+    draft: String,        //浏览历史前正在编辑的内容
+}
+
+impl LineState {
+    pub fn char_count(&self) -> usize {
+        self.chars.len()
+    }
+
+    //从光标处重画到行尾，然后把光标移回原位置
+    fn redraw_tail(&mut self, out: &mut dyn LineOutput) {
+        let tail: String = self.chars[self.cursor..].iter().collect();
+        out.write_str(&tail);
+        out.clear_to_end();
+        out.cursor_left(self.chars.len() - self.cursor);
+    }
+
+    fn insert(&mut self, text: &str, out: &mut dyn LineOutput) {
+        let count = text.chars().count();
+        for (i, c) in text.chars().enumerate() {
+            self.chars.insert(self.cursor + i, c);
+        }
+        let inserted: String = self.chars[self.cursor..self.cursor + count].iter().collect();
+        out.write_str(&inserted);
+        self.cursor += count;
+        self.redraw_tail(out);
+    }
+
+    //删除 [start, end) 区间的字符并返回被删除的文本
+    fn remove(&mut self, start: usize, end: usize, out: &mut dyn LineOutput) -> String {
+        self.move_to(start, out);
+        let removed: String = self.chars.drain(start..end).collect();
+        self.redraw_tail(out);
+        removed
+    }
+
+    fn move_to(&mut self, pos: usize, out: &mut dyn LineOutput) {
+        if pos < self.cursor {
+            out.cursor_left(self.cursor - pos);
+        } else {
+            out.cursor_right(pos - self.cursor);
+        }
+        self.cursor = pos;
+    }
+
+    //整行替换(切换历史记录时使用)
+    fn replace(&mut self, text: &str, out: &mut dyn LineOutput) {
+        self.move_to(0, out);
+        out.clear_to_end();
+        self.chars = text.chars().collect();
+        out.write_str(text);
+        self.cursor = self.chars.len();
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+}
+
+impl LineEditor {
+    pub fn new(history_limit: usize) -> LineEditor {
+        LineEditor {
+            history: Vec::new(),
+            history_limit,
+            kill_ring: Vec::new(),
+        }
+    }
+
+    fn kill(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        if self.kill_ring.len() == KILL_RING_SIZE {
+            self.kill_ring.remove(0);
+        }
+        self.kill_ring.push(text);
+    }
+
+    fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        if self.history.len() == self.history_limit {
+            self.history.remove(0);
+        }
+        self.history.push(String::from(line));
+    }
+
+    //历史记录，最早的在前
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    //开始编辑新的一行
+    pub fn begin(&self) -> LineState {
+        LineState { chars: Vec::new(), cursor: 0, history_index: self.history.len(), draft: String::new() }
+    }
+
+    //读取一行输入，回车后返回内容(不含换行)，非空的行会加入历史记录
+    pub fn read_line(&mut self, input: &mut dyn KeySource, out: &mut dyn LineOutput) -> String {
+        let mut line = self.begin();
+        loop {
+            if let Some(text) = self.feed(&mut line, input.read_key(), out) {
+                return text;
+            }
+        }
+    }
+
+    //处理一个按键，回车时返回这一行的内容并把 line 重置为新的一行
+    pub fn feed(&mut self, line: &mut LineState, key: Key, out: &mut dyn LineOutput) -> Option<String> {
+        match key {
+            Key::Enter => {
+                line.move_to(line.chars.len(), out);
+                out.write_str("\n");
+                let text = line.text();
+                self.add_history(&text);
+                *line = self.begin();
+                return Some(text);
+            }
+            Key::Char(c) => {
+                let mut buf = [0u8; 4];
+                line.insert(c.encode_utf8(&mut buf), out);
+            }
+            Key::Backspace if line.cursor > 0 => {
+                line.remove(line.cursor - 1, line.cursor, out);
+            }
+            Key::Delete if line.cursor < line.chars.len() => {
+                line.remove(line.cursor, line.cursor + 1, out);
+            }
+            Key::Left if line.cursor > 0 => line.move_to(line.cursor - 1, out),
+            Key::Right if line.cursor < line.chars.len() => line.move_to(line.cursor + 1, out),
+            Key::Home => line.move_to(0, out),
+            Key::End => line.move_to(line.chars.len(), out),
+            Key::Up if line.history_index > 0 => {
+                if line.history_index == self.history.len() {
+                    line.draft = line.text();
+                }
+                line.history_index -= 1;
+                line.replace(&self.history[line.history_index], out);
+            }
+            Key::Down if line.history_index < self.history.len() => {
+                line.history_index += 1;
+                match self.history.get(line.history_index) {
+                    Some(entry) => line.replace(entry, out),
+                    None => {
+                        let draft = core::mem::take(&mut line.draft);
+                        line.replace(&draft, out);
+                    }
+                }
+            }
+            Key::KillToEnd => {
+                let killed = line.remove(line.cursor, line.chars.len(), out);
+                self.kill(killed);
+            }
+            Key::KillToStart => {
+                let killed = line.remove(0, line.cursor, out);
+                self.kill(killed);
+            }
+            Key::KillWord => {
+                //先跳过光标前的空白，再跳过一个单词
+                let mut start = line.cursor;
+                while start > 0 && line.chars[start - 1] == ' ' {
+                    start -= 1;
+                }
+                while start > 0 && line.chars[start - 1] != ' ' {
+                    start -= 1;
+                }
+                let killed = line.remove(start, line.cursor, out);
+                self.kill(killed);
+            }
+            Key::Yank => {
+                if let Some(text) = self.kill_ring.last() {
+                    line.insert(text, out);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+//把终端发来的字节翻译成编辑按键：VT100 的方向键和 Home/End/Delete 序列、Ctrl 组合键、UTF-8 字符
+//一个按键可能分在两次输入里(例如两个数据报)，没有完成的序列留在 pending 中
+#[derive(Default)]
+pub struct KeyDecoder {
+    pending: Vec<u8>,
+    after_cr: bool, //\r\n 只算一次回车
+}
+
+impl KeyDecoder {
+    pub fn decode(&mut self, byte: u8) -> Option<Key> {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        if !self.pending.is_empty() || byte == 0x1b || byte >= 0x80 {
+            self.pending.push(byte);
+            return self.decode_pending();
+        }
+        Some(match byte {
+            b'\r' => Key::Enter,
+            b'\n' if after_cr => return None,
+            b'\n' => Key::Enter,
+            0x7f | 0x08 => Key::Backspace,
+            0x01 => Key::Home,
+            0x05 => Key::End,
+            0x02 => Key::Left,
+            0x06 => Key::Right,
+            0x10 => Key::Up,
+            0x0e => Key::Down,
+            0x0b => Key::KillToEnd,
+            0x15 => Key::KillToStart,
+            0x17 => Key::KillWord,
+            0x19 => Key::Yank,
+            0x20..=0x7e => Key::Char(byte as char),
+            _ => Key::Other,
+        })
+    }
+
+    fn decode_pending(&mut self) -> Option<Key> {
+        let key = match self.pending.as_slice() {
+            [0x1b] | [0x1b, b'['] | [0x1b, b'O'] | [0x1b, b'[', b'0'..=b'9'] => return None,
+            [0x1b, b'[' | b'O', code] => match code {
+                b'A' => Key::Up,
+                b'B' => Key::Down,
+                b'C' => Key::Right,
+                b'D' => Key::Left,
+                b'H' => Key::Home,
+                b'F' => Key::End,
+                _ => Key::Other,
+            },
+            [0x1b, b'[', b'1' | b'7', b'~'] => Key::Home,
+            [0x1b, b'[', b'4' | b'8', b'~'] => Key::End,
+            [0x1b, b'[', b'3', b'~'] => Key::Delete,
+            [0x1b, ..] => Key::Other,
+            bytes => match core::str::from_utf8(bytes) {
+                Ok(text) => Key::Char(text.chars().next().unwrap_or('?')),
+                Err(err) if err.error_len().is_none() && bytes.len() < 4 => return None, //UTF-8 字符还没有收完
+                Err(_) => Key::Other,
+            },
+        };
+        self.pending.clear();
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::VecDeque;
+
+    //按给定顺序交出按键
+    struct Script(VecDeque<Key>);
+
+    impl KeySource for Script {
+        fn read_key(&mut self) -> Key {
+            self.0.pop_front().expect("script ran out of keys")
+        }
+    }
+
+    fn keys(text: &str, tail: &[Key]) -> Script {
+        Script(text.chars().map(Key::Char).chain(tail.iter().copied()).collect())
+    }
+
+    //模拟一行屏幕：记下每个位置的字符和光标
+    #[derive(Default)]
+    struct Screen {
+        cells: Vec<char>,
+        cursor: usize,
+    }
+
+    impl Screen {
+        fn text(&self) -> String {
+            self.cells.iter().collect()
+        }
+    }
+
+    impl LineOutput for Screen {
+        fn write_str(&mut self, s: &str) {
+            for c in s.chars().filter(|&c| c != '\n') {
+                if self.cursor < self.cells.len() {
+                    self.cells[self.cursor] = c;
+                } else {
+                    self.cells.push(c);
+                }
+                self.cursor += 1;
+            }
+        }
+
+        fn cursor_left(&mut self, n: usize) {
+            self.cursor -= n;
+        }
+
+        fn cursor_right(&mut self, n: usize) {
+            self.cursor += n;
+            assert!(self.cursor <= self.cells.len(), "cursor moved past the end of the line");
+        }
+
+        fn clear_to_end(&mut self) {
+            self.cells.truncate(self.cursor);
+        }
+    }
+
+    fn read(editor: &mut LineEditor, mut script: Script) -> (String, Screen) {
+        let mut screen = Screen::default();
+        let line = editor.read_line(&mut script, &mut screen);
+        assert!(script.0.is_empty(), "keys left after enter");
+        (line, screen)
+    }
+
+    #[test]
+    fn insert() {
+        let (line, screen) = read(&mut LineEditor::new(8), keys("echo hi", &[Key::Enter]));
+        assert_eq!(line, "echo hi");
+        assert_eq!(screen.text(), "echo hi");
+    }
+
+    #[test]
+    fn backspace_and_delete() {
+        let script = keys("abcd", &[Key::Backspace, Key::Left, Key::Left, Key::Delete, Key::Backspace, Key::Backspace, Key::Enter]);
+        let (line, screen) = read(&mut LineEditor::new(8), script);
+        assert_eq!(line, "c");
+        assert_eq!(screen.text(), "c");
+    }
+
+    #[test]
+    fn cursor_movement() {
+        let mut script = keys("ac", &[Key::Left, Key::Char('b'), Key::Home, Key::Char('>'), Key::End, Key::Char('<')]);
+        script.0.push_back(Key::Enter);
+        let (line, screen) = read(&mut LineEditor::new(8), script);
+        assert_eq!(line, ">abc<");
+        assert_eq!(screen.text(), ">abc<");
+    }
+
+    #[test]
+    fn kill_and_yank() {
+        let script = keys("ls /bin /etc", &[Key::KillWord, Key::Home, Key::Yank, Key::End, Key::KillToStart, Key::Yank, Key::Enter]);
+        let (line, _) = read(&mut LineEditor::new(8), script);
+        assert_eq!(line, "/etcls /bin ");
+    }
+
+    #[test]
+    fn history() {
+        let mut editor = LineEditor::new(2);
+        for text in ["one", "two", "two", "three"] {
+            read(&mut editor, keys(text, &[Key::Enter]));
+        }
+        //重复的行只记一次，超过上限时丢掉最旧的
+        assert_eq!(editor.history, ["two", "three"]);
+        let (line, screen) = read(&mut editor, keys("x", &[Key::Up, Key::Up, Key::Up, Key::Enter]));
+        assert_eq!(line, "two");
+        assert_eq!(screen.text(), "two");
+        //回到正在编辑的草稿
+        let (line, _) = read(&mut editor, keys("draft", &[Key::Up, Key::Down, Key::Down, Key::Enter]));
+        assert_eq!(line, "draft");
+    }
+
+    #[test]
+    fn feed_keeps_state_between_keys() {
+        let mut editor = LineEditor::new(8);
+        let mut line = editor.begin();
+        let mut screen = Screen::default();
+        for key in [Key::Char('o'), Key::Char('k'), Key::Left, Key::Char('!')] {
+            assert_eq!(editor.feed(&mut line, key, &mut screen), None);
+        }
+        assert_eq!(editor.feed(&mut line, Key::Enter, &mut screen).as_deref(), Some("o!k"));
+        assert_eq!(line.char_count(), 0);
+    }
+
+    #[test]
+    fn decode_terminal_bytes() {
+        let mut decoder = KeyDecoder::default();
+        let keys: Vec<Key> = "a\x1b[A\x1b[3~\x15é\r\n".bytes().filter_map(|b| decoder.decode(b)).collect();
+        assert_eq!(keys, [Key::Char('a'), Key::Up, Key::Delete, Key::KillToStart, Key::Char('é'), Key::Enter]);
+    }
+}
@@ -0,0 +1,236 @@
+//Linux 兼容层：不做修改地运行小型的静态 Linux x86_64 程序
+//这类进程的 personality 是 Linux：系统调用按 Linux 的编号在 dispatch 中分发，初始栈按 Linux 的约定排列(参数、环境变量、辅助向量)
+//只实现了一小部分系统调用：read、write、writev、brk、匿名 mmap、munmap、clock_gettime、exit、exit_group，
+//以及 C 库启动时要用的 arch_prctl(ARCH_SET_FS)、set_tid_address、ioctl(总是 ENOTTY)和 getpid，其余返回 ENOSYS
+//用户地址空间从 USER_BASE 开始：程序要么是 static-pie(加载器整体平移到 USER_BASE)，要么链接在 USER_BASE 之上，
+//链接在 0x400000 的普通静态程序不能加载
+//ELF 头的 EI_OSABI 为 GNU/Linux 时自动使用这个 personality，其他程序用 run -l 指定
+use crate::cred::Credentials;
+use crate::loader::elf::Program;
+use crate::process;
+use crate::syscall::{self, EFAULT, EINVAL, ENOSYS, ENOTTY};
+use crate::time;
+use crate::usermode::{self, UserContext};
+use crate::vm::{self, Backing};
+use alloc::vec::Vec;
+use x86_64::registers::model_specific::FsBase;
+use x86_64::VirtAddr;
+
+//Linux x86_64 的系统调用号
+pub const SYS_READ: u64 = 0;
+pub const SYS_WRITE: u64 = 1;
+pub const SYS_MMAP: u64 = 9;
+pub const SYS_MUNMAP: u64 = 11;
+pub const SYS_BRK: u64 = 12;
+pub const SYS_IOCTL: u64 = 16;
+pub const SYS_WRITEV: u64 = 20;
+pub const SYS_GETPID: u64 = 39;
+pub const SYS_EXIT: u64 = 60;
+pub const SYS_ARCH_PRCTL: u64 = 158;
+pub const SYS_SET_TID_ADDRESS: u64 = 218;
+pub const SYS_CLOCK_GETTIME: u64 = 228;
+pub const SYS_EXIT_GROUP: u64 = 231;
+
+const MAP_FIXED: u64 = 0x10;
+const MAP_ANONYMOUS: u64 = 0x20;
+
+const ARCH_SET_FS: u64 = 0x1002;
+const ARCH_GET_FS: u64 = 0x1003;
+
+const MAX_CLOCK: u64 = 11; //CLOCK_TAI，编号更大的时钟不存在
+
+const IOV_MAX: u64 = 1024;
+const PAGE_SIZE: u64 = 4096;
+const BRK_LIMIT: u64 = usermode::USER_STACK_BOTTOM - PAGE_SIZE; //堆和用户栈之间留一个不映射的页面
+
+//辅助向量的类型
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_ENTRY: u64 = 9;
+const AT_UID: u64 = 11;
+const AT_EUID: u64 = 12;
+const AT_GID: u64 = 13;
+const AT_EGID: u64 = 14;
+const AT_SECURE: u64 = 23;
+const AT_RANDOM: u64 = 25;
+
+//Linux 进程的附加状态，保存在进程表中
+#[derive(Debug, Clone, Copy)]
+pub struct LinuxState {
+    brk_start: u64, //堆的起点，按页对齐
+    brk: u64,       //当前的 program break
+    pub fs_base: u64, //arch_prctl 设置的 FS 段基址(C 库的线程局部存储)，进程开始运行时装入
+}
+
+impl LinuxState {
+    pub fn new(program: &Program) -> LinuxState {
+        LinuxState { brk_start: program.brk, brk: program.brk, fs_base: 0 }
+    }
+}
+
+//按 Linux 的约定准备初始栈，返回开始运行时的寄存器；当前地址空间必须是新程序的，cred 是程序运行时的身份
+//栈顶往下依次是：参数字符串、AT_RANDOM 的 16 个随机字节，然后从 rsp 开始是 argc、argv、NULL、envp(空)、NULL、辅助向量
+pub fn start(program: &Program, args: &[&[u8]], cred: Credentials) -> UserContext {
+    let mut sp = program.stack_top;
+    let mut pointers = Vec::with_capacity(args.len());
+    for arg in args {
+        sp -= arg.len() as u64 + 1;
+        unsafe {
+            core::ptr::copy_nonoverlapping(arg.as_ptr(), sp as *mut u8, arg.len());
+            *((sp + arg.len() as u64) as *mut u8) = 0;
+        }
+        pointers.push(sp);
+    }
+    let mut random = [0u8; 16];
+    crate::rand::fill_bytes(&mut random);
+    sp = (sp - 16) & !0xf;
+    unsafe { core::ptr::copy_nonoverlapping(random.as_ptr(), sp as *mut u8, 16) };
+
+    let auxv = [
+        (AT_PHDR, program.phdr),
+        (AT_PHENT, 56),
+        (AT_PHNUM, program.phnum),
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_ENTRY, program.entry),
+        (AT_UID, cred.uid as u64),
+        (AT_EUID, cred.uid as u64),
+        (AT_GID, cred.gid as u64),
+        (AT_EGID, cred.gid as u64),
+        (AT_SECURE, 0),
+        (AT_RANDOM, sp),
+        (AT_NULL, 0),
+    ];
+    let mut words: Vec<u64> = Vec::new();
+    words.push(args.len() as u64);
+    words.extend_from_slice(&pointers);
+    words.push(0);
+    words.push(0); //没有环境变量
+    for (key, value) in auxv.iter() {
+        words.push(*key);
+        words.push(*value);
+    }
+    sp = (sp - words.len() as u64 * 8) & !0xf; //进程入口处 rsp 16 字节对齐
+    unsafe { core::ptr::copy_nonoverlapping(words.as_ptr(), sp as *mut u64, words.len()) };
+    UserContext::new(program.entry, sp)
+}
+
+//Linux personality 的进程的系统调用，number 和前三个参数由 syscall_dispatch 传入，第四个参数在 r10 里
+pub fn dispatch(number: u64, arg0: u64, arg1: u64, arg2: u64) -> i64 {
+    let context = usermode::syscall_context();
+    let arg3 = context.r10;
+    match number {
+        SYS_READ => syscall::sys_read(arg0, arg1, arg2),
+        SYS_WRITE => syscall::sys_write(arg0, arg1, arg2),
+        SYS_WRITEV => writev(arg0, arg1, arg2),
+        SYS_MMAP => mmap(arg1, arg2, arg3),
+        SYS_MUNMAP => syscall::sys_munmap(arg0, arg1, 0),
+        SYS_BRK => brk(arg0),
+        SYS_IOCTL => ENOTTY, //没有终端设备，C 库据此把标准输出当作普通文件
+        SYS_GETPID | SYS_SET_TID_ADDRESS => process::current_pid() as i64, //单线程进程的线程号就是 PID
+        SYS_EXIT | SYS_EXIT_GROUP => usermode::exit(arg0 as i32 as i64),
+        SYS_ARCH_PRCTL => arch_prctl(arg0, arg1),
+        SYS_CLOCK_GETTIME => clock_gettime(arg0, arg1),
+        _ => ENOSYS,
+    }
+}
+
+//writev(fd, iov, count)：依次写出每个 struct iovec { base, len }，某一段没有写完时停止
+fn writev(fd: u64, iov: u64, count: u64) -> i64 {
+    if count > IOV_MAX {
+        return EINVAL;
+    }
+    let vectors = match usermode::user_slice(iov, count * 16) {
+        Some(vectors) => vectors,
+        None => return EFAULT,
+    };
+    let mut total = 0;
+    for vector in vectors.chunks(16) {
+        let base = u64::from_le_bytes(vector[0..8].try_into().unwrap());
+        let len = u64::from_le_bytes(vector[8..16].try_into().unwrap());
+        if len == 0 {
+            continue;
+        }
+        let written = syscall::sys_write(fd, base, len);
+        if written < 0 {
+            return if total > 0 { total } else { written };
+        }
+        total += written;
+        if (written as u64) < len {
+            break;
+        }
+    }
+    total
+}
+
+//mmap(addr, len, prot, flags, fd, offset)：只支持匿名映射(忽略 fd 和 offset)，地址由内核选择(忽略 addr，不支持 MAP_FIXED)
+fn mmap(len: u64, prot: u64, flags: u64) -> i64 {
+    if flags & MAP_ANONYMOUS == 0 || flags & MAP_FIXED != 0 {
+        return EINVAL;
+    }
+    match vm::mmap(len, prot & (vm::PROT_READ | vm::PROT_WRITE | vm::PROT_EXEC), Backing::Anonymous) {
+        Ok(addr) => addr as i64,
+        Err(err) => syscall::vm_errno(err),
+    }
+}
+
+fn page_align_up(addr: u64) -> u64 {
+    (addr + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+//brk(addr)：把 program break 移到 addr，返回新的 break；失败(或 addr 为 0 查询)时返回原来的
+fn brk(addr: u64) -> i64 {
+    let (start, current) = match process::with_linux(|state| (state.brk_start, state.brk)) {
+        Some(brk) => brk,
+        None => return ENOSYS,
+    };
+    if addr < start || addr > BRK_LIMIT {
+        return current as i64;
+    }
+    if vm::resize_heap(start, page_align_up(addr)).is_err() {
+        return current as i64;
+    }
+    process::with_linux(|state| state.brk = addr);
+    addr as i64
+}
+
+//arch_prctl(code, addr)：设置或读取 FS 段基址
+fn arch_prctl(code: u64, addr: u64) -> i64 {
+    match code {
+        ARCH_SET_FS => {
+            if addr >= usermode::MMAP_END {
+                return EINVAL; //非规范地址写进 MSR 会引起异常
+            }
+            FsBase::write(VirtAddr::new(addr));
+            process::with_linux(|state| state.fs_base = addr);
+            0
+        }
+        ARCH_GET_FS => match usermode::user_slice_mut(addr, 8) {
+            Some(out) => {
+                out.copy_from_slice(&FsBase::read().as_u64().to_le_bytes());
+                0
+            }
+            None => EFAULT,
+        },
+        _ => EINVAL,
+    }
+}
+
+//clock_gettime(clock, tp)：写入 struct timespec { sec, nsec }
+//没有实时时钟，CLOCK_REALTIME 与其他时钟一样从启动时开始计时
+fn clock_gettime(clock: u64, tp: u64) -> i64 {
+    if clock > MAX_CLOCK {
+        return EINVAL;
+    }
+    let now = time::uptime();
+    match usermode::user_slice_mut(tp, 16) {
+        Some(out) => {
+            out[0..8].copy_from_slice(&now.as_secs().to_le_bytes());
+            out[8..16].copy_from_slice(&(now.subsec_nanos() as u64).to_le_bytes());
+            0
+        }
+        None => EFAULT,
+    }
+}
@@ -0,0 +1,139 @@
+//程序映像缓存：最近加载过的程序文件留在内存里，反复启动同一个程序时不必再读磁盘
+//以规范化的路径为键，同时记下文件的大小和 mtime，任何一个变了就重新读取；
+//通过 vfs 创建或删除文件时，fs::watch 通知到这里，对应的项直接作废
+use crate::fs::vfs::{self, InodeKind};
+use crate::fs::{watch, FsError};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+const CACHE_LIMIT: usize = 256 * 1024; //缓存的映像总大小上限，超出时淘汰最久没用的
+
+struct Entry {
+    size: u64,
+    mtime: u64,
+    image: Arc<[u8]>,
+    last_used: u64, //用于淘汰的逻辑时钟
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub entries: usize,
+    pub bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct Cache {
+    entries: BTreeMap<String, Entry>,
+    bytes: usize,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache { entries: BTreeMap::new(), bytes: 0, clock: 0, hits: 0, misses: 0 });
+
+impl Cache {
+    fn remove(&mut self, path: &str) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.bytes -= entry.image.len();
+        }
+    }
+
+    //腾出 needed 字节的空间
+    fn evict_for(&mut self, needed: usize) {
+        while self.bytes + needed > CACHE_LIMIT {
+            let oldest = self.entries.iter().min_by_key(|(_, entry)| entry.last_used).map(|(path, _)| path.clone());
+            match oldest {
+                Some(path) => self.remove(&path),
+                None => return,
+            }
+        }
+    }
+}
+
+pub fn init() {
+    watch::subscribe(invalidate);
+}
+
+//一次读入整个文件：大小已知，预先分配好缓冲区，按大块顺序读取
+fn read_whole(path: &str, size: u64) -> Result<Arc<[u8]>, FsError> {
+    let mut file = vfs::open(path)?;
+    let mut image = vec![0u8; size as usize];
+    let mut done = 0;
+    while done < image.len() {
+        match file.read(&mut image[done..])? {
+            0 => return Err(FsError::InvalidFilesystem), //文件比 metadata 报告的短
+            n => done += n,
+        }
+    }
+    Ok(Arc::from(image))
+}
+
+//取得程序文件的内容，缓存中的副本仍然有效时直接返回
+pub fn image(path: &str) -> Result<Arc<[u8]>, FsError> {
+    let path = vfs::normalize(path)?;
+    let meta = vfs::metadata(&path)?;
+    if meta.kind != InodeKind::File {
+        return Err(FsError::IsADirectory);
+    }
+    {
+        let mut cache = CACHE.lock();
+        cache.clock += 1;
+        let clock = cache.clock;
+        if let Some(entry) = cache.entries.get_mut(&path) {
+            //mtime 为 0 的文件系统无法判断文件是否变过，只能比较大小
+            if entry.size == meta.size && entry.mtime == meta.mtime {
+                entry.last_used = clock;
+                let image = entry.image.clone();
+                cache.hits += 1;
+                return Ok(image);
+            }
+        }
+        cache.remove(&path);
+        cache.misses += 1;
+    }
+
+    let image = read_whole(&path, meta.size)?;
+    let mut cache = CACHE.lock();
+    if image.len() <= CACHE_LIMIT {
+        cache.evict_for(image.len());
+        cache.bytes += image.len();
+        let last_used = cache.clock;
+        cache.entries.insert(path, Entry { size: meta.size, mtime: meta.mtime, image: image.clone(), last_used });
+    }
+    Ok(image)
+}
+
+//fs::watch 的回调：路径(或它下面的某个文件)变化后作废对应的项
+pub fn invalidate(path: &str) {
+    let path = match vfs::normalize(path) {
+        Ok(path) => path,
+        Err(_) => return,
+    };
+    let mut cache = CACHE.lock();
+    let stale: Vec<String> = cache
+        .entries
+        .keys()
+        .filter(|cached| **cached == path || cached.starts_with(&path) && cached[path.len()..].starts_with('/'))
+        .cloned()
+        .collect();
+    for cached in stale {
+        cache.remove(&cached);
+    }
+}
+
+pub fn clear() {
+    let mut cache = CACHE.lock();
+    cache.entries.clear();
+    cache.bytes = 0;
+}
+
+pub fn stats() -> Stats {
+    let cache = CACHE.lock();
+    Stats { entries: cache.entries.len(), bytes: cache.bytes, hits: cache.hits, misses: cache.misses }
+}
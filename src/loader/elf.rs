@@ -0,0 +1,227 @@
+//ELF64 加载器：校验文件头，按程序头把 PT_LOAD 段映射到用户地址空间，返回入口地址
+//位置无关的静态程序(static-pie，ET_DYN)整体平移到 USER_BASE，它们启动时自己完成重定位
+use crate::memory::{self, MemoryError};
+use crate::usermode::{self, UserError, USER_BASE};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1; //小端
+const ELFOSABI_GNU: u8 = 3; //GNU/Linux，Linux 的工具链给用到 GNU 扩展的程序打上这个标记
+const ET_EXEC: u16 = 2;    //静态链接的可执行文件
+const ET_DYN: u16 = 3;     //位置无关的可执行文件
+const EM_X86_64: u16 = 0x3E;
+const PT_LOAD: u32 = 1;
+const PT_PHDR: u32 = 6;
+const PHDR_SIZE: usize = 56;
+
+//程序头中的段权限位
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    BadMagic,          //不是 ELF 文件
+    Unsupported,       //不是 x86_64 上的 64 位小端静态可执行文件(或位置无关的可执行文件)
+    Truncated,         //文件长度不足，头部或段数据越界
+    BadSegment,        //段不在用户地址空间内，或文件大小大于内存大小
+    BadEntry,          //入口地址不在任何可执行段内
+    Memory(MemoryError),
+}
+
+impl From<MemoryError> for ElfError {
+    fn from(err: MemoryError) -> ElfError {
+        ElfError::Memory(err)
+    }
+}
+
+impl From<UserError> for ElfError {
+    fn from(err: UserError) -> ElfError {
+        match err {
+            UserError::Memory(err) => ElfError::Memory(err),
+            UserError::TooLarge => ElfError::BadSegment,
+        }
+    }
+}
+
+//加载完成、可以开始执行的程序
+#[derive(Debug, Clone, Copy)]
+pub struct Program {
+    pub entry: u64,
+    pub stack_top: u64,
+    pub phdr: u64,  //程序头表在内存中的地址，Linux 程序从辅助向量 AT_PHDR 得到它，不在任何段内时为 0
+    pub phnum: u64,
+    pub brk: u64,   //最高的段结束之后的第一页，Linux 兼容层的 brk 堆从这里开始
+    pub linux: bool, //EI_OSABI 标明是 GNU/Linux 程序
+}
+
+//parse 的结果，地址都已经加上了位置无关程序的平移量
+struct Parsed {
+    entry: u64,
+    segments: Vec<Segment>,
+    phdr: u64,
+    phnum: u64,
+    linux: bool,
+}
+
+struct Segment {
+    flags: u32,
+    offset: usize,
+    vaddr: u64,
+    file_size: usize,
+    mem_size: u64,
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+pub fn is_elf(image: &[u8]) -> bool {
+    image.len() >= 4 && image[0..4] == ELF_MAGIC
+}
+
+//校验文件头并解析出所有 PT_LOAD 段
+fn parse(image: &[u8]) -> Result<Parsed, ElfError> {
+    if !is_elf(image) {
+        return Err(ElfError::BadMagic);
+    }
+    if image.len() < 64 {
+        return Err(ElfError::Truncated);
+    }
+    let kind = read_u16(image, 16);
+    if image[4] != ELFCLASS64 || image[5] != ELFDATA2LSB
+        || (kind != ET_EXEC && kind != ET_DYN) || read_u16(image, 18) != EM_X86_64
+    {
+        return Err(ElfError::Unsupported);
+    }
+    let mut entry = read_u64(image, 24);
+    let phoff = read_u64(image, 32) as usize;
+    let phentsize = read_u16(image, 54) as usize;
+    let phnum = read_u16(image, 56) as usize;
+    if phentsize < PHDR_SIZE {
+        return Err(ElfError::Unsupported);
+    }
+    let table_end = phnum.checked_mul(phentsize).and_then(|size| size.checked_add(phoff));
+    if table_end.is_none_or(|end| end > image.len()) {
+        return Err(ElfError::Truncated);
+    }
+
+    let mut segments = Vec::new();
+    let mut phdr = None;
+    for i in 0..phnum {
+        let ph = &image[phoff + i * phentsize..];
+        if read_u32(ph, 0) == PT_PHDR {
+            phdr = Some(read_u64(ph, 16));
+        }
+        if read_u32(ph, 0) != PT_LOAD {
+            continue;
+        }
+        let segment = Segment {
+            flags: read_u32(ph, 4),
+            offset: read_u64(ph, 8) as usize,
+            vaddr: read_u64(ph, 16),
+            file_size: read_u64(ph, 32) as usize,
+            mem_size: read_u64(ph, 40),
+        };
+        if segment.offset.checked_add(segment.file_size).is_none_or(|end| end > image.len()) {
+            return Err(ElfError::Truncated);
+        }
+        segments.push(segment);
+    }
+    //没有 PT_PHDR 时，程序头表在包含文件偏移 phoff 的段里
+    let phdr = phdr.or_else(|| {
+        segments
+            .iter()
+            .find(|s| s.offset <= phoff && phoff < s.offset + s.file_size)
+            .map(|s| s.vaddr + (phoff - s.offset) as u64)
+    });
+
+    //位置无关的程序链接在 0 附近，整体平移到 USER_BASE
+    let bias = match segments.iter().map(|s| s.vaddr & !0xfff).min() {
+        Some(lowest) if kind == ET_DYN && lowest < USER_BASE => USER_BASE - lowest,
+        _ => 0,
+    };
+    entry = entry.wrapping_add(bias);
+    for segment in segments.iter_mut() {
+        segment.vaddr = segment.vaddr.wrapping_add(bias);
+        //段必须完整地落在用户程序区(栈的下方)
+        let end = segment.vaddr.checked_add(segment.mem_size);
+        if segment.file_size as u64 > segment.mem_size
+            || segment.vaddr < USER_BASE
+            || end.is_none_or(|end| end > usermode::USER_STACK_BOTTOM)
+        {
+            return Err(ElfError::BadSegment);
+        }
+    }
+
+    let entry_ok = segments.iter().any(|s| s.flags & PF_X != 0 && entry >= s.vaddr && entry < s.vaddr + s.mem_size);
+    if !entry_ok {
+        return Err(ElfError::BadEntry);
+    }
+    let phdr = phdr.map_or(0, |phdr| phdr.wrapping_add(bias));
+    Ok(Parsed { entry, segments, phdr, phnum: phnum as u64, linux: image[7] == ELFOSABI_GNU })
+}
+
+//段权限对应的页表标志
+fn page_flags(flags: u32) -> PageTableFlags {
+    let mut page_flags = PageTableFlags::empty();
+    if flags & PF_W != 0 {
+        page_flags |= PageTableFlags::WRITABLE;
+    }
+    if flags & PF_X == 0 {
+        page_flags |= PageTableFlags::NO_EXECUTE;
+    }
+    page_flags
+}
+
+//把程序映射到用户地址空间并准备好用户栈
+pub fn load(image: &[u8]) -> Result<Program, ElfError> {
+    let Parsed { entry, segments, phdr, phnum, linux } = parse(image)?;
+
+    //两个段可能共用一页，这一页的权限取两者的并集
+    let mut pages: BTreeMap<u64, PageTableFlags> = BTreeMap::new();
+    for segment in &segments {
+        if segment.mem_size == 0 {
+            continue;
+        }
+        let first = segment.vaddr & !0xfff;
+        let last = (segment.vaddr + segment.mem_size - 1) & !0xfff;
+        for page in (first..=last).step_by(4096) {
+            let flags = page_flags(segment.flags);
+            let merged = match pages.get(&page) {
+                Some(&old) => (old | flags) - ((old ^ flags) & PageTableFlags::NO_EXECUTE),
+                None => flags,
+            };
+            pages.insert(page, merged);
+        }
+    }
+
+    //先以可写方式映射并拷贝数据(.bss 部分已经被清零)，最后再设置真正的权限
+    for &page in pages.keys() {
+        memory::map_user_range(VirtAddr::new(page), 4096, PageTableFlags::WRITABLE)?;
+    }
+    for segment in &segments {
+        let data = &image[segment.offset..segment.offset + segment.file_size];
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), segment.vaddr as *mut u8, data.len());
+        }
+    }
+    for (&page, &flags) in &pages {
+        memory::update_user_flags(VirtAddr::new(page), 4096, flags)?;
+    }
+
+    let brk = pages.keys().next_back().map_or(USER_BASE, |&page| page + 4096);
+    let stack_top = usermode::setup_stack()?;
+    Ok(Program { entry, stack_top, phdr, phnum, brk, linux })
+}
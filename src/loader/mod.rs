@@ -0,0 +1,2 @@
+pub mod cache; //程序映像缓存
+pub mod elf; //ELF64 可执行文件加载器
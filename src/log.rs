@@ -0,0 +1,113 @@
+//内核日志：log!(Level, ...) 的输出按各个输出端(屏幕、串口、dmesg)自己的级别阈值分发，运行时可以单独打开、关闭或者调整阈值
+//例如调试新硬件时屏幕只显示 warn 以上，串口记录全部 trace：log sink vga level=warn; log sink serial level=trace
+//dmesg 就是控制台的滚动缓冲区(scrollback 命令和 Ctrl+F 搜索看到的内容)
+//普通的 println! 不经过这里，仍然同时输出到屏幕和滚动缓冲区
+use crate::cmdline::{self, LogLevel};
+use crate::drivers::uart::{self, Uart};
+use crate::{console, vga_buffer};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+//按级别输出一条日志，例如 log!(Info, "driver {}: {}", name, status)
+#[macro_export]
+macro_rules! log {
+    ($level:ident, $($arg:tt)*) => {
+        $crate::log::_log($crate::cmdline::LogLevel::$level, format_args!($($arg)*))
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sink {
+    Vga,
+    Serial,
+    Dmesg,
+}
+
+pub const SINKS: [Sink; 3] = [Sink::Vga, Sink::Serial, Sink::Dmesg];
+
+const LEVELS: [LogLevel; 5] = [LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug, LogLevel::Trace];
+
+struct SinkState {
+    enabled: AtomicBool,
+    level: AtomicU8, //不高于这个级别(数值不大于)的日志才输出
+}
+
+impl SinkState {
+    const fn new(enabled: bool, level: LogLevel) -> SinkState {
+        SinkState { enabled: AtomicBool::new(enabled), level: AtomicU8::new(level as u8) }
+    }
+}
+
+//init 之前屏幕和 dmesg 按 info 输出，串口关闭
+static STATES: [SinkState; 3] = [
+    SinkState::new(true, LogLevel::Info),
+    SinkState::new(false, LogLevel::Debug),
+    SinkState::new(true, LogLevel::Info),
+];
+
+impl Sink {
+    pub fn parse(name: &str) -> Option<Sink> {
+        SINKS.iter().copied().find(|sink| sink.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Sink::Vga => "vga",
+            Sink::Serial => "serial",
+            Sink::Dmesg => "dmesg",
+        }
+    }
+
+    fn state(self) -> &'static SinkState {
+        &STATES[self as usize]
+    }
+
+    pub fn enabled(self) -> bool {
+        self.state().enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(self, enabled: bool) {
+        self.state().enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn level(self) -> LogLevel {
+        LEVELS[self.state().level.load(Ordering::Relaxed) as usize]
+    }
+
+    pub fn set_level(self, level: LogLevel) {
+        self.state().level.store(level as u8, Ordering::Relaxed);
+    }
+
+    fn accepts(self, level: LogLevel) -> bool {
+        self.enabled() && level <= self.level()
+    }
+}
+
+//启动时调用：屏幕和 dmesg 使用命令行的 loglevel，有串口时串口记录 debug 以上的全部日志
+pub fn init() {
+    let level = cmdline::log_level();
+    Sink::Vga.set_level(level);
+    Sink::Dmesg.set_level(level.max(LogLevel::Info));
+    let serial = Uart::new(uart::COM1);
+    if serial.present() {
+        serial.init();
+        Sink::Serial.set_enabled(true);
+        Sink::Serial.set_level(level.max(LogLevel::Debug));
+    }
+}
+
+#[doc(hidden)]
+pub fn _log(level: LogLevel, args: fmt::Arguments) {
+    let line = format_args!("{}\n", args);
+    //屏幕输出改写到串口时，串口输出端也要的日志不再写两遍
+    let on_serial = vga_buffer::serial_console() && crate::framebuffer::with_console(|_| ()).is_none();
+    if Sink::Vga.accepts(level) && !(on_serial && Sink::Serial.accepts(level)) {
+        vga_buffer::print_screen(line);
+    }
+    if Sink::Dmesg.accepts(level) {
+        console::record(line);
+    }
+    if Sink::Serial.accepts(level) {
+        let _ = write!(Uart::new(uart::COM1), "[{}] {}", level.name(), line);
+    }
+}
@@ -1,13 +1,78 @@
-#![no_std] //禁用Rust标准库
-#![no_main] //禁用所有 Rust 层级的入口点
+#![cfg_attr(not(test), no_std)] //禁用Rust标准库；宿主机上运行单元测试时保留
+#![cfg_attr(not(test), no_main)] //禁用所有 Rust 层级的入口点
+#![cfg_attr(test, allow(dead_code))] //测试只用到内核的一小部分
+#![feature(abi_x86_interrupt)] //异常处理函数使用 x86-interrupt 调用约定
+
+extern crate alloc; //使用 alloc 库提供的 Box、Vec、String 等堆上的类型
 
 mod vga_buffer;
-use core::panic::PanicInfo;
+mod framebuffer;
+mod console;
+mod console_filter;
+mod i18n;
+mod cmdline;
+mod abi;
+mod allocator;
+mod audit;
+mod bell;
+mod block;
+mod coredump;
+mod cred;
+mod crypto;
+mod rand;
+mod rconsole;
+mod resources;
+mod driver;
+mod failpoint;
+mod gdbstub;
+mod drivers;
+mod fs;
+mod line_editor;
+mod linux;
+mod shell;
+mod screencheck;
+mod schedcheck;
+mod service;
+mod gdt;
+mod interrupts;
+mod memory;
+mod memaudit;
+mod memlayout;
+mod net;
+mod syscall;
+mod process;
+mod shm;
+mod signal;
+mod ksm;
+mod latency;
+mod log;
+mod oom;
+mod power;
+mod group;
+mod stats;
+mod sync;
+mod time;
+mod trace;
+mod thread;
+mod tui;
+mod usermode;
+mod vm;
+mod watch;
+mod watchdog;
+mod xmodem;
+mod zerofill;
+mod loader;
+use alloc::format;
+use alloc::string::String;
+use bootloader::BootInfo;
+use x86_64::VirtAddr;
 
 static HELLO: &[u8] = b"Hello World!";
 
-#[no_mangle] // 不重整函数名
-pub extern "C" fn _start() -> ! {
+#[cfg(not(test))]
+bootloader::entry_point!(kernel_main); //由 bootloader 定义真正的 `_start`，并以 BootInfo 为参数调用 kernel_main
+
+fn kernel_main(boot_info: &'static BootInfo) -> ! {
     /*let vga_buffer = 0xb8000 as *mut u8;
 
     for (i, &byte) in HELLO.iter().enumerate() {
@@ -19,12 +84,144 @@ pub extern "C" fn _start() -> ! {
     // 因为链接器会寻找一个名为 `_start` 的函数，所以这个函数就是入口点
     // 默认命名为 `_start`
     */
-    println!("Hello Joakim");
-    loop {}
+    //编译时给出的 lang= 在欢迎信息之前生效；fw_cfg 追加的参数要等到下面读出之后
+    if let Some(lang) = cmdline::get("lang").and_then(i18n::Language::from_name) {
+        i18n::set_language(lang);
+    }
+    println!("{}", msg!(Welcome));
+
+    allocator::init(); //初始化内核堆，之后才能使用 alloc 中的类型
+    console::init(); //开始把控制台输出记入滚动缓冲区
+    gdt::init(); //加载包含用户段和 TSS 的 GDT
+    interrupts::init(); //加载 IDT，处理缺页异常、断点和调试异常
+    memory::init(VirtAddr::new(boot_info.physical_memory_offset), &boot_info.memory_map);
+    memaudit::run(); //检查内核映射有没有落在固件保留的区域里
+    syscall::init(); //启用 syscall/sysret 指令
+    let tsc_hz = time::init(); //校准 TSC，之后才能按时间计时
+    let mhz = tsc_hz / 1_000_000;
+    if time::invariant_tsc() {
+        println!("{}", msg!(TscInvariant, mhz));
+    } else {
+        println!("{}", msg!(TscFrequency, mhz));
+    }
+    println!("{}", msg!(RngSeeded, format_args!("{:?}", rand::init()))); //播种内核随机数发生器
+    interrupts::init_timer(); //打开 PIT 时钟中断，看门狗靠它检查 CPU 是否卡住
+    match power::init() {
+        Some(acpi) if acpi.s5.is_some() => println!("{}", msg!(AcpiS5Found, acpi.revision)),
+        Some(acpi) => println!("{}", msg!(AcpiS5Missing, acpi.revision)),
+        None => println!("{}", msg!(AcpiNone)),
+    }
+    if let Some(extra) = drivers::fw_cfg::cmdline() {
+        cmdline::extend(&extra); //QEMU 主机追加的参数
+    }
+    if !cmdline::raw().is_empty() {
+        println!("{}", msg!(Cmdline, cmdline::raw()));
+    }
+    log::init(); //按 loglevel 设置各个日志输出端的级别
+    if let Some(layout) = cmdline::get("keymap") {
+        if !drivers::keymap::select(layout) {
+            println!("{}", msg!(UnknownKeymap, layout));
+        }
+    }
+    if let Some(name) = cmdline::get("lang") {
+        match i18n::Language::from_name(name) {
+            Some(lang) => i18n::set_language(lang),
+            None => println!("{}", msg!(UnknownLanguage, name)),
+        }
+    }
+    if let Err(mode) = bell::init() {
+        println!("{}", msg!(UnknownBellMode, mode));
+    }
+    if let Err(cpus) = thread::init() {
+        println!("{}", msg!(InvalidIsolcpus, cpus));
+    }
+    if cmdline::flag("nocoredump") {
+        coredump::set_enabled(false);
+    }
+    if cmdline::flag("noksm") {
+        ksm::set_enabled(false);
+    }
+    if cmdline::flag("nozerofill") {
+        zerofill::set_enabled(false);
+    }
+    //watchdog=<秒>，0 表示关闭
+    let timeout = cmdline::get("watchdog").and_then(|secs| secs.parse().ok()).unwrap_or(10);
+    watchdog::init(core::time::Duration::from_secs(timeout));
+
+    driver::init_all(); //按依赖顺序初始化全部驱动(PCI、ATA、virtio 块设备、网卡)
+    //每个接收队列一个线程，放在 housekeeping 的 CPU 上，空闲时也能回应 ARP 请求和 ping
+    if let Err(count) = net::rss::init() {
+        println!("{}", msg!(InvalidNetqueues, count));
+    }
+    if cmdline::flag("gdb") && gdbstub::available() {
+        gdbstub::breakpoint(); //等待 gdb 连接后再继续启动
+    }
+
+    fs::init(); //根目录为 ramfs，包含 /dev/console 和 /dev/null
+    loader::cache::init(); //文件变化时作废缓存的程序映像
+    match resources::install_files() {
+        Ok(0) => {}
+        Ok(count) => println!("{}", msg!(ResourcesInstalled, count)),
+        Err(err) => println!("{}", msg!(ResourcesFailed, format_args!("{:?}", err))),
+    }
+    match drivers::fw_cfg::inject_files() {
+        Ok(0) => {}
+        Ok(count) => println!("{}", msg!(FwCfgCopied, count)),
+        Err(err) => println!("{}", msg!(FwCfgFailed, format_args!("{:?}", err))),
+    }
+
+    //第一个 FAT32 卷挂载到 /boot，其余的挂载到 /mnt/<设备名>
+    let mut boot_mounted = false;
+    for (name, dev) in block::devices() {
+        if let Ok(fat) = fs::fat32::Fat32::mount(fs::cache::wrap(dev.clone())) {
+            let path = if boot_mounted { format!("/mnt/{}", name) } else { String::from("/boot") };
+            if boot_mounted {
+                let _ = fs::vfs::mkdir(&path);
+            }
+            if fs::vfs::mount_device(&path, fat.into_root(), dev).is_ok() {
+                println!("{}", msg!(Mounted, name, path));
+                boot_mounted = true;
+            }
+        }
+    }
+
+    //rconsole：启动时打开远程控制台，密钥来自 /etc/rconsole.key(可以由 fw_cfg 或挂载的卷提供)
+    if cmdline::flag("rconsole") {
+        if let Err(err) = rconsole::start() {
+            println!("{}", msg!(RconsoleFailed, format_args!("{:?}", err)));
+        }
+    }
+    if memlayout::enabled() {
+        memlayout::check(); //与 /etc/memlayout 中的基准比较
+    }
+    if screencheck::enabled() {
+        screencheck::check("boot"); //启动信息的黄金映像
+    }
+    if schedcheck::enabled() {
+        schedcheck::check(); //时钟和线程调度的顺序，与 /etc/schedtrace 比较
+    }
+    service::run(&SHELL_SERVICE); //shell 崩溃时自动重启，而不是让整个系统停机
+    loop {
+        x86_64::instructions::hlt(); //shell 不再重启时停在这里，时钟中断仍然照常处理
+    }
+}
+
+static SHELL_SERVICE: service::Service = service::Service {
+    name: "shell",
+    entry: shell_main,
+    policy: service::RestartPolicy::Always,
+};
+
+fn shell_main() {
+    shell::run();
 }
 
 // 这个函数将在 panic 时被调用
+#[cfg(not(test))]
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    emergency_println!("{}: {}", msg!(KernelPanic), info); //panic 时可能正持有输出的锁
+    screencheck::check_panic();
+    service::recover_from_panic(); //panic 发生在可重启的服务中时不会返回
     loop {}
 }
\ No newline at end of file
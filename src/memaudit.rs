@@ -0,0 +1,83 @@
+//启动时检查内核映射：除了 map_mmio 建立的设备内存区域，内核不应该直接映射固件保留的内存(保留区、ACPI)
+//或者内存布局中没有的地址(一般是设备内存)。写死的物理地址在 QEMU 上能用，在真机上可能正好落在固件的区域里
+//已知的合法例外(VGA 文本缓冲区)列在 ALLOWED 里
+use crate::memory;
+use crate::println;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use bootloader::bootinfo::MemoryRegionType;
+
+const PAGE_SIZE: u64 = 4096;
+const MAX_REPORTED: usize = 16; //最多打印的违规条数
+
+//允许直接映射的物理地址范围
+const ALLOWED: &[(u64, u64, &str)] = &[(0xb8000, 0xc0000, "VGA text buffer")];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Violation {
+    pub virt: u64,
+    pub phys: u64,
+    pub size: u64,
+    pub region: Option<MemoryRegionType>, //None 表示不在内存布局中
+}
+
+//物理页面所在区域的类型，不在任何区域中时返回 None
+fn region_of(phys: u64) -> Option<MemoryRegionType> {
+    memory::memory_map()
+        .iter()
+        .find(|r| r.range.start_addr() <= phys && phys < r.range.end_addr())
+        .map(|r| r.region_type)
+}
+
+fn forbidden(region: Option<MemoryRegionType>) -> bool {
+    matches!(
+        region,
+        None | Some(MemoryRegionType::Reserved)
+            | Some(MemoryRegionType::AcpiReclaimable)
+            | Some(MemoryRegionType::AcpiNvs)
+            | Some(MemoryRegionType::BadMemory)
+            | Some(MemoryRegionType::FrameZero)
+    )
+}
+
+//找出全部违规的映射，虚拟地址和物理地址都连续、区域类型相同的页面合并成一条
+pub fn audit() -> Vec<Violation> {
+    let mut found: Vec<Violation> = Vec::new();
+    memory::for_each_kernel_mapping(&mut |virt, phys, size, _| {
+        for offset in (0..size).step_by(PAGE_SIZE as usize) {
+            let (virt, phys) = (virt + offset, phys + offset);
+            if ALLOWED.iter().any(|&(start, end, _)| start <= phys && phys < end) {
+                continue;
+            }
+            let region = region_of(phys);
+            if !forbidden(region) {
+                continue;
+            }
+            match found.last_mut() {
+                Some(last) if last.virt + last.size == virt && last.phys + last.size == phys && last.region == region => {
+                    last.size += PAGE_SIZE;
+                }
+                _ => found.push(Violation { virt, phys, size: PAGE_SIZE, region }),
+            }
+        }
+    });
+    found
+}
+
+//启动时调用，打印检查结果
+pub fn run() {
+    let violations = audit();
+    if violations.is_empty() {
+        println!("memory audit: no kernel mapping overlaps reserved regions");
+        return;
+    }
+    println!("memory audit: {} kernel mapping(s) overlap reserved regions", violations.len());
+    for v in violations.iter().take(MAX_REPORTED) {
+        let region = match v.region {
+            Some(region) => format!("{:?}", region),
+            None => String::from("not in memory map"),
+        };
+        println!("  virt {:#x}..{:#x} -> phys {:#x}..{:#x}: {}", v.virt, v.virt + v.size, v.phys, v.phys + v.size, region);
+    }
+}
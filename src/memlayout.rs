@@ -0,0 +1,161 @@
+//内存布局报告：启动完成后内核的内存布局，每行一项，用来比较两次构建的布局有没有意外的变化
+//  kernel <r-x|r--|rw-|rwx> <起点> <终点>   内核自己的映射，虚拟地址连续、权限相同的页面合成一段(代码、只读数据、数据和 bss、栈)
+//  heap kernel <起点> <终点>                内核堆
+//  stack boot|interrupt <起点> <终点>        启动时的内核栈(shell 也在上面运行)和 TSS 中从用户态进入内核时使用的栈
+//  mmio <序号> <起点> <终点> <物理地址>      map_mmio 建立的设备内存映射，按建立的顺序
+//  physmap offset <起点> <终点>              物理内存的线性映射
+//  phys <类型> <起点> <终点>                 bootloader 给出的物理内存布局
+//地址都是 16 位十六进制；报告里没有随运行变化的内容(进程的内核栈在堆里，不单独列出)，同样的构建和机器配置每次得到同样的报告
+//基准保存在 /etc/memlayout，diff 逐行比较，"-" 是基准中有而现在没有的行，"+" 相反
+//命令行开关 memlayout 在启动完成时把报告和与基准的比较结果写到 COM1，每行以 "memlayout: " 开头，CI 可以据此发现布局漂移
+use crate::allocator;
+use crate::drivers::uart::{self, Uart};
+use crate::fs::vfs;
+use crate::fs::FsError;
+use crate::gdt;
+use crate::memory;
+use crate::{cmdline, println};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use x86_64::structures::paging::PageTableFlags;
+
+pub const BASELINE_PATH: &str = "/etc/memlayout";
+
+fn line(kind: &str, name: &str, start: u64, end: u64) -> String {
+    format!("{} {} {:016x} {:016x}", kind, name, start, end)
+}
+
+fn permissions(flags: PageTableFlags) -> &'static str {
+    match (flags.contains(PageTableFlags::WRITABLE), flags.contains(PageTableFlags::NO_EXECUTE)) {
+        (false, false) => "r-x",
+        (false, true) => "r--",
+        (true, true) => "rw-",
+        (true, false) => "rwx",
+    }
+}
+
+//内核映射按权限合成的段：(起点, 终点, 权限)
+fn kernel_segments() -> Vec<(u64, u64, &'static str)> {
+    let mut segments: Vec<(u64, u64, &'static str)> = Vec::new();
+    memory::for_each_kernel_mapping(&mut |virt, _, size, flags| {
+        let perm = permissions(flags);
+        match segments.last_mut() {
+            Some(last) if last.1 == virt && last.2 == perm => last.1 += size,
+            _ => segments.push((virt, virt + size, perm)),
+        }
+    });
+    segments
+}
+
+fn current_rsp() -> u64 {
+    let rsp: u64;
+    unsafe { core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack)) };
+    rsp
+}
+
+//生成报告，每项一行
+pub fn report() -> Vec<String> {
+    let mut lines = Vec::new();
+    let segments = kernel_segments();
+    for &(start, end, perm) in &segments {
+        lines.push(line("kernel", perm, start, end));
+    }
+    let (start, end) = allocator::heap_range();
+    lines.push(line("heap", "kernel", start, end));
+    let rsp = current_rsp();
+    if let Some(&(start, end, _)) = segments.iter().find(|&&(start, end, _)| start <= rsp && rsp < end) {
+        lines.push(line("stack", "boot", start, end));
+    }
+    let (start, end) = gdt::privilege_stack();
+    lines.push(line("stack", "interrupt", start, end));
+    for (i, mapping) in memory::mmio_mappings().iter().enumerate() {
+        let name = format!("{}", i);
+        lines.push(format!("{} {:016x}", line("mmio", &name, mapping.virt, mapping.virt + mapping.size), mapping.phys));
+    }
+    let offset = memory::physical_memory_offset();
+    let phys_end = memory::memory_map().iter().map(|r| r.range.end_addr()).max().unwrap_or(0);
+    lines.push(line("physmap", "offset", offset, offset + phys_end));
+    for region in memory::memory_map().iter() {
+        let kind = format!("{:?}", region.region_type);
+        lines.push(line("phys", &kind, region.range.start_addr(), region.range.end_addr()));
+    }
+    lines
+}
+
+//把当前的报告保存为基准
+pub fn save(path: &str) -> Result<usize, FsError> {
+    let lines = report();
+    let mut text = String::new();
+    for line in &lines {
+        text.push_str(line);
+        text.push('\n');
+    }
+    if vfs::metadata(path).is_ok() {
+        vfs::remove(path)?;
+    }
+    let mut file = vfs::create(path)?;
+    let mut data = text.as_bytes();
+    while !data.is_empty() {
+        match file.write(data)? {
+            0 => return Err(FsError::NoSpace),
+            n => data = &data[n..],
+        }
+    }
+    Ok(lines.len())
+}
+
+pub fn baseline(path: &str) -> Result<Vec<String>, FsError> {
+    let data = vfs::open(path)?.read_to_end()?;
+    let text = String::from_utf8_lossy(&data);
+    Ok(text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).map(String::from).collect())
+}
+
+//和基准比较，返回不同的行：基准中有而现在没有的以 "- " 开头，现在有而基准中没有的以 "+ " 开头
+pub fn diff(baseline: &[String], current: &[String]) -> Vec<String> {
+    let mut out = Vec::new();
+    for line in baseline.iter().filter(|line| !current.contains(line)) {
+        out.push(format!("- {}", line));
+    }
+    for line in current.iter().filter(|line| !baseline.contains(line)) {
+        out.push(format!("+ {}", line));
+    }
+    out
+}
+
+pub fn enabled() -> bool {
+    cmdline::flag("memlayout")
+}
+
+//启动完成时调用：报告和比较结果写到串口，有漂移时在控制台上警告
+pub fn check() {
+    let current = report();
+    let mut port = Uart::new(uart::COM1);
+    let serial = port.present();
+    if serial {
+        for line in &current {
+            let _ = writeln!(port, "memlayout: {}", line);
+        }
+    }
+    let result = match baseline(BASELINE_PATH) {
+        Ok(baseline) => {
+            let differences = diff(&baseline, &current);
+            if serial {
+                for line in &differences {
+                    let _ = writeln!(port, "memlayout: {}", line);
+                }
+            }
+            if differences.is_empty() {
+                String::from("match")
+            } else {
+                println!("memlayout: {} lines differ from {}", differences.len(), BASELINE_PATH);
+                format!("DRIFT {}", differences.len())
+            }
+        }
+        Err(_) => String::from("new"),
+    };
+    if serial {
+        let _ = writeln!(port, "memlayout: result {}", result);
+    }
+}
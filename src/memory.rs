@@ -0,0 +1,670 @@
+//分页和物理内存管理：bootloader 把全部物理内存映射到 physical_memory_offset 开始的虚拟地址上
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use crate::sync;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::registers::control::Cr3;
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::structures::paging::mapper::{MappedFrame, Translate, TranslateResult};
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags,
+    PhysFrame, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryError {
+    OutOfFrames, //没有可用的物理帧
+    MapFailed,   //页表操作失败(例如地址已被大页映射)
+}
+
+//内核的页表和物理帧分配器，在 init 之后可用
+pub static MAPPER: sync::Mutex<Option<OffsetPageTable<'static>>> = sync::Mutex::new("MAPPER", None);
+pub static FRAME_ALLOCATOR: sync::Mutex<Option<BootInfoFrameAllocator>> = sync::Mutex::new("FRAME_ALLOCATOR", None);
+
+static PHYSICAL_MEMORY_OFFSET: Mutex<Option<VirtAddr>> = Mutex::new(None);
+
+//页表项中留给操作系统使用的位
+pub const COW: PageTableFlags = PageTableFlags::BIT_9; //写时复制：原来可写的页面，fork 之后暂时只读
+pub const SHARED: PageTableFlags = PageTableFlags::BIT_10; //共享内存段的页面：fork 后仍然共享，回收地址空间时不释放
+
+//被多个地址空间映射的物理帧的额外引用数(引用数为 1 的帧不在表里)
+static FRAME_REFS: Mutex<BTreeMap<PhysFrame, usize>> = Mutex::new(BTreeMap::new());
+
+//需要清零的帧的分配次数：从预先清零的帧中取到的和只能当场清零的
+static ZERO_HITS: AtomicU64 = AtomicU64::new(0);
+static ZERO_MISSES: AtomicU64 = AtomicU64::new(0);
+
+//设备内存(帧缓冲、网卡寄存器等)映射到这个区域，按顺序分配，不回收
+const MMIO_BASE: u64 = 0x5000_0000_0000;
+const MMIO_SIZE: u64 = 0x80_0000_0000; //一个 4 级页表项覆盖的 512 GiB
+static MMIO_NEXT: Mutex<u64> = Mutex::new(MMIO_BASE);
+static MMIO_MAPPINGS: Mutex<Vec<MmioMapping>> = Mutex::new(Vec::new()); //按建立的顺序
+
+//map_mmio 建立的一段映射，地址按页对齐
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmioMapping {
+    pub virt: u64,
+    pub phys: u64,
+    pub size: u64,
+}
+
+pub fn init(physical_memory_offset: VirtAddr, memory_map: &'static MemoryMap) {
+    unsafe {
+        Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE)); //允许使用页表中的 NO_EXECUTE 位
+    }
+    *PHYSICAL_MEMORY_OFFSET.lock() = Some(physical_memory_offset);
+    let level_4_table = unsafe { active_level_4_table(physical_memory_offset) };
+    *MAPPER.lock() = Some(unsafe { OffsetPageTable::new(level_4_table, physical_memory_offset) });
+    *FRAME_ALLOCATOR.lock() = Some(BootInfoFrameAllocator::new(memory_map));
+    reserve_mmio_table();
+}
+
+//预先建好设备内存区域的 3 级页表，之后新建的和已有的地址空间都共享同一个 4 级页表项
+fn reserve_mmio_table() {
+    let frame = FRAME_ALLOCATOR
+        .lock()
+        .as_mut()
+        .unwrap()
+        .allocate_frame()
+        .expect("no frame for the MMIO page table");
+    let table: &mut PageTable = unsafe { &mut *phys_to_virt(frame.start_address()).as_mut_ptr() };
+    table.zero();
+    let mut mapper = MAPPER.lock();
+    let level_4 = mapper.as_mut().unwrap().level_4_table();
+    let index = Page::<Size4KiB>::containing_address(VirtAddr::new(MMIO_BASE)).p4_index();
+    level_4[index].set_frame(frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+}
+
+//把一段物理地址(设备寄存器或显存)映射到内核的设备内存区域，返回对应的虚拟地址
+//extra 是额外的页表标志，例如 NO_CACHE
+pub fn map_mmio(phys: PhysAddr, size: u64, extra: PageTableFlags) -> Result<VirtAddr, MemoryError> {
+    let first = PhysFrame::<Size4KiB>::containing_address(phys);
+    let last = PhysFrame::<Size4KiB>::containing_address(phys + size.max(1) - 1u64);
+    let pages = last.start_address().as_u64() / 4096 - first.start_address().as_u64() / 4096 + 1;
+
+    let base = {
+        let mut next = MMIO_NEXT.lock();
+        if *next + pages * 4096 > MMIO_BASE + MMIO_SIZE {
+            return Err(MemoryError::MapFailed);
+        }
+        let base = *next;
+        *next += pages * 4096;
+        base
+    };
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE | extra;
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().expect("memory::init not called");
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().expect("memory::init not called");
+    for (i, frame) in PhysFrame::range_inclusive(first, last).enumerate() {
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(base + i as u64 * 4096));
+        unsafe { mapper.map_to(page, frame, flags, allocator) }
+            .map_err(|_| MemoryError::MapFailed)?
+            .flush();
+    }
+    MMIO_MAPPINGS.lock().push(MmioMapping { virt: base, phys: first.start_address().as_u64(), size: pages * 4096 });
+    Ok(VirtAddr::new(base + phys.as_u64() % 4096))
+}
+
+pub fn mmio_mappings() -> Vec<MmioMapping> {
+    MMIO_MAPPINGS.lock().clone()
+}
+
+//物理内存线性映射的起点
+pub fn physical_memory_offset() -> u64 {
+    PHYSICAL_MEMORY_OFFSET.lock().expect("memory::init not called").as_u64()
+}
+
+//新建一个地址空间：复制当前 4 级页表中内核部分的表项，用户部分留空
+//只有用户页面所在的表项带有 USER_ACCESSIBLE 标志(见 set_user_accessible_parents)，据此区分两者
+pub fn new_address_space() -> Result<PhysFrame, MemoryError> {
+    let frame = FRAME_ALLOCATOR
+        .lock()
+        .as_mut()
+        .expect("memory::init not called")
+        .allocate_frame()
+        .ok_or(MemoryError::OutOfFrames)?;
+    let mut mapper = MAPPER.lock();
+    let current = mapper.as_mut().expect("memory::init not called").level_4_table();
+    let table: &mut PageTable = unsafe { &mut *phys_to_virt(frame.start_address()).as_mut_ptr() };
+    table.zero();
+    for (dst, src) in table.iter_mut().zip(current.iter()) {
+        if !src.flags().contains(PageTableFlags::USER_ACCESSIBLE) {
+            *dst = src.clone();
+        }
+    }
+    Ok(frame)
+}
+
+//切换到 frame 指向的 4 级页表，之后的映射操作都作用于新的地址空间，返回原来的页表
+pub fn switch_address_space(frame: PhysFrame) -> PhysFrame {
+    let offset = PHYSICAL_MEMORY_OFFSET.lock().expect("memory::init not called");
+    let mut mapper = MAPPER.lock();
+    let (old, flags) = Cr3::read();
+    unsafe {
+        Cr3::write(frame, flags);
+    }
+    let table: &'static mut PageTable = unsafe { &mut *(offset + frame.start_address().as_u64()).as_mut_ptr() };
+    *mapper = Some(unsafe { OffsetPageTable::new(table, offset) });
+    old
+}
+
+//分配 frames 个物理上连续的帧并清零，给需要 DMA 的设备使用，返回物理地址和可以直接访问的虚拟地址
+//分配器按顺序分配，遇到不连续的地方(内存区域的边界)时丢弃已分配的帧重新开始
+pub fn alloc_dma(frames: usize) -> Result<(PhysAddr, VirtAddr), MemoryError> {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().expect("memory::init not called");
+    let mut first = allocator.allocate_frame().ok_or(MemoryError::OutOfFrames)?;
+    let mut count = 1;
+    while count < frames {
+        let frame = allocator.allocate_frame().ok_or(MemoryError::OutOfFrames)?;
+        if frame.start_address() == first.start_address() + count as u64 * 4096 {
+            count += 1;
+        } else {
+            first = frame;
+            count = 1;
+        }
+    }
+    let virt = phys_to_virt(first.start_address());
+    unsafe { core::ptr::write_bytes(virt.as_mut_ptr::<u8>(), 0, frames * 4096) };
+    Ok((first.start_address(), virt))
+}
+
+//把物理地址转换为可以直接访问的虚拟地址
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    let offset = PHYSICAL_MEMORY_OFFSET.lock().expect("memory::init not called");
+    offset + addr.as_u64()
+}
+
+//映射 [start, start + size) 覆盖的所有页面并清零，页面已映射时沿用原来的物理帧，只更新标志位
+//用户页面需要在各级页表项上都带有 USER_ACCESSIBLE 标志，CPU 才允许 ring 3 访问
+pub fn map_user_range(start: VirtAddr, size: u64, flags: PageTableFlags) -> Result<(), MemoryError> {
+    let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().expect("memory::init not called");
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().expect("memory::init not called");
+
+    let first = Page::<Size4KiB>::containing_address(start);
+    let last = Page::<Size4KiB>::containing_address(start + size.max(1) - 1u64);
+    for page in Page::range_inclusive(first, last) {
+        let frame = match mapper.translate_page(page) {
+            Ok(frame) => {
+                unsafe { mapper.update_flags(page, flags) }.map_err(|_| MemoryError::MapFailed)?.flush();
+                frame
+            }
+            Err(_) => {
+                let frame = allocator.allocate_zeroed().ok_or(MemoryError::OutOfFrames)?;
+                unsafe { mapper.map_to(page, frame, flags, allocator) }
+                    .map_err(|_| MemoryError::MapFailed)?
+                    .flush();
+                set_user_accessible_parents(mapper, page);
+                continue;
+            }
+        };
+        set_user_accessible_parents(mapper, page);
+        let virt = mapper.phys_offset() + frame.start_address().as_u64();
+        unsafe {
+            core::ptr::write_bytes(virt.as_mut_ptr::<u8>(), 0, 4096);
+        }
+    }
+    x86_64::instructions::tlb::flush_all(); //上级页表项的标志变了，刷新整个 TLB
+    Ok(())
+}
+
+//修改已映射的用户页面的权限(不清零内容)，例如加载器拷贝完代码后去掉可写标志
+pub fn update_user_flags(start: VirtAddr, size: u64, flags: PageTableFlags) -> Result<(), MemoryError> {
+    let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().expect("memory::init not called");
+    let first = Page::<Size4KiB>::containing_address(start);
+    let last = Page::<Size4KiB>::containing_address(start + size.max(1) - 1u64);
+    for page in Page::range_inclusive(first, last) {
+        unsafe { mapper.update_flags(page, flags) }.map_err(|_| MemoryError::MapFailed)?.flush();
+    }
+    Ok(())
+}
+
+//取消 [start, start + size) 中已映射页面的映射，没有映射的页面跳过，返回原来映射的物理帧
+pub fn unmap_user_range(start: VirtAddr, size: u64) -> Vec<PhysFrame> {
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().expect("memory::init not called");
+    let first = Page::<Size4KiB>::containing_address(start);
+    let last = Page::<Size4KiB>::containing_address(start + size.max(1) - 1u64);
+    let mut frames = Vec::new();
+    for page in Page::range_inclusive(first, last) {
+        if let Ok((frame, flush)) = mapper.unmap(page) {
+            flush.flush();
+            frames.push(frame);
+        }
+    }
+    frames
+}
+
+//把 frame 映射到用户页面 page(不清零)，用于多个进程共享同一个物理帧
+pub fn map_user_frame(page: VirtAddr, frame: PhysFrame, flags: PageTableFlags) -> Result<(), MemoryError> {
+    let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().expect("memory::init not called");
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().expect("memory::init not called");
+    let page = Page::<Size4KiB>::containing_address(page);
+    unsafe { mapper.map_to(page, frame, flags | SHARED, allocator) }.map_err(|_| MemoryError::MapFailed)?.flush();
+    set_user_accessible_parents(mapper, page);
+    x86_64::instructions::tlb::flush_all();
+    Ok(())
+}
+
+//分配一个清零的物理帧
+pub fn alloc_frame() -> Result<PhysFrame, MemoryError> {
+    FRAME_ALLOCATOR
+        .lock()
+        .as_mut()
+        .expect("memory::init not called")
+        .allocate_zeroed()
+        .ok_or(MemoryError::OutOfFrames)
+}
+
+fn zero_frame(frame: PhysFrame) {
+    unsafe { core::ptr::write_bytes(phys_to_virt(frame.start_address()).as_mut_ptr::<u8>(), 0, 4096) };
+}
+
+//给页面的 4、3、2 级页表项加上 USER_ACCESSIBLE 标志
+fn set_user_accessible_parents(mapper: &mut OffsetPageTable<'static>, page: Page<Size4KiB>) {
+    let offset = mapper.phys_offset();
+    let mut table: &mut PageTable = mapper.level_4_table();
+    for index in [page.p4_index(), page.p3_index(), page.p2_index()] {
+        let entry = &mut table[index];
+        entry.set_flags(entry.flags() | PageTableFlags::USER_ACCESSIBLE);
+        let next = offset + entry.addr().as_u64();
+        table = unsafe { &mut *next.as_mut_ptr() };
+    }
+}
+
+//检查 [start, start + size) 中的页面是否都已映射
+pub fn is_mapped(start: VirtAddr, size: u64) -> bool {
+    let mapper = MAPPER.lock();
+    let mapper = match mapper.as_ref() {
+        Some(mapper) => mapper,
+        None => return false,
+    };
+    let first = Page::<Size4KiB>::containing_address(start);
+    let last = Page::<Size4KiB>::containing_address(start + size.max(1) - 1u64);
+    Page::range_inclusive(first, last).all(|page| mapper.translate_page(page).is_ok())
+}
+
+//检查 [start, start + size) 中的页面是否都已映射并且可写
+pub fn is_writable(start: VirtAddr, size: u64) -> bool {
+    let mapper = MAPPER.lock();
+    let mapper = match mapper.as_ref() {
+        Some(mapper) => mapper,
+        None => return false,
+    };
+    let first = Page::<Size4KiB>::containing_address(start);
+    let last = Page::<Size4KiB>::containing_address(start + size.max(1) - 1u64);
+    Page::range_inclusive(first, last).all(|page| match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped { flags, .. } => flags.contains(PageTableFlags::WRITABLE),
+        _ => false,
+    })
+}
+
+//addr 在物理内存线性映射中的别名，通过它写入不受页面只读属性的限制(调试器在代码里写断点)
+//调试异常里调用，所以不等待锁，页表正被修改或者 addr 没有映射时返回 None
+pub fn linear_alias(addr: VirtAddr) -> Option<VirtAddr> {
+    let mapper = MAPPER.try_lock()?;
+    let mapper = mapper.as_ref()?;
+    let phys = mapper.translate_addr(addr)?;
+    Some(mapper.phys_offset() + phys.as_u64())
+}
+
+//bootloader 提供的物理内存布局
+pub fn memory_map() -> &'static MemoryMap {
+    FRAME_ALLOCATOR.lock().as_ref().expect("memory::init not called").memory_map
+}
+
+//依次访问当前地址空间中内核自己建立的映射：f(虚拟地址, 物理地址, 大小, 页表项标志)，大页按整页给出
+//跳过用户页面、物理内存的线性映射(从 physical_memory_offset 开始)和 map_mmio 使用的设备内存区域
+pub fn for_each_kernel_mapping(f: &mut dyn FnMut(u64, u64, u64, PageTableFlags)) {
+    let offset = PHYSICAL_MEMORY_OFFSET.lock().expect("memory::init not called").as_u64();
+    let phys_end = memory_map().iter().map(|r| r.range.end_addr()).max().unwrap_or(0);
+    let level_4 = unsafe { active_level_4_table(VirtAddr::new(offset)) };
+    for (i, entry) in level_4.iter().enumerate() {
+        //4 级页表的后一半是高半区，虚拟地址要做符号扩展
+        let virt = VirtAddr::new_truncate((i as u64) << 39).as_u64();
+        let end = virt + (1 << 39);
+        let linear = virt < offset + phys_end && end > offset;
+        let mmio = virt < MMIO_BASE + MMIO_SIZE && end > MMIO_BASE;
+        if entry.is_unused() || entry.flags().contains(PageTableFlags::USER_ACCESSIBLE) || linear || mmio {
+            continue;
+        }
+        walk_kernel(next_table(entry), 3, virt, f);
+    }
+}
+
+fn walk_kernel(table: &PageTable, level: u8, base: u64, f: &mut dyn FnMut(u64, u64, u64, PageTableFlags)) {
+    let size = 1u64 << (12 + 9 * (level as u64 - 1)); //这一级每个表项覆盖的大小
+    for (i, entry) in table.iter().enumerate().filter(|(_, entry)| !entry.is_unused()) {
+        let virt = base + i as u64 * size;
+        if level == 1 || entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            f(virt, entry.addr().as_u64(), size, entry.flags());
+        } else {
+            walk_kernel(next_table(entry), level - 1, virt, f);
+        }
+    }
+}
+
+//依次访问当前地址空间中映射的用户页面：f(虚拟地址, 页表项标志)，地址从低到高；用户页面都是 4 KiB 的页
+pub fn for_each_user_page(f: &mut dyn FnMut(u64, PageTableFlags)) {
+    let (frame, _) = Cr3::read();
+    for (i, entry) in next_table_of(frame).iter().enumerate() {
+        if !entry.is_unused() && entry.flags().contains(PageTableFlags::USER_ACCESSIBLE) {
+            walk_user(next_table(entry), 3, (i as u64) << 39, f); //用户空间在低半区，不用符号扩展
+        }
+    }
+}
+
+fn walk_user(table: &PageTable, level: u8, base: u64, f: &mut dyn FnMut(u64, PageTableFlags)) {
+    let size = 1u64 << (12 + 9 * (level as u64 - 1));
+    for (i, entry) in table.iter().enumerate().filter(|(_, entry)| !entry.is_unused()) {
+        let virt = base + i as u64 * size;
+        if level == 1 {
+            f(virt, entry.flags());
+        } else {
+            walk_user(next_table(entry), level - 1, virt, f);
+        }
+    }
+}
+
+//返回当前 CR3 指向的 4 级页表
+unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
+    let (level_4_table_frame, _) = Cr3::read();
+    let virt = physical_memory_offset + level_4_table_frame.start_address().as_u64();
+    &mut *virt.as_mut_ptr()
+}
+
+//从 bootloader 提供的内存布局中依次分配可用的物理帧，回收的帧放进空闲链表，优先再次使用
+//空闲时 zerofill 把空闲链表上的帧清零后移到 zeroed，需要清零的分配先从 zeroed 取，其他分配最后才用它们
+pub struct BootInfoFrameAllocator {
+    memory_map: &'static MemoryMap,
+    next: usize,
+    free: Vec<PhysFrame>,
+    zeroed: Vec<PhysFrame>,
+}
+
+impl BootInfoFrameAllocator {
+    fn new(memory_map: &'static MemoryMap) -> BootInfoFrameAllocator {
+        BootInfoFrameAllocator { memory_map, next: 0, free: Vec::new(), zeroed: Vec::new() }
+    }
+
+    //分配一个清零的帧，没有预先清零的帧时当场清零
+    fn allocate_zeroed(&mut self) -> Option<PhysFrame> {
+        if let Some(frame) = self.zeroed.pop() {
+            ZERO_HITS.fetch_add(1, Ordering::Relaxed);
+            return Some(frame);
+        }
+        let frame = self.allocate_frame()?;
+        ZERO_MISSES.fetch_add(1, Ordering::Relaxed);
+        zero_frame(frame);
+        Some(frame)
+    }
+
+    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
+        self.memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable)
+            .map(|r| r.range.start_addr()..r.range.end_addr())
+            .flat_map(|r| r.step_by(4096))
+            .map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        if let Some(frame) = self.free.pop() {
+            return Some(frame);
+        }
+        if let Some(frame) = self.usable_frames().nth(self.next) {
+            self.next += 1;
+            return Some(frame);
+        }
+        self.zeroed.pop()
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.free.push(frame);
+    }
+}
+
+//bootloader 报告的可用物理帧总数
+pub fn total_frame_count() -> usize {
+    FRAME_ALLOCATOR.lock().as_ref().expect("memory::init not called").usable_frames().count()
+}
+
+//还能分配的物理帧数(空闲链表加上从未分配过的)
+pub fn free_frame_count() -> usize {
+    let allocator = FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_ref().expect("memory::init not called");
+    allocator.free.len() + allocator.zeroed.len() + allocator.usable_frames().count().saturating_sub(allocator.next)
+}
+
+//空闲时调用：把空闲链表上最多 max 个帧清零后移到预先清零的帧中，返回清零的帧数
+//清零时不持有分配器的锁，正在清零的帧暂时不算空闲
+pub fn zero_free_frames(max: usize) -> usize {
+    let mut count = 0;
+    while count < max {
+        let frame = match FRAME_ALLOCATOR.lock().as_mut().expect("memory::init not called").free.pop() {
+            Some(frame) => frame,
+            None => break,
+        };
+        zero_frame(frame);
+        FRAME_ALLOCATOR.lock().as_mut().expect("memory::init not called").zeroed.push(frame);
+        count += 1;
+    }
+    count
+}
+
+//(预先清零的帧数, 空闲链表上还没有清零的帧数)
+pub fn zero_pool() -> (usize, usize) {
+    let allocator = FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_ref().expect("memory::init not called");
+    (allocator.zeroed.len(), allocator.free.len())
+}
+
+//需要清零的分配中(直接取到预先清零的帧的次数, 当场清零的次数)
+pub fn zero_hits() -> (u64, u64) {
+    (ZERO_HITS.load(Ordering::Relaxed), ZERO_MISSES.load(Ordering::Relaxed))
+}
+
+//回收不再使用的物理帧，调用者要保证没有页表还映射着它
+pub fn free_frame(frame: PhysFrame) {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    unsafe { allocator.as_mut().expect("memory::init not called").deallocate_frame(frame) };
+}
+
+//释放用户页面对物理帧的一个引用，没有其他地址空间映射它时回收
+pub fn release_frame(frame: PhysFrame) {
+    let mut refs = FRAME_REFS.lock();
+    match refs.get_mut(&frame) {
+        Some(extra) if *extra > 1 => *extra -= 1,
+        Some(_) => {
+            refs.remove(&frame);
+        }
+        None => free_frame(frame),
+    }
+}
+
+fn add_frame_ref(frame: PhysFrame) {
+    *FRAME_REFS.lock().entry(frame).or_insert(0) += 1;
+}
+
+//页表项指向的下一级页表
+fn next_table(entry: &PageTableEntry) -> &'static mut PageTable {
+    unsafe { &mut *phys_to_virt(entry.addr()).as_mut_ptr() }
+}
+
+//复制 frame 指向的地址空间(fork)：内核部分与当前地址空间相同，用户页面由两个地址空间共享
+//原来可写的私有页面在两边都改成只读并打上 COW 标记，任何一方写入时在缺页异常中复制
+//调用时 frame 必须是当前地址空间，返回新地址空间的 4 级页表
+pub fn clone_address_space(frame: PhysFrame) -> Result<PhysFrame, MemoryError> {
+    let child = new_address_space()?;
+    let src = next_table_of(frame);
+    let dst = next_table_of(child);
+    for (i, entry) in src.iter().enumerate() {
+        if entry.is_unused() || !entry.flags().contains(PageTableFlags::USER_ACCESSIBLE) {
+            continue;
+        }
+        dst[i].set_frame(clone_table(next_table(entry), 3)?, entry.flags());
+    }
+    x86_64::instructions::tlb::flush_all(); //当前地址空间中的页面变成了只读
+    Ok(child)
+}
+
+fn next_table_of(frame: PhysFrame) -> &'static mut PageTable {
+    unsafe { &mut *phys_to_virt(frame.start_address()).as_mut_ptr() }
+}
+
+//复制 level 级页表(3、2、1)，1 级页表中的页面按 clone_address_space 的说明共享
+fn clone_table(src: &mut PageTable, level: u8) -> Result<PhysFrame, MemoryError> {
+    let frame = alloc_frame()?;
+    let dst = next_table_of(frame);
+    for (i, entry) in src.iter_mut().enumerate() {
+        if entry.is_unused() {
+            continue;
+        }
+        if level > 1 {
+            dst[i].set_frame(clone_table(next_table(entry), level - 1)?, entry.flags());
+            continue;
+        }
+        let mut flags = entry.flags();
+        if !flags.contains(SHARED) {
+            if flags.contains(PageTableFlags::WRITABLE) {
+                flags = (flags - PageTableFlags::WRITABLE) | COW;
+                entry.set_flags(flags);
+            }
+            add_frame_ref(PhysFrame::containing_address(entry.addr()));
+        }
+        dst[i].set_addr(entry.addr(), flags);
+    }
+    Ok(frame)
+}
+
+//回收 frame 指向的地址空间中的用户页面和页表(exec 替换程序或进程退出后)
+//共享内存段的页面由段自己回收；调用时 frame 不能是当前地址空间
+pub fn free_address_space(frame: PhysFrame) {
+    let table = next_table_of(frame);
+    for entry in table.iter() {
+        if !entry.is_unused() && entry.flags().contains(PageTableFlags::USER_ACCESSIBLE) {
+            free_table(next_table(entry), 3);
+            free_frame(PhysFrame::containing_address(entry.addr()));
+        }
+    }
+    free_frame(frame);
+}
+
+fn free_table(table: &mut PageTable, level: u8) {
+    for entry in table.iter().filter(|entry| !entry.is_unused()) {
+        let frame = PhysFrame::containing_address(entry.addr());
+        if level > 1 {
+            free_table(next_table(entry), level - 1);
+            free_frame(frame);
+        } else if !entry.flags().contains(SHARED) {
+            release_frame(frame);
+        }
+    }
+}
+
+//依次访问 space 中每个映射了私有用户页面的 1 级页表项，共享内存段的页面不在其中
+pub fn for_each_private_page(space: PhysFrame, f: &mut dyn FnMut(&mut PageTableEntry)) {
+    walk_private(next_table_of(space), 4, f);
+}
+
+fn walk_private(table: &mut PageTable, level: u8, f: &mut dyn FnMut(&mut PageTableEntry)) {
+    for entry in table.iter_mut().filter(|entry| !entry.is_unused()) {
+        if level == 4 && !entry.flags().contains(PageTableFlags::USER_ACCESSIBLE) {
+            continue;
+        }
+        if level > 1 {
+            walk_private(next_table(entry), level - 1, f);
+        } else if !entry.flags().contains(SHARED) {
+            f(entry);
+        }
+    }
+}
+
+//space 中映射的私有页面数(常驻集大小，与其他进程共享的页面也计算在内)
+pub fn resident_pages(space: PhysFrame) -> u64 {
+    let mut pages = 0;
+    for_each_private_page(space, &mut |_| pages += 1);
+    pages
+}
+
+//让 entry 和其他映射共享它的帧：可写的页面改成只读并打上 COW 标记
+pub fn make_cow(entry: &mut PageTableEntry) {
+    let flags = entry.flags();
+    if flags.contains(PageTableFlags::WRITABLE) {
+        entry.set_flags((flags - PageTableFlags::WRITABLE) | COW);
+    }
+}
+
+//让 entry 改为映射内容相同的 frame(同时变成写时复制)，原来的帧释放一个引用
+//调用后需要刷新 TLB
+pub fn remap_shared(entry: &mut PageTableEntry, frame: PhysFrame) {
+    let old = PhysFrame::containing_address(entry.addr());
+    make_cow(entry);
+    add_frame_ref(frame);
+    entry.set_frame(frame, entry.flags());
+    release_frame(old);
+}
+
+//当前地址空间中 page 是否是写时复制的页面
+pub fn is_cow(page: VirtAddr) -> bool {
+    let mapper = MAPPER.lock();
+    match mapper.as_ref().map(|mapper| mapper.translate(page)) {
+        Some(TranslateResult::Mapped { flags, .. }) => flags.contains(COW),
+        _ => false,
+    }
+}
+
+//处理对写时复制页面的写入：帧只剩这一个引用时直接恢复可写，否则复制到新的帧
+pub fn copy_on_write(page: VirtAddr) -> Result<(), MemoryError> {
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().expect("memory::init not called");
+    let page = Page::<Size4KiB>::containing_address(page);
+    let (frame, flags) = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped { frame: MappedFrame::Size4KiB(frame), flags, .. } if flags.contains(COW) => (frame, flags),
+        _ => return Err(MemoryError::MapFailed),
+    };
+    let flags = (flags - COW) | PageTableFlags::WRITABLE;
+    let mut refs = FRAME_REFS.lock();
+    match refs.get_mut(&frame) {
+        None => {
+            unsafe { mapper.update_flags(page, flags) }.map_err(|_| MemoryError::MapFailed)?.flush();
+        }
+        Some(extra) => {
+            let mut allocator = FRAME_ALLOCATOR.lock();
+            let allocator = allocator.as_mut().expect("memory::init not called");
+            let copy = allocator.allocate_frame().ok_or(MemoryError::OutOfFrames)?;
+            *extra -= 1;
+            if *extra == 0 {
+                refs.remove(&frame);
+            }
+            drop(refs);
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    phys_to_virt(frame.start_address()).as_ptr::<u8>(),
+                    phys_to_virt(copy.start_address()).as_mut_ptr::<u8>(),
+                    4096,
+                );
+            }
+            mapper.unmap(page).map_err(|_| MemoryError::MapFailed)?.1.flush();
+            unsafe { mapper.map_to(page, copy, flags, allocator) }.map_err(|_| MemoryError::MapFailed)?.flush();
+        }
+    }
+    Ok(())
+}
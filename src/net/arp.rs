@@ -0,0 +1,68 @@
+//ARP：把同一网段内的 IPv4 地址解析成 MAC 地址，结果缓存起来(不过期)
+use super::{config, ethernet, poll_until, Ipv4Addr, StackError, DEFAULT_TIMEOUT};
+use crate::drivers::net::MacAddress;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+const PACKET_SIZE: usize = 28;
+const OP_REQUEST: u16 = 1;
+const OP_REPLY: u16 = 2;
+const RETRIES: usize = 3;
+
+static CACHE: Mutex<BTreeMap<Ipv4Addr, MacAddress>> = Mutex::new(BTreeMap::new());
+
+fn packet(op: u16, sender_mac: MacAddress, sender_ip: Ipv4Addr, target_mac: MacAddress, target_ip: Ipv4Addr) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(PACKET_SIZE);
+    packet.extend_from_slice(&[0x00, 0x01, 0x08, 0x00, 6, 4]); //以太网、IPv4、地址长度
+    packet.extend_from_slice(&op.to_be_bytes());
+    packet.extend_from_slice(&sender_mac);
+    packet.extend_from_slice(&sender_ip.0);
+    packet.extend_from_slice(&target_mac);
+    packet.extend_from_slice(&target_ip.0);
+    packet
+}
+
+//记下发送者的地址；询问本机地址的请求要回应
+pub fn handle(frame: &[u8]) {
+    if frame.len() < PACKET_SIZE || frame[0..6] != [0x00, 0x01, 0x08, 0x00, 6, 4] {
+        return;
+    }
+    let op = u16::from_be_bytes([frame[6], frame[7]]);
+    let mut sender_mac = [0u8; 6];
+    sender_mac.copy_from_slice(&frame[8..14]);
+    let sender_ip = Ipv4Addr([frame[14], frame[15], frame[16], frame[17]]);
+    let target_ip = Ipv4Addr([frame[24], frame[25], frame[26], frame[27]]);
+    CACHE.lock().insert(sender_ip, sender_mac);
+
+    let local = config().address;
+    if op == OP_REQUEST && target_ip == local {
+        if let Ok(mac) = ethernet::local_mac() {
+            let reply = packet(OP_REPLY, mac, local, sender_mac, sender_ip);
+            let _ = ethernet::send(sender_mac, ethernet::ETHERTYPE_ARP, &reply);
+        }
+    }
+}
+
+//解析 ip 的 MAC 地址：先查缓存，没有时广播请求并等待回应
+pub fn resolve(ip: Ipv4Addr) -> Result<MacAddress, StackError> {
+    if ip == Ipv4Addr::BROADCAST {
+        return Ok(ethernet::BROADCAST);
+    }
+    if let Some(&mac) = CACHE.lock().get(&ip) {
+        return Ok(mac);
+    }
+    let request = packet(OP_REQUEST, ethernet::local_mac()?, config().address, [0; 6], ip);
+    for _ in 0..RETRIES {
+        ethernet::send(ethernet::BROADCAST, ethernet::ETHERTYPE_ARP, &request)?;
+        if let Some(mac) = poll_until(DEFAULT_TIMEOUT / RETRIES as u32, || CACHE.lock().get(&ip).copied()) {
+            return Ok(mac);
+        }
+    }
+    Err(StackError::Unreachable)
+}
+
+//缓存中的所有条目
+pub fn entries() -> Vec<(Ipv4Addr, MacAddress)> {
+    CACHE.lock().iter().map(|(&ip, &mac)| (ip, mac)).collect()
+}
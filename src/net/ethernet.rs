@@ -0,0 +1,44 @@
+//以太网帧：目的 MAC、源 MAC、2 字节的类型，后面是上层数据
+use super::{arp, ipv4, rss, StackError};
+use crate::drivers::net::{self as nic, MacAddress, MAX_FRAME_SIZE};
+use alloc::vec::Vec;
+
+pub const HEADER_SIZE: usize = 14;
+pub const MAX_PAYLOAD: usize = MAX_FRAME_SIZE - HEADER_SIZE;
+pub const BROADCAST: MacAddress = [0xFF; 6];
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+//按类型把帧交给上层处理，其他类型的帧丢弃
+pub fn handle(frame: &[u8]) {
+    if frame.len() < HEADER_SIZE {
+        return;
+    }
+    let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+    let payload = &frame[HEADER_SIZE..];
+    match ethertype {
+        ETHERTYPE_ARP => arp::handle(payload),
+        ETHERTYPE_IPV4 => ipv4::handle(payload),
+        _ => {}
+    }
+}
+
+pub fn send(dst: MacAddress, ethertype: u16, payload: &[u8]) -> Result<(), StackError> {
+    if payload.len() > MAX_PAYLOAD {
+        return Err(StackError::TooLarge);
+    }
+    let mut frame = Vec::with_capacity(HEADER_SIZE + payload.len());
+    frame.extend_from_slice(&dst);
+    frame.extend_from_slice(&local_mac()?);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    let queue = rss::steer(&frame);
+    nic::with_nic(|nic| nic.send_queue(queue % nic.queues(), &frame))??;
+    rss::count_tx(queue, frame.len());
+    Ok(())
+}
+
+pub fn local_mac() -> Result<MacAddress, StackError> {
+    Ok(nic::with_nic(|nic| nic.mac())?)
+}
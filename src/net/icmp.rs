@@ -0,0 +1,58 @@
+//ICMP：回应别人的回显请求，并为 ping 发送回显请求、收集应答
+use super::{ipv4, poll_until, Ipv4Addr, StackError};
+use crate::time::Instant;
+use alloc::vec::Vec;
+use core::time::Duration;
+use spin::Mutex;
+
+const TYPE_ECHO_REPLY: u8 = 0;
+const TYPE_ECHO_REQUEST: u8 = 8;
+const HEADER_SIZE: usize = 8;
+const PING_ID: u16 = 0x4A4F; //"JO"
+const PING_PAYLOAD: &[u8] = b"JoakimOS ping payload 0123456789";
+
+//收到的回显应答：来源和序号
+static REPLIES: Mutex<Vec<(Ipv4Addr, u16)>> = Mutex::new(Vec::new());
+
+fn echo(kind: u8, id: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_SIZE + payload.len());
+    packet.extend_from_slice(&[kind, 0, 0, 0]);
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(payload);
+    let sum = ipv4::checksum(&packet, 0);
+    packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    packet
+}
+
+pub fn handle(src: Ipv4Addr, packet: &[u8]) {
+    if packet.len() < HEADER_SIZE || ipv4::checksum(packet, 0) != 0 {
+        return;
+    }
+    let id = u16::from_be_bytes([packet[4], packet[5]]);
+    let seq = u16::from_be_bytes([packet[6], packet[7]]);
+    match packet[0] {
+        TYPE_ECHO_REQUEST => {
+            let reply = echo(TYPE_ECHO_REPLY, id, seq, &packet[HEADER_SIZE..]);
+            let _ = ipv4::send(src, ipv4::PROTOCOL_ICMP, &reply);
+        }
+        TYPE_ECHO_REPLY if id == PING_ID => REPLIES.lock().push((src, seq)),
+        _ => {}
+    }
+}
+
+//向 dst 发送一个回显请求并等待应答，返回往返时间
+pub fn ping(dst: Ipv4Addr, seq: u16, timeout: Duration) -> Result<Duration, StackError> {
+    let start = Instant::now();
+    ipv4::send(dst, ipv4::PROTOCOL_ICMP, &echo(TYPE_ECHO_REQUEST, PING_ID, seq, PING_PAYLOAD))?;
+    let answered = poll_until(timeout, || {
+        let mut replies = REPLIES.lock();
+        let index = replies.iter().position(|&reply| reply == (dst, seq))?;
+        replies.remove(index);
+        Some(())
+    });
+    match answered {
+        Some(()) => Ok(start.elapsed()),
+        None => Err(StackError::Timeout),
+    }
+}
@@ -0,0 +1,83 @@
+//IPv4：只处理不分片的包，首部不带选项
+use super::{arp, config, ethernet, icmp, udp, Ipv4Addr, StackError};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+
+pub const HEADER_SIZE: usize = 20;
+pub const MAX_PAYLOAD: usize = ethernet::MAX_PAYLOAD - HEADER_SIZE;
+pub const PROTOCOL_ICMP: u8 = 1;
+pub const PROTOCOL_UDP: u8 = 17;
+const DEFAULT_TTL: u8 = 64;
+const FLAG_MORE_FRAGMENTS: u16 = 0x2000;
+const FRAGMENT_OFFSET_MASK: u16 = 0x1FFF;
+
+static NEXT_ID: AtomicU16 = AtomicU16::new(1);
+
+//互联网校验和：按 16 位大端序求反码和再取反；initial 用于累加伪首部
+pub fn checksum(data: &[u8], initial: u32) -> u16 {
+    let mut sum = initial;
+    for chunk in data.chunks(2) {
+        let word = match chunk {
+            [high, low] => u16::from_be_bytes([*high, *low]),
+            [high] => u16::from_be_bytes([*high, 0]),
+            _ => unreachable!(),
+        };
+        sum += word as u32;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+//检查首部，把发给本机(或广播)的包交给上层协议；分片的包丢弃
+pub fn handle(packet: &[u8]) {
+    if packet.len() < HEADER_SIZE || packet[0] >> 4 != 4 {
+        return;
+    }
+    let header_len = (packet[0] & 0x0F) as usize * 4;
+    let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    if header_len < HEADER_SIZE || total_len < header_len || total_len > packet.len() {
+        return;
+    }
+    if checksum(&packet[..header_len], 0) != 0 {
+        return;
+    }
+    let fragment = u16::from_be_bytes([packet[6], packet[7]]);
+    if fragment & (FLAG_MORE_FRAGMENTS | FRAGMENT_OFFSET_MASK) != 0 {
+        return;
+    }
+    let src = Ipv4Addr([packet[12], packet[13], packet[14], packet[15]]);
+    let dst = Ipv4Addr([packet[16], packet[17], packet[18], packet[19]]);
+    if dst != config().address && dst != Ipv4Addr::BROADCAST {
+        return;
+    }
+    let payload = &packet[header_len..total_len];
+    match packet[9] {
+        PROTOCOL_ICMP => icmp::handle(src, payload),
+        PROTOCOL_UDP => udp::handle(src, dst, payload),
+        _ => {}
+    }
+}
+
+pub fn send(dst: Ipv4Addr, protocol: u8, payload: &[u8]) -> Result<(), StackError> {
+    if payload.len() > MAX_PAYLOAD {
+        return Err(StackError::TooLarge);
+    }
+    let config = config();
+    let mac = arp::resolve(config.next_hop(dst))?;
+    let total_len = (HEADER_SIZE + payload.len()) as u16;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    let mut packet = Vec::with_capacity(total_len as usize);
+    packet.extend_from_slice(&[0x45, 0]); //版本 4，首部 5 个 32 位字
+    packet.extend_from_slice(&total_len.to_be_bytes());
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&[0, 0, DEFAULT_TTL, protocol, 0, 0]);
+    packet.extend_from_slice(&config.address.0);
+    packet.extend_from_slice(&dst.0);
+    let sum = checksum(&packet, 0);
+    packet[10..12].copy_from_slice(&sum.to_be_bytes());
+    packet.extend_from_slice(payload);
+    ethernet::send(mac, ethernet::ETHERTYPE_IPV4, &packet)
+}
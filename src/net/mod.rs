@@ -0,0 +1,122 @@
+//网络协议栈：以太网、ARP、IPv4、ICMP 和 UDP
+//还没有中断，收到的帧按 RSS 分到各个接收队列，由队列线程或者 poll 处理；需要等待回应的函数自己循环调用 poll
+use crate::drivers::net::NetError;
+use crate::time::Instant;
+use core::fmt;
+use core::time::Duration;
+use spin::Mutex;
+
+pub mod arp; //地址解析，带缓存
+pub mod ethernet; //以太网帧的收发和分派
+pub mod icmp; //回显请求和应答(ping)
+pub mod ipv4; //IPv4 首部和校验和
+pub mod rss; //多个接收队列和流散列
+pub mod udp; //UDP 套接字
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Ipv4Addr(pub [u8; 4]);
+
+impl Ipv4Addr {
+    pub const BROADCAST: Ipv4Addr = Ipv4Addr([255; 4]);
+
+    //解析点分十进制地址，如 10.0.2.2
+    pub fn parse(text: &str) -> Option<Ipv4Addr> {
+        let mut octets = [0u8; 4];
+        let mut parts = text.split('.');
+        for octet in octets.iter_mut() {
+            *octet = parts.next()?.parse().ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Ipv4Addr(octets))
+    }
+
+    fn masked(self, mask: Ipv4Addr) -> [u8; 4] {
+        let mut out = self.0;
+        for (byte, m) in out.iter_mut().zip(mask.0.iter()) {
+            *byte &= m;
+        }
+        out
+    }
+}
+
+impl fmt::Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.0[0], self.0[1], self.0[2], self.0[3])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub address: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+}
+
+impl Config {
+    //发往 dst 的包下一跳交给谁：同一子网内直接发送，否则交给网关
+    pub fn next_hop(&self, dst: Ipv4Addr) -> Ipv4Addr {
+        if dst.masked(self.netmask) == self.address.masked(self.netmask) {
+            dst
+        } else {
+            self.gateway
+        }
+    }
+}
+
+//默认使用 QEMU 用户模式网络(-netdev user)分配的地址
+static CONFIG: Mutex<Config> = Mutex::new(Config {
+    address: Ipv4Addr([10, 0, 2, 15]),
+    netmask: Ipv4Addr([255, 255, 255, 0]),
+    gateway: Ipv4Addr([10, 0, 2, 2]),
+});
+
+pub fn config() -> Config {
+    *CONFIG.lock()
+}
+
+pub fn set_config(config: Config) {
+    *CONFIG.lock() = config;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackError {
+    Device(NetError), //网卡出错或没有网卡
+    Unreachable,      //ARP 解析超时
+    Timeout,          //等待回应超时
+    AddrInUse,        //端口已被绑定
+    TooLarge,         //数据放不进一个以太网帧(不支持分片)
+}
+
+impl From<NetError> for StackError {
+    fn from(err: NetError) -> StackError {
+        StackError::Device(err)
+    }
+}
+
+//处理网卡里已经收到的所有帧(所有队列的)
+pub fn poll() {
+    crate::watchdog::touch();
+    rss::receive();
+    for queue in 0..rss::count() {
+        rss::process(queue);
+    }
+}
+
+//反复调用 poll，直到 done 返回 Some 或者超过 timeout
+pub fn poll_until<T>(timeout: Duration, mut done: impl FnMut() -> Option<T>) -> Option<T> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        poll();
+        if let Some(value) = done() {
+            return Some(value);
+        }
+        if Instant::now() > deadline {
+            return None;
+        }
+        core::hint::spin_loop();
+    }
+}
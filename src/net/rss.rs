@@ -0,0 +1,183 @@
+//接收方扩展(RSS)：收到的帧按流的 Toeplitz 散列值分到多个接收队列，每个队列由绑定在一个 CPU 上的内核线程处理
+//网卡自己有多个队列时(硬件 RSS)，硬件队列 n 的帧直接进入队列 n；只有一个队列时(RTL8139)在软件里计算散列再分派
+//散列的输入与硬件 RSS 相同：IPv4 源地址、目的地址，TCP/UDP 且不是分片时再加上源端口、目的端口；其他帧(ARP 等)进入队列 0
+//发送时也按散列选择队列，只用于统计和选择硬件发送队列
+//队列数由命令行 netqueues=<n> 给出，默认每个 housekeeping CPU 一个；网卡还没有中断，队列线程在空闲循环里轮询
+use crate::cmdline;
+use crate::drivers::net as nic;
+use crate::thread;
+use crate::watch;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+pub const MAX_QUEUES: usize = 16;
+
+const INDIRECTION_SIZE: usize = 128; //散列值的低 7 位查表得到队列号
+const BACKLOG_LIMIT: usize = 256; //每个队列最多积压的帧，满了以后丢弃新来的
+
+//常用的默认密钥(与大多数网卡驱动相同)
+const KEY: [u8; 40] = [
+    0x6d, 0x5a, 0x56, 0xda, 0x25, 0x5b, 0x0e, 0xc2, 0x41, 0x67, 0x25, 0x3d, 0x43, 0xa3, 0x8f, 0xb0, 0xd0, 0xca, 0x2b, 0xcb,
+    0xae, 0x7b, 0x30, 0xb4, 0x77, 0xcb, 0x2d, 0xa3, 0x80, 0x30, 0xf2, 0x0c, 0x6a, 0x42, 0xb7, 0x3b, 0xbe, 0xac, 0x01, 0xfa,
+];
+
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueueStats {
+    pub cpu: usize,
+    pub rx_packets: u64,
+    pub rx_bytes: u64,
+    pub rx_dropped: u64, //积压满了丢弃的帧
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+    pub backlog: usize, //还没有处理的帧
+}
+
+struct Queue {
+    backlog: VecDeque<Vec<u8>>,
+    stats: QueueStats,
+}
+
+//还没有初始化时为空，收到的帧直接处理
+static QUEUES: Mutex<Vec<Queue>> = Mutex::new(Vec::new());
+static INDIRECTION: Mutex<[u8; INDIRECTION_SIZE]> = Mutex::new([0; INDIRECTION_SIZE]);
+
+//Toeplitz 散列：输入的每个置位的位把密钥中从该位开始的 32 位异或进结果
+pub fn toeplitz(input: &[u8]) -> u32 {
+    let mut result = 0u32;
+    let mut window = u32::from_be_bytes([KEY[0], KEY[1], KEY[2], KEY[3]]);
+    for (i, byte) in input.iter().enumerate() {
+        let next = KEY.get(i + 4).copied().unwrap_or(0);
+        for bit in (0..8).rev() {
+            if byte & (1 << bit) != 0 {
+                result ^= window;
+            }
+            window = window << 1 | ((next >> bit) & 1) as u32;
+        }
+    }
+    result
+}
+
+//以太网帧的流散列，不是 IPv4 时返回 None
+pub fn hash(frame: &[u8]) -> Option<u32> {
+    let ip = frame.get(14..)?;
+    if frame[12..14] != [0x08, 0x00] || ip.len() < 20 || ip[0] >> 4 != 4 {
+        return None;
+    }
+    let header_len = (ip[0] & 0xf) as usize * 4;
+    let fragment = u16::from_be_bytes([ip[6], ip[7]]) & 0x3fff; //MF 位和片偏移
+    let mut input = [0u8; 12];
+    input[..8].copy_from_slice(&ip[12..20]);
+    let ports = match ip.get(header_len..header_len + 4) {
+        Some(ports) if fragment == 0 && (ip[9] == PROTOCOL_TCP || ip[9] == PROTOCOL_UDP) => ports,
+        _ => return Some(toeplitz(&input[..8])),
+    };
+    input[8..].copy_from_slice(ports);
+    Some(toeplitz(&input))
+}
+
+pub fn count() -> usize {
+    QUEUES.lock().len()
+}
+
+//帧应该进入的队列
+pub fn steer(frame: &[u8]) -> usize {
+    match hash(frame) {
+        Some(hash) => INDIRECTION.lock()[hash as usize % INDIRECTION_SIZE] as usize,
+        None => 0,
+    }
+}
+
+//启动时调用：建立队列，每个队列创建一个绑定到 CPU 上的线程，队列轮流绑定到各个 housekeeping CPU 上
+//netqueues= 无效时仍按默认的队列数建立，返回原来的文字
+pub fn init() -> Result<(), &'static str> {
+    let cpus: Vec<usize> = (0..thread::MAX_CPUS).filter(|cpu| thread::housekeeping() & (1 << cpu) != 0).collect();
+    let default = cpus.len().min(MAX_QUEUES);
+    let (count, result) = match cmdline::get("netqueues") {
+        None => (default, Ok(())),
+        Some(text) => match text.parse::<usize>() {
+            Ok(count) if (1..=MAX_QUEUES).contains(&count) => (count, Ok(())),
+            _ => (default, Err(text)),
+        },
+    };
+    {
+        let mut table = INDIRECTION.lock();
+        for (i, entry) in table.iter_mut().enumerate() {
+            *entry = (i % count) as u8;
+        }
+    }
+    let mut queues = QUEUES.lock();
+    for queue in 0..count {
+        let cpu = cpus[queue % cpus.len()];
+        queues.push(Queue { backlog: VecDeque::new(), stats: QueueStats { cpu, ..QueueStats::default() } });
+    }
+    drop(queues);
+    for queue in 0..count {
+        let cpu = cpus[queue % cpus.len()];
+        let _ = thread::spawn_on(cpu, "net-rx", move || {
+            receive();
+            process(queue);
+            true
+        });
+    }
+    let _ = watch::register("net.rx_packets", watch::Kind::Rate, "/s", || stats().iter().map(|queue| queue.rx_packets).sum());
+    let _ = watch::register("net.tx_packets", watch::Kind::Rate, "/s", || stats().iter().map(|queue| queue.tx_packets).sum());
+    let _ = watch::register("net.rx_dropped", watch::Kind::Rate, "/s", || stats().iter().map(|queue| queue.rx_dropped).sum());
+    crate::log!(Info, "net: {} receive queues", count);
+    result
+}
+
+//把网卡里已经收到的帧取出来分到各个队列
+pub fn receive() {
+    let hardware = nic::with_nic(|nic| nic.queues()).unwrap_or(0);
+    for hw_queue in 0..hardware {
+        while let Ok(Some(frame)) = nic::with_nic(|nic| nic.recv_queue(hw_queue)) {
+            crate::trace!(Net, frame.len(), 0);
+            let queue = if hardware > 1 { Some(hw_queue) } else { None };
+            enqueue(frame, queue);
+        }
+    }
+}
+
+fn enqueue(frame: Vec<u8>, queue: Option<usize>) {
+    let mut queues = QUEUES.lock();
+    if queues.is_empty() {
+        drop(queues);
+        return super::ethernet::handle(&frame);
+    }
+    let count = queues.len();
+    let queue = &mut queues[queue.unwrap_or_else(|| steer(&frame)) % count];
+    queue.stats.rx_packets += 1;
+    queue.stats.rx_bytes += frame.len() as u64;
+    if queue.backlog.len() >= BACKLOG_LIMIT {
+        queue.stats.rx_dropped += 1;
+        return;
+    }
+    queue.backlog.push_back(frame);
+}
+
+//处理队列里积压的帧；处理时不持有锁，协议栈可能要发送回应
+pub fn process(queue: usize) {
+    loop {
+        let frame = match QUEUES.lock().get_mut(queue).and_then(|queue| queue.backlog.pop_front()) {
+            Some(frame) => frame,
+            None => return,
+        };
+        super::ethernet::handle(&frame);
+    }
+}
+
+//发送一帧之后记入它所属的队列
+pub fn count_tx(queue: usize, len: usize) {
+    if let Some(queue) = QUEUES.lock().get_mut(queue) {
+        queue.stats.tx_packets += 1;
+        queue.stats.tx_bytes += len as u64;
+    }
+}
+
+pub fn stats() -> Vec<QueueStats> {
+    QUEUES.lock().iter().map(|queue| QueueStats { backlog: queue.backlog.len(), ..queue.stats }).collect()
+}
@@ -0,0 +1,205 @@
+//UDP 套接字：绑定一个本地端口，收到的数据报排队等待 recv_from 取走
+use super::{config, ipv4, poll_until, Ipv4Addr, StackError};
+use crate::fs::vfs::FileHandle;
+use crate::fs::FsError;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::time::Duration;
+use spin::Mutex;
+
+const HEADER_SIZE: usize = 8;
+const QUEUE_LIMIT: usize = 32; //每个套接字最多排队的数据报，满了以后丢弃新来的
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 49152..=65535;
+
+#[allow(dead_code)]
+pub struct Datagram {
+    pub src: Ipv4Addr,
+    pub src_port: u16,
+    pub data: Vec<u8>,
+}
+
+//已绑定的端口和它们的接收队列
+static SOCKETS: Mutex<BTreeMap<u16, VecDeque<Datagram>>> = Mutex::new(BTreeMap::new());
+
+//伪首部(源地址、目的地址、协议、UDP 长度)的部分和
+fn pseudo_header_sum(src: Ipv4Addr, dst: Ipv4Addr, len: usize) -> u32 {
+    let mut sum = 0u32;
+    for pair in src.0.chunks(2).chain(dst.0.chunks(2)) {
+        sum += u16::from_be_bytes([pair[0], pair[1]]) as u32;
+    }
+    sum + ipv4::PROTOCOL_UDP as u32 + len as u32
+}
+
+pub fn handle(src: Ipv4Addr, dst: Ipv4Addr, packet: &[u8]) {
+    if packet.len() < HEADER_SIZE {
+        return;
+    }
+    let src_port = u16::from_be_bytes([packet[0], packet[1]]);
+    let dst_port = u16::from_be_bytes([packet[2], packet[3]]);
+    let len = u16::from_be_bytes([packet[4], packet[5]]) as usize;
+    let sum = u16::from_be_bytes([packet[6], packet[7]]);
+    if len < HEADER_SIZE || len > packet.len() {
+        return;
+    }
+    //校验和为 0 表示发送方没有计算
+    if sum != 0 && ipv4::checksum(&packet[..len], pseudo_header_sum(src, dst, len)) != 0 {
+        return;
+    }
+    if let Some(queue) = SOCKETS.lock().get_mut(&dst_port) {
+        if queue.len() < QUEUE_LIMIT {
+            queue.push_back(Datagram { src, src_port, data: Vec::from(&packet[HEADER_SIZE..len]) });
+        }
+    }
+}
+
+pub struct UdpSocket {
+    port: u16,
+}
+
+#[allow(dead_code)]
+impl UdpSocket {
+    //绑定本地端口，port 为 0 时分配一个空闲的临时端口
+    pub fn bind(port: u16) -> Result<UdpSocket, StackError> {
+        let mut sockets = SOCKETS.lock();
+        let port = if port == 0 {
+            EPHEMERAL_PORTS.clone().find(|p| !sockets.contains_key(p)).ok_or(StackError::AddrInUse)?
+        } else {
+            port
+        };
+        if sockets.contains_key(&port) {
+            return Err(StackError::AddrInUse);
+        }
+        sockets.insert(port, VecDeque::new());
+        Ok(UdpSocket { port })
+    }
+
+    pub fn local_port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn send_to(&self, data: &[u8], dst: Ipv4Addr, dst_port: u16) -> Result<(), StackError> {
+        let len = HEADER_SIZE + data.len();
+        if len > ipv4::MAX_PAYLOAD {
+            return Err(StackError::TooLarge);
+        }
+        let mut packet = Vec::with_capacity(len);
+        packet.extend_from_slice(&self.port.to_be_bytes());
+        packet.extend_from_slice(&dst_port.to_be_bytes());
+        packet.extend_from_slice(&(len as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(data);
+        let sum = match ipv4::checksum(&packet, pseudo_header_sum(config().address, dst, len)) {
+            0 => 0xFFFF, //算出 0 时要发送 0xFFFF，0 留给 "没有校验和"
+            sum => sum,
+        };
+        packet[6..8].copy_from_slice(&sum.to_be_bytes());
+        ipv4::send(dst, ipv4::PROTOCOL_UDP, &packet)
+    }
+
+    //不等待：队列为空时返回 None
+    pub fn try_recv_from(&self) -> Option<Datagram> {
+        super::poll();
+        SOCKETS.lock().get_mut(&self.port)?.pop_front()
+    }
+
+    //等待一个数据报，最多等待 timeout
+    pub fn recv_from(&self, timeout: Duration) -> Result<Datagram, StackError> {
+        poll_until(timeout, || SOCKETS.lock().get_mut(&self.port)?.pop_front()).ok_or(StackError::Timeout)
+    }
+}
+
+//已连接的 UDP 套接字：只和一个对端通信，可以像文件一样读写，每次读写一个数据报
+//读取时缓冲区放不下的部分被丢弃；来自其他地址的数据报直接丢弃
+pub struct UdpStream {
+    socket: UdpSocket,
+    peer: Ipv4Addr,
+    peer_port: u16,
+    nonblocking: bool,
+}
+
+impl UdpStream {
+    pub fn connect(local_port: u16, peer: Ipv4Addr, peer_port: u16) -> Result<UdpStream, StackError> {
+        Ok(UdpStream { socket: UdpSocket::bind(local_port)?, peer, peer_port, nonblocking: false })
+    }
+}
+
+impl FileHandle for UdpStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        loop {
+            match self.socket.try_recv_from() {
+                Some(datagram) if datagram.src == self.peer && datagram.src_port == self.peer_port => {
+                    let n = buf.len().min(datagram.data.len());
+                    buf[..n].copy_from_slice(&datagram.data[..n]);
+                    return Ok(n);
+                }
+                Some(_) => continue,
+                None if self.nonblocking => return Err(FsError::WouldBlock),
+                None => core::hint::spin_loop(),
+            }
+        }
+    }
+
+    //数据报要么完整发出，要么失败，不会只写一部分
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        match self.socket.send_to(buf, self.peer, self.peer_port) {
+            Ok(()) => Ok(buf.len()),
+            Err(StackError::TooLarge) => Err(FsError::InvalidArgument),
+            Err(_) => Err(FsError::Network),
+        }
+    }
+
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), FsError> {
+        self.nonblocking = nonblocking;
+        Ok(())
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        SOCKETS.lock().remove(&self.port);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCAL: Ipv4Addr = Ipv4Addr([10, 0, 2, 15]);
+    const PEER: Ipv4Addr = Ipv4Addr([10, 0, 2, 2]);
+
+    //送来一个不带校验和的数据报
+    fn deliver(src: Ipv4Addr, src_port: u16, dst_port: u16, data: &[u8]) {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&src_port.to_be_bytes());
+        packet.extend_from_slice(&dst_port.to_be_bytes());
+        packet.extend_from_slice(&((HEADER_SIZE + data.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(data);
+        handle(src, LOCAL, &packet);
+    }
+
+    #[test]
+    fn nonblocking_stream_reads_one_datagram_per_call() {
+        let mut stream = UdpStream::connect(40001, PEER, 7).unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(stream.read(&mut buf), Err(FsError::WouldBlock));
+        deliver(PEER, 7, 40001, b"first");
+        deliver(PEER, 7, 40001, b"second datagram");
+        assert_eq!(stream.read(&mut buf), Ok(5));
+        assert_eq!(&buf[..5], b"first");
+        //放不下的部分被丢弃，不会留到下一次读
+        assert_eq!(stream.read(&mut buf), Ok(8));
+        assert_eq!(&buf, b"second d");
+        assert_eq!(stream.read(&mut buf), Err(FsError::WouldBlock));
+    }
+
+    #[test]
+    fn nonblocking_stream_ignores_other_peers() {
+        let mut stream = UdpStream::connect(40002, PEER, 7).unwrap();
+        stream.set_nonblocking(true).unwrap();
+        deliver(PEER, 8, 40002, b"wrong port");
+        deliver(Ipv4Addr([10, 0, 2, 3]), 7, 40002, b"wrong host");
+        assert_eq!(stream.read(&mut [0u8; 16]), Err(FsError::WouldBlock));
+    }
+}
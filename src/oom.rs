@@ -0,0 +1,60 @@
+//内存不足时的处理：用户程序的缺页异常分配不到物理帧时，先尝试合并相同页面回收内存
+//还是不够就结束常驻内存最多的进程，打印报告后重试，而不是让整个内核 panic
+//shell 在内核中运行，不是进程，不会被选中；正在等待子进程的祖先进程栈还在使用中，也不会被选中
+use crate::process::{self, ProcessInfo, State};
+use crate::usermode;
+use crate::{ksm, memory, println, vm};
+use alloc::format;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+static KILLS: AtomicU64 = AtomicU64::new(0);
+
+//被 OOM 结束的进程数
+pub fn kills() -> u64 {
+    KILLS.load(Ordering::Relaxed)
+}
+
+//分配用户页面失败时调用，返回 true 表示释放了内存，调用者可以重试
+//group 不为 None 时是进程组用到了内存上限，只在组内选择进程，也不尝试合并页面(合并不减少常驻页面数)
+//选中的是当前进程时直接结束它，不会返回
+pub fn out_of_memory(group: Option<&str>) -> bool {
+    let processes = match process::try_list() {
+        Some(processes) => processes,
+        None => return false, //异常发生时内核正持有进程表的锁，什么也做不了
+    };
+    if group.is_none() && ksm::scan() > 0 {
+        return true;
+    }
+    let current = process::current_pid();
+    let victim = processes
+        .iter()
+        .filter(|info| info.pid == current || matches!(info.state, State::Ready | State::Blocked))
+        .filter(|info| info.rss > 0 && group.is_none_or(|group| info.group == group))
+        .max_by_key(|info| info.rss);
+    let victim = match victim {
+        Some(victim) => victim,
+        None => return false,
+    };
+    report(&processes, group, victim.pid);
+    KILLS.fetch_add(1, Ordering::Relaxed);
+    if victim.pid == current {
+        usermode::exit(process::KILLED_EXIT_CODE);
+    }
+    process::kill(victim.pid).is_ok()
+}
+
+//打印内存状况和各进程的常驻页面数
+fn report(processes: &[ProcessInfo], group: Option<&str>, victim: u64) {
+    let faults = vm::fault_stats();
+    if let Some(group) = group {
+        println!("group {} reached its memory limit", group);
+    }
+    println!("out of memory: {} free frames, {} page faults ({} cow)", memory::free_frame_count(), faults.total(), faults.cow);
+    println!("{:>5}  {:>8}  {:<8}  {:<8}  {}", "PID", "RSS(KiB)", "STATE", "GROUP", "NAME");
+    for info in processes.iter().filter(|info| info.state != State::Zombie) {
+        let state = format!("{:?}", info.state);
+        println!("{:>5}  {:>8}  {:<8}  {:<8}  {}", info.pid, info.rss * 4, state, info.group, info.name);
+    }
+    let name = processes.iter().find(|info| info.pid == victim).map_or("", |info| info.name.as_str());
+    println!("killed pid {} ({})", victim, name);
+}
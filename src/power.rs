@@ -0,0 +1,259 @@
+//电源管理：关机(ACPI S5)、重启和空闲时让 CPU 休息
+//ACPI 只解析关机和重启需要的部分：RSDP -> RSDT/XSDT -> FADT 取 PM1 控制端口和复位寄存器，DSDT 里的 \_S5 取睡眠类型
+//ACPI 不可用时关机依次尝试 QEMU、Bochs、VirtualBox 的专用端口，重启依次尝试键盘控制器和三重错误
+use crate::audit::{self, Operation};
+use crate::{memory, println, time};
+use core::arch::asm;
+use core::arch::x86_64::__cpuid;
+use core::sync::atomic::{AtomicU8, Ordering};
+use core::time::Duration;
+use spin::Mutex;
+use x86_64::instructions::port::{Port, PortReadOnly};
+use x86_64::instructions::{hlt, interrupts};
+use x86_64::structures::DescriptorTablePointer;
+use x86_64::{PhysAddr, VirtAddr};
+
+const SDT_HEADER_SIZE: usize = 36;
+const SLP_EN: u16 = 1 << 13;
+const SCI_EN: u16 = 1;
+const RESET_REG_SUP: u32 = 1 << 10; //FADT 标志：复位寄存器可用
+const ADDRESS_SPACE_IO: u8 = 1;
+
+//没有 ACPI 时关机用的端口和写入的值
+const FALLBACK_SHUTDOWN: &[(u16, u16)] = &[(0x604, 0x2000), (0xB004, 0x2000), (0x4004, 0x3400)];
+
+//键盘控制器的复位命令
+const KBC_STATUS: u16 = 0x64;
+const KBC_INPUT_FULL: u8 = 0x02;
+const KBC_RESET: u8 = 0xFE;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Acpi {
+    pub revision: u8,
+    pub pm1a_control: u16,
+    pub pm1b_control: u16, //0 表示没有
+    pub s5: Option<(u16, u16)>, //\_S5 的 SLP_TYPa/SLP_TYPb
+    pub reset: Option<(u16, u8)>, //复位寄存器(I/O 端口)和要写入的值
+    smi_command: u16,
+    acpi_enable: u8,
+}
+
+static ACPI: Mutex<Option<Acpi>> = Mutex::new(None);
+
+fn read<T: Copy>(phys: u64) -> T {
+    let addr = memory::phys_to_virt(PhysAddr::new(phys));
+    unsafe { core::ptr::read_unaligned(addr.as_ptr::<T>()) }
+}
+
+fn checksum_ok(phys: u64, len: usize) -> bool {
+    (0..len as u64).fold(0u8, |sum, i| sum.wrapping_add(read::<u8>(phys + i))) == 0
+}
+
+//RSDP 在 EBDA 的前 1 KiB 或者 0xE0000..0x100000 中，16 字节对齐，以 "RSD PTR " 开头
+fn find_rsdp() -> Option<u64> {
+    let ebda = (read::<u16>(0x40E) as u64) << 4;
+    let areas = [(ebda, ebda + 1024), (0xE0000, 0x100000)];
+    areas
+        .iter()
+        .filter(|&&(start, _)| start != 0)
+        .flat_map(|&(start, end)| (start..end).step_by(16))
+        .find(|&addr| read::<[u8; 8]>(addr) == *b"RSD PTR " && checksum_ok(addr, 20))
+}
+
+//在 RSDT(4 字节指针)或 XSDT(8 字节指针)里找签名为 signature 的表
+fn find_table(root: u64, entry_size: usize, signature: &[u8; 4]) -> Option<u64> {
+    let len = read::<u32>(root + 4) as u64;
+    (root + SDT_HEADER_SIZE as u64..root + len)
+        .step_by(entry_size)
+        .map(|entry| if entry_size == 8 { read::<u64>(entry) } else { read::<u32>(entry) as u64 })
+        .find(|&table| read::<[u8; 4]>(table) == *signature && checksum_ok(table, read::<u32>(table + 4) as usize))
+}
+
+//在 DSDT 的 AML 里找 Name(_S5, Package() { SLP_TYPa, SLP_TYPb, ... })
+fn parse_s5(dsdt: u64) -> Option<(u16, u16)> {
+    let len = read::<u32>(dsdt + 4) as u64;
+    let name = (dsdt + SDT_HEADER_SIZE as u64..dsdt + len.saturating_sub(4)).find(|&p| {
+        read::<[u8; 4]>(p) == *b"_S5_"
+            && (read::<u8>(p - 1) == 0x08 || (read::<u8>(p - 2) == 0x08 && read::<u8>(p - 1) == b'\\'))
+    })?;
+    let mut p = name + 4;
+    if read::<u8>(p) != 0x12 {
+        return None; //不是 PackageOp
+    }
+    p += 1;
+    p += ((read::<u8>(p) >> 6) & 3) as u64 + 1; //PkgLength 的字节数在第一个字节的高两位
+    p += 1; //NumElements
+    let mut integer = || {
+        let mut value = read::<u8>(p);
+        if value == 0x0A {
+            p += 1; //BytePrefix
+            value = read::<u8>(p);
+        }
+        p += 1;
+        value as u16
+    };
+    Some((integer(), integer()))
+}
+
+fn parse() -> Option<Acpi> {
+    let rsdp = find_rsdp()?;
+    let revision = read::<u8>(rsdp + 15);
+    let fadt = if revision >= 2 && read::<u64>(rsdp + 24) != 0 {
+        find_table(read::<u64>(rsdp + 24), 8, b"FACP")?
+    } else {
+        find_table(read::<u32>(rsdp + 16) as u64, 4, b"FACP")?
+    };
+    let fadt_len = read::<u32>(fadt + 4);
+    let dsdt = match read::<u32>(fadt + 40) {
+        0 if fadt_len >= 148 => read::<u64>(fadt + 140),
+        dsdt => dsdt as u64,
+    };
+    let reset = if fadt_len >= 129 && read::<u32>(fadt + 112) & RESET_REG_SUP != 0 && read::<u8>(fadt + 116) == ADDRESS_SPACE_IO {
+        Some((read::<u64>(fadt + 120) as u16, read::<u8>(fadt + 128)))
+    } else {
+        None
+    };
+    Some(Acpi {
+        revision,
+        pm1a_control: read::<u32>(fadt + 64) as u16,
+        pm1b_control: read::<u32>(fadt + 68) as u16,
+        s5: if dsdt != 0 && checksum_ok(dsdt, read::<u32>(dsdt + 4) as usize) { parse_s5(dsdt) } else { None },
+        reset,
+        smi_command: read::<u32>(fadt + 48) as u16,
+        acpi_enable: read::<u8>(fadt + 52),
+    })
+}
+
+//启动时调用：解析 ACPI 表，选择空闲方式
+pub fn init() -> Option<Acpi> {
+    let acpi = parse();
+    *ACPI.lock() = acpi;
+    IDLE_METHOD.store(detect_idle_method() as u8, Ordering::Relaxed);
+    acpi
+}
+
+pub fn acpi() -> Option<Acpi> {
+    *ACPI.lock()
+}
+
+//固件还没有切换到 ACPI 模式时(SCI_EN 为 0)，向 SMI 命令端口写入 ACPI_ENABLE
+fn enable_acpi(acpi: &Acpi) {
+    let mut control = Port::<u16>::new(acpi.pm1a_control);
+    if unsafe { control.read() } & SCI_EN != 0 || acpi.smi_command == 0 || acpi.acpi_enable == 0 {
+        return;
+    }
+    unsafe { Port::<u8>::new(acpi.smi_command).write(acpi.acpi_enable) };
+    let deadline = time::Instant::now() + Duration::from_secs(1);
+    while unsafe { control.read() } & SCI_EN == 0 && time::Instant::now() < deadline {
+        core::hint::spin_loop();
+    }
+}
+
+//关机，失败时停在这里
+pub fn shutdown() -> ! {
+    audit::record(Operation::Shutdown, "", true);
+    println!("power: shutting down");
+    sync_disks();
+    if let Some(acpi) = acpi() {
+        if let Some((typ_a, typ_b)) = acpi.s5 {
+            enable_acpi(&acpi);
+            unsafe {
+                Port::<u16>::new(acpi.pm1a_control).write(typ_a << 10 | SLP_EN);
+                if acpi.pm1b_control != 0 {
+                    Port::<u16>::new(acpi.pm1b_control).write(typ_b << 10 | SLP_EN);
+                }
+            }
+            time::sleep(Duration::from_millis(100));
+        }
+    }
+    for &(port, value) in FALLBACK_SHUTDOWN {
+        unsafe { Port::<u16>::new(port).write(value) };
+    }
+    time::sleep(Duration::from_millis(100));
+    println!("power: shutdown failed, it is now safe to turn off the machine");
+    halt_forever()
+}
+
+//重启，依次尝试 ACPI 复位寄存器、键盘控制器和三重错误
+pub fn reboot() -> ! {
+    audit::record(Operation::Reboot, "", true);
+    println!("power: rebooting");
+    sync_disks();
+    if let Some((port, value)) = acpi().and_then(|acpi| acpi.reset) {
+        unsafe { Port::<u8>::new(port).write(value) };
+        time::sleep(Duration::from_millis(100));
+    }
+    let mut status = PortReadOnly::<u8>::new(KBC_STATUS);
+    while unsafe { status.read() } & KBC_INPUT_FULL != 0 {
+        core::hint::spin_loop();
+    }
+    unsafe { Port::<u8>::new(KBC_STATUS).write(KBC_RESET) };
+    time::sleep(Duration::from_millis(100));
+    //加载空的 IDT 后触发异常：找不到处理函数导致双重错误，再导致三重错误，CPU 复位
+    unsafe {
+        interrupts::disable();
+        x86_64::instructions::tables::lidt(&DescriptorTablePointer { limit: 0, base: VirtAddr::zero() });
+    }
+    interrupts::int3();
+    halt_forever()
+}
+
+//块缓存里还没有写回的块
+fn sync_disks() {
+    if let Err(err) = crate::fs::cache::sync() {
+        println!("power: writing back cached blocks failed: {:?}", err);
+    }
+}
+
+fn halt_forever() -> ! {
+    interrupts::disable();
+    loop {
+        hlt();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleMethod {
+    Spin,  //pause 指令
+    Halt,  //hlt，等待下一个中断
+    Mwait, //monitor/mwait，可以进入更深的 C 状态
+}
+
+static IDLE_METHOD: AtomicU8 = AtomicU8::new(IdleMethod::Spin as u8);
+static WAKE: u64 = 0; //mwait 监视的地址，没有人写它，只靠中断唤醒
+
+//中断还没有打开时 hlt 之后没有东西能唤醒 CPU，只能用 pause 空转
+fn detect_idle_method() -> IdleMethod {
+    if !interrupts::are_enabled() {
+        IdleMethod::Spin
+    } else if __cpuid(1).ecx & (1 << 3) != 0 {
+        IdleMethod::Mwait
+    } else {
+        IdleMethod::Halt
+    }
+}
+
+pub fn idle_method() -> IdleMethod {
+    match IDLE_METHOD.load(Ordering::Relaxed) {
+        1 => IdleMethod::Halt,
+        2 => IdleMethod::Mwait,
+        _ => IdleMethod::Spin,
+    }
+}
+
+//轮询等待时调用，让 CPU 休息到下一个中断(或者稍等片刻)
+//系统调用里中断是关闭的，这时也只能空转
+pub fn idle() {
+    if !interrupts::are_enabled() {
+        core::hint::spin_loop();
+        return;
+    }
+    match idle_method() {
+        IdleMethod::Spin => core::hint::spin_loop(),
+        IdleMethod::Halt => hlt(),
+        IdleMethod::Mwait => unsafe {
+            asm!("monitor", in("rax") &WAKE as *const u64, in("ecx") 0, in("edx") 0, options(nostack));
+            asm!("mwait", in("eax") 0, in("ecx") 0, options(nostack)); //C1
+        },
+    }
+}
@@ -0,0 +1,532 @@
+//进程：每个用户程序有自己的 PID、地址空间(4 级页表)、内核栈、文件描述符表和退出码
+//时钟中断只用于看门狗，不做抢占，所以进程在 wait 时才真正运行，并且一直运行到调用 exit 为止
+//用户程序 fork 出的子进程也一样：父进程调用 wait 时，子进程在父进程的系统调用中运行
+use crate::audit::{self, Operation};
+use crate::cred::{self, Credentials};
+use crate::fs::devfs;
+use crate::fs::vfs::{self, FileHandle, Inode};
+use crate::fs::FsError;
+use crate::loader::cache;
+use crate::loader::elf::{self, ElfError};
+use crate::group;
+use crate::latency;
+use crate::linux::{self, LinuxState};
+use crate::memory::{self, MemoryError};
+use crate::signal::{self, SignalState};
+use crate::stats;
+use crate::sync::Mutex;
+use crate::time::Instant;
+use crate::usermode::{self, UserContext, UserError};
+use crate::vm::{FaultKind, FaultStats, VmArea};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use x86_64::registers::model_specific::FsBase;
+use x86_64::structures::paging::PhysFrame;
+use x86_64::VirtAddr;
+
+const KERNEL_STACK_SIZE: usize = 4096 * 4; //每个进程的内核栈(系统调用时使用)
+const STDIO_FDS: usize = 3; //标准输入、标准输出、标准错误都指向控制台
+const MAX_FDS: usize = 64; //每个进程最多打开的文件描述符数
+pub const O_NONBLOCK: u64 = 0o4000; //文件状态标志，数值与 Linux 相同
+pub const KILLED_EXIT_CODE: i64 = -9; //被 kill 结束的进程的退出码
+pub const SEGFAULT_EXIT_CODE: i64 = -11; //访问非法地址被结束的进程的退出码
+
+//用户程序使用的系统调用接口
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Personality {
+    Native, //本系统的系统调用号，程序开始运行时 rdi 为参数个数、rsi 为参数数组
+    Linux,  //Linux x86_64 的系统调用号和初始栈，见 linux.rs
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running, //正在 CPU 上执行
+    Ready,   //已加载，等待运行
+    Blocked, //等待某个事件(暂时没有会阻塞的系统调用)
+    Zombie,  //已退出，等待父进程用 wait 回收
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessError {
+    NoSuchProcess,    //PID 不存在
+    NotChild,         //用户程序只能等待自己的子进程
+    Busy,             //进程正在运行，不能对它执行这个操作
+    Fs(FsError),      //读取程序文件失败
+    Elf(ElfError),    //ELF 文件不合法
+    User(UserError),  //平坦二进制太大或映射失败
+    Memory(MemoryError),
+}
+
+impl From<FsError> for ProcessError {
+    fn from(err: FsError) -> ProcessError {
+        ProcessError::Fs(err)
+    }
+}
+
+impl From<ElfError> for ProcessError {
+    fn from(err: ElfError) -> ProcessError {
+        ProcessError::Elf(err)
+    }
+}
+
+impl From<UserError> for ProcessError {
+    fn from(err: UserError) -> ProcessError {
+        ProcessError::User(err)
+    }
+}
+
+impl From<MemoryError> for ProcessError {
+    fn from(err: MemoryError) -> ProcessError {
+        ProcessError::Memory(err)
+    }
+}
+
+pub struct Process {
+    pid: u64,
+    name: String,
+    state: State,
+    exit_code: Option<i64>,
+    parent: u64, //0 表示由内核(shell)启动
+    address_space: PhysFrame, //4 级页表所在的物理帧，进程退出后回收
+    kernel_stack: Box<[u8]>,
+    context: UserContext, //开始运行时的寄存器
+    fds: Vec<Option<Fd>>, //下标就是文件描述符
+    areas: Vec<VmArea>,   //mmap 建立的区域，按起始地址排序
+    faults: FaultStats,
+    group: String, //所在的进程组
+    ready_since: Option<Instant>, //变成就绪的时刻，开始运行时计入运行队列延迟
+    signals: SignalState,
+    cred: Credentials, //运行时的身份，文件的权限检查按它进行
+    linux: Option<LinuxState>, //Linux personality 的进程才有
+}
+
+//新进程的地址空间和开始运行时的状态
+struct Image {
+    address_space: PhysFrame,
+    context: UserContext,
+    areas: Vec<VmArea>,
+    linux: Option<LinuxState>,
+}
+
+//打开的文件描述符：句柄和 fcntl 设置的状态标志
+struct Fd {
+    handle: Box<dyn FileHandle>,
+    flags: u64,
+}
+
+impl Process {
+    fn info(&self) -> ProcessInfo {
+        ProcessInfo {
+            pid: self.pid,
+            name: self.name.clone(),
+            state: self.state,
+            exit_code: self.exit_code,
+            parent: self.parent,
+            faults: self.faults,
+            rss: if self.state == State::Zombie { 0 } else { memory::resident_pages(self.address_space) },
+            group: self.group.clone(),
+            cred: self.cred,
+        }
+    }
+
+    fn kernel_stack_top(&self) -> u64 {
+        let top = self.kernel_stack.as_ptr() as u64 + self.kernel_stack.len() as u64;
+        top & !0xf
+    }
+}
+
+//ps 显示的进程信息
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    pub pid: u64,
+    pub name: String,
+    pub state: State,
+    pub exit_code: Option<i64>,
+    pub parent: u64,
+    pub faults: FaultStats,
+    pub rss: u64, //常驻的私有页面数，Zombie 为 0
+    pub group: String,
+    pub cred: Credentials,
+}
+
+static PROCESSES: Mutex<BTreeMap<u64, Process>> = Mutex::new("PROCESSES", BTreeMap::new());
+static NEXT_PID: AtomicU64 = AtomicU64::new(1);
+static CURRENT_PID: AtomicU64 = AtomicU64::new(0); //0 表示当前没有用户程序在运行
+static CURRENT_LINUX: AtomicBool = AtomicBool::new(false); //正在运行的进程是不是 Linux personality，和 CURRENT_PID 一起切换
+
+pub fn current_pid() -> u64 {
+    CURRENT_PID.load(Ordering::SeqCst)
+}
+
+//正在运行的进程的系统调用接口，每次系统调用都要查，不锁进程表
+pub fn personality() -> Personality {
+    if CURRENT_LINUX.load(Ordering::SeqCst) {
+        Personality::Linux
+    } else {
+        Personality::Native
+    }
+}
+
+//在当前地址空间中加载程序映像，返回开始运行时的寄存器和 Linux 程序的附加状态
+//personality 为 None 时按 ELF 头判断；args 为 None 时本系统的程序不在栈上放参数(spawn)，Linux 程序只有一个参数 path
+fn load(
+    path: &str,
+    image: &[u8],
+    personality: Option<Personality>,
+    args: Option<&[&[u8]]>,
+    cred: Credentials,
+) -> Result<(UserContext, Option<LinuxState>), ProcessError> {
+    let native = |entry: u64, stack_top: u64| {
+        let context = UserContext::new(entry, stack_top);
+        args.map_or(context, |args| usermode::push_args(context, args))
+    };
+    if !elf::is_elf(image) {
+        if personality == Some(Personality::Linux) {
+            return Err(ProcessError::Elf(ElfError::BadMagic)); //Linux 程序必须是 ELF 文件
+        }
+        let entry = usermode::load_flat(image)?;
+        return Ok((native(entry, usermode::setup_stack()?), None));
+    }
+    let program = elf::load(image)?;
+    let detected = if program.linux { Personality::Linux } else { Personality::Native };
+    match personality.unwrap_or(detected) {
+        Personality::Linux => {
+            let context = linux::start(&program, args.unwrap_or(&[path.as_bytes()]), cred);
+            Ok((context, Some(LinuxState::new(&program))))
+        }
+        Personality::Native => Ok((native(program.entry, program.stack_top), None)),
+    }
+}
+
+//在新的地址空间中加载程序：ELF 文件由加载器按段映射，其他文件当作平坦二进制
+//程序以 cred 的身份运行，cred 要对程序文件有执行权限；加载完成后进程处于 Ready 状态，返回它的 PID
+//personality 为 None 时由 ELF 头决定(见 load)
+pub fn spawn_user(path: &str, cred: Credentials, personality: Option<Personality>) -> Result<u64, ProcessError> {
+    vfs::access(path, cred, vfs::MAY_EXEC)?;
+    let image = cache::image(path)?; //反复启动同一个程序时不必再读磁盘
+    let address_space = memory::new_address_space()?;
+
+    let kernel_space = memory::switch_address_space(address_space);
+    let loaded = load(path, &image, personality, None, cred);
+    memory::switch_address_space(kernel_space);
+    let (context, linux) = loaded?;
+
+    let image = Image { address_space, context, areas: Vec::new(), linux };
+    insert(String::from(path), current_pid(), image, SignalState::new(), cred)
+}
+
+//新进程的标准输入、标准输出、标准错误都指向控制台
+fn stdio() -> Result<Vec<Option<Fd>>, ProcessError> {
+    let mut fds = Vec::new();
+    for _ in 0..STDIO_FDS {
+        fds.push(Some(Fd { handle: devfs::Console.open()?, flags: 0 }));
+    }
+    Ok(fds)
+}
+
+fn insert(name: String, parent: u64, image: Image, signals: SignalState, cred: Credentials) -> Result<u64, ProcessError> {
+    let fds = stdio()?;
+    let pid = NEXT_PID.fetch_add(1, Ordering::SeqCst);
+    let mut processes = PROCESSES.lock();
+    let group = processes.get(&parent).map_or_else(|| String::from(group::ROOT), |p| p.group.clone());
+    let process = Process {
+        pid,
+        name,
+        state: State::Ready,
+        exit_code: None,
+        parent,
+        address_space: image.address_space,
+        kernel_stack: vec![0u8; KERNEL_STACK_SIZE].into_boxed_slice(),
+        context: image.context,
+        fds,
+        areas: image.areas,
+        faults: FaultStats::default(),
+        group,
+        ready_since: Some(Instant::now()),
+        signals,
+        cred,
+        linux: image.linux,
+    };
+    processes.insert(pid, process);
+    Ok(pid)
+}
+
+//复制当前进程(在 fork 系统调用中使用)，子进程从系统调用返回处开始运行，返回值为 0
+//用户页面写时复制；还没有 dup，子进程不继承打开的文件，标准输入输出重新指向控制台
+//子进程继承信号的处理方式和身份
+pub fn fork() -> Result<u64, ProcessError> {
+    let context = *usermode::syscall_context();
+    let (name, address_space, areas, signals, cred, linux) = {
+        let processes = PROCESSES.lock();
+        let process = processes.get(&current_pid()).ok_or(ProcessError::NoSuchProcess)?;
+        (process.name.clone(), process.address_space, process.areas.clone(), process.signals.fork(), process.cred, process.linux)
+    };
+    let child = memory::clone_address_space(address_space)?;
+    insert(name, current_pid(), Image { address_space: child, context, areas, linux }, signals, cred)
+}
+
+//用 path 处的程序替换当前进程(在 exec 系统调用中使用)，args 放到新程序的栈上
+//成功时返回新程序开始运行时的寄存器，旧的地址空间已经回收；失败时当前程序不受影响
+pub fn exec(path: &str, args: &[&[u8]]) -> Result<UserContext, ProcessError> {
+    vfs::access(path, cred::current(), vfs::MAY_EXEC)?;
+    let image = cache::image(path)?;
+    let address_space = memory::new_address_space()?;
+    let old_space = memory::switch_address_space(address_space);
+    let (context, linux) = match load(path, &image, None, Some(args), cred::current()) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            memory::switch_address_space(old_space);
+            memory::free_address_space(address_space);
+            return Err(err);
+        }
+    };
+    if let Some(process) = PROCESSES.lock().get_mut(&current_pid()) {
+        process.name = String::from(path);
+        process.address_space = address_space;
+        process.areas.clear();
+        process.signals.exec();
+        process.linux = linux;
+    }
+    //系统调用返回时就是新程序，换成它的系统调用接口
+    CURRENT_LINUX.store(linux.is_some(), Ordering::SeqCst);
+    FsBase::write(VirtAddr::zero());
+    memory::free_address_space(old_space);
+    Ok(context)
+}
+
+//切换到进程的地址空间运行它，直到它调用 exit，然后把它变成 Zombie
+//在用户程序的系统调用中运行时(wait 子进程)，结束后回到调用者的地址空间和 PID
+//开始运行前先处理还没运行时收到的信号，可能直接结束或者从信号处理函数开始运行
+fn run(pid: u64) -> Result<(), ProcessError> {
+    let (address_space, mut context, kernel_stack, process_cred, linux) = {
+        let mut processes = PROCESSES.lock();
+        let process = processes.get_mut(&pid).ok_or(ProcessError::NoSuchProcess)?;
+        process.state = State::Running;
+        if let Some(since) = process.ready_since.take() {
+            latency::RUNQUEUE.record(since.elapsed());
+        }
+        (process.address_space, process.context, process.kernel_stack_top(), process.cred, process.linux)
+    };
+    //运行期间不能持有进程表的锁，系统调用还要访问文件描述符表
+    let caller = CURRENT_PID.swap(pid, Ordering::SeqCst);
+    let caller_cred = cred::switch(process_cred);
+    let caller_linux = CURRENT_LINUX.swap(linux.is_some(), Ordering::SeqCst);
+    let caller_fs = FsBase::read(); //在 Linux 程序的系统调用里运行子进程时，回来后要恢复它的 FS
+    FsBase::write(VirtAddr::new(linux.map_or(0, |state| state.fs_base)));
+    crate::trace!(Sched, pid, caller);
+    let caller_space = memory::switch_address_space(address_space);
+    stats::count_context_switch();
+    let code = match signal::prepare(pid, &mut context, 0) {
+        Ok(()) => usermode::enter(&context, kernel_stack),
+        Err(code) => code,
+    };
+    memory::switch_address_space(caller_space);
+    stats::count_context_switch();
+    CURRENT_PID.store(caller, Ordering::SeqCst);
+    cred::switch(caller_cred);
+    CURRENT_LINUX.store(caller_linux, Ordering::SeqCst);
+    FsBase::write(caller_fs);
+    crate::trace!(Exit, pid, code);
+
+    let mut processes = PROCESSES.lock();
+    if let Some(process) = processes.get_mut(&pid) {
+        process.state = State::Zombie;
+        process.exit_code = Some(code);
+        process.fds.clear();
+        process.areas.clear();
+        memory::free_address_space(process.address_space); //exec 可能已经换过地址空间，这里取进程表中的
+    }
+    Ok(())
+}
+
+//等待进程结束并回收它，返回退出码；进程还没运行过时先运行它
+//用户程序调用时只能等待自己的子进程
+pub fn wait(pid: u64) -> Result<i64, ProcessError> {
+    let (state, parent) = PROCESSES.lock().get(&pid).map(|p| (p.state, p.parent)).ok_or(ProcessError::NoSuchProcess)?;
+    if current_pid() != 0 && parent != current_pid() {
+        return Err(ProcessError::NotChild);
+    }
+    match state {
+        State::Running => return Err(ProcessError::Busy),
+        State::Ready => run(pid)?,
+        State::Blocked | State::Zombie => {}
+    }
+    let process = PROCESSES.lock().remove(&pid).ok_or(ProcessError::NoSuchProcess)?;
+    Ok(process.exit_code.unwrap_or(KILLED_EXIT_CODE))
+}
+
+//结束一个还没有运行的进程，它变成 Zombie，退出码为 KILLED_EXIT_CODE
+pub fn kill(pid: u64) -> Result<(), ProcessError> {
+    terminate(pid, KILLED_EXIT_CODE)
+}
+
+//与 kill 相同，退出码为 code(被信号结束时是负的信号编号)
+pub fn terminate(pid: u64, code: i64) -> Result<(), ProcessError> {
+    let mut processes = PROCESSES.lock();
+    let process = processes.get_mut(&pid).ok_or(ProcessError::NoSuchProcess)?;
+    match process.state {
+        State::Running => Err(ProcessError::Busy),
+        State::Zombie => Ok(()),
+        State::Ready | State::Blocked => {
+            process.state = State::Zombie;
+            process.exit_code = Some(code);
+            process.fds.clear();
+            process.areas.clear();
+            memory::free_address_space(process.address_space);
+            Ok(())
+        }
+    }
+}
+
+//把还没有运行的进程的标准输入换成 handle(shell 的输入重定向和 here 文档)
+pub fn set_stdin(pid: u64, handle: Box<dyn FileHandle>) -> Result<(), ProcessError> {
+    let mut processes = PROCESSES.lock();
+    let process = processes.get_mut(&pid).ok_or(ProcessError::NoSuchProcess)?;
+    if process.state != State::Ready {
+        return Err(ProcessError::Busy);
+    }
+    process.fds[0] = Some(Fd { handle, flags: 0 });
+    Ok(())
+}
+
+//在当前进程的 Linux 兼容层状态上执行 f，当前进程不是 Linux personality 时返回 None
+pub fn with_linux<T>(f: impl FnOnce(&mut LinuxState) -> T) -> Option<T> {
+    let mut processes = PROCESSES.lock();
+    processes.get_mut(&current_pid())?.linux.as_mut().map(f)
+}
+
+//在进程的信号状态上执行 f，同时给出进程的状态
+pub fn with_signals<T>(pid: u64, f: impl FnOnce(State, &mut SignalState) -> T) -> Result<T, ProcessError> {
+    let mut processes = PROCESSES.lock();
+    let process = processes.get_mut(&pid).ok_or(ProcessError::NoSuchProcess)?;
+    Ok(f(process.state, &mut process.signals))
+}
+
+//所有进程(包括还没有回收的 Zombie)，按 PID 排序
+pub fn list() -> Vec<ProcessInfo> {
+    PROCESSES.lock().values().map(Process::info).collect()
+}
+
+//与 list 相同，但进程表已被锁住时(缺页异常发生在内核访问文件描述符表期间)返回 None
+pub fn try_list() -> Option<Vec<ProcessInfo>> {
+    Some(PROCESSES.try_lock()?.values().map(Process::info).collect())
+}
+
+//在当前进程的文件描述符 fd 上执行 f，fd 不存在时返回 None
+pub fn with_fd<T>(fd: u64, f: impl FnOnce(&mut dyn FileHandle) -> T) -> Option<T> {
+    let mut processes = PROCESSES.lock();
+    let process = processes.get_mut(&current_pid())?;
+    let fd = process.fds.get_mut(fd as usize)?.as_mut()?;
+    Some(f(fd.handle.as_mut()))
+}
+
+//把句柄放到当前进程编号最小的空闲文件描述符上，描述符用完时返回 None
+pub fn add_fd(handle: Box<dyn FileHandle>) -> Option<u64> {
+    let mut processes = PROCESSES.lock();
+    let fds = &mut processes.get_mut(&current_pid())?.fds;
+    let index = match fds.iter().position(|fd| fd.is_none()) {
+        Some(index) => index,
+        None if fds.len() < MAX_FDS => {
+            fds.push(None);
+            fds.len() - 1
+        }
+        None => return None,
+    };
+    fds[index] = Some(Fd { handle, flags: 0 });
+    Some(index as u64)
+}
+
+//从当前进程的描述符表中取出句柄和状态标志(描述符随之关闭)，用于把它传给别的进程
+pub fn take_fd(fd: u64) -> Option<(Box<dyn FileHandle>, u64)> {
+    let mut processes = PROCESSES.lock();
+    let slot = processes.get_mut(&current_pid())?.fds.get_mut(fd as usize)?;
+    slot.take().map(|fd| (fd.handle, fd.flags))
+}
+
+//把 take_fd 取出的句柄放回原来的描述符，传递失败时使用
+pub fn restore_fd(fd: u64, handle: Box<dyn FileHandle>, flags: u64) {
+    if let Some(process) = PROCESSES.lock().get_mut(&current_pid()) {
+        if let Some(slot) = process.fds.get_mut(fd as usize) {
+            *slot = Some(Fd { handle, flags });
+        }
+    }
+}
+
+pub fn close_fd(fd: u64) {
+    if let Some(process) = PROCESSES.lock().get_mut(&current_pid()) {
+        if let Some(slot) = process.fds.get_mut(fd as usize) {
+            *slot = None;
+        }
+    }
+}
+
+pub fn fd_flags(fd: u64) -> Option<u64> {
+    let processes = PROCESSES.lock();
+    let process = processes.get(&current_pid())?;
+    Some(process.fds.get(fd as usize)?.as_ref()?.flags)
+}
+
+//设置文件状态标志，目前只支持 O_NONBLOCK
+pub fn set_fd_flags(fd: u64, flags: u64) -> Option<Result<(), FsError>> {
+    let mut processes = PROCESSES.lock();
+    let process = processes.get_mut(&current_pid())?;
+    let fd = process.fds.get_mut(fd as usize)?.as_mut()?;
+    Some(fd.handle.set_nonblocking(flags & O_NONBLOCK != 0).map(|()| fd.flags = flags & O_NONBLOCK))
+}
+
+//在当前进程的虚拟内存区域表上执行 f，没有用户程序在运行时返回 None
+pub fn with_areas<T>(f: impl FnOnce(&mut Vec<VmArea>) -> T) -> Option<T> {
+    let mut processes = PROCESSES.lock();
+    Some(f(&mut processes.get_mut(&current_pid())?.areas))
+}
+
+//当前进程中包含 addr 的区域，由缺页异常处理程序调用
+//异常可能发生在内核持有进程表的锁时，这时不等待锁，直接当作没有找到
+pub fn find_area(addr: u64) -> Option<VmArea> {
+    let processes = PROCESSES.try_lock()?;
+    processes.get(&current_pid())?.areas.iter().find(|area| area.contains(addr)).cloned()
+}
+
+//还保留着地址空间的进程(没有退出也没有在运行)的 4 级页表，给页面合并扫描用
+pub fn idle_address_spaces() -> Vec<PhysFrame> {
+    PROCESSES
+        .lock()
+        .values()
+        .filter(|p| p.state != State::Zombie && p.state != State::Running)
+        .map(|p| p.address_space)
+        .collect()
+}
+
+//记一次当前进程的缺页异常，由缺页异常处理程序调用，拿不到进程表的锁时不记
+pub fn count_fault(kind: FaultKind) {
+    if let Some(mut processes) = PROCESSES.try_lock() {
+        if let Some(process) = processes.get_mut(&current_pid()) {
+            process.faults.add(kind);
+        }
+    }
+}
+
+//改变当前进程的身份(setuid 系统调用)，只有 root 可以
+pub fn set_credentials(cred: Credentials) -> Result<(), ProcessError> {
+    let allowed = cred::current().is_root();
+    audit::record(Operation::SetUid, &format!("{}:{}", cred.uid, cred.gid), allowed);
+    if !allowed {
+        return Err(ProcessError::Fs(FsError::PermissionDenied));
+    }
+    let mut processes = PROCESSES.lock();
+    let process = processes.get_mut(&current_pid()).ok_or(ProcessError::NoSuchProcess)?;
+    process.cred = cred;
+    cred::switch(cred);
+    Ok(())
+}
+
+//把进程移到 group 组，进程不存在时返回 None
+pub fn set_group(pid: u64, group: &str) -> Option<()> {
+    PROCESSES.lock().get_mut(&pid).map(|process| process.group = String::from(group))
+}
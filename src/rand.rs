@@ -0,0 +1,150 @@
+//内核随机数发生器：用 ChaCha20 的密钥流作为随机数，密钥在启动时由熵源经 SHA-256 混合得到
+//熵源优先使用 CPU 的 RDSEED/RDRAND 指令；CPU 不支持时只能用 TSC 和 CMOS 实时时钟，
+//这时的随机数无法抵抗能观察启动时间的攻击者，只适合打乱地址、序列号等场合
+use crate::crypto::chacha20::{ChaCha20, KEY_SIZE, NONCE_SIZE};
+use crate::crypto::sha256::Sha256;
+use core::arch::asm;
+use core::arch::x86_64::__cpuid;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+const HARDWARE_SAMPLES: usize = 8; //从 RDSEED/RDRAND 取的 64 位随机数个数
+const HARDWARE_RETRIES: usize = 10; //指令暂时取不到随机数(CF=0)时的重试次数
+const BUFFER_SIZE: usize = 64; //每次生成一块密钥流
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    RdSeed,   //真随机数发生器
+    RdRand,   //由硬件熵源定期重新播种的伪随机数发生器
+    TimeOnly, //只有 TSC 和实时时钟
+}
+
+fn has_rdrand() -> bool {
+    __cpuid(1).ecx & (1 << 30) != 0
+}
+
+fn has_rdseed() -> bool {
+    __cpuid(0).eax >= 7 && __cpuid(7).ebx & (1 << 18) != 0
+}
+
+fn rdseed() -> Option<u64> {
+    for _ in 0..HARDWARE_RETRIES {
+        let (value, ok): (u64, u8);
+        unsafe { asm!("rdseed {0}", "setc {1}", out(reg) value, out(reg_byte) ok, options(nomem, nostack)) };
+        if ok != 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn rdrand() -> Option<u64> {
+    for _ in 0..HARDWARE_RETRIES {
+        let (value, ok): (u64, u8);
+        unsafe { asm!("rdrand {0}", "setc {1}", out(reg) value, out(reg_byte) ok, options(nomem, nostack)) };
+        if ok != 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+//读取 CMOS 实时时钟的秒、分、时、日、月、年寄存器(不关心是 BCD 还是二进制，只用作熵)
+fn rtc_bytes() -> [u8; 6] {
+    const REGISTERS: [u8; 6] = [0x00, 0x02, 0x04, 0x07, 0x08, 0x09];
+    let mut index = Port::<u8>::new(0x70);
+    let mut data = Port::<u8>::new(0x71);
+    let mut bytes = [0u8; 6];
+    for (byte, &register) in bytes.iter_mut().zip(REGISTERS.iter()) {
+        unsafe {
+            index.write(register);
+            *byte = data.read();
+        }
+    }
+    bytes
+}
+
+//收集熵并混合成 256 位密钥，返回密钥和实际用到的最好的熵源
+fn gather_seed() -> ([u8; KEY_SIZE], Source) {
+    let mut hasher = Sha256::new();
+    let mut source = Source::TimeOnly;
+    let sample: Option<fn() -> Option<u64>> = if has_rdseed() {
+        Some(rdseed)
+    } else if has_rdrand() {
+        Some(rdrand)
+    } else {
+        None
+    };
+    if let Some(sample) = sample {
+        let mut collected = 0;
+        for _ in 0..HARDWARE_SAMPLES {
+            if let Some(value) = sample() {
+                hasher.update(&value.to_le_bytes());
+                collected += 1;
+            }
+        }
+        if collected == HARDWARE_SAMPLES {
+            source = if has_rdseed() { Source::RdSeed } else { Source::RdRand };
+        }
+    }
+    //时间总是混进去：即使硬件熵源有问题，也不会比只用时间更差
+    hasher.update(&crate::console::timestamp().to_le_bytes());
+    hasher.update(&rtc_bytes());
+    hasher.update(&crate::console::timestamp().to_le_bytes());
+    (hasher.finalize(), source)
+}
+
+pub struct KernelRng {
+    cipher: ChaCha20,
+    buffer: [u8; BUFFER_SIZE],
+    used: usize, //buffer 中已经取走的字节数
+}
+
+impl KernelRng {
+    pub fn from_seed(seed: &[u8; KEY_SIZE]) -> KernelRng {
+        KernelRng { cipher: ChaCha20::new(seed, &[0; NONCE_SIZE], 0), buffer: [0; BUFFER_SIZE], used: BUFFER_SIZE }
+    }
+
+    pub fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for byte in dest.iter_mut() {
+            if self.used == BUFFER_SIZE {
+                self.buffer = [0; BUFFER_SIZE];
+                self.cipher.apply_keystream(&mut self.buffer);
+                self.used = 0;
+            }
+            *byte = self.buffer[self.used];
+            self.buffer[self.used] = 0; //取走的随机数不留在缓冲区里
+            self.used += 1;
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+}
+
+static RNG: Mutex<Option<KernelRng>> = Mutex::new(None);
+
+//播种全局随机数发生器，返回使用的熵源
+pub fn init() -> Source {
+    let (seed, source) = gather_seed();
+    *RNG.lock() = Some(KernelRng::from_seed(&seed));
+    source
+}
+
+//全局随机数发生器还没有初始化时先用当前的熵源初始化
+fn with_rng<T>(f: impl FnOnce(&mut KernelRng) -> T) -> T {
+    let mut rng = RNG.lock();
+    f(rng.get_or_insert_with(|| KernelRng::from_seed(&gather_seed().0)))
+}
+
+#[allow(dead_code)]
+pub fn fill_bytes(dest: &mut [u8]) {
+    with_rng(|rng| rng.fill_bytes(dest))
+}
+
+pub fn next_u64() -> u64 {
+    with_rng(|rng| rng.next_u64())
+}
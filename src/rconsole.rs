@@ -0,0 +1,375 @@
+//远程控制台：在 UDP 端口 2323 上接受加密的会话，远程执行 shell 命令，每个会话绑定一个后台虚拟终端(1..3)
+//认证靠预共享密钥：/etc/rconsole.key 中的 64 位十六进制数(32 字节)，能用它正确加密的一方就是可信的
+//每个数据报：会话号(4 字节，大端)、序号(8 字节，大端)，然后是 ChaCha20-Poly1305 加密的内容；
+//这 12 字节既是 nonce 也是附加数据，服务器发出的序号最高位为 1，两个方向的 nonce 不会重复
+//内容的第一个字节是类型：
+//  客户端 -> 服务器  O 打开会话(会话号 0，序号随机)  K<按键> 终端输入  L<命令> 执行一条命令(不经过行编辑)  C 关闭会话
+//  服务器 -> 客户端  W<打开请求的序号><终端> 会话已打开(会话号在头部)  T<文字> 命令的输出  P 命令结束  E<文字> 出错(回复打开请求时文字前面是请求的序号)
+//K 的内容是终端发出的原始字节(VT100 方向键、Ctrl+A/E/K/U/W/Y 等)，由每个会话自己的 LineEditor 编辑，回显作为 T 发回，回车时执行这一行
+//会话内客户端的序号必须递增，重放的数据报被丢弃；最近 OPEN_HISTORY 个打开请求的序号也被记住，重放的打开请求不会占用终端
+//(服务重新启动后记录清空)；空闲超过 IDLE_TIMEOUT 的会话被关闭
+//命令在 rconsole 线程里执行(shell 等待按键时)，输出写到会话的终端上，按 Alt+F2..F4 可以在本地看到
+//还没有 TCP，丢失的数据报不会重发；等待本地按键的命令(如 scrollback)会一直等到本地有人按键
+use crate::audit::{self, Operation};
+use crate::crypto::aead;
+use crate::crypto::chacha20::KEY_SIZE;
+use crate::fs::vfs;
+use crate::fs::FsError;
+use crate::line_editor::{Key, KeyDecoder, LineEditor, LineOutput, LineState};
+use crate::net::udp::UdpSocket;
+use crate::net::{Ipv4Addr, StackError};
+use crate::rand;
+use crate::shell;
+use crate::thread;
+use crate::time::Instant;
+use crate::vga_buffer;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use spin::Mutex;
+
+pub const PORT: u16 = 2323;
+pub const KEY_PATH: &str = "/etc/rconsole.key";
+
+const HEADER_SIZE: usize = 12;
+const REPLY: u64 = 1 << 63; //服务器发出的序号
+const MAX_SESSIONS: usize = vga_buffer::TERMINALS - 1; //0 号终端留给本地
+const OUTPUT_CHUNK: usize = 1024; //每个数据报最多带的输出
+const MAX_LINE: usize = 256;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+const OPEN_HISTORY: usize = 64;
+const HISTORY_LIMIT: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RconsoleError {
+    AlreadyRunning,
+    NotRunning,
+    BadKey, //密钥文件不是 64 位十六进制数
+    NoThread,
+    Fs(FsError),
+    Net(StackError),
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub id: u32,
+    pub peer: Ipv4Addr,
+    pub port: u16,
+    pub terminal: usize,
+    pub commands: u64,
+}
+
+struct Session {
+    id: u32,
+    peer: Ipv4Addr,
+    port: u16,
+    terminal: usize,
+    received: u64, //收到的最大序号
+    sent: u64,
+    pending: VecDeque<String>,
+    commands: u64,
+    last_active: Instant,
+    editor: LineEditor,
+    line: LineState,
+    input: KeyDecoder,
+}
+
+struct Service {
+    socket: UdpSocket,
+    key: [u8; KEY_SIZE],
+    sessions: Vec<Session>,
+    opened: VecDeque<u64>, //最近的打开请求的序号
+    generation: u64, //每次启动加一，停止后再启动时旧线程会发现自己过时了
+}
+
+//用 volatile 写清零，不会被当作无用的写入优化掉
+fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { core::ptr::write_volatile(byte, 0) };
+    }
+}
+
+impl Drop for Service {
+    //不在堆上留下密钥
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+    }
+}
+
+static SERVICE: Mutex<Option<Service>> = Mutex::new(None);
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn parse_key(text: &str) -> Option<[u8; KEY_SIZE]> {
+    let text = text.trim();
+    if text.len() != KEY_SIZE * 2 {
+        return None;
+    }
+    let mut key = [0u8; KEY_SIZE];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(key)
+}
+
+//密钥文件读到栈上的缓冲区，用完清零，密钥的文本不经过堆
+fn read_key() -> Result<[u8; KEY_SIZE], RconsoleError> {
+    let mut file = vfs::open(KEY_PATH).map_err(RconsoleError::Fs)?;
+    let mut text = [0u8; KEY_SIZE * 2 + 16]; //留出行尾的空白
+    let mut len = 0;
+    let result = loop {
+        match file.read(&mut text[len..]) {
+            Ok(0) => break core::str::from_utf8(&text[..len]).ok().and_then(parse_key).ok_or(RconsoleError::BadKey),
+            Ok(n) => len += n,
+            Err(err) => break Err(RconsoleError::Fs(err)),
+        }
+        if len == text.len() {
+            break Err(RconsoleError::BadKey);
+        }
+    };
+    wipe(&mut text);
+    result
+}
+
+fn header(session: u32, sequence: u64) -> [u8; HEADER_SIZE] {
+    let mut header = [0u8; HEADER_SIZE];
+    header[..4].copy_from_slice(&session.to_be_bytes());
+    header[4..].copy_from_slice(&sequence.to_be_bytes());
+    header
+}
+
+fn seal(key: &[u8; KEY_SIZE], session: u32, sequence: u64, payload: &[u8]) -> Vec<u8> {
+    let header = header(session, sequence);
+    let mut packet = Vec::from(&header[..]);
+    packet.extend_from_slice(&aead::seal(key, &header, &header, payload));
+    packet
+}
+
+//读取密钥，绑定端口，创建处理会话的线程
+pub fn start() -> Result<(), RconsoleError> {
+    let mut service = SERVICE.lock();
+    if service.is_some() {
+        return Err(RconsoleError::AlreadyRunning);
+    }
+    let key = read_key()?;
+    let socket = UdpSocket::bind(PORT).map_err(RconsoleError::Net)?;
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    if thread::spawn("rconsole", move || poll(generation)).is_err() {
+        return Err(RconsoleError::NoThread);
+    }
+    *service = Some(Service { socket, key, sessions: Vec::new(), opened: VecDeque::new(), generation });
+    crate::log!(Info, "rconsole: listening on udp port {}", PORT);
+    Ok(())
+}
+
+//关闭全部会话并停止服务(释放端口)，线程在下一次运行时结束
+pub fn stop() -> Result<(), RconsoleError> {
+    SERVICE.lock().take().map(drop).ok_or(RconsoleError::NotRunning)
+}
+
+pub fn running() -> bool {
+    SERVICE.lock().is_some()
+}
+
+pub fn sessions() -> Vec<SessionInfo> {
+    match SERVICE.lock().as_ref() {
+        Some(service) => service
+            .sessions
+            .iter()
+            .map(|s| SessionInfo { id: s.id, peer: s.peer, port: s.port, terminal: s.terminal, commands: s.commands })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+//处理收到的数据报，然后执行一条等待中的命令；服务已停止(或者重新启动过)时返回 false
+fn poll(generation: u64) -> bool {
+    let job = {
+        let mut guard = SERVICE.lock();
+        let service = match guard.as_mut() {
+            Some(service) if service.generation == generation => service,
+            _ => return false,
+        };
+        while let Some(datagram) = service.socket.try_recv_from() {
+            receive(service, datagram.src, datagram.src_port, &datagram.data);
+        }
+        service.sessions.retain(|session| session.last_active.elapsed() < IDLE_TIMEOUT);
+        service
+            .sessions
+            .iter_mut()
+            .find_map(|session| session.pending.pop_front().map(|line| (session.id, session.terminal, line)))
+    };
+    //执行命令时不持有锁，命令可能是 rconsole 自己
+    if let Some((id, terminal, line)) = job {
+        let ((), output) = vga_buffer::with_terminal(terminal, || shell::execute(&line));
+        let mut guard = SERVICE.lock();
+        if let Some(service) = guard.as_mut() {
+            for chunk in output.as_bytes().chunks(OUTPUT_CHUNK) {
+                let mut payload = Vec::from(&b"T"[..]);
+                payload.extend_from_slice(chunk);
+                send(service, id, &payload);
+            }
+            send(service, id, b"P");
+        }
+    }
+    true
+}
+
+fn send(service: &mut Service, id: u32, payload: &[u8]) {
+    let (peer, port, sequence) = match service.sessions.iter_mut().find(|session| session.id == id) {
+        Some(session) => {
+            session.sent += 1;
+            (session.peer, session.port, session.sent | REPLY)
+        }
+        None => return,
+    };
+    let packet = seal(&service.key, id, sequence, payload);
+    let _ = service.socket.send_to(&packet, peer, port);
+}
+
+fn receive(service: &mut Service, peer: Ipv4Addr, port: u16, packet: &[u8]) {
+    if packet.len() < HEADER_SIZE {
+        return;
+    }
+    let header: [u8; HEADER_SIZE] = packet[..HEADER_SIZE].try_into().unwrap();
+    let id = u32::from_be_bytes(header[..4].try_into().unwrap());
+    let sequence = u64::from_be_bytes(header[4..].try_into().unwrap());
+    if sequence & REPLY != 0 {
+        return;
+    }
+    let payload = match aead::open(&service.key, &header, &header, &packet[HEADER_SIZE..]) {
+        Some(payload) => payload,
+        None => {
+            crate::log!(Debug, "rconsole: dropped unauthenticated datagram from {}:{}", peer, port);
+            return;
+        }
+    };
+    if id == 0 {
+        return open_session(service, peer, port, sequence, &payload);
+    }
+    let session = match service.sessions.iter_mut().find(|session| session.id == id) {
+        Some(session) if sequence > session.received => session,
+        _ => return, //不存在的会话或者重放
+    };
+    session.received = sequence;
+    session.last_active = Instant::now();
+    session.peer = peer;
+    session.port = port;
+    match payload.split_first() {
+        Some((b'K', keys)) => {
+            let mut echo = TerminalOutput(String::new());
+            for &byte in keys {
+                let key = match session.input.decode(byte) {
+                    Some(Key::Char(_)) if session.line.char_count() >= MAX_LINE => continue,
+                    Some(key) => key,
+                    None => continue,
+                };
+                if let Some(line) = session.editor.feed(&mut session.line, key, &mut echo) {
+                    session.commands += 1;
+                    session.pending.push_back(line);
+                }
+            }
+            if !echo.0.is_empty() {
+                let mut payload = Vec::from(&b"T"[..]);
+                payload.extend_from_slice(echo.0.as_bytes());
+                send(service, id, &payload);
+            }
+        }
+        Some((b'L', line)) if line.len() <= MAX_LINE => {
+            session.commands += 1;
+            session.pending.push_back(String::from_utf8_lossy(line).into_owned());
+        }
+        Some((b'C', _)) => {
+            crate::log!(Info, "rconsole: session {:08x} from {} closed", id, peer);
+            service.sessions.retain(|session| session.id != id);
+        }
+        _ => send(service, id, b"Ebad request"),
+    }
+}
+
+//回复的 nonce 用新分配的会话号，重放的打开请求不会让服务器用同一个 nonce 加密不同的内容
+fn open_session(service: &mut Service, peer: Ipv4Addr, port: u16, sequence: u64, payload: &[u8]) {
+    if payload.first() != Some(&b'O') {
+        return;
+    }
+    if service.opened.contains(&sequence) {
+        crate::log!(Debug, "rconsole: dropped replayed open request from {}:{}", peer, port);
+        return;
+    }
+    if service.opened.len() == OPEN_HISTORY {
+        service.opened.pop_front();
+    }
+    service.opened.push_back(sequence);
+    let id = loop {
+        let id = rand::next_u64() as u32;
+        if id != 0 && service.sessions.iter().all(|session| session.id != id) {
+            break id;
+        }
+    };
+    let editor = LineEditor::new(HISTORY_LIMIT);
+    let terminal = (1..=MAX_SESSIONS).find(|terminal| service.sessions.iter().all(|session| session.terminal != *terminal));
+    let mut reply = Vec::new();
+    match terminal {
+        Some(terminal) => {
+            reply.push(b'W');
+            reply.extend_from_slice(&sequence.to_be_bytes());
+            reply.push(terminal as u8);
+            service.sessions.push(Session {
+                id,
+                peer,
+                port,
+                terminal,
+                received: 0,
+                sent: 0,
+                pending: VecDeque::new(),
+                commands: 0,
+                last_active: Instant::now(),
+                line: editor.begin(),
+                editor,
+                input: KeyDecoder::default(),
+            });
+            audit::record(Operation::RemoteSession, &format!("{:08x} from {}:{}", id, peer, port), true);
+            crate::log!(Info, "rconsole: session {:08x} from {}:{} on terminal {}", id, peer, port, terminal + 1);
+        }
+        None => {
+            audit::record(Operation::RemoteSession, &format!("from {}:{}: too many sessions", peer, port), false);
+            reply.push(b'E');
+            reply.extend_from_slice(&sequence.to_be_bytes());
+            reply.extend_from_slice(b"too many sessions");
+        }
+    }
+    let packet = seal(&service.key, id, REPLY, &reply);
+    let _ = service.socket.send_to(&packet, peer, port);
+}
+
+//回显用 VT100 序列移动光标，换行是 \r\n
+struct TerminalOutput(String);
+
+impl LineOutput for TerminalOutput {
+    fn write_str(&mut self, s: &str) {
+        for (i, part) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.0.push_str("\r\n");
+            }
+            self.0.push_str(part);
+        }
+    }
+
+    fn cursor_left(&mut self, n: usize) {
+        if n > 0 {
+            self.0.push_str(&format!("\x1b[{}D", n));
+        }
+    }
+
+    fn cursor_right(&mut self, n: usize) {
+        if n > 0 {
+            self.0.push_str(&format!("\x1b[{}C", n));
+        }
+    }
+
+    fn clear_to_end(&mut self) {
+        self.0.push_str("\x1b[K");
+    }
+}
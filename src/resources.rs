@@ -0,0 +1,105 @@
+//编译时嵌入内核映像的资源：字体、键盘布局、程序、配置文件等，放在仓库的 resources/ 目录下，由 build.rs 压缩后生成资源表
+//按名字(相对 resources/ 的路径)查找，取用时解压；压缩格式见 build.rs
+//files/ 下的资源在启动时复制到 ramfs(files/etc/motd 成为 /etc/motd)，小的配置不需要磁盘映像或 initrd；
+//fw_cfg 传进来的同名文件在之后复制，会覆盖它们
+use crate::fs::vfs;
+use crate::fs::FsError;
+use alloc::format;
+use alloc::vec::Vec;
+
+pub const FILES_PREFIX: &str = "files/";
+
+pub struct Resource {
+    pub name: &'static str,
+    pub size: usize, //解压后的大小
+    pub compressed: bool,
+    pub data: &'static [u8],
+}
+
+//build.rs 生成的资源表
+macro_rules! resources {
+    () => {
+        include!(concat!(env!("OUT_DIR"), "/resources.rs"))
+    };
+}
+
+static RESOURCES: &[Resource] = resources!();
+
+pub fn list() -> &'static [Resource] {
+    RESOURCES
+}
+
+pub fn find(name: &str) -> Option<&'static Resource> {
+    RESOURCES.iter().find(|resource| resource.name == name)
+}
+
+//解压后的内容
+pub fn get(name: &str) -> Option<Vec<u8>> {
+    let resource = find(name)?;
+    if !resource.compressed {
+        return Some(Vec::from(resource.data));
+    }
+    decompress(resource.data, resource.size)
+}
+
+//数据损坏(往回的距离超出已经解出的内容、长度不对)时返回 None
+fn decompress(data: &[u8], size: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(size);
+    let mut pos = 0;
+    while pos < data.len() {
+        let control = data[pos] as usize;
+        pos += 1;
+        if control < 0x80 {
+            let literals = data.get(pos..pos + control + 1)?;
+            out.extend_from_slice(literals);
+            pos += control + 1;
+        } else {
+            let distance = u16::from_le_bytes([*data.get(pos)?, *data.get(pos + 1)?]) as usize;
+            pos += 2;
+            if distance == 0 || distance > out.len() {
+                return None;
+            }
+            let start = out.len() - distance;
+            for i in 0..(control & 0x7f) + 3 {
+                let byte = out[start + i]; //重复的部分可以和正在写出的部分重叠
+                out.push(byte);
+            }
+        }
+    }
+    if out.len() != size {
+        return None;
+    }
+    Some(out)
+}
+
+//启动时调用：把 files/ 下的资源复制到 ramfs，返回复制的文件数
+pub fn install_files() -> Result<usize, FsError> {
+    let mut count = 0;
+    for resource in RESOURCES {
+        let relative = match resource.name.strip_prefix(FILES_PREFIX) {
+            Some(relative) if !relative.is_empty() => relative,
+            _ => continue,
+        };
+        let data = get(resource.name).ok_or(FsError::InvalidFilesystem)?;
+        for (end, _) in relative.match_indices('/') {
+            match vfs::mkdir(&format!("/{}", &relative[..end])) {
+                Ok(()) | Err(FsError::AlreadyExists) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let path = format!("/{}", relative);
+        if vfs::metadata(&path).is_ok() {
+            vfs::remove(&path)?;
+        }
+        let mut file = vfs::create(&path)?;
+        let mut written = 0;
+        while written < data.len() {
+            match file.write(&data[written..])? {
+                0 => return Err(FsError::NoSpace),
+                n => written += n,
+            }
+        }
+        count += 1;
+    }
+    Ok(count)
+}
@@ -0,0 +1,112 @@
+//时钟和线程调度的确定性检查：从一个时钟中断刚发生的时刻开始，运行一段固定的场景(创建线程、轮流运行、sleep)，
+//把每个事件连同发生时距开始的时钟中断数记下来，与 /etc/schedtrace 中的黄金记录逐行比较，顺序和中断数都必须一致
+//只有在 QEMU 的 -icount 下结果才是确定的：虚拟时钟按执行的指令数前进，PIT 中断和 TSC 每次都落在同样的位置；
+//按墙上时间运行时宿主机的负载会让 sleep 跨过不同数量的时钟中断，所以这种回归平常的测试发现不了
+//场景中的 sleep 都在两次时钟中断的中间结束，离边沿至少 2 毫秒，内核代码的小改动不会改变结果
+//事件行："<时钟中断数> <事件>"；线程 a 运行 3 次，b 运行 2 次并在第一次运行时创建 c，c 运行 1 次
+//命令行开关 schedcheck 在启动完成时运行，记录和比较结果写到 COM1，每行以 "schedcheck: " 开头，
+//scripts/schedcheck.sh 构建映像，在 qemu-system-x86_64 -icount shift=0 -nic none -serial stdio 下运行并检查 "schedcheck: result"
+//目前没有定时器回调和抢占：时钟中断只计数，线程在 thread::poll 里轮流运行，这里检查的就是这两件事的相对顺序
+use crate::drivers::uart::{self, Uart};
+use crate::interrupts;
+use crate::memlayout;
+use crate::{cmdline, println, thread, time};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::time::Duration;
+use spin::Mutex;
+
+pub const GOLDEN_PATH: &str = "/etc/schedtrace";
+
+const ROUNDS: u64 = 4;
+const ROUND_SLEEP: Duration = Duration::from_millis(12); //时钟中断间隔 10 毫秒，每轮结束时离边沿 2..8 毫秒
+
+static TRACE: Mutex<Vec<String>> = Mutex::new(Vec::new());
+static START: Mutex<u64> = Mutex::new(0);
+
+fn event(text: &str) {
+    let tick = interrupts::ticks() - *START.lock();
+    TRACE.lock().push(format!("{} {}", tick, text));
+}
+
+//步数达到 steps 时结束的线程，每次运行记一个事件
+fn stepper(name: &'static str, steps: u64) -> impl FnMut() -> bool + Send + 'static {
+    let mut step = 0;
+    move || {
+        event(&format!("{} {}", name, step));
+        step += 1;
+        step < steps
+    }
+}
+
+//运行场景，返回事件记录
+pub fn run() -> Vec<String> {
+    TRACE.lock().clear();
+    //等到一次时钟中断刚发生
+    let last = interrupts::ticks();
+    while interrupts::ticks() == last {
+        core::hint::spin_loop();
+    }
+    *START.lock() = interrupts::ticks();
+
+    let _ = thread::spawn("sched-a", stepper("a", 3));
+    let mut spawned = false;
+    let mut b = stepper("b", 2);
+    let _ = thread::spawn("sched-b", move || {
+        if !spawned {
+            spawned = true;
+            let _ = thread::spawn("sched-c", stepper("c", 1));
+        }
+        b()
+    });
+    for round in 0..ROUNDS {
+        event(&format!("poll {}", round));
+        thread::poll();
+        time::sleep(ROUND_SLEEP);
+    }
+    event("done");
+    core::mem::take(&mut *TRACE.lock())
+}
+
+//按顺序比较，返回第一处不同："<行号> 期望的行 | 实际的行"，缺少的一边是 "-"
+fn first_difference(golden: &[String], trace: &[String]) -> Option<String> {
+    (0..golden.len().max(trace.len())).find_map(|i| {
+        let expected = golden.get(i).map_or("-", |line| line.as_str());
+        let actual = trace.get(i).map_or("-", |line| line.as_str());
+        (expected != actual).then(|| format!("line {}: expected {} | got {}", i + 1, expected, actual))
+    })
+}
+
+pub fn enabled() -> bool {
+    cmdline::flag("schedcheck")
+}
+
+//启动完成时调用：记录和比较结果写到串口，不一致时在控制台上警告
+pub fn check() {
+    let trace = run();
+    let mut port = Uart::new(uart::COM1);
+    let serial = port.present();
+    if serial {
+        for line in &trace {
+            let _ = writeln!(port, "schedcheck: {}", line);
+        }
+    }
+    let result = match memlayout::baseline(GOLDEN_PATH) {
+        Ok(golden) => match first_difference(&golden, &trace) {
+            None => String::from("pass"),
+            Some(difference) => {
+                println!("schedcheck: trace differs from {}: {}", GOLDEN_PATH, difference);
+                if serial {
+                    let _ = writeln!(port, "schedcheck: {}", difference);
+                }
+                String::from("FAIL")
+            }
+        },
+        Err(_) => String::from("new"),
+    };
+    if serial {
+        let _ = writeln!(port, "schedcheck: result {}", result);
+    }
+}
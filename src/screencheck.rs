@@ -0,0 +1,105 @@
+//屏幕内容的黄金映像检查：对整个屏幕(VGA 文本模式下是 80x25 个字符和属性，图形模式下是全部像素)求哈希，和事先记录的值比较
+//用来在改动 Writer、TUI 之后自动发现显示上的回归；被检查的画面必须是确定的(不能含有时间、地址这类每次运行都不同的内容)
+//黄金值放在 /etc/golden-screens 中，每行 "<名字> <16 位十六进制哈希>"，CI 通过 fw_cfg 的 opt/joakimos/files/etc/golden-screens 传进来
+//结果同时写到 COM1，一行 "screencheck: <名字> <哈希> pass|FAIL|new"，没有黄金值时是 new，CI 可以把它记录下来作为新的黄金值
+//命令行开关 screencheck 在启动完成(shell 启动前)时自动检查 "boot" 画面；panic 时只输出 "panic" 画面的哈希(结果为 unchecked)
+use crate::drivers::uart::{self, Uart};
+use crate::fs::vfs;
+use crate::{cmdline, framebuffer, vga_buffer};
+use alloc::string::String;
+use core::fmt::Write;
+
+pub const GOLDEN_PATH: &str = "/etc/golden-screens";
+
+const VGA_TEXT: usize = 0xb8000;
+const VGA_TEXT_SIZE: usize = 80 * 25 * 2;
+
+//64 位 FNV-1a：不分配内存，panic 时也能用
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+//当前屏幕内容的哈希
+pub fn hash() -> u64 {
+    let mut fnv = Fnv::new();
+    let graphics = framebuffer::with_console(|console| {
+        let fb = console.framebuffer();
+        fnv.update(&(fb.width() as u32).to_le_bytes());
+        fnv.update(&(fb.height() as u32).to_le_bytes());
+        for pixel in fb.pixels() {
+            fnv.update(&(pixel & 0x00ff_ffff).to_le_bytes()); //最高字节没有用，不同的显卡可能不一样
+        }
+    });
+    if graphics.is_none() && vga_buffer::present() {
+        //前台终端的内容总在显存里
+        let text = unsafe { core::slice::from_raw_parts(VGA_TEXT as *const u8, VGA_TEXT_SIZE) };
+        fnv.update(text);
+    }
+    fnv.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail(u64), //记录的黄金值
+    New,       //还没有这个画面的黄金值
+}
+
+//黄金值文件中 name 对应的哈希
+pub fn golden(name: &str) -> Option<u64> {
+    let data = vfs::open(GOLDEN_PATH).and_then(|mut file| file.read_to_end()).ok()?;
+    let text = String::from_utf8_lossy(&data);
+    text.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(n), Some(hash), None) if n == name => u64::from_str_radix(hash, 16).ok(),
+            _ => None,
+        }
+    })
+}
+
+//把结果写到串口，没有串口时什么也不做
+fn report(name: &str, hash: u64, outcome: &str) {
+    let mut port = Uart::new(uart::COM1);
+    if port.present() {
+        let _ = writeln!(port, "screencheck: {} {:016x} {}", name, hash, outcome);
+    }
+}
+
+//检查当前画面，结果也写到串口
+pub fn check(name: &str) -> (u64, Outcome) {
+    let hash = hash();
+    let outcome = match golden(name) {
+        Some(expected) if expected == hash => Outcome::Pass,
+        Some(expected) => Outcome::Fail(expected),
+        None => Outcome::New,
+    };
+    let text = match outcome {
+        Outcome::Pass => "pass",
+        Outcome::Fail(_) => "FAIL",
+        Outcome::New => "new",
+    };
+    report(name, hash, text);
+    (hash, outcome)
+}
+
+pub fn enabled() -> bool {
+    cmdline::flag("screencheck")
+}
+
+//panic 时调用：文件系统的锁可能正被持有，只把哈希写到串口，由 CI 比较
+pub fn check_panic() {
+    if enabled() {
+        report("panic", hash(), "unchecked");
+    }
+}
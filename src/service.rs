@@ -0,0 +1,190 @@
+//可重启的服务：服务函数内部发生 panic 时，不让整个系统停机，而是回到服务边界按策略重启
+//内核编译时禁用了栈展开(panic = "abort")，所以用类似 setjmp/longjmp 的方式直接恢复到进入服务前保存的上下文
+//恢复时不会执行服务栈上对象的析构函数；服务加上、还没有释放的 sync::Mutex 锁在恢复前强制释放，
+//否则重启后的服务第一次输出或访问文件就会死锁。直接使用 spin::Mutex 的锁不在记录里，仍然可能留在加锁状态
+//panic 发生在中断/异常处理函数里时不恢复：被打断的代码状态未知，时钟中断也还没有发送 EOI
+//关中断运行时(例如系统调用路径)的 panic 可以恢复，恢复时把 RFLAGS.IF 还原成进入服务时的状态
+use crate::{emergency_println, interrupts, println};
+use crate::sync::{self, HeldSet};
+use core::arch::global_asm;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+const BACKTRACE_DEPTH: usize = 16; //回溯最多打印的栈帧数
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    Never,      //panic 后不再重启
+    Always,     //总是重启
+    Limit(u32), //最多重启若干次
+}
+
+pub struct Service {
+    pub name: &'static str,
+    pub entry: fn(),
+    pub policy: RestartPolicy,
+}
+
+//进入服务前保存的被调用者保存寄存器、栈指针和返回地址，字段顺序与下面的汇编一致
+#[repr(C)]
+#[derive(Default)]
+struct Context {
+    rbx: u64,
+    rbp: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+    rsp: u64,
+    rip: u64,
+}
+
+struct Recovery {
+    context: Context,
+    service: &'static str,
+    locks: HeldSet, //进入服务时已经持有的锁，不是服务加的
+    previous: *mut Recovery, //嵌套运行服务时，外层服务的恢复点
+    interrupts: bool, //进入服务时中断是否打开
+}
+
+global_asm!(
+    //service_call_with_recovery(ctx, entry, arg)：保存上下文后调用 entry(arg)
+    //正常返回时返回 0；服务 panic 后通过 service_resume 回到这里时返回 1
+    ".global service_call_with_recovery",
+    "service_call_with_recovery:",
+    "mov [rdi + 0x00], rbx",
+    "mov [rdi + 0x08], rbp",
+    "mov [rdi + 0x10], r12",
+    "mov [rdi + 0x18], r13",
+    "mov [rdi + 0x20], r14",
+    "mov [rdi + 0x28], r15",
+    "lea rax, [rsp + 8]", //返回后调用者看到的栈指针
+    "mov [rdi + 0x30], rax",
+    "mov rax, [rsp]", //返回地址
+    "mov [rdi + 0x38], rax",
+    "mov rdi, rdx",
+    "sub rsp, 8", //保持调用 entry 时栈按 16 字节对齐
+    "call rsi",
+    "add rsp, 8",
+    "xor eax, eax",
+    "ret",
+    //service_resume(ctx)：恢复保存的上下文，让 service_call_with_recovery 返回 1
+    ".global service_resume",
+    "service_resume:",
+    "mov rbx, [rdi + 0x00]",
+    "mov rbp, [rdi + 0x08]",
+    "mov r12, [rdi + 0x10]",
+    "mov r13, [rdi + 0x18]",
+    "mov r14, [rdi + 0x20]",
+    "mov r15, [rdi + 0x28]",
+    "mov rsp, [rdi + 0x30]",
+    "mov eax, 1",
+    "jmp [rdi + 0x38]",
+);
+
+extern "C" {
+    fn service_call_with_recovery(ctx: *mut Context, entry: extern "C" fn(*mut u8), arg: *mut u8) -> u64;
+    fn service_resume(ctx: *const Context) -> !;
+}
+
+//当前运行中的最内层服务的恢复点
+static CURRENT: AtomicPtr<Recovery> = AtomicPtr::new(ptr::null_mut());
+
+extern "C" fn trampoline(arg: *mut u8) {
+    let service = unsafe { &*(arg as *const Service) };
+    (service.entry)();
+}
+
+//运行一个服务，直到它正常返回或者按策略不再重启
+pub fn run(service: &'static Service) {
+    let mut restarts = 0u32;
+    loop {
+        let mut recovery = Recovery {
+            context: Context::default(),
+            service: service.name,
+            locks: sync::held(),
+            previous: CURRENT.load(Ordering::SeqCst),
+            interrupts: x86_64::instructions::interrupts::are_enabled(),
+        };
+        CURRENT.store(&mut recovery, Ordering::SeqCst);
+        let panicked = unsafe {
+            service_call_with_recovery(
+                &mut recovery.context,
+                trampoline,
+                service as *const Service as *mut u8,
+            )
+        };
+        CURRENT.store(recovery.previous, Ordering::SeqCst);
+
+        if panicked == 0 {
+            return;
+        }
+        restarts += 1;
+        let restart = match service.policy {
+            RestartPolicy::Never => false,
+            RestartPolicy::Always => true,
+            RestartPolicy::Limit(max) => restarts <= max,
+        };
+        if !restart {
+            println!("service {}: crashed, not restarting", service.name);
+            return;
+        }
+        println!("service {}: restarting (attempt {})", service.name, restarts);
+    }
+}
+
+//由 panic 处理函数调用：如果 panic 发生在可重启的服务里，打印回溯并回到服务边界，否则直接返回
+pub fn recover_from_panic() {
+    let recovery = CURRENT.load(Ordering::SeqCst);
+    if recovery.is_null() {
+        return;
+    }
+    let recovery = unsafe { &*recovery };
+    if interrupts::in_handler() {
+        emergency_println!("service {}: panicked in an interrupt handler, not restarting", recovery.service);
+        return;
+    }
+    //先释放锁再输出，输出用的锁可能就在其中；释放的锁记在栈上，这时不能依赖堆
+    let mut released = [("", None); sync::MAX_HELD];
+    let mut count = 0;
+    unsafe {
+        sync::force_release_since(&recovery.locks, &mut |name, location| {
+            if count < released.len() {
+                released[count] = (name, location);
+                count += 1;
+            }
+        });
+    }
+    println!("service {} panicked, backtrace:", recovery.service);
+    print_backtrace(recovery.context.rbp);
+    for (name, location) in &released[..count] {
+        match location {
+            Some(location) => println!("service {}: released lock {} held at {}", recovery.service, name, location),
+            None => println!("service {}: released lock {}", recovery.service, name),
+        }
+    }
+    if recovery.interrupts {
+        x86_64::instructions::interrupts::enable();
+    } else {
+        x86_64::instructions::interrupts::disable();
+    }
+    unsafe { service_resume(&recovery.context) }
+}
+
+//沿着帧指针链回溯，直到回到服务边界所在的栈帧(需要编译时开启 force-frame-pointers)
+fn print_backtrace(boundary_rbp: u64) {
+    let mut rbp: u64;
+    unsafe {
+        core::arch::asm!("mov {}, rbp", out(reg) rbp);
+    }
+    for depth in 0..BACKTRACE_DEPTH {
+        if rbp == 0 || rbp == boundary_rbp || !rbp.is_multiple_of(8) {
+            break;
+        }
+        let frame = rbp as *const u64;
+        let (saved_rbp, return_address) = unsafe { (*frame, *frame.add(1)) };
+        println!("  #{:<2} {:#018x}", depth, return_address);
+        rbp = saved_rbp;
+    }
+}
@@ -0,0 +1,1969 @@
+use crate::abi;
+use crate::audit;
+use crate::bell;
+use crate::block;
+use crate::cmdline::LogLevel;
+use crate::console::{self, ProgressBar};
+use crate::console_filter::{self, Action as FilterAction};
+use crate::cred::{self, Credentials};
+use crate::crypto::{self, sha256::Sha256};
+use crate::driver;
+use crate::drivers::uart::{self, Uart};
+use crate::drivers::net as nic;
+use crate::drivers::{crypt, fw_cfg, keyboard, keymap, ramdisk, smart, snapshot};
+use crate::fs::vfs::{FileHandle, InodeKind};
+use crate::framebuffer;
+use crate::gdbstub;
+use crate::ksm;
+use crate::latency;
+use crate::failpoint::{self, Action, Config};
+use crate::group;
+use crate::fs::{cache, crashtest, fat32, loopback, vfs, FsError};
+use crate::log::{self, Sink};
+use crate::memlayout;
+use crate::line_editor::{Key, KeySource, LineEditor, LineOutput};
+use crate::loader::cache as image_cache;
+use crate::net::{self, arp, icmp, rss, Ipv4Addr};
+use crate::power;
+use crate::rconsole;
+use crate::resources;
+use crate::process::{self, Personality, ProcessError, State};
+use crate::screencheck::{self, Outcome};
+use crate::signal;
+use crate::thread;
+use crate::trace;
+use crate::tui::{self, Align, BoxStyle, Canvas, Rect, Table};
+use crate::vga_buffer::{self, Color, ColorCode};
+use crate::vm;
+use crate::watch::{self, Kind as WatchKind};
+use crate::watchdog;
+use crate::xmodem::{self, XmodemError};
+use crate::zerofill;
+use crate::{allocator, memory, msg, print, println};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use pc_keyboard::{DecodedKey, KeyCode, KeyState};
+use spin::Mutex;
+
+const HISTORY_LIMIT: usize = 32; //最多保留的历史命令条数
+const CONTINUATION_PROMPT: &str = ".. "; //续行和 here 文档的提示符
+const HEREDOC_DIR: &str = "/run"; //here 文档的临时文件放在这里，命令结束后删除
+const MOTD_PATH: &str = "/etc/motd"; //shell 启动时显示
+const COPY_CHUNK: usize = 16 * 1024; //复制文件时每次读写的字节数
+const PROGRESS_THRESHOLD: u64 = 64 * 1024; //复制超过这个大小的文件时显示进度条
+
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub run: fn(&[&str]), //参数不包含命令名本身
+}
+
+static COMMANDS: &[Command] = &[
+    Command { name: "help", usage: "help", run: help },
+    Command { name: "ls", usage: "ls [-l] [path]", run: ls },
+    Command { name: "cat", usage: "cat <path>...", run: cat },
+    Command { name: "cp", usage: "cp [-r] <src> <dest>", run: cp },
+    Command { name: "mv", usage: "mv <src> <dest>", run: mv },
+    Command { name: "ln", usage: "ln [-s] <target> <link>", run: ln },
+    Command { name: "readlink", usage: "readlink [-f] <path>", run: readlink },
+    Command { name: "rm", usage: "rm [-r] <path>...", run: rm },
+    Command { name: "mkdir", usage: "mkdir [-p] <path>...", run: mkdir },
+    Command { name: "chmod", usage: "chmod <mode> <path>...", run: chmod },
+    Command { name: "chown", usage: "chown <uid>[:<gid>] <path>...", run: chown },
+    Command { name: "sha256sum", usage: "sha256sum <path>...", run: sha256sum },
+    Command { name: "verify", usage: "verify <path> <sha256>", run: verify },
+    Command { name: "sx", usage: "sx [-k] <path>", run: sx },
+    Command { name: "rx", usage: "rx <path> [size]", run: rx },
+    Command { name: "run", usage: "run [-l] [-u <uid>[:<gid>]] <path> [< file | <<TAG]", run: run_program },
+    Command { name: "spawn", usage: "spawn [-l] [-u <uid>[:<gid>]] <path> [< file | <<TAG]", run: spawn },
+    Command { name: "wait", usage: "wait <pid>", run: wait },
+    Command { name: "kill", usage: "kill [-<signal>] <pid>", run: kill },
+    Command { name: "ps", usage: "ps", run: ps },
+    Command { name: "sysinfo", usage: "sysinfo", run: sysinfo },
+    Command { name: "shutdown", usage: "shutdown", run: shutdown },
+    Command { name: "reboot", usage: "reboot", run: reboot },
+    Command { name: "drivers", usage: "drivers", run: drivers_cmd },
+    Command { name: "audit", usage: "audit show", run: audit_cmd },
+    Command { name: "progcache", usage: "progcache [clear]", run: progcache },
+    Command { name: "vmstat", usage: "vmstat", run: vmstat },
+    Command { name: "latency", usage: "latency report|reset", run: latency_cmd },
+    Command { name: "tracedump", usage: "tracedump [serial|clear|on|off]", run: tracedump },
+    Command { name: "log", usage: "log [sink <vga|serial|dmesg> [level=<level>] [on|off]]", run: log_sinks },
+    Command { name: "cgroup", usage: "cgroup [create|delete <name> | set <name> [mem=<KiB>|max] [shares=<n>] | add <name> <pid>]", run: cgroup },
+    Command { name: "failpoint", usage: "failpoint [set <name> error|panic|delay=<ms> [percent] [count] | clear <name>]", run: failpoint_cmd },
+    Command { name: "allocator", usage: "allocator [check [all]]", run: allocator_cmd },
+    Command { name: "loadkeys", usage: "loadkeys [layout]", run: loadkeys },
+    Command { name: "kbd", usage: "kbd [raw on|off | inject <scancode>... | press <key>... | key <key> down|up]", run: kbd },
+    Command { name: "bell", usage: "bell [audible|visual|off|test]", run: bell_cmd },
+    Command { name: "smartctl", usage: "smartctl <device>", run: smartctl },
+    Command { name: "fwcfg", usage: "fwcfg [cat <name>]", run: fwcfg },
+    Command { name: "gdb", usage: "gdb", run: gdb },
+    Command { name: "ksm", usage: "ksm [scan|on|off]", run: ksm_cmd },
+    Command { name: "watch", usage: "watch [add <name> <provider> | del <name> | providers]", run: watch_cmd },
+    Command { name: "zerofill", usage: "zerofill [on|off]", run: zerofill_cmd },
+    Command { name: "threads", usage: "threads", run: threads },
+    Command { name: "hl", usage: "hl [add <pattern> <color> | del <pattern>]", run: hl },
+    Command { name: "suppress", usage: "suppress [add <pattern> | del <pattern>]", run: suppress },
+    Command { name: "scrollback", usage: "scrollback", run: scrollback },
+    Command { name: "screencheck", usage: "screencheck <name>", run: screencheck_cmd },
+    Command { name: "abi", usage: "abi", run: abi_cmd },
+    Command { name: "memlayout", usage: "memlayout [save|diff] [path]", run: memlayout_cmd },
+    Command { name: "resources", usage: "resources", run: resources_cmd },
+    Command { name: "fbset", usage: "fbset <width>x<height> [font.psf|res:<name>]", run: fbset },
+    Command { name: "mkfs.fat", usage: "mkfs.fat <device> [--fat32] [--label NAME]", run: mkfs_fat },
+    Command { name: "losetup", usage: "losetup [<file> | -d <device>]", run: losetup },
+    Command { name: "ramdisk", usage: "ramdisk create <size>[K|M] | destroy <device>", run: ramdisk_cmd },
+    Command { name: "snapshot", usage: "snapshot [create|commit|discard|destroy <device> | fail <device> <blocks>|off]", run: snapshot_cmd },
+    Command { name: "fsck", usage: "fsck <device>", run: fsck },
+    Command { name: "crashtest", usage: "crashtest <device> <workload> [iterations]", run: crashtest_cmd },
+    Command { name: "ifconfig", usage: "ifconfig [<address> <netmask> <gateway>]", run: ifconfig },
+    Command { name: "netqueues", usage: "netqueues", run: netqueues },
+    Command { name: "rconsole", usage: "rconsole [start | stop]", run: rconsole_cmd },
+    Command { name: "arp", usage: "arp", run: arp_cmd },
+    Command { name: "ping", usage: "ping <address> [count]", run: ping },
+    Command { name: "cryptsetup", usage: "cryptsetup [open <device> | close <device>]", run: cryptsetup },
+    Command { name: "mount", usage: "mount <device> <dir>", run: mount },
+    Command { name: "umount", usage: "umount <dir>", run: umount },
+    Command { name: "sync", usage: "sync", run: sync },
+];
+
+//shell 主循环：显示提示符，读取一行并执行
+pub fn run() -> ! {
+    if let Ok(motd) = vfs::open(MOTD_PATH).and_then(|mut file| file.read_to_end()) {
+        print!("{}", String::from_utf8_lossy(&motd));
+    }
+    let mut editor = LineEditor::new(HISTORY_LIMIT);
+    loop {
+        print!("> ");
+        let (line, heredoc) = read_command(&mut editor);
+        execute_with_input(&line, heredoc.as_deref());
+    }
+}
+
+//读取一条完整的命令：行尾的 \ 表示命令在下一行继续；命令中有 <<TAG 时接着读取 here 文档，直到只有 TAG 的一行
+//返回命令和 here 文档的内容
+fn read_command(editor: &mut LineEditor) -> (String, Option<String>) {
+    let mut line = editor.read_line(&mut KeyboardInput, &mut VgaOutput);
+    while let Some(head) = line.strip_suffix('\\') {
+        let mut joined = String::from(head);
+        print!("{}", CONTINUATION_PROMPT);
+        joined.push_str(&editor.read_line(&mut KeyboardInput, &mut VgaOutput));
+        line = joined;
+    }
+    let mut args: Vec<&str> = line.split_whitespace().collect();
+    let tag = match take_redirect(&mut args) {
+        Ok(Some(Redirect::Heredoc(tag))) => String::from(tag),
+        _ => return (line, None),
+    };
+    let mut body = String::new();
+    loop {
+        print!("{}", CONTINUATION_PROMPT);
+        let text = editor.read_line(&mut KeyboardInput, &mut VgaOutput);
+        if text == tag {
+            break;
+        }
+        body.push_str(&text);
+        body.push('\n');
+    }
+    (line, Some(body))
+}
+
+//命令的标准输入重定向，只有启动用户程序的命令(run、spawn)使用
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Redirect<'a> {
+    File(&'a str),    //< path
+    Heredoc(&'a str), //<<TAG，内容是命令之后的几行
+}
+
+//从参数中取出 "< path"、"<path"、"<< TAG" 或 "<<TAG"，TAG 可以带引号；同一条命令只能有一个重定向
+fn take_redirect<'a>(args: &mut Vec<&'a str>) -> Result<Option<Redirect<'a>>, &'static str> {
+    let index = match args.iter().position(|arg| arg.starts_with('<')) {
+        Some(index) => index,
+        None => return Ok(None),
+    };
+    let arg = args.remove(index);
+    let (heredoc, rest) = match arg.strip_prefix("<<") {
+        Some(rest) => (true, rest),
+        None => (false, &arg[1..]),
+    };
+    let target = if !rest.is_empty() {
+        rest
+    } else if index < args.len() {
+        args.remove(index)
+    } else {
+        return Err("missing redirection target");
+    };
+    if args.iter().any(|arg| arg.starts_with('<')) {
+        return Err("only one input redirection is supported");
+    }
+    Ok(Some(if heredoc { Redirect::Heredoc(target.trim_matches(|c| c == '\'' || c == '"')) } else { Redirect::File(target) }))
+}
+
+//当前命令的标准输入(文件路径)，启动用户程序时取走，见 start_program
+static STDIN: Mutex<Option<String>> = Mutex::new(None);
+static HEREDOCS: AtomicU64 = AtomicU64::new(0);
+
+//把 here 文档写到临时文件，返回文件路径
+fn write_heredoc(body: &str) -> Result<String, FsError> {
+    let path = format!("{}/heredoc.{}", HEREDOC_DIR, HEREDOCS.fetch_add(1, Ordering::Relaxed));
+    let mut file = vfs::create(&path)?;
+    let mut written = 0;
+    while written < body.len() {
+        match file.write(&body.as_bytes()[written..])? {
+            0 => return Err(FsError::NoSpace),
+            n => written += n,
+        }
+    }
+    Ok(path)
+}
+
+//创建进程，当前命令有输入重定向时把它的标准输入换成那个文件
+fn start_program(path: &str, cred: Credentials, personality: Option<Personality>) -> Result<u64, ProcessError> {
+    let pid = process::spawn_user(path, cred, personality)?;
+    if let Some(input) = STDIN.lock().take() {
+        if let Err(err) = vfs::open(&input).map_err(ProcessError::from).and_then(|handle| process::set_stdin(pid, handle)) {
+            let _ = process::kill(pid).and_then(|_| process::wait(pid));
+            return Err(err);
+        }
+    }
+    Ok(pid)
+}
+
+//把 PS/2 键盘的按键翻译成行编辑器的按键
+struct KeyboardInput;
+
+impl KeySource for KeyboardInput {
+    fn read_key(&mut self) -> Key {
+        //等待按键的同时运行内核线程(包括收包)和各种空闲时的工作
+        let key = loop {
+            if let Some(key) = keyboard::poll_key() {
+                break key;
+            }
+            watchdog::touch();
+            thread::poll();
+            ksm::idle();
+            zerofill::idle();
+            watch::idle();
+            smart::idle();
+            gdbstub::idle();
+            power::idle();
+        };
+        match key {
+            DecodedKey::Unicode('\n') => Key::Enter,
+            DecodedKey::Unicode('\u{8}') => Key::Backspace,
+            DecodedKey::Unicode('\u{7f}') => Key::Delete,
+            DecodedKey::Unicode('\u{1}') => Key::Home,
+            DecodedKey::Unicode('\u{5}') => Key::End,
+            DecodedKey::Unicode('\u{b}') => Key::KillToEnd,
+            DecodedKey::Unicode('\u{15}') => Key::KillToStart,
+            DecodedKey::Unicode('\u{17}') => Key::KillWord,
+            DecodedKey::Unicode('\u{19}') => Key::Yank,
+            DecodedKey::Unicode(c) if c.is_ascii() && !c.is_ascii_control() => Key::Char(c),
+            DecodedKey::RawKey(KeyCode::ArrowLeft) => Key::Left,
+            DecodedKey::RawKey(KeyCode::ArrowRight) => Key::Right,
+            DecodedKey::RawKey(KeyCode::ArrowUp) => Key::Up,
+            DecodedKey::RawKey(KeyCode::ArrowDown) => Key::Down,
+            DecodedKey::RawKey(KeyCode::Home) => Key::Home,
+            DecodedKey::RawKey(KeyCode::End) => Key::End,
+            DecodedKey::RawKey(KeyCode::Delete) => Key::Delete,
+            _ => Key::Other,
+        }
+    }
+}
+
+struct VgaOutput;
+
+impl LineOutput for VgaOutput {
+    fn write_str(&mut self, s: &str) {
+        print!("{}", s);
+    }
+
+    fn cursor_left(&mut self, n: usize) {
+        vga_buffer::cursor_left(n);
+    }
+
+    fn cursor_right(&mut self, n: usize) {
+        vga_buffer::cursor_right(n);
+    }
+
+    fn clear_to_end(&mut self) {
+        vga_buffer::clear_to_end();
+    }
+}
+
+//按空白分割命令行，在命令表中查找并执行
+pub fn execute(line: &str) {
+    execute_with_input(line, None);
+}
+
+//heredoc 是命令中 <<TAG 对应的 here 文档内容
+fn execute_with_input(line: &str, heredoc: Option<&str>) {
+    let mut args: Vec<&str> = line.split_whitespace().collect();
+    let redirect = match take_redirect(&mut args) {
+        Ok(redirect) => redirect,
+        Err(msg) => return println!("shell: {}", msg),
+    };
+    let name = match args.first() {
+        Some(&name) => name,
+        None => return,
+    };
+    let cmd = match COMMANDS.iter().find(|cmd| cmd.name == name) {
+        Some(cmd) => cmd,
+        None => return println!("unknown command: {}", name),
+    };
+    let temporary = match (redirect, heredoc) {
+        (Some(Redirect::Heredoc(_)), Some(body)) => match write_heredoc(body) {
+            Ok(path) => Some(path),
+            Err(err) => return println!("shell: here-document: {:?}", err),
+        },
+        _ => None,
+    };
+    *STDIN.lock() = match redirect {
+        Some(Redirect::File(path)) => Some(String::from(path)),
+        Some(Redirect::Heredoc(_)) => temporary.clone(),
+        None => None,
+    };
+    (cmd.run)(&args[1..]);
+    if redirect.is_some() && STDIN.lock().take().is_some() {
+        println!("{}: standard input is not used", name);
+    }
+    if let Some(path) = temporary {
+        let _ = vfs::remove(&path);
+    }
+}
+
+fn help(_args: &[&str]) {
+    println!("{}", msg!(ShellHelp));
+    for cmd in COMMANDS {
+        println!("  {}", cmd.usage);
+    }
+}
+
+//把以 - 开头的参数当作选项，返回选项字母和其余参数；出现 allowed 以外的选项时返回该选项
+fn parse_flags<'a>(args: &[&'a str], allowed: &str) -> Result<(String, Vec<&'a str>), char> {
+    let mut flags = String::new();
+    let mut rest = Vec::new();
+    for &arg in args {
+        match arg.strip_prefix('-') {
+            Some(letters) if !letters.is_empty() => {
+                for c in letters.chars() {
+                    if !allowed.contains(c) {
+                        return Err(c);
+                    }
+                    flags.push(c);
+                }
+            }
+            _ => rest.push(arg),
+        }
+    }
+    Ok((flags, rest))
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+//路径的最后一级名字
+fn basename(path: &str) -> &str {
+    path.trim_end_matches('/').rsplit('/').next().unwrap_or(path)
+}
+
+fn is_dir(path: &str) -> bool {
+    matches!(vfs::metadata(path), Ok(meta) if meta.kind == InodeKind::Directory)
+}
+
+fn ls(args: &[&str]) {
+    let (flags, paths) = match parse_flags(args, "l") {
+        Ok(parsed) => parsed,
+        Err(c) => return println!("ls: unknown option -{}", c),
+    };
+    let long = flags.contains('l');
+    let path = paths.first().copied().unwrap_or("/");
+    match vfs::read_dir(path) {
+        Ok(entries) => {
+            for entry in entries {
+                let suffix = if entry.is_dir { "/" } else { "" };
+                if long {
+                    //类型：d 目录，c 设备，s 套接字，l 符号链接，- 普通文件；然后是权限、所有者和组
+                    let full = join(path, &entry.name);
+                    match vfs::link_metadata(&full) {
+                        Ok(meta) => {
+                            let kind = match meta.kind {
+                                InodeKind::Directory => 'd',
+                                InodeKind::Device => 'c',
+                                InodeKind::Socket => 's',
+                                InodeKind::Symlink => 'l',
+                                InodeKind::File => '-',
+                            };
+                            let mode = meta.mode_string();
+                            let target = vfs::read_link(&full).map(|target| format!(" -> {}", target)).unwrap_or_default();
+                            println!(
+                                "{}{} {:>5} {:>5} {:>10}  {}{}{}",
+                                kind, mode, meta.uid, meta.gid, entry.size, entry.name, suffix, target
+                            );
+                        }
+                        Err(_) => println!("?????????? {:>5} {:>5} {:>10}  {}{}", "?", "?", entry.size, entry.name, suffix),
+                    }
+                } else if entry.is_dir {
+                    println!("{:>10}  {}{}", "<DIR>", entry.name, suffix);
+                } else {
+                    println!("{:>10}  {}", entry.size, entry.name);
+                }
+            }
+        }
+        Err(err) => println!("ls: {}: {:?}", path, err),
+    }
+}
+
+fn cat(args: &[&str]) {
+    if args.is_empty() {
+        return println!("usage: cat <path>...");
+    }
+    for &path in args {
+        match vfs::open(path).and_then(|mut file| file.read_to_end()) {
+            Ok(data) => print!("{}", String::from_utf8_lossy(&data)),
+            Err(err) => println!("cat: {}: {:?}", path, err),
+        }
+    }
+}
+
+//把 input 的剩余内容全部写入 output，每写完一块调用一次 progress(已写入的字节数)
+fn copy_stream(input: &mut dyn FileHandle, output: &mut dyn FileHandle, progress: &mut dyn FnMut(u64)) -> Result<(), FsError> {
+    let mut buf = vec![0u8; COPY_CHUNK];
+    let mut done = 0u64;
+    loop {
+        let n = input.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        let mut written = 0;
+        while written < n {
+            match output.write(&buf[written..n])? {
+                0 => return Err(FsError::InvalidArgument), //设备已写满
+                count => written += count,
+            }
+        }
+        done += n as u64;
+        progress(done);
+    }
+}
+
+//复制一个文件：目标是已有的普通文件时先删除再重建，是设备时直接写入设备
+fn copy_file(src: &str, dest: &str) -> Result<(), FsError> {
+    let size = vfs::metadata(src)?.size;
+    let mut input = vfs::open(src)?;
+    let mut output = match vfs::metadata(dest) {
+        Ok(meta) if meta.kind == InodeKind::Device => vfs::open(dest)?,
+        Ok(meta) if meta.kind == InodeKind::Directory => return Err(FsError::IsADirectory),
+        Ok(_) => {
+            vfs::remove(dest)?;
+            vfs::create(dest)?
+        }
+        Err(FsError::NotFound) => vfs::create(dest)?,
+        Err(err) => return Err(err),
+    };
+    let mut bar = if size >= PROGRESS_THRESHOLD { Some(ProgressBar::new(dest, size)) } else { None };
+    let result = copy_stream(&mut *input, &mut *output, &mut |done| {
+        if let Some(bar) = bar.as_mut() {
+            bar.update(done);
+        }
+    });
+    match (bar, result.is_ok()) {
+        (Some(bar), true) => bar.finish(),
+        (Some(bar), false) => bar.abandon(),
+        (None, _) => {}
+    }
+    result
+}
+
+//递归复制文件或目录
+fn copy_tree(src: &str, dest: &str) -> Result<(), FsError> {
+    if !is_dir(src) {
+        return copy_file(src, dest);
+    }
+    match vfs::mkdir(dest) {
+        Err(FsError::AlreadyExists) if is_dir(dest) => {}
+        result => result?,
+    }
+    for entry in vfs::read_dir(src)? {
+        copy_tree(&join(src, &entry.name), &join(dest, &entry.name))?;
+    }
+    Ok(())
+}
+
+//递归删除文件或目录，符号链接只删除链接本身
+fn remove_tree(path: &str) -> Result<(), FsError> {
+    if matches!(vfs::link_metadata(path), Ok(meta) if meta.kind == InodeKind::Directory) {
+        for entry in vfs::read_dir(path)? {
+            remove_tree(&join(path, &entry.name))?;
+        }
+    }
+    vfs::remove(path)
+}
+
+//目标是已有目录时，把源文件放进这个目录；返回最终的目标路径
+//源和目标是同一个路径，或者要把目录复制到它自己里面时返回 Err
+fn copy_target(src: &str, dest: &str) -> Result<String, FsError> {
+    let target = if is_dir(dest) { join(dest, basename(src)) } else { String::from(dest) };
+    let src = vfs::normalize(src)?;
+    let normalized = vfs::normalize(&target)?;
+    if normalized == src || normalized.starts_with(&join(&src, "")) {
+        return Err(FsError::InvalidArgument);
+    }
+    Ok(target)
+}
+
+fn cp(args: &[&str]) {
+    const USAGE: &str = "usage: cp [-r] <src> <dest>";
+    let (flags, paths) = match parse_flags(args, "r") {
+        Ok(parsed) => parsed,
+        Err(c) => return println!("cp: unknown option -{}", c),
+    };
+    let (src, dest) = match paths[..] {
+        [src, dest] => (src, dest),
+        _ => return println!("{}", USAGE),
+    };
+    if is_dir(src) && !flags.contains('r') {
+        return println!("cp: {}: is a directory (use -r)", src);
+    }
+    let result = copy_target(src, dest).and_then(|target| copy_tree(src, &target));
+    if let Err(err) = result {
+        println!("cp: {} -> {}: {:?}", src, dest, err);
+    }
+}
+
+//同一个文件系统里直接改名；跨文件系统时先复制再删除源文件
+fn mv(args: &[&str]) {
+    let (src, dest) = match args {
+        [src, dest] => (*src, *dest),
+        _ => return println!("usage: mv <src> <dest>"),
+    };
+    let result = copy_target(src, dest).and_then(|target| match vfs::rename(src, &target) {
+        Err(FsError::CrossDevice) => copy_tree(src, &target).and_then(|_| remove_tree(src)),
+        result => result,
+    });
+    if let Err(err) = result {
+        println!("mv: {} -> {}: {:?}", src, dest, err);
+    }
+}
+
+//-s 建立符号链接，target 原样保存(可以是相对路径)；否则建立硬链接
+fn ln(args: &[&str]) {
+    let (flags, paths) = match parse_flags(args, "s") {
+        Ok(parsed) => parsed,
+        Err(c) => return println!("ln: unknown option -{}", c),
+    };
+    let (target, link) = match paths[..] {
+        [target, link] => (target, link),
+        _ => return println!("usage: ln [-s] <target> <link>"),
+    };
+    let result = if flags.contains('s') { vfs::symlink(target, link) } else { vfs::link(target, link) };
+    if let Err(err) = result {
+        println!("ln: {} -> {}: {:?}", link, target, err);
+    }
+}
+
+//-f 显示解析掉全部符号链接之后的路径
+fn readlink(args: &[&str]) {
+    let result = match args {
+        ["-f", path] => vfs::canonicalize(path),
+        [path] => vfs::read_link(path),
+        _ => return println!("usage: readlink [-f] <path>"),
+    };
+    match result {
+        Ok(target) => println!("{}", target),
+        Err(err) => println!("readlink: {:?}", err),
+    }
+}
+
+fn rm(args: &[&str]) {
+    let (flags, paths) = match parse_flags(args, "r") {
+        Ok(parsed) => parsed,
+        Err(c) => return println!("rm: unknown option -{}", c),
+    };
+    if paths.is_empty() {
+        return println!("usage: rm [-r] <path>...");
+    }
+    let recursive = flags.contains('r');
+    for path in paths {
+        let result = if recursive {
+            remove_tree(path)
+        } else if is_dir(path) {
+            Err(FsError::IsADirectory)
+        } else {
+            vfs::remove(path)
+        };
+        if let Err(err) = result {
+            println!("rm: {}: {:?}", path, err);
+        }
+    }
+}
+
+//mode 是八进制数，例如 chmod 755 /bin/hello
+fn chmod(args: &[&str]) {
+    let (mode, paths) = match args {
+        [mode, paths @ ..] if !paths.is_empty() => match u16::from_str_radix(mode, 8) {
+            Ok(mode) if mode <= 0o7777 => (mode, paths),
+            _ => return println!("chmod: invalid mode {}", mode),
+        },
+        _ => return println!("usage: chmod <mode> <path>..."),
+    };
+    for path in paths {
+        if let Err(err) = vfs::chmod(path, mode) {
+            println!("chmod: {}: {:?}", path, err);
+        }
+    }
+}
+
+fn chown(args: &[&str]) {
+    let (owner, paths) = match args {
+        [owner, paths @ ..] if !paths.is_empty() => match Credentials::parse(owner) {
+            Some(owner) => (owner, paths),
+            None => return println!("chown: invalid owner {}", owner),
+        },
+        _ => return println!("usage: chown <uid>[:<gid>] <path>..."),
+    };
+    for path in paths {
+        if let Err(err) = vfs::chown(path, owner.uid, owner.gid) {
+            println!("chown: {}: {:?}", path, err);
+        }
+    }
+}
+
+//-p：逐级创建路径中不存在的目录，目录已存在时不报错
+fn mkdir(args: &[&str]) {
+    let (flags, paths) = match parse_flags(args, "p") {
+        Ok(parsed) => parsed,
+        Err(c) => return println!("mkdir: unknown option -{}", c),
+    };
+    if paths.is_empty() {
+        return println!("usage: mkdir [-p] <path>...");
+    }
+    let parents = flags.contains('p');
+    for path in paths {
+        let result = if parents {
+            vfs::normalize(path).and_then(|normalized| {
+                let mut prefix = String::new();
+                for part in normalized.split('/').filter(|p| !p.is_empty()) {
+                    prefix.push('/');
+                    prefix.push_str(part);
+                    match vfs::mkdir(&prefix) {
+                        Err(FsError::AlreadyExists) if is_dir(&prefix) => {}
+                        result => result?,
+                    }
+                }
+                Ok(())
+            })
+        } else {
+            vfs::mkdir(path)
+        };
+        if let Err(err) = result {
+            println!("mkdir: {}: {:?}", path, err);
+        }
+    }
+}
+
+//边读边计算文件的 SHA-256，大文件显示进度条，返回十六进制摘要
+fn file_sha256(path: &str) -> Result<String, FsError> {
+    let size = vfs::metadata(path)?.size;
+    let mut file = vfs::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut bar = if size >= PROGRESS_THRESHOLD { Some(ProgressBar::new(path, size)) } else { None };
+    let mut buf = vec![0u8; COPY_CHUNK];
+    let mut done = 0u64;
+    loop {
+        let n = match file.read(&mut buf) {
+            Ok(n) => n,
+            Err(err) => {
+                if let Some(bar) = bar {
+                    bar.abandon();
+                }
+                return Err(err);
+            }
+        };
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        done += n as u64;
+        if let Some(bar) = bar.as_mut() {
+            bar.update(done);
+        }
+    }
+    if let Some(bar) = bar {
+        bar.finish();
+    }
+    Ok(crypto::to_hex(&hasher.finalize()))
+}
+
+//通过 COM1 用 XMODEM 把文件发送给主机，-k 使用 1 KiB 的块
+fn sx(args: &[&str]) {
+    let (large, path) = match args {
+        ["-k", path] => (true, *path),
+        [path] => (false, *path),
+        _ => return println!("usage: sx [-k] <path>"),
+    };
+    let (size, mut input) = match vfs::metadata(path).and_then(|meta| Ok((meta.size, vfs::open(path)?))) {
+        Ok(file) => file,
+        Err(err) => return println!("sx: {}: {:?}", path, err),
+    };
+    println!("sx: waiting for the XMODEM receiver on COM1");
+    let mut bar = ProgressBar::new(path, size);
+    let result = xmodem::send(&mut *input, large, &mut |done| bar.update(done));
+    match result {
+        Ok(sent) => {
+            bar.finish();
+            println!("sx: sent {} bytes", sent);
+        }
+        Err(XmodemError::Fs(err)) => {
+            bar.abandon();
+            println!("sx: {}: {:?}", path, err);
+        }
+        Err(err) => {
+            bar.abandon();
+            println!("sx: transfer failed: {:?}", err);
+        }
+    }
+}
+
+//通过 COM1 用 XMODEM 接收主机发来的文件；给出 size 时按它截断并显示进度
+//先写到同一目录下的 <path>.rx，接收成功后再改名替换 path，传输失败时原来的文件保持不变
+fn rx(args: &[&str]) {
+    let (path, size) = match args {
+        [path] => (*path, None),
+        [path, size] => match size.parse::<u64>() {
+            Ok(size) => (*path, Some(size)),
+            Err(_) => return println!("rx: invalid size {}", size),
+        },
+        _ => return println!("usage: rx <path> [size]"),
+    };
+    match vfs::metadata(path) {
+        Ok(meta) if meta.kind == InodeKind::Directory => return println!("rx: {}: {:?}", path, FsError::IsADirectory),
+        Ok(_) | Err(FsError::NotFound) => {}
+        Err(err) => return println!("rx: {}: {:?}", path, err),
+    }
+    let temporary = format!("{}.rx", path);
+    let created = match vfs::remove(&temporary) {
+        Ok(()) | Err(FsError::NotFound) => vfs::create(&temporary), //上次中断的接收留下的临时文件
+        Err(err) => Err(err),
+    };
+    let mut output = match created {
+        Ok(output) => output,
+        Err(err) => return println!("rx: {}: {:?}", temporary, err),
+    };
+    println!("rx: start the XMODEM sender on COM1");
+    let mut bar = size.map(|size| ProgressBar::new(path, size));
+    let result = xmodem::receive(&mut *output, size, &mut |done| {
+        if let Some(bar) = bar.as_mut() {
+            bar.update(done);
+        }
+    });
+    match (bar, &result) {
+        (Some(bar), Ok(_)) => bar.finish(),
+        (Some(bar), Err(_)) => bar.abandon(),
+        (None, _) => {}
+    }
+    drop(output);
+    match result {
+        Ok(received) => match vfs::rename(&temporary, path) {
+            Ok(()) => println!("rx: received {} bytes", received),
+            Err(err) => {
+                let _ = vfs::remove(&temporary);
+                println!("rx: {}: {:?}", path, err);
+            }
+        },
+        Err(err) => {
+            let _ = vfs::remove(&temporary);
+            match err {
+                XmodemError::Fs(err) => println!("rx: {}: {:?}", temporary, err),
+                err => println!("rx: transfer failed: {:?}", err),
+            }
+        }
+    }
+}
+
+//输出格式与 coreutils 的 sha256sum 相同："<摘要>  <路径>"
+fn sha256sum(args: &[&str]) {
+    if args.is_empty() {
+        return println!("usage: sha256sum <path>...");
+    }
+    for &path in args {
+        match file_sha256(path) {
+            Ok(digest) => println!("{}  {}", digest, path),
+            Err(err) => println!("sha256sum: {}: {:?}", path, err),
+        }
+    }
+}
+
+//比较文件的 SHA-256 和期望值(十六进制，不区分大小写)
+fn verify(args: &[&str]) {
+    let (path, expected) = match args {
+        [path, expected] => (*path, *expected),
+        _ => return println!("usage: verify <path> <sha256>"),
+    };
+    if expected.len() != 64 || !expected.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return println!("verify: expected digest must be 64 hex digits");
+    }
+    match file_sha256(path) {
+        Ok(digest) if digest.eq_ignore_ascii_case(expected) => println!("{}: OK", path),
+        Ok(digest) => println!("{}: FAILED (got {})", path, digest),
+        Err(err) => println!("verify: {}: {:?}", path, err),
+    }
+}
+
+//"[-l] [-u <uid>[:<gid>]] <path>"：-u 指定程序以哪个用户运行(默认是 root)，-l 把它当作 Linux 程序运行(默认由 ELF 头决定)
+fn parse_program<'a>(mut args: &[&'a str]) -> Option<(Credentials, Option<Personality>, &'a str)> {
+    let mut cred = cred::ROOT;
+    let mut personality = None;
+    loop {
+        match args {
+            [path] => return Some((cred, personality, *path)),
+            ["-l", rest @ ..] => {
+                personality = Some(Personality::Linux);
+                args = rest;
+            }
+            ["-u", user, rest @ ..] => {
+                cred = Credentials::parse(user)?;
+                args = rest;
+            }
+            _ => return None,
+        }
+    }
+}
+
+//创建进程并等待它结束
+fn run_program(args: &[&str]) {
+    let (cred, personality, path) = match parse_program(args) {
+        Some(parsed) => parsed,
+        None => return println!("usage: run [-l] [-u <uid>[:<gid>]] <path> [< file | <<TAG]"),
+    };
+    match start_program(path, cred, personality).and_then(process::wait) {
+        Ok(code) => println!("run: {} exited with code {}", path, code),
+        Err(err) => println!("run: {}: {:?}", path, err),
+    }
+}
+
+//只加载程序，不运行，之后可以用 wait 运行或用 kill 结束
+fn spawn(args: &[&str]) {
+    let (cred, personality, path) = match parse_program(args) {
+        Some(parsed) => parsed,
+        None => return println!("usage: spawn [-l] [-u <uid>[:<gid>]] <path> [< file | <<TAG]"),
+    };
+    match start_program(path, cred, personality) {
+        Ok(pid) => println!("spawn: {} has pid {}", path, pid),
+        Err(err) => println!("spawn: {}: {:?}", path, err),
+    }
+}
+
+fn parse_pid(name: &str, args: &[&str]) -> Option<u64> {
+    let pid = args.first().and_then(|arg| arg.parse().ok());
+    if pid.is_none() {
+        println!("usage: {} <pid>", name);
+    }
+    pid
+}
+
+fn wait(args: &[&str]) {
+    if let Some(pid) = parse_pid("wait", args) {
+        match process::wait(pid) {
+            Ok(code) => println!("wait: pid {} exited with code {}", pid, code),
+            Err(err) => println!("wait: {}: {:?}", pid, err),
+        }
+    }
+}
+
+//默认发送 SIGTERM，信号可以写成 -9、-KILL 或 -SIGKILL
+fn kill(args: &[&str]) {
+    let (sig, args) = match args {
+        [first, rest @ ..] if first.starts_with('-') => match signal::parse(&first[1..]) {
+            Some(sig) => (sig, rest),
+            None => return println!("kill: unknown signal {}", &first[1..]),
+        },
+        _ => (signal::SIGTERM, args),
+    };
+    if let Some(pid) = parse_pid("kill", args) {
+        if let Err(err) = signal::send(pid, sig) {
+            println!("kill: {}: {:?}", pid, err);
+        }
+    }
+}
+
+fn state_name(state: State) -> &'static str {
+    match state {
+        State::Running => "running",
+        State::Ready => "ready",
+        State::Blocked => "blocked",
+        State::Zombie => "zombie",
+    }
+}
+
+//被信号结束的进程的退出码是负的信号编号，后面加上信号名
+fn exit_text(exit_code: Option<i64>) -> String {
+    match exit_code {
+        Some(code) if (-(signal::NSIG as i64)..0).contains(&code) => match signal::name(-code as u32) {
+            Some(name) => format!("{} (SIG{})", code, name),
+            None => format!("{}", code),
+        },
+        Some(code) => format!("{}", code),
+        None => String::from("-"),
+    }
+}
+
+fn ps(_args: &[&str]) {
+    println!("{:>5}  {:>5}  {:<8}  {:>6}  {}", "PID", "UID", "STATE", "EXIT", "NAME");
+    for info in process::list() {
+        println!(
+            "{:>5}  {:>5}  {:<8}  {:>6}  {}",
+            info.pid,
+            info.cred.uid,
+            state_name(info.state),
+            exit_text(info.exit_code),
+            info.name
+        );
+    }
+}
+
+//全屏显示内存使用情况和进程表，按 k 在对话框里输入 kill 的参数，按其他键返回
+fn sysinfo(_args: &[&str]) {
+    let saved = vga_buffer::snapshot();
+    let normal = ColorCode::new(Color::LightGray, Color::Blue);
+    let header = ColorCode::new(Color::Yellow, Color::Blue);
+    let screen = Canvas::content();
+    let area = Rect::new(0, 0, vga_buffer::BUFFER_WIDTH, vga_buffer::BUFFER_HEIGHT - 1);
+    screen.fill(area, b' ', normal);
+    screen.draw_box(area, BoxStyle::Double, header, Some("sysinfo"));
+
+    let (memory_area, process_area) = area.inset(1).split_top(3);
+    let (heap_used, heap_size) = allocator::usage();
+    let (frames, free) = (memory::total_frame_count(), memory::free_frame_count());
+    let bars = [
+        (format!("heap   {:>6} KiB", heap_size / 1024), heap_used as u64, heap_size as u64),
+        (format!("memory {:>6} KiB", frames * 4), (frames - free) as u64, frames as u64),
+    ];
+    for (i, (label, used, total)) in bars.iter().enumerate() {
+        let row = Rect::new(memory_area.row + i, memory_area.col + 1, memory_area.width - 2, 1);
+        tui::ProgressBar::new(row, label, *total).draw(&screen, *used, normal);
+    }
+
+    let mut table = Table::new(&[("PID", Align::Right), ("STATE", Align::Left), ("EXIT", Align::Right), ("NAME", Align::Left)]);
+    for info in process::list() {
+        table.push(vec![format!("{}", info.pid), String::from(state_name(info.state)), exit_text(info.exit_code), info.name.clone()]);
+    }
+    table.draw(&screen, process_area.inset(1), BoxStyle::Single, normal, header);
+
+    let status = Canvas::screen();
+    let bar = Rect::status_bar();
+    status.fill(bar, b' ', ColorCode::new(Color::Black, Color::LightGray));
+    status.text(bar.row, 1, "k: send a signal   any other key: return", bar.width - 1, ColorCode::new(Color::Black, Color::LightGray));
+    let command = match keyboard::read_key() {
+        DecodedKey::Unicode('k') => {
+            let dialog = Rect::new(area.height / 2 - 2, 10, area.width - 20, 3);
+            let mut editor = LineEditor::new(HISTORY_LIMIT);
+            Some(tui::input_dialog(&screen, dialog, "kill", "[-<signal>] <pid>:", &mut editor, &mut KeyboardInput, header))
+        }
+        _ => None,
+    };
+    vga_buffer::restore(&saved);
+    if let Some(command) = command {
+        kill(&command.split_whitespace().collect::<Vec<_>>());
+    }
+}
+
+//按时间顺序列出需要特权的操作
+fn audit_cmd(args: &[&str]) {
+    if args != ["show"] {
+        return println!("usage: audit show");
+    }
+    let (dropped, records) = audit::records();
+    if dropped > 0 {
+        println!("({} earlier records dropped)", dropped);
+    }
+    for record in records {
+        println!(
+            "{:>5} [{:>5}.{:03}] pid {:<3} uid {:<5} {:<9} {:<6} {}",
+            record.sequence,
+            record.time.as_secs(),
+            record.time.subsec_millis(),
+            record.pid,
+            record.uid,
+            record.operation.name(),
+            if record.allowed { "ok" } else { "failed" },
+            record.detail
+        );
+    }
+}
+
+//按初始化顺序列出驱动和它们的状态
+fn drivers_cmd(_args: &[&str]) {
+    for info in driver::list() {
+        match info.state {
+            driver::State::Ready(status) => println!("{:<12}  ready   {}", info.name, status),
+            driver::State::Absent => println!("{:<12}  absent", info.name),
+            driver::State::Failed(err) => println!("{:<12}  failed  {:?}", info.name, err),
+        }
+    }
+}
+
+//全屏查看控制台的历史输出，Ctrl+F 搜索
+fn scrollback(_args: &[&str]) {
+    console::pager();
+}
+
+//显示程序映像缓存的使用情况，clear 清空缓存
+fn progcache(args: &[&str]) {
+    match args {
+        [] => {
+            let stats = image_cache::stats();
+            println!("{} programs, {} bytes cached, {} hits, {} misses", stats.entries, stats.bytes, stats.hits, stats.misses);
+        }
+        ["clear"] => image_cache::clear(),
+        _ => println!("usage: progcache [clear]"),
+    }
+}
+
+//全局和每个进程的缺页异常次数
+fn vmstat(_args: &[&str]) {
+    let faults = vm::fault_stats();
+    println!("page faults: {} minor, {} major, {} cow", faults.minor, faults.major, faults.cow);
+    println!("{:>5}  {:>8}  {:>8}  {:>8}  {}", "PID", "MINOR", "MAJOR", "COW", "NAME");
+    for info in process::list() {
+        let f = info.faults;
+        println!("{:>5}  {:>8}  {:>8}  {:>8}  {}", info.pid, f.minor, f.major, f.cow, info.name);
+    }
+}
+
+//微秒，保留一位小数
+fn micros(duration: Duration) -> String {
+    let nanos = duration.as_nanos();
+    format!("{}.{}", nanos / 1000, nanos % 1000 / 100)
+}
+
+fn latency_cmd(args: &[&str]) {
+    match args {
+        ["report"] => {
+            println!("{:<10}  {:>8}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}", "NAME", "COUNT", "P50(us)", "P90", "P99", "P99.9", "MAX");
+            for (name, histogram) in latency::HISTOGRAMS {
+                println!(
+                    "{:<10}  {:>8}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}",
+                    name,
+                    histogram.count(),
+                    micros(histogram.percentile(50.0)),
+                    micros(histogram.percentile(90.0)),
+                    micros(histogram.percentile(99.0)),
+                    micros(histogram.percentile(99.9)),
+                    micros(histogram.max())
+                );
+            }
+        }
+        ["reset"] => latency::HISTOGRAMS.iter().for_each(|(_, histogram)| histogram.reset()),
+        _ => println!("usage: latency report|reset"),
+    }
+}
+
+//把 fmt::Write 的输出转给 print!
+struct ConsoleWriter;
+
+impl core::fmt::Write for ConsoleWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        print!("{}", s);
+        Ok(())
+    }
+}
+
+//按时间顺序打印跟踪缓冲区里的事件，serial 输出到 COM1
+fn tracedump(args: &[&str]) {
+    match args {
+        [] | ["serial"] => {
+            let events = trace::events();
+            let result = if args.is_empty() {
+                trace::write_events(&mut ConsoleWriter, &events)
+            } else {
+                let port = Uart::new(uart::COM1);
+                if !port.present() {
+                    return println!("tracedump: no serial port");
+                }
+                port.init();
+                trace::write_events(&mut { port }, &events)
+            };
+            if result.is_ok() {
+                let state = if trace::enabled() { "" } else { ", tracing off" };
+                println!("tracedump: {} events, {} overwritten{}", events.len(), trace::dropped(), state);
+            }
+        }
+        ["clear"] => trace::clear(),
+        ["on"] => trace::set_enabled(true),
+        ["off"] => trace::set_enabled(false),
+        _ => println!("usage: tracedump [serial|clear|on|off]"),
+    }
+}
+
+//不带参数时列出全部输出端；level= 和 on/off 可以一起给出，例如 log sink serial level=trace on
+fn log_sinks(args: &[&str]) {
+    match args {
+        [] => {
+            for sink in log::SINKS {
+                println!("{:<8} {:<3} {}", sink.name(), if sink.enabled() { "on" } else { "off" }, sink.level().name());
+            }
+        }
+        ["sink", name, options @ ..] => {
+            let sink = match Sink::parse(name) {
+                Some(sink) => sink,
+                None => return println!("log: unknown sink {}", name),
+            };
+            for option in options {
+                match *option {
+                    "on" => sink.set_enabled(true),
+                    "off" => sink.set_enabled(false),
+                    _ => match option.strip_prefix("level=").map(LogLevel::parse) {
+                        Some(Some(level)) => sink.set_level(level),
+                        Some(None) => return println!("log: unknown level {}", &option[6..]),
+                        None => return println!("usage: log [sink <vga|serial|dmesg> [level=<level>] [on|off]]"),
+                    },
+                }
+            }
+            println!("{}: {} {}", sink.name(), if sink.enabled() { "on" } else { "off" }, sink.level().name());
+        }
+        _ => println!("usage: log [sink <vga|serial|dmesg> [level=<level>] [on|off]]"),
+    }
+}
+
+fn cgroup(args: &[&str]) {
+    let result = match args {
+        [] => {
+            println!("{:<12}  {:>10}  {:>10}  {:>6}  {}", "GROUP", "LIMIT(KiB)", "USED(KiB)", "SHARES", "PIDS");
+            for info in group::list() {
+                let limit = info.limits.memory_pages.map_or(String::from("-"), |pages| format!("{}", pages * 4));
+                let pids: Vec<String> = info.pids.iter().map(|pid| format!("{}", pid)).collect();
+                println!(
+                    "{:<12}  {:>10}  {:>10}  {:>6}  {}",
+                    info.name,
+                    limit,
+                    info.memory_pages * 4,
+                    info.limits.cpu_shares,
+                    pids.join(",")
+                );
+            }
+            Ok(())
+        }
+        ["create", name] => group::create(name),
+        ["delete", name] => group::remove(name),
+        ["add", name, pid] => match pid.parse() {
+            Ok(pid) => group::attach(name, pid),
+            Err(_) => return println!("cgroup: invalid pid {}", pid),
+        },
+        ["set", name, settings @ ..] if !settings.is_empty() => {
+            let mut limits = match group::limits(name) {
+                Some(limits) => limits,
+                None => return println!("cgroup: {}: {:?}", name, group::GroupError::NotFound),
+            };
+            for setting in settings {
+                match setting.split_once('=') {
+                    Some(("mem", "max")) => limits.memory_pages = None,
+                    Some(("mem", kib)) => match kib.parse::<u64>() {
+                        Ok(kib) => limits.memory_pages = Some(kib.div_ceil(4)),
+                        Err(_) => return println!("cgroup: invalid memory limit {}", kib),
+                    },
+                    Some(("shares", shares)) => match shares.parse() {
+                        Ok(shares) if shares > 0 => limits.cpu_shares = shares,
+                        _ => return println!("cgroup: invalid shares {}", shares),
+                    },
+                    _ => return println!("cgroup: unknown setting {}", setting),
+                }
+            }
+            group::set_limits(name, limits)
+        }
+        _ => return println!("usage: cgroup [create|delete <name> | set <name> [mem=<KiB>|max] [shares=<n>] | add <name> <pid>]"),
+    };
+    if let Err(err) = result {
+        println!("cgroup: {:?}", err);
+    }
+}
+
+//启用或解除故障注入点，不带参数时列出已启用的
+fn failpoint_cmd(args: &[&str]) {
+    match args {
+        [] => {
+            for info in failpoint::list() {
+                let Config { action, probability, remaining } = info.config;
+                let remaining = remaining.map_or(String::from("-"), |n| format!("{}", n));
+                println!("{}: {:?} {}% remaining={} hits={}", info.name, action, probability, remaining, info.hits);
+            }
+        }
+        ["set", name, action, rest @ ..] if rest.len() <= 2 => {
+            let action = match *action {
+                "error" => Some(Action::Error),
+                "panic" => Some(Action::Panic),
+                _ => action.strip_prefix("delay=").and_then(|ms| ms.parse().ok()).map(|ms| Action::Delay(Duration::from_millis(ms))),
+            };
+            let probability = rest.first().map_or(Some(100), |p| p.trim_end_matches('%').parse().ok().filter(|&p| p <= 100));
+            let remaining = rest.get(1).map(|n| n.parse().ok().filter(|&n| n > 0));
+            match (action, probability, remaining) {
+                (Some(action), Some(probability), None) => failpoint::arm(name, Config { action, probability, remaining: None }),
+                (Some(action), Some(probability), Some(Some(count))) => {
+                    failpoint::arm(name, Config { action, probability, remaining: Some(count) })
+                }
+                _ => println!("usage: failpoint [set <name> error|panic|delay=<ms> [percent] [count] | clear <name>]"),
+            }
+        }
+        ["clear", name] => {
+            if !failpoint::disarm(name) {
+                println!("failpoint: {} is not armed", name);
+            }
+        }
+        _ => println!("usage: failpoint [set <name> error|panic|delay=<ms> [percent] [count] | clear <name>]"),
+    }
+}
+
+//内核堆使用的实现和用量；check 在一块临时的堆上运行一致性和压力测试，all 对全部实现运行
+fn allocator_cmd(args: &[&str]) {
+    match args {
+        [] => {
+            let (used, size) = allocator::usage();
+            println!("allocator: {}, {} of {} KiB used", allocator::name(), used / 1024, size / 1024);
+        }
+        ["check"] => allocator_check(allocator::Selected::new()),
+        ["check", "all"] => {
+            allocator_check(allocator::linked_list::LinkedList::new());
+            allocator_check(allocator::bump::Bump::new());
+            allocator_check(allocator::buddy::Buddy::new());
+            allocator_check(allocator::slab::Slab::new());
+        }
+        _ => println!("usage: allocator [check [all]]"),
+    }
+}
+
+fn allocator_check<A: allocator::KernelAllocator>(heap: A) {
+    let name = heap.name();
+    match allocator::check::run(heap) {
+        Ok(report) => println!(
+            "allocator check: {}: ok ({} allocations, {} out of memory, peak {} KiB)",
+            name,
+            report.allocations,
+            report.failures,
+            report.peak / 1024
+        ),
+        Err(err) => println!("allocator check: {}: FAILED: {}", name, err),
+    }
+}
+
+//切换键盘布局，不带参数时列出全部布局
+fn loadkeys(args: &[&str]) {
+    match args {
+        [] => {
+            let current = keymap::current().name();
+            for keymap in keymap::all() {
+                let mark = if keymap.name() == current { '*' } else { ' ' };
+                println!("{} {:<4} {}", mark, keymap.name(), keymap.description());
+            }
+        }
+        [name] => {
+            if !keymap::select(name) {
+                println!("loadkeys: unknown layout {}", name);
+            }
+        }
+        _ => println!("usage: loadkeys [layout]"),
+    }
+}
+
+//原始按键模式(显示扫描码和修饰键，也可以用 Alt+F12 切换)，inject 注入十六进制的第一套扫描码，
+//press 按名字依次按下并松开按键，key 只按下或只松开一个键(用来按住修饰键)
+fn kbd(args: &[&str]) {
+    match args {
+        [] => println!("kbd: raw mode {}, layout {}", if keyboard::raw_mode() { "on" } else { "off" }, keymap::current().name()),
+        ["raw", "on"] => keyboard::set_raw_mode(true),
+        ["raw", "off"] => keyboard::set_raw_mode(false),
+        ["inject", scancodes @ ..] if !scancodes.is_empty() => {
+            let mut bytes = Vec::new();
+            for scancode in scancodes {
+                match u8::from_str_radix(scancode.trim_start_matches("0x"), 16) {
+                    Ok(byte) => bytes.push(byte),
+                    Err(_) => return println!("kbd: bad scancode {}", scancode),
+                }
+            }
+            keyboard::inject_scancodes(&bytes);
+        }
+        ["press", names @ ..] if !names.is_empty() => {
+            let mut codes = Vec::new();
+            for name in names {
+                match keyboard::key_code(name) {
+                    Some(code) => codes.push(code),
+                    None => return println!("kbd: unknown key {}", name),
+                }
+            }
+            for code in codes {
+                keyboard::inject(code, KeyState::Down);
+                keyboard::inject(code, KeyState::Up);
+            }
+        }
+        ["key", name, state @ ("down" | "up")] => match keyboard::key_code(name) {
+            Some(code) => keyboard::inject(code, if *state == "down" { KeyState::Down } else { KeyState::Up }),
+            None => println!("kbd: unknown key {}", name),
+        },
+        _ => println!("usage: kbd [raw on|off | inject <scancode>... | press <key>... | key <key> down|up]"),
+    }
+}
+
+//显示或者改变 BEL 的处理方式，test 输出一个 BEL 试听(看)效果
+fn bell_cmd(args: &[&str]) {
+    match args {
+        [] => println!("bell: {} ({} rings)", bell::mode().name(), bell::rings()),
+        ["test"] => print!("{}", bell::BEL as char),
+        [name] => match bell::Mode::parse(name) {
+            Some(mode) => bell::set_mode(mode),
+            None => println!("bell: unknown mode {}", name),
+        },
+        _ => println!("usage: bell [audible|visual|off|test]"),
+    }
+}
+
+fn shutdown(_args: &[&str]) {
+    power::shutdown();
+}
+
+fn reboot(_args: &[&str]) {
+    power::reboot();
+}
+
+//命令行按空白分割，含空格的模式可以加引号："spurious interrupt"
+fn pattern(words: &[&str]) -> Option<String> {
+    let joined = words.join(" ");
+    let pattern = joined.strip_prefix('"').and_then(|p| p.strip_suffix('"')).unwrap_or(&joined);
+    if pattern.is_empty() {
+        None
+    } else {
+        Some(String::from(pattern))
+    }
+}
+
+fn list_filters(suppress: bool) {
+    for filter in console_filter::list() {
+        match filter.action {
+            FilterAction::Highlight(color) if !suppress => println!("\"{}\" {:?}", filter.pattern, color),
+            FilterAction::Suppress if suppress => println!("\"{}\"", filter.pattern),
+            _ => {}
+        }
+    }
+}
+
+//高亮匹配的行
+fn hl(args: &[&str]) {
+    match args {
+        [] => list_filters(false),
+        ["add", words @ .., color] => match (pattern(words), console_filter::parse_color(color)) {
+            (Some(pattern), Some(color)) => console_filter::add(&pattern, FilterAction::Highlight(color)),
+            (_, None) => println!("hl: unknown color {}", color),
+            _ => println!("usage: hl [add <pattern> <color> | del <pattern>]"),
+        },
+        ["del", words @ ..] => match pattern(words) {
+            Some(pattern) if !console_filter::remove(&pattern, false) => println!("hl: no filter for \"{}\"", pattern),
+            Some(_) => {}
+            None => println!("usage: hl [add <pattern> <color> | del <pattern>]"),
+        },
+        _ => println!("usage: hl [add <pattern> <color> | del <pattern>]"),
+    }
+}
+
+//不在屏幕上显示匹配的行(仍然记入滚动缓冲区)
+fn suppress(args: &[&str]) {
+    match args {
+        [] => list_filters(true),
+        ["add", words @ ..] => match pattern(words) {
+            Some(pattern) => console_filter::add(&pattern, FilterAction::Suppress),
+            None => println!("usage: suppress [add <pattern> | del <pattern>]"),
+        },
+        ["del", words @ ..] => match pattern(words) {
+            Some(pattern) if !console_filter::remove(&pattern, true) => println!("suppress: no filter for \"{}\"", pattern),
+            Some(_) => {}
+            None => println!("usage: suppress [add <pattern> | del <pattern>]"),
+        },
+        _ => println!("usage: suppress [add <pattern> | del <pattern>]"),
+    }
+}
+
+//把当前画面和 /etc/golden-screens 中记录的哈希比较(先求哈希，再输出结果)
+fn screencheck_cmd(args: &[&str]) {
+    let name = match args {
+        [name] => *name,
+        _ => return println!("usage: screencheck <name>"),
+    };
+    match screencheck::check(name) {
+        (hash, Outcome::Pass) => println!("screencheck: {} {:016x} pass", name, hash),
+        (hash, Outcome::Fail(expected)) => println!("screencheck: {} {:016x} FAIL (expected {:016x})", name, hash, expected),
+        (hash, Outcome::New) => println!("screencheck: {} {:016x} new (no entry in {})", name, hash, screencheck::GOLDEN_PATH),
+    }
+}
+
+//显示内存布局报告；save 把它保存为基准(默认 /etc/memlayout)，diff 与基准逐行比较
+fn memlayout_cmd(args: &[&str]) {
+    match args {
+        [] => {
+            for line in memlayout::report() {
+                println!("{}", line);
+            }
+        }
+        ["save"] | ["save", _] => {
+            let path = args.get(1).copied().unwrap_or(memlayout::BASELINE_PATH);
+            match memlayout::save(path) {
+                Ok(count) => println!("memlayout: saved {} lines to {}", count, path),
+                Err(err) => println!("memlayout: {}: {:?}", path, err),
+            }
+        }
+        ["diff"] | ["diff", _] => {
+            let path = args.get(1).copied().unwrap_or(memlayout::BASELINE_PATH);
+            let baseline = match memlayout::baseline(path) {
+                Ok(baseline) => baseline,
+                Err(err) => return println!("memlayout: {}: {:?}", path, err),
+            };
+            let differences = memlayout::diff(&baseline, &memlayout::report());
+            for line in &differences {
+                println!("{}", line);
+            }
+            if differences.is_empty() {
+                println!("memlayout: layout matches {}", path);
+            }
+        }
+        _ => println!("usage: memlayout [save|diff] [path]"),
+    }
+}
+
+//停下来等待 gdb 连接(或者把控制交给已经连接的 gdb)
+fn gdb(_args: &[&str]) {
+    if !gdbstub::available() {
+        return println!("gdb: no serial port for the debugger");
+    }
+    gdbstub::breakpoint();
+}
+
+//列出当前内核的系统调用和 ABI 结构体的大小，格式与 abi/syscalls.golden 相同，修改 ABI 后用它更新黄金表
+fn abi_cmd(_args: &[&str]) {
+    for line in abi::report() {
+        println!("{}", line);
+    }
+}
+
+//列出 QEMU fw_cfg 中的文件，cat 显示一个文件的内容
+fn fwcfg(args: &[&str]) {
+    match args {
+        [] => {
+            if !fw_cfg::present() {
+                return println!("fwcfg: no fw_cfg device");
+            }
+            println!("{:>6}  {:>8}  {}", "SELECT", "SIZE", "NAME");
+            for entry in fw_cfg::files() {
+                println!("{:>#6x}  {:>8}  {}", entry.select, entry.size, entry.name);
+            }
+        }
+        ["cat", name] => match fw_cfg::read(name) {
+            Some(data) => println!("{}", String::from_utf8_lossy(&data)),
+            None => println!("fwcfg: {}: not found", name),
+        },
+        _ => println!("usage: fwcfg [cat <name>]"),
+    }
+}
+
+//显示 ATA 驱动器的 SMART 信息
+fn smartctl(args: &[&str]) {
+    let name = match args {
+        [name] => *name,
+        _ => return println!("usage: smartctl <device>"),
+    };
+    let data = match smart::read_device(name) {
+        Ok(data) => data,
+        Err(err) => return println!("smartctl: {}: {:?}", name, err),
+    };
+    let show = |value: Option<u64>| value.map_or(String::from("-"), |v| format!("{}", v));
+    println!("health: {}", if data.healthy { "PASSED" } else { "FAILED" });
+    println!("temperature: {} C", show(data.temperature()));
+    println!("reallocated sectors: {}", show(data.reallocated_sectors()));
+    println!("power-on hours: {}", show(data.power_on_hours()));
+    println!("{:>3}  {:>5}  {:>5}  {}", "ID", "VALUE", "WORST", "RAW");
+    for attr in &data.attributes {
+        println!("{:>3}  {:>5}  {:>5}  {}", attr.id, attr.value, attr.worst, attr.raw);
+    }
+}
+
+//观察表达式在 shell 等待输入时每秒更新，显示在屏幕第一行的右边
+fn watch_cmd(args: &[&str]) {
+    let result = match args {
+        [] => {
+            for watch in watch::list() {
+                match watch.value {
+                    Some(value) => println!("{:<12} {:<16} {}{}", watch.label, watch.provider, value, watch.unit),
+                    None => println!("{:<12} {:<16} -", watch.label, watch.provider),
+                }
+            }
+            return;
+        }
+        ["providers"] => {
+            for provider in watch::providers() {
+                let kind = match provider.kind {
+                    WatchKind::Gauge => "gauge",
+                    WatchKind::Rate => "rate",
+                };
+                println!("{:<16} {:<6} {}", provider.name, kind, (provider.read)());
+            }
+            return;
+        }
+        ["add", name, provider] => watch::add(name, provider),
+        ["del", name] => watch::remove(name),
+        _ => return println!("usage: watch [add <name> <provider> | del <name> | providers]"),
+    };
+    if let Err(err) = result {
+        println!("watch: {:?}", err);
+    }
+}
+
+fn zerofill_cmd(args: &[&str]) {
+    match args {
+        [] => {}
+        ["on"] => zerofill::set_enabled(true),
+        ["off"] => zerofill::set_enabled(false),
+        _ => return println!("usage: zerofill [on|off]"),
+    }
+    let stats = zerofill::stats();
+    println!(
+        "{}, {} frames zeroed while idle, {} ready, {} free frames not yet zeroed",
+        if zerofill::enabled() { "enabled" } else { "disabled" },
+        stats.zeroed,
+        stats.pool,
+        stats.dirty
+    );
+    match stats.hit_rate() {
+        Some(rate) => println!("zeroed allocations: {} pre-zeroed, {} zeroed on demand ({}% hit rate)", stats.hits, stats.misses, rate),
+        None => println!("zeroed allocations: none yet"),
+    }
+}
+
+fn ksm_cmd(args: &[&str]) {
+    match args {
+        [] => {}
+        ["scan"] => println!("merged {} pages", ksm::scan()),
+        ["on"] => ksm::set_enabled(true),
+        ["off"] => ksm::set_enabled(false),
+        _ => return println!("usage: ksm [scan|on|off]"),
+    }
+    let stats = ksm::stats();
+    println!(
+        "{}, {} scans, {} pages merged in total",
+        if ksm::enabled() { "enabled" } else { "disabled" },
+        stats.scans,
+        stats.merged
+    );
+    println!(
+        "last scan: {} pages in {} frames ({} zero), {} KiB saved",
+        stats.scanned,
+        stats.frames,
+        stats.zero,
+        stats.saved_bytes() / 1024
+    );
+}
+
+fn threads(_args: &[&str]) {
+    println!(
+        "cpus: online {}, isolated {}, housekeeping {}",
+        thread::format_cpus(thread::online()),
+        thread::format_cpus(thread::isolated()),
+        thread::format_cpus(thread::housekeeping())
+    );
+    println!("{:>4} {:>4} {:>10}  NAME", "TID", "CPU", "RUNS");
+    for info in thread::list() {
+        println!("{:>4} {:>4} {:>10}  {}", info.id, info.cpu, info.runs, info.name);
+    }
+}
+
+//切换到图形模式(或在图形模式下更换分辨率和字体)，之后的输出都画在帧缓冲上
+fn fbset(args: &[&str]) {
+    const USAGE: &str = "usage: fbset <width>x<height> [font.psf|res:<name>]";
+    let (mode, font_path) = match args {
+        [mode] => (*mode, None),
+        [mode, font] => (*mode, Some(*font)),
+        _ => return println!("{}", USAGE),
+    };
+    let size = mode.split_once('x').and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+    let (width, height) = match size {
+        Some(size) => size,
+        None => return println!("{}", USAGE),
+    };
+    let font = match font_path {
+        Some(path) => match read_font_file(path) {
+            Ok(data) => match framebuffer::load_font(&data) {
+                Ok(font) => Some(font),
+                Err(err) => return println!("fbset: {}: {:?}", path, err),
+            },
+            Err(err) => return println!("fbset: {}: {:?}", path, err),
+        },
+        None => None,
+    };
+    match framebuffer::init(width, height, font) {
+        Ok((cols, rows)) => match framebuffer::with_console(|console| console.framebuffer().scroll_mode()) {
+            Some(scroll) => println!("fbset: {}x{}, text console {}x{}, scrolling {:?}", width, height, cols, rows, scroll),
+            None => println!("{}", USAGE),
+        },
+        Err(err) => println!("fbset: {:?}", err),
+    }
+}
+
+//res:<名字> 是嵌入内核的资源(resources/ 下的路径)，其他是文件路径
+fn read_font_file(path: &str) -> Result<Vec<u8>, FsError> {
+    match path.strip_prefix("res:") {
+        Some(name) => resources::get(name).ok_or(FsError::NotFound),
+        None => vfs::open(path).and_then(|mut file| file.read_to_end()),
+    }
+}
+
+//列出嵌入内核的资源
+fn resources_cmd(_args: &[&str]) {
+    for resource in resources::list() {
+        let packed = if resource.compressed { format!("{}", resource.data.len()) } else { String::from("-") };
+        println!("{:>8} {:>8}  {}", resource.size, packed, resource.name);
+    }
+}
+
+//在块设备(如 /dev/ata0p1)上建立 FAT32 文件系统，目前只支持 FAT32，--fat32 可以省略
+fn mkfs_fat(args: &[&str]) {
+    const USAGE: &str = "usage: mkfs.fat <device> [--fat32] [--label NAME]";
+    let mut device = None;
+    let mut label = None;
+    let mut rest = args.iter();
+    while let Some(&arg) = rest.next() {
+        match arg {
+            "--fat32" => {}
+            "--label" => match rest.next() {
+                Some(&name) => label = Some(name),
+                None => return println!("{}", USAGE),
+            },
+            _ if arg.starts_with("--") => return println!("mkfs.fat: unknown option {}", arg),
+            _ if device.is_none() => device = Some(arg),
+            _ => return println!("{}", USAGE),
+        }
+    }
+    let path = match device {
+        Some(path) => path,
+        None => return println!("{}", USAGE),
+    };
+    let mut dev = match block::from_path(path) {
+        Some(dev) => dev,
+        None => return println!("mkfs.fat: {}: no such block device", path),
+    };
+    match fat32::format(&mut dev, label) {
+        Ok(()) => println!("mkfs.fat: {}: created FAT32 volume", path),
+        Err(err) => println!("mkfs.fat: {}: {:?}", path, err),
+    }
+}
+
+//不带参数时列出回环设备；带文件路径时把文件挂接为 /dev/loopN；-d 拆除设备
+fn losetup(args: &[&str]) {
+    match args {
+        [] => {
+            for (name, path) in loopback::list() {
+                println!("/dev/{}: {}", name, path);
+            }
+        }
+        ["-d", device] => {
+            if let Err(err) = loopback::detach(device) {
+                println!("losetup: {}: {:?}", device, err);
+            }
+        }
+        [path] if !path.starts_with('-') => match loopback::attach(path) {
+            Ok(name) => println!("/dev/{}", name),
+            Err(err) => println!("losetup: {}: {:?}", path, err),
+        },
+        _ => println!("usage: losetup [<file> | -d <device>]"),
+    }
+}
+
+//挂载块设备上的 FAT32 卷
+fn mount(args: &[&str]) {
+    let (device, dir) = match args {
+        [device, dir] => (*device, *dir),
+        _ => return println!("usage: mount <device> <dir>"),
+    };
+    let dev = match block::from_path(device) {
+        Some(dev) => dev,
+        None => return println!("mount: {}: no such block device", device),
+    };
+    if !is_dir(dir) {
+        return println!("mount: {}: {:?}", dir, FsError::NotADirectory);
+    }
+    match fat32::Fat32::mount(cache::wrap(dev.clone())).and_then(|fat| vfs::mount_device(dir, fat.into_root(), dev)) {
+        Ok(()) => println!("mounted {} (fat32) on {}", device, dir),
+        Err(err) => println!("mount: {}: {:?}", device, err),
+    }
+}
+
+fn umount(args: &[&str]) {
+    let dir = match args {
+        [dir] => *dir,
+        _ => return println!("usage: umount <dir>"),
+    };
+    let device = match vfs::unmount(dir) {
+        Ok(device) => device,
+        Err(err) => return println!("umount: {}: {:?}", dir, err),
+    };
+    //写回并丢弃这个设备缓存的块；不是块设备上的文件系统时写回全部
+    let result = match device {
+        Some(device) => cache::invalidate(&device),
+        None => cache::sync(),
+    };
+    if let Err(err) = result {
+        println!("umount: {}: writing back cached blocks failed: {:?}", dir, err);
+    }
+}
+
+//把块缓存中修改过的块写回设备
+fn sync(_args: &[&str]) {
+    if let Err(err) = cache::sync() {
+        println!("sync: {:?}", err);
+    }
+}
+
+//解析带可选单位(K、M，以 1024 为进制)的大小，例如 512K
+fn parse_size(text: &str) -> Option<usize> {
+    let (digits, unit) = match text.as_bytes().last()? {
+        b'k' | b'K' => (&text[..text.len() - 1], 1024),
+        b'm' | b'M' => (&text[..text.len() - 1], 1024 * 1024),
+        _ => (text, 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+fn ramdisk_cmd(args: &[&str]) {
+    const USAGE: &str = "usage: ramdisk create <size>[K|M] | destroy <device>";
+    match args {
+        ["create", size] => match parse_size(size) {
+            Some(size) => match ramdisk::create(size) {
+                Ok(name) => println!("/dev/{}: {} blocks", name, size / 512),
+                Err(err) => println!("ramdisk: {:?}", err),
+            },
+            None => println!("{}", USAGE),
+        },
+        ["destroy", device] => {
+            if let Err(err) = ramdisk::destroy(device) {
+                println!("ramdisk: {}: {:?}", device, err);
+            }
+        }
+        _ => println!("{}", USAGE),
+    }
+}
+
+//读取口令：不回显，回车结束，退格删除最后一个字符
+fn read_passphrase(prompt: &str) -> Vec<u8> {
+    print!("{}", prompt);
+    let mut passphrase = Vec::new();
+    loop {
+        match keyboard::read_key() {
+            DecodedKey::Unicode('\n') => break,
+            DecodedKey::Unicode('\u{8}') => {
+                passphrase.pop();
+            }
+            DecodedKey::Unicode(c) if c.is_ascii() && !c.is_ascii_control() => passphrase.push(c as u8),
+            _ => {}
+        }
+    }
+    println!();
+    passphrase
+}
+
+//不带参数时列出加密设备；open 询问口令后建立 /dev/cryptN；close 关闭设备
+fn cryptsetup(args: &[&str]) {
+    match args {
+        [] => {
+            for (name, device) in crypt::list() {
+                println!("/dev/{}: /dev/{}", name, device);
+            }
+        }
+        ["open", device] => {
+            let mut passphrase = read_passphrase("passphrase: ");
+            let result = crypt::open(device, &passphrase);
+            passphrase.iter_mut().for_each(|b| *b = 0);
+            match result {
+                Ok(name) => println!("/dev/{}", name),
+                Err(err) => println!("cryptsetup: {}: {:?}", device, err),
+            }
+        }
+        ["close", device] => {
+            if let Err(err) = crypt::close(device) {
+                println!("cryptsetup: {}: {:?}", device, err);
+            }
+        }
+        _ => println!("usage: cryptsetup [open <device> | close <device>]"),
+    }
+}
+
+//不带参数时列出快照设备；create 在底层设备上建立快照，commit/discard 提交或丢弃写入，fail 模拟断电
+fn snapshot_cmd(args: &[&str]) {
+    const USAGE: &str = "usage: snapshot [create|commit|discard|destroy <device> | fail <device> <blocks>|off]";
+    let result = match args {
+        [] => {
+            for (name, base, status) in snapshot::list() {
+                print!("/dev/{}: /dev/{}, {} dirty blocks", name, base, status.dirty_blocks);
+                match status.writes_left {
+                    Some(0) => println!(", power off ({} writes dropped)", status.dropped),
+                    Some(left) => println!(", power off after {} blocks", left),
+                    None => println!(),
+                }
+            }
+            Ok(())
+        }
+        ["create", device] => snapshot::create(device).map(|name| println!("/dev/{}", name)),
+        ["commit", device] => snapshot::commit(device),
+        ["discard", device] => snapshot::discard(device),
+        ["destroy", device] => snapshot::destroy(device),
+        ["fail", device, "off"] => snapshot::with_snapshot(device, |cow| cow.fail_after(None)),
+        ["fail", device, blocks] => match blocks.parse::<u64>() {
+            Ok(blocks) => snapshot::with_snapshot(device, |cow| cow.fail_after(Some(blocks))),
+            Err(_) => return println!("{}", USAGE),
+        },
+        _ => return println!("{}", USAGE),
+    };
+    if let Err(err) = result {
+        println!("snapshot: {:?}", err);
+    }
+}
+
+//检查块设备上 FAT32 卷的一致性，不修改磁盘
+fn fsck(args: &[&str]) {
+    let device = match args {
+        [device] => *device,
+        _ => return println!("usage: fsck <device>"),
+    };
+    let dev = match block::from_path(device) {
+        Some(dev) => dev,
+        None => return println!("fsck: {}: no such block device", device),
+    };
+    match fat32::Fat32::mount(cache::wrap(dev)).and_then(|mut fs| fs.check()) {
+        Ok(report) => {
+            for problem in &report.problems {
+                println!("fsck: {:?}", problem);
+            }
+            println!("{}: {} directories, {} files, {} clusters used, {} lost, {}", device, report.directories,
+                report.files, report.used_clusters, report.lost_clusters,
+                if report.is_clean() { "clean" } else { "CORRUPTED" });
+        }
+        Err(err) => println!("fsck: {}: {:?}", device, err),
+    }
+}
+
+const CRASHTEST_ITERATIONS: u32 = 32;
+const CRASHTEST_SHOWN: usize = 8; //最多列出的损坏次数
+
+//在设备的快照上反复运行写盘操作并随机断电，报告断电后留下的损坏
+fn crashtest_cmd(args: &[&str]) {
+    let (device, name, iterations) = match args {
+        [device, name] => (*device, *name, Some(CRASHTEST_ITERATIONS)),
+        [device, name, n] => (*device, *name, n.parse().ok()),
+        _ => ("", "", None),
+    };
+    let (workload, iterations) = match (crashtest::find(name), iterations) {
+        (Some(workload), Some(iterations)) => (workload, iterations),
+        _ => {
+            println!("usage: crashtest <device> <workload> [iterations]");
+            let names: Vec<&str> = crashtest::WORKLOADS.iter().map(|w| w.name).collect();
+            return println!("workloads: {}", names.join(" "));
+        }
+    };
+    let dev = match block::from_path(device) {
+        Some(dev) => dev,
+        None => return println!("crashtest: {}: no such block device", device),
+    };
+    let report = match crashtest::run(dev, workload, iterations) {
+        Ok(report) => report,
+        Err(err) => return println!("crashtest: {}: {:?}", device, err),
+    };
+    for corruption in report.corrupted.iter().take(CRASHTEST_SHOWN) {
+        println!("power cut at block {}/{}: {:?}", corruption.cut, report.total_writes, corruption.problems);
+    }
+    println!("{} iterations: {} clean, {} unmountable, {} corrupted", report.iterations, report.clean,
+        report.unmountable, report.corrupted.len());
+}
+
+//不带参数时显示网络配置，否则设置本机地址、子网掩码和网关
+fn ifconfig(args: &[&str]) {
+    match args {
+        [] => {
+            let config = net::config();
+            println!("address {} netmask {} gateway {}", config.address, config.netmask, config.gateway);
+        }
+        [address, netmask, gateway] => match (Ipv4Addr::parse(address), Ipv4Addr::parse(netmask), Ipv4Addr::parse(gateway)) {
+            (Some(address), Some(netmask), Some(gateway)) => net::set_config(net::Config { address, netmask, gateway }),
+            _ => println!("ifconfig: invalid address"),
+        },
+        _ => println!("usage: ifconfig [<address> <netmask> <gateway>]"),
+    }
+}
+
+//各个接收队列的 CPU 和收发统计
+fn netqueues(_args: &[&str]) {
+    match nic::with_nic(|nic| nic.queues()) {
+        Ok(1) => println!("hardware queues: 1 (steering in software)"),
+        Ok(count) => println!("hardware queues: {}", count),
+        Err(_) => println!("no network device"),
+    }
+    println!("{:>5} {:>3} {:>10} {:>12} {:>8} {:>10} {:>12} {:>7}", "QUEUE", "CPU", "RX-PKTS", "RX-BYTES", "DROPPED", "TX-PKTS", "TX-BYTES", "BACKLOG");
+    for (queue, stats) in rss::stats().iter().enumerate() {
+        println!(
+            "{:>5} {:>3} {:>10} {:>12} {:>8} {:>10} {:>12} {:>7}",
+            queue, stats.cpu, stats.rx_packets, stats.rx_bytes, stats.rx_dropped, stats.tx_packets, stats.tx_bytes, stats.backlog
+        );
+    }
+}
+
+fn rconsole_cmd(args: &[&str]) {
+    let result = match args {
+        [] => {
+            if !rconsole::running() {
+                return println!("rconsole: not running");
+            }
+            println!("rconsole: listening on udp port {}", rconsole::PORT);
+            for session in rconsole::sessions() {
+                println!("  {:08x} {}:{} terminal {} commands {}", session.id, session.peer, session.port, session.terminal + 1, session.commands);
+            }
+            return;
+        }
+        ["start"] => rconsole::start(),
+        ["stop"] => rconsole::stop(),
+        _ => return println!("usage: rconsole [start | stop]"),
+    };
+    if let Err(err) = result {
+        println!("rconsole: {:?}", err);
+    }
+}
+
+fn arp_cmd(_args: &[&str]) {
+    for (ip, mac) in arp::entries() {
+        println!("{:<15} {:02x?}", format!("{}", ip), mac);
+    }
+}
+
+const PING_COUNT: u16 = 4;
+
+fn ping(args: &[&str]) {
+    let (address, count) = match args {
+        [address] => (*address, Some(PING_COUNT)),
+        [address, count] => (*address, count.parse().ok()),
+        _ => return println!("usage: ping <address> [count]"),
+    };
+    let (dst, count) = match (Ipv4Addr::parse(address), count) {
+        (Some(dst), Some(count)) => (dst, count),
+        _ => return println!("usage: ping <address> [count]"),
+    };
+    let mut received = 0;
+    for seq in 0..count {
+        match icmp::ping(dst, seq, net::DEFAULT_TIMEOUT) {
+            Ok(rtt) => {
+                println!("reply from {}: seq={} time={}.{:03} ms", dst, seq, rtt.as_micros() / 1000, rtt.as_micros() % 1000);
+                received += 1;
+            }
+            Err(err) => println!("ping {}: seq={}: {:?}", dst, seq, err),
+        }
+    }
+    println!("{} packets transmitted, {} received", count, received);
+}
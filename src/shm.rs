@@ -0,0 +1,185 @@
+//共享内存段：按名字打开，由物理帧组成，映射到多个进程时共享同一批帧，进程之间交换数据不需要经过内核复制
+//段由打开它的句柄、映射它的区域和名字表共同引用(Arc)，名字被删除且最后一个引用消失时回收它的帧
+//还没有用户的概念，权限按进程区分：创建段的进程是所有者，mode 中的所有者位和其他人位分别控制读写
+use crate::fs::vfs::{FileHandle, SeekFrom};
+use crate::fs::FsError;
+use crate::memory::{self, MemoryError};
+use crate::process;
+use crate::vm::Backing;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::structures::paging::PhysFrame;
+
+const PAGE_SIZE: u64 = 4096;
+pub const MAX_SIZE: u64 = 16 * 1024 * 1024; //一个段最大 16 MiB
+const NAME_MAX: usize = 255;
+
+//权限位，与 Unix 的 mode 相同(不区分组)
+pub const MODE_OWNER_READ: u16 = 0o400;
+pub const MODE_OWNER_WRITE: u16 = 0o200;
+pub const MODE_OTHER_READ: u16 = 0o004;
+pub const MODE_OTHER_WRITE: u16 = 0o002;
+
+pub struct Segment {
+    owner: u64, //创建段的进程
+    mode: u16,
+    frames: Mutex<Vec<PhysFrame>>,
+}
+
+impl Segment {
+    fn size(&self) -> u64 {
+        self.frames.lock().len() as u64 * PAGE_SIZE
+    }
+
+    //当前进程是否有读(write 为 false)或写的权限
+    fn allowed(&self, write: bool) -> bool {
+        let (read_bit, write_bit) = if process::current_pid() == self.owner {
+            (MODE_OWNER_READ, MODE_OWNER_WRITE)
+        } else {
+            (MODE_OTHER_READ, MODE_OTHER_WRITE)
+        };
+        self.mode & if write { write_bit } else { read_bit } != 0
+    }
+
+    //第 index 页对应的物理帧
+    pub fn frame(&self, index: u64) -> Option<PhysFrame> {
+        self.frames.lock().get(index as usize).copied()
+    }
+
+    //把段扩大到至少 size 字节，新的页面清零
+    fn grow(&self, size: u64) -> Result<(), MemoryError> {
+        let mut frames = self.frames.lock();
+        while (frames.len() as u64) * PAGE_SIZE < size {
+            frames.push(memory::alloc_frame()?);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        for frame in self.frames.lock().drain(..) {
+            memory::free_frame(frame);
+        }
+    }
+}
+
+static SEGMENTS: Mutex<BTreeMap<String, Arc<Segment>>> = Mutex::new(BTreeMap::new());
+
+//打开名为 name 的段，create 为真且段不存在时以 mode 创建一个大小为 0 的段
+//exclusive 为真时段已经存在返回 AlreadyExists；write 表示以读写方式打开
+pub fn open(name: &str, create: bool, exclusive: bool, mode: u16, write: bool) -> Result<ShmHandle, FsError> {
+    if name.is_empty() || name.len() > NAME_MAX || name.contains('/') {
+        return Err(FsError::InvalidPath);
+    }
+    let mut segments = SEGMENTS.lock();
+    let segment = match segments.get(name) {
+        Some(_) if create && exclusive => return Err(FsError::AlreadyExists),
+        Some(segment) => segment.clone(),
+        None if create => {
+            let segment = Arc::new(Segment { owner: process::current_pid(), mode, frames: Mutex::new(Vec::new()) });
+            segments.insert(String::from(name), segment.clone());
+            segment
+        }
+        None => return Err(FsError::NotFound),
+    };
+    if !segment.allowed(false) || (write && !segment.allowed(true)) {
+        return Err(FsError::PermissionDenied);
+    }
+    Ok(ShmHandle { segment, write, pos: 0 })
+}
+
+//删除段的名字，只有所有者可以删除；已经打开或映射的进程还能继续使用它
+pub fn unlink(name: &str) -> Result<(), FsError> {
+    let mut segments = SEGMENTS.lock();
+    let segment = segments.get(name).ok_or(FsError::NotFound)?;
+    if segment.owner != process::current_pid() {
+        return Err(FsError::PermissionDenied);
+    }
+    segments.remove(name);
+    Ok(())
+}
+
+//打开的段，像文件一样可以读写，也可以用 shm_map 映射
+pub struct ShmHandle {
+    segment: Arc<Segment>,
+    write: bool, //以读写方式打开
+    pos: u64,
+}
+
+impl ShmHandle {
+    //按页访问段中 [pos, pos + len) 的内容
+    fn for_each_chunk(&self, len: usize, mut f: impl FnMut(*mut u8, usize, usize)) -> usize {
+        let size = self.segment.size();
+        let len = (len as u64).min(size.saturating_sub(self.pos)) as usize;
+        let mut done = 0;
+        while done < len {
+            let offset = self.pos + done as u64;
+            let frame = self.segment.frame(offset / PAGE_SIZE).unwrap();
+            let in_page = (offset % PAGE_SIZE) as usize;
+            let count = (len - done).min(PAGE_SIZE as usize - in_page);
+            let virt = memory::phys_to_virt(frame.start_address()) + in_page as u64;
+            f(virt.as_mut_ptr(), done, count);
+            done += count;
+        }
+        len
+    }
+}
+
+impl FileHandle for ShmHandle {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let n = self.for_each_chunk(buf.len(), |src, done, count| unsafe {
+            core::ptr::copy_nonoverlapping(src, buf[done..].as_mut_ptr(), count);
+        });
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    //写入不会扩大段，超出段末尾的部分被截断
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        if !self.write {
+            return Err(FsError::ReadOnly);
+        }
+        let n = self.for_each_chunk(buf.len(), |dst, done, count| unsafe {
+            core::ptr::copy_nonoverlapping(buf[done..].as_ptr(), dst, count);
+        });
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, FsError> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+            SeekFrom::End(offset) => self.segment.size() as i64 + offset,
+        };
+        if target < 0 {
+            return Err(FsError::InvalidArgument);
+        }
+        self.pos = target as u64;
+        Ok(self.pos)
+    }
+
+    //段的大小为 0 时先扩大到 len(相当于 ftruncate)，已有的段不能映射超出大小的部分
+    fn map_shared(&mut self, len: u64, write: bool) -> Result<Backing, FsError> {
+        if write && !self.write {
+            return Err(FsError::PermissionDenied);
+        }
+        if len == 0 || len > MAX_SIZE {
+            return Err(FsError::InvalidArgument);
+        }
+        if self.segment.size() == 0 {
+            if !self.write {
+                return Err(FsError::PermissionDenied);
+            }
+            self.segment.grow(len).map_err(|_| FsError::NoSpace)?;
+        }
+        if len > self.segment.size() {
+            return Err(FsError::InvalidArgument);
+        }
+        Ok(Backing::Shared { segment: self.segment.clone(), offset: 0 })
+    }
+}
@@ -0,0 +1,289 @@
+//信号：给用户进程的异步通知，每个进程有一个待处理信号的位图和每个信号的处理方式
+//kill 只是把位图中的一位置上，真正的处理发生在进程返回用户态之前(系统调用返回时，以及进程第一次开始运行时)
+//用户程序关闭中断运行，没有抢占，所以一直不做系统调用的程序要等到下一次系统调用才会收到信号
+//处理方式：默认(结束进程或忽略，见 default_terminates)、忽略、或者用户程序自己的处理函数
+//调用处理函数时在用户栈上放一个 SignalFrame 保存原来的寄存器，处理函数返回到 sigaction 登记的 restorer，
+//restorer 不动栈，直接调用 sigreturn 恢复寄存器，回到被信号打断的地方
+use crate::coredump;
+use crate::process::{self, ProcessError, State};
+use crate::usermode::{self, UserContext};
+
+pub const NSIG: u32 = 32; //信号编号 1..NSIG，数值与 Linux 相同
+
+pub const SIGHUP: u32 = 1;
+pub const SIGINT: u32 = 2;
+pub const SIGQUIT: u32 = 3;
+pub const SIGILL: u32 = 4;
+pub const SIGTRAP: u32 = 5;
+pub const SIGABRT: u32 = 6;
+pub const SIGBUS: u32 = 7;
+pub const SIGFPE: u32 = 8;
+pub const SIGKILL: u32 = 9;
+pub const SIGUSR1: u32 = 10;
+pub const SIGSEGV: u32 = 11;
+pub const SIGUSR2: u32 = 12;
+pub const SIGPIPE: u32 = 13;
+pub const SIGALRM: u32 = 14;
+pub const SIGTERM: u32 = 15;
+pub const SIGCHLD: u32 = 17;
+pub const SIGCONT: u32 = 18;
+pub const SIGSTOP: u32 = 19;
+pub const SIGTSTP: u32 = 20;
+pub const SIGTTIN: u32 = 21;
+pub const SIGTTOU: u32 = 22;
+pub const SIGURG: u32 = 23;
+pub const SIGWINCH: u32 = 28;
+
+//sigaction 的 handler 参数中的两个特殊值
+pub const SIG_DFL: u64 = 0;
+pub const SIG_IGN: u64 = 1;
+
+const NAMES: [(u32, &str); 23] = [
+    (SIGHUP, "HUP"),
+    (SIGINT, "INT"),
+    (SIGQUIT, "QUIT"),
+    (SIGILL, "ILL"),
+    (SIGTRAP, "TRAP"),
+    (SIGABRT, "ABRT"),
+    (SIGBUS, "BUS"),
+    (SIGFPE, "FPE"),
+    (SIGKILL, "KILL"),
+    (SIGUSR1, "USR1"),
+    (SIGSEGV, "SEGV"),
+    (SIGUSR2, "USR2"),
+    (SIGPIPE, "PIPE"),
+    (SIGALRM, "ALRM"),
+    (SIGTERM, "TERM"),
+    (SIGCHLD, "CHLD"),
+    (SIGCONT, "CONT"),
+    (SIGSTOP, "STOP"),
+    (SIGTSTP, "TSTP"),
+    (SIGTTIN, "TTIN"),
+    (SIGTTOU, "TTOU"),
+    (SIGURG, "URG"),
+    (SIGWINCH, "WINCH"),
+];
+
+const RED_ZONE: u64 = 128; //System V ABI 允许函数不移动 rsp 就使用栈顶以下 128 字节
+const STATUS_FLAGS: u64 = 0xcd5; //sigreturn 只恢复 CF、PF、AF、ZF、SF、DF、OF，其余 RFLAGS 位由内核决定
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Default,
+    Ignore,
+    Handler { entry: u64, restorer: u64 },
+}
+
+//每个进程的信号状态，fork 时复制处理方式，exec 时把处理函数恢复为默认
+#[derive(Debug, Clone)]
+pub struct SignalState {
+    pending: u32, //第 n 位表示信号 n 等待处理
+    blocked: u32, //正在执行处理函数的信号，sigreturn 之前不再递送
+    actions: [Action; NSIG as usize],
+}
+
+impl SignalState {
+    pub const fn new() -> SignalState {
+        SignalState { pending: 0, blocked: 0, actions: [Action::Default; NSIG as usize] }
+    }
+
+    //子进程继承处理方式，不继承待处理的信号
+    pub fn fork(&self) -> SignalState {
+        SignalState { pending: 0, blocked: self.blocked, actions: self.actions }
+    }
+
+    //新程序里原来的处理函数已经不存在，忽略的信号仍然忽略
+    pub fn exec(&mut self) {
+        self.blocked = 0;
+        for action in self.actions.iter_mut() {
+            if let Action::Handler { .. } = action {
+                *action = Action::Default;
+            }
+        }
+    }
+
+    //取出编号最小的可以递送的信号
+    fn take(&mut self) -> Option<(u32, Action)> {
+        let ready = self.pending & !self.blocked;
+        if ready == 0 {
+            return None;
+        }
+        let sig = ready.trailing_zeros();
+        self.pending &= !(1 << sig);
+        Some((sig, self.actions[sig as usize]))
+    }
+}
+
+//sigreturn 从用户栈上读回的内容，处理函数开始运行时 rsp 指向 restorer
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SignalFrame {
+    restorer: u64,
+    context: UserContext,
+    rax: i64,     //被打断的系统调用的返回值
+    blocked: u64, //递送之前的 blocked
+}
+
+pub const FRAME_SIZE: u64 = core::mem::size_of::<SignalFrame>() as u64;
+
+pub fn name(sig: u32) -> Option<&'static str> {
+    NAMES.iter().find(|&&(n, _)| n == sig).map(|&(_, name)| name)
+}
+
+//"INT"、"SIGINT" 或者编号
+pub fn parse(text: &str) -> Option<u32> {
+    let name = text.strip_prefix("SIG").unwrap_or(text);
+    match name.parse() {
+        Ok(sig) if sig < NSIG => Some(sig),
+        Ok(_) => None,
+        Err(_) => NAMES.iter().find(|&&(_, n)| n == name).map(|&(sig, _)| sig),
+    }
+}
+
+//停止、继续进程的信号需要作业控制，还不支持
+fn supported(sig: u32) -> bool {
+    sig < NSIG && !matches!(sig, SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU)
+}
+
+//没有登记处理方式时：这几个信号忽略，其余的结束进程
+fn default_terminates(sig: u32) -> bool {
+    !matches!(sig, SIGCHLD | SIGCONT | SIGURG | SIGWINCH)
+}
+
+//默认处理方式下除了结束进程还要写 core 文件的信号
+pub fn dumps_core(sig: u32) -> bool {
+    matches!(sig, SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGSEGV)
+}
+
+fn terminates(sig: u32, action: Action) -> bool {
+    sig == SIGKILL || (action == Action::Default && default_terminates(sig))
+}
+
+//被信号结束的进程的退出码，与 process::KILLED_EXIT_CODE、SEGFAULT_EXIT_CODE 一致
+pub fn exit_code(sig: u32) -> i64 {
+    -(sig as i64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalError {
+    Invalid,         //编号不合法、不支持，或者不能改变处理方式(SIGKILL)
+    Process(ProcessError),
+}
+
+impl From<ProcessError> for SignalError {
+    fn from(err: ProcessError) -> SignalError {
+        SignalError::Process(err)
+    }
+}
+
+//给进程发送信号，sig 为 0 时只检查进程是否存在
+//还没有开始运行的进程收到会结束它的信号时直接结束，和以前的 kill 一样不必等到 wait
+pub fn send(pid: u64, sig: u32) -> Result<(), SignalError> {
+    if !supported(sig) {
+        return Err(SignalError::Invalid);
+    }
+    let terminate = process::with_signals(pid, |state, signals| {
+        if sig == 0 || state == State::Zombie {
+            return false;
+        }
+        if state != State::Running && terminates(sig, signals.actions[sig as usize]) {
+            return true;
+        }
+        signals.pending |= 1 << sig;
+        false
+    })?;
+    if terminate {
+        process::terminate(pid, exit_code(sig))?;
+    }
+    Ok(())
+}
+
+//sigaction(sig, handler, restorer) 的实现，在当前进程上登记处理方式
+pub fn set_action(sig: u32, action: Action) -> Result<(), SignalError> {
+    if sig == 0 || sig == SIGKILL || !supported(sig) {
+        return Err(SignalError::Invalid);
+    }
+    process::with_signals(process::current_pid(), |_, signals| signals.actions[sig as usize] = action)?;
+    Ok(())
+}
+
+fn user_address(addr: u64) -> bool {
+    (usermode::USER_BASE..usermode::MMAP_END).contains(&addr)
+}
+
+//在用户栈上放一个 SignalFrame，让 context 从处理函数开始执行，当前地址空间必须是进程自己的
+fn push_frame(context: &mut UserContext, sig: u32, entry: u64, restorer: u64, rax: i64, blocked: u32) -> bool {
+    if context.rsp < usermode::USER_BASE + RED_ZONE + FRAME_SIZE + 16 {
+        return false;
+    }
+    let sp = ((context.rsp - RED_ZONE - FRAME_SIZE) & !0xf) - 8; //处理函数入口处 rsp + 8 要 16 字节对齐
+    let bytes = match usermode::user_slice_mut(sp, FRAME_SIZE) {
+        Some(bytes) => bytes,
+        None => return false,
+    };
+    let frame = SignalFrame { restorer, context: *context, rax, blocked: blocked as u64 };
+    unsafe { core::ptr::write_unaligned(bytes.as_mut_ptr() as *mut SignalFrame, frame) };
+    context.rip = entry;
+    context.rsp = sp;
+    context.rdi = sig as u64;
+    true
+}
+
+//处理进程 pid 的待处理信号，context 是它返回用户态时的寄存器，rax 是那时的返回值
+//需要结束进程时返回 Err(退出码)；有处理函数时改写 context 从处理函数开始执行，一次只递送一个
+pub fn prepare(pid: u64, context: &mut UserContext, rax: i64) -> Result<(), i64> {
+    loop {
+        let taken = process::with_signals(pid, |_, signals| signals.take().map(|taken| (taken, signals.blocked)));
+        let ((sig, action), blocked) = match taken {
+            Ok(Some(taken)) => taken,
+            _ => return Ok(()),
+        };
+        if terminates(sig, action) {
+            return Err(fatal(pid, sig, context, rax));
+        }
+        if let Action::Handler { entry, restorer } = action {
+            if !push_frame(context, sig, entry, restorer, rax, blocked) {
+                return Err(fatal(pid, SIGSEGV, context, rax)); //用户栈已经坏了，没法调用处理函数
+            }
+            let _ = process::with_signals(pid, |_, signals| signals.blocked |= 1 << sig);
+            return Ok(());
+        }
+    }
+}
+
+//进程被信号 sig 结束，返回退出码；需要时先写 core 文件(这时还在进程自己的地址空间里)
+fn fatal(pid: u64, sig: u32, context: &UserContext, rax: i64) -> i64 {
+    if dumps_core(sig) {
+        coredump::write(pid, sig, context, rax);
+    }
+    exit_code(sig)
+}
+
+//系统调用返回用户态之前调用，result 是系统调用的返回值
+pub fn deliver(result: i64) -> i64 {
+    let pid = process::current_pid();
+    if pid == 0 {
+        return result;
+    }
+    match prepare(pid, usermode::syscall_context(), result) {
+        Ok(()) => result,
+        Err(code) => usermode::exit(code),
+    }
+}
+
+//sigreturn 的实现：restorer 执行时 rsp 刚好越过 SignalFrame 的 restorer 字段
+//恢复被信号打断时的寄存器，返回值是那时系统调用的返回值
+pub fn restore() -> Result<i64, SignalError> {
+    let context = usermode::syscall_context();
+    let bytes = usermode::user_slice(context.rsp.wrapping_sub(8), FRAME_SIZE).ok_or(SignalError::Invalid)?;
+    let frame = unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const SignalFrame) };
+    let mut saved = frame.context;
+    //sysretq 跳到非规范地址会在内核态出错，只接受用户地址空间里的 rip 和 rsp
+    if !user_address(saved.rip) || !user_address(saved.rsp) {
+        return Err(SignalError::Invalid);
+    }
+    saved.rflags = (saved.rflags & STATUS_FLAGS) | usermode::USER_RFLAGS;
+    process::with_signals(process::current_pid(), |_, signals| signals.blocked = frame.blocked as u32 & !(1 << SIGKILL))?;
+    *context = saved;
+    Ok(frame.rax)
+}
@@ -0,0 +1,102 @@
+//内核统计：各子系统在事件发生时更新计数器，snapshot 一次取出全部数值
+///proc/meminfo、/proc/vmstat 和 /proc/interrupts 由这里的数据生成
+//还不支持多处理器，每个 CPU 的计数只有 CPU 0 一份
+use crate::fs::cache::{self, CacheStats};
+use crate::vm::{self, FaultStats};
+use crate::{allocator, memory};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+const CPU_COUNT: usize = 1;
+
+//一个 CPU 上的计数器
+struct CpuCounters {
+    interrupts: [AtomicU64; 256], //按中断向量号计数
+    context_switches: AtomicU64,  //进入和离开用户进程的次数
+}
+
+impl CpuCounters {
+    const fn new() -> CpuCounters {
+        CpuCounters { interrupts: [const { AtomicU64::new(0) }; 256], context_switches: AtomicU64::new(0) }
+    }
+}
+
+static CPUS: [CpuCounters; CPU_COUNT] = [CpuCounters::new()];
+
+fn this_cpu() -> &'static CpuCounters {
+    &CPUS[0]
+}
+
+//由中断和异常处理程序调用
+pub fn count_interrupt(vector: u8) {
+    this_cpu().interrupts[vector as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+//切换到另一个地址空间运行时调用
+pub fn count_context_switch() {
+    this_cpu().context_switches.fetch_add(1, Ordering::Relaxed);
+}
+
+//全部 CPU 上下文切换的总次数
+pub fn context_switches() -> u64 {
+    CPUS.iter().map(|cpu| cpu.context_switches.load(Ordering::Relaxed)).sum()
+}
+
+//全部 CPU 中断和异常的总次数
+pub fn interrupts() -> u64 {
+    CPUS.iter().flat_map(|cpu| cpu.interrupts.iter()).map(|count| count.load(Ordering::Relaxed)).sum()
+}
+
+#[derive(Debug, Clone)]
+pub struct CpuStats {
+    pub interrupts: Vec<(u8, u64)>, //发生过的中断：(向量号, 次数)
+    pub context_switches: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub cpus: Vec<CpuStats>,
+    pub faults: FaultStats,
+    pub block_cache: CacheStats,
+    pub heap_used: usize, //字节
+    pub heap_size: usize,
+    pub frames_free: usize,
+    pub frames_total: usize,
+}
+
+pub fn snapshot() -> Snapshot {
+    let cpus = CPUS
+        .iter()
+        .map(|cpu| CpuStats {
+            interrupts: (0..=255u8)
+                .map(|vector| (vector, cpu.interrupts[vector as usize].load(Ordering::Relaxed)))
+                .filter(|&(_, count)| count > 0)
+                .collect(),
+            context_switches: cpu.context_switches.load(Ordering::Relaxed),
+        })
+        .collect();
+    let (heap_used, heap_size) = allocator::usage();
+    Snapshot {
+        cpus,
+        faults: vm::fault_stats(),
+        block_cache: cache::stats(),
+        heap_used,
+        heap_size,
+        frames_free: memory::free_frame_count(),
+        frames_total: memory::total_frame_count(),
+    }
+}
+
+//中断向量的名字，/proc/interrupts 的最后一列
+pub fn vector_name(vector: u8) -> &'static str {
+    match vector {
+        0 => "Divide error",
+        1 => "Debug",
+        3 => "Breakpoint",
+        6 => "Invalid opcode",
+        8 => "Double fault",
+        13 => "General protection",
+        14 => "Page fault",
+        _ => "",
+    }
+}
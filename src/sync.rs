@@ -0,0 +1,149 @@
+//带名字的自旋锁：用法和 spin::Mutex 相同，另外记录当前由哪一行代码持有
+//看门狗发现 CPU 卡住时打印所有被持有的这类锁，用来查死锁；只用在屏幕、滚动缓冲区、页表、进程表、文件系统这些容易互相嵌套的锁上
+//服务 panic 后也靠这张表找出它没有释放的锁(见 service)
+//持有记录放在固定大小的表里，不分配内存，中断里也能读
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+pub const MAX_HELD: usize = 32; //同时持有的锁超过这个数时不再记录(锁本身照常工作)
+
+struct LockInfo {
+    name: &'static str,
+    holder: AtomicPtr<Location<'static>>, //加锁的代码位置，没有持有时为空
+    unlock: unsafe fn(*const LockInfo),   //强制解锁包含它的 Mutex<T>
+}
+
+#[repr(C)] //info 在开头，从 LockInfo 的地址能得到 Mutex 的地址
+pub struct Mutex<T> {
+    info: LockInfo,
+    inner: spin::Mutex<T>,
+}
+
+pub struct MutexGuard<'a, T> {
+    guard: spin::MutexGuard<'a, T>,
+    info: &'a LockInfo,
+}
+
+//正被持有的锁
+static HELD: [AtomicPtr<LockInfo>; MAX_HELD] = [const { AtomicPtr::new(ptr::null_mut()) }; MAX_HELD];
+
+fn info_ptr(info: &LockInfo) -> *mut LockInfo {
+    info as *const LockInfo as *mut LockInfo
+}
+
+impl LockInfo {
+    fn acquired(&self, location: &'static Location<'static>) {
+        self.holder.store(location as *const _ as *mut _, Ordering::Relaxed);
+        let me = info_ptr(self);
+        for slot in HELD.iter() {
+            if slot.compare_exchange(ptr::null_mut(), me, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+                return;
+            }
+        }
+    }
+
+    fn released(&self) {
+        self.holder.store(ptr::null_mut(), Ordering::Relaxed);
+        let me = info_ptr(self);
+        for slot in HELD.iter() {
+            if slot.compare_exchange(me, ptr::null_mut(), Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+                return;
+            }
+        }
+    }
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(name: &'static str, value: T) -> Mutex<T> {
+        let info = LockInfo { name, holder: AtomicPtr::new(ptr::null_mut()), unlock: unlock_raw::<T> };
+        Mutex { info, inner: spin::Mutex::new(value) }
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let location = Location::caller();
+        let guard = self.inner.lock();
+        self.info.acquired(location);
+        MutexGuard { guard, info: &self.info }
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let location = Location::caller();
+        let guard = self.inner.try_lock()?;
+        self.info.acquired(location);
+        Some(MutexGuard { guard, info: &self.info })
+    }
+
+    //强制解锁，只能在 panic 这类持有者再也不会释放锁的情况下使用
+    pub unsafe fn force_unlock(&self) {
+        self.info.released();
+        self.inner.force_unlock();
+    }
+}
+
+unsafe fn unlock_raw<T>(info: *const LockInfo) {
+    (*(info as *const Mutex<T>)).force_unlock();
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.info.released();
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+//正被持有的锁：(名字, 加锁的位置)
+pub fn for_each_held(f: &mut dyn FnMut(&'static str, Option<&'static Location<'static>>)) {
+    for slot in HELD.iter() {
+        let info = slot.load(Ordering::Acquire);
+        if info.is_null() {
+            continue;
+        }
+        let info = unsafe { &*info };
+        let holder = info.holder.load(Ordering::Relaxed);
+        f(info.name, unsafe { holder.as_ref() });
+    }
+}
+
+//某一时刻被持有的锁
+#[derive(Clone, Copy)]
+pub struct HeldSet([*mut LockInfo; MAX_HELD]);
+
+pub fn held() -> HeldSet {
+    let mut set = HeldSet([ptr::null_mut(); MAX_HELD]);
+    for (slot, held) in set.0.iter_mut().zip(HELD.iter()) {
+        *slot = held.load(Ordering::Acquire);
+    }
+    set
+}
+
+//强制释放 before 之后才加上的锁，对每个释放的锁调用 f(名字, 加锁的位置)
+//只能在持有者再也不会运行的时候调用(服务 panic 后)，锁保护的数据可能停在修改了一半的状态
+pub unsafe fn force_release_since(before: &HeldSet, f: &mut dyn FnMut(&'static str, Option<&'static Location<'static>>)) {
+    for slot in HELD.iter() {
+        let info = slot.load(Ordering::Acquire);
+        if info.is_null() || before.0.contains(&info) {
+            continue;
+        }
+        let info = &*info;
+        let holder = info.holder.load(Ordering::Relaxed);
+        (info.unlock)(info);
+        f(info.name, holder.as_ref());
+    }
+}
@@ -0,0 +1,748 @@
+//系统调用：用户程序通过 syscall 指令进入内核，rax 为调用号，rdi/rsi/rdx 为参数，返回值放在 rax
+use crate::fs::socket::{self, Kind};
+use crate::fs::vfs::{FileHandle, SeekFrom};
+use crate::fs::{pipe, FsError};
+use crate::net::udp::UdpStream;
+use crate::net::{Ipv4Addr, StackError};
+use crate::vm::{self, Backing, VmError};
+use crate::process::{Personality, ProcessError};
+use crate::signal::{self, Action, SignalError};
+use crate::cred::{self, Credentials};
+use crate::{gdt, linux, process, shm, time, usermode};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use core::time::Duration;
+use x86_64::registers::model_specific::{Efer, EferFlags, LStar, SFMask, Star};
+use x86_64::registers::rflags::RFlags;
+use x86_64::VirtAddr;
+
+//系统调用号
+pub const SYS_WRITE: u64 = 0;
+pub const SYS_EXIT: u64 = 1;
+pub const SYS_SLEEP: u64 = 2;
+pub const SYS_GETPID: u64 = 3;
+pub const SYS_READ: u64 = 4;
+pub const SYS_PIPE: u64 = 5;
+pub const SYS_FCNTL: u64 = 6;
+pub const SYS_UDP_CONNECT: u64 = 7;
+pub const SYS_SOCKETPAIR: u64 = 8;
+pub const SYS_BIND: u64 = 9;
+pub const SYS_CONNECT: u64 = 10;
+pub const SYS_ACCEPT: u64 = 11;
+pub const SYS_SENDMSG: u64 = 12;
+pub const SYS_RECVMSG: u64 = 13;
+pub const SYS_MMAP: u64 = 14;
+pub const SYS_MUNMAP: u64 = 15;
+pub const SYS_SHM_OPEN: u64 = 16;
+pub const SYS_SHM_MAP: u64 = 17;
+pub const SYS_SHM_UNLINK: u64 = 18;
+pub const SYS_FORK: u64 = 19;
+pub const SYS_EXEC: u64 = 20;
+pub const SYS_WAIT: u64 = 21;
+pub const SYS_KILL: u64 = 22;
+pub const SYS_SIGACTION: u64 = 23;
+pub const SYS_SIGRETURN: u64 = 24;
+pub const SYS_GETUID: u64 = 25;
+pub const SYS_GETGID: u64 = 26;
+pub const SYS_SETUID: u64 = 27;
+
+//fcntl 的命令，数值与 Linux 相同
+pub const F_GETFL: u64 = 3;
+pub const F_SETFL: u64 = 4;
+
+//shm_open 的打开方式，数值与 Linux 的 open 相同；权限位放在 16 位以上
+pub const O_RDWR: u64 = 2;
+pub const O_CREAT: u64 = 0o100;
+pub const O_EXCL: u64 = 0o200;
+pub const SHM_MODE_SHIFT: u64 = 16;
+
+//本地套接字的类型，数值与 Linux 相同
+pub const SOCK_STREAM: u64 = 1;
+pub const SOCK_DGRAM: u64 = 2;
+
+//错误码，和 Linux 一样以负数返回
+pub const EPERM: i64 = -1;
+pub const ENOENT: i64 = -2;
+pub const ESRCH: i64 = -3;
+pub const EINTR: i64 = -4;
+pub const EIO: i64 = -5;
+pub const E2BIG: i64 = -7;
+pub const ENOEXEC: i64 = -8;
+pub const EBADF: i64 = -9;
+pub const ECHILD: i64 = -10;
+pub const EAGAIN: i64 = -11;
+pub const ENOMEM: i64 = -12;
+pub const EACCES: i64 = -13;
+pub const EFAULT: i64 = -14;
+pub const EEXIST: i64 = -17;
+pub const EXDEV: i64 = -18;
+pub const ENODEV: i64 = -19;
+pub const EINVAL: i64 = -22;
+pub const EMFILE: i64 = -24;
+pub const ENOTTY: i64 = -25;
+pub const ENOSPC: i64 = -28;
+pub const EPIPE: i64 = -32;
+pub const ENOSYS: i64 = -38;
+pub const ELOOP: i64 = -40;
+pub const ENOTSOCK: i64 = -88;
+pub const EADDRINUSE: i64 = -98;
+pub const ECONNREFUSED: i64 = -111;
+
+global_asm!(
+    ".pushsection .bss",
+    ".balign 8",
+    ".global syscall_kernel_rsp",
+    "syscall_kernel_rsp: .zero 8", //进入系统调用后使用的内核栈，由 user_resume 设置
+    "syscall_user_rsp: .zero 8",
+    ".popsection",
+    //syscall 指令不会切换栈：rcx 保存用户 RIP，r11 保存用户 RFLAGS，rsp 仍然是用户栈
+    //用户程序的全部寄存器按 usermode::UserContext 的布局保存在内核栈顶，fork 和 exec 读取或改写它们
+    ".global syscall_entry",
+    "syscall_entry:",
+    "mov [rip + syscall_user_rsp], rsp",
+    "mov rsp, [rip + syscall_kernel_rsp]",
+    "push qword ptr [rip + syscall_user_rsp]",
+    "push rcx",
+    "push r11",
+    "push rdi",
+    "push rsi",
+    "push rdx",
+    "push r10",
+    "push r8",
+    "push r9",
+    "push rbx",
+    "push rbp",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "sub rsp, 8", //15 个寄存器之后补齐 16 字节对齐
+    //按 System V 调用约定重新排列参数：syscall_dispatch(rax, rdi, rsi, rdx)
+    "mov rcx, rdx",
+    "mov rdx, rsi",
+    "mov rsi, rdi",
+    "mov rdi, rax",
+    "call syscall_dispatch",
+    "add rsp, 8",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbp",
+    "pop rbx",
+    "pop r9",
+    "pop r8",
+    "pop r10",
+    "pop rdx",
+    "pop rsi",
+    "pop rdi",
+    "pop r11",
+    "pop rcx",
+    "pop rsp", //用户栈保存在各个进程自己的内核栈上，嵌套运行子进程也不会覆盖
+    "sysretq",
+);
+
+extern "C" {
+    fn syscall_entry();
+}
+
+type Handler = fn(u64, u64, u64) -> i64;
+
+//系统调用的个数，abi::SYSCALLS 必须逐个列出
+pub const SYSCALL_COUNT: usize = 28;
+
+static TABLE: [(u64, Handler); SYSCALL_COUNT] = [
+    (SYS_WRITE, sys_write),
+    (SYS_EXIT, sys_exit),
+    (SYS_SLEEP, sys_sleep),
+    (SYS_GETPID, sys_getpid),
+    (SYS_READ, sys_read),
+    (SYS_PIPE, sys_pipe),
+    (SYS_FCNTL, sys_fcntl),
+    (SYS_UDP_CONNECT, sys_udp_connect),
+    (SYS_SOCKETPAIR, sys_socketpair),
+    (SYS_BIND, sys_bind),
+    (SYS_CONNECT, sys_connect),
+    (SYS_ACCEPT, sys_accept),
+    (SYS_SENDMSG, sys_sendmsg),
+    (SYS_RECVMSG, sys_recvmsg),
+    (SYS_MMAP, sys_mmap),
+    (SYS_MUNMAP, sys_munmap),
+    (SYS_SHM_OPEN, sys_shm_open),
+    (SYS_SHM_MAP, sys_shm_map),
+    (SYS_SHM_UNLINK, sys_shm_unlink),
+    (SYS_FORK, sys_fork),
+    (SYS_EXEC, sys_exec),
+    (SYS_WAIT, sys_wait),
+    (SYS_KILL, sys_kill),
+    (SYS_SIGACTION, sys_sigaction),
+    (SYS_SIGRETURN, sys_sigreturn),
+    (SYS_GETUID, sys_getuid),
+    (SYS_GETGID, sys_getgid),
+    (SYS_SETUID, sys_setuid),
+];
+
+//exec 的参数个数和总长度(含结尾的 0)上限，参数要放在只有 16 KiB 的用户栈上
+const MAX_ARGS: usize = 32;
+const ARG_MAX: usize = 4096;
+
+//把文件系统的错误转换成错误码
+fn errno(err: FsError) -> i64 {
+    match err {
+        FsError::WouldBlock => EAGAIN,
+        FsError::BrokenPipe => EPIPE,
+        FsError::InvalidArgument => EINVAL,
+        FsError::ReadOnly => EBADF,
+        FsError::NotFound => ENOENT,
+        FsError::AlreadyExists => EEXIST,
+        FsError::ConnectionRefused => ECONNREFUSED,
+        FsError::PermissionDenied => EACCES,
+        FsError::NoSpace => ENOSPC,
+        FsError::InvalidPath => EINVAL,
+        FsError::Interrupted => EINTR,
+        FsError::TooManyLinks => ELOOP,
+        FsError::CrossDevice => EXDEV,
+        _ => EIO,
+    }
+}
+
+//设置 syscall/sysret 用到的 MSR
+pub fn init() {
+    let selectors = gdt::selectors();
+    Star::write(selectors.user_code, selectors.user_data, selectors.kernel_code, selectors.kernel_data).unwrap();
+    LStar::write(VirtAddr::new(syscall_entry as *const () as u64));
+    //进入内核时清除中断、方向和单步标志
+    SFMask::write(RFlags::INTERRUPT_FLAG | RFlags::DIRECTION_FLAG | RFlags::TRAP_FLAG);
+    unsafe {
+        Efer::update(|flags| flags.insert(EferFlags::SYSTEM_CALL_EXTENSIONS));
+    }
+}
+
+#[no_mangle]
+extern "C" fn syscall_dispatch(number: u64, arg0: u64, arg1: u64, arg2: u64) -> i64 {
+    let result = match process::personality() {
+        Personality::Linux => linux::dispatch(number, arg0, arg1, arg2),
+        Personality::Native => match TABLE.iter().find(|(n, _)| *n == number) {
+            Some((_, handler)) => handler(arg0, arg1, arg2),
+            None => ENOSYS,
+        },
+    };
+    crate::trace!(Syscall, number, result);
+    signal::deliver(result) //返回用户态之前处理待处理的信号
+}
+
+//write(fd, buf, len)：写入当前进程的文件描述符，新进程的 0、1、2 都指向控制台
+//返回实际写入的字节数，管道满时可能少于 len；非阻塞模式下一个字节也写不进去时返回 EAGAIN
+pub fn sys_write(fd: u64, buf: u64, len: u64) -> i64 {
+    let bytes = match usermode::user_slice(buf, len) {
+        Some(bytes) => bytes,
+        None => return EFAULT,
+    };
+    match process::with_fd(fd, |handle| handle.write(bytes)) {
+        Some(Ok(n)) => n as i64,
+        Some(Err(err)) => errno(err),
+        None => EBADF,
+    }
+}
+
+//read(fd, buf, len)：返回读到的字节数，0 表示文件末尾；非阻塞模式下没有数据时返回 EAGAIN
+pub fn sys_read(fd: u64, buf: u64, len: u64) -> i64 {
+    let bytes = match usermode::user_slice_mut(buf, len) {
+        Some(bytes) => bytes,
+        None => return EFAULT,
+    };
+    match process::with_fd(fd, |handle| handle.read(bytes)) {
+        Some(Ok(n)) => n as i64,
+        Some(Err(err)) => errno(err),
+        None => EBADF,
+    }
+}
+
+//pipe(fds)：建立管道，读端和写端的描述符依次写入 fds 指向的两个 u32
+fn sys_pipe(fds: u64, _: u64, _: u64) -> i64 {
+    let out = match usermode::user_slice_mut(fds, 8) {
+        Some(out) => out,
+        None => return EFAULT,
+    };
+    let (reader, writer) = pipe::pipe();
+    let read_fd = match process::add_fd(reader) {
+        Some(fd) => fd,
+        None => return EMFILE,
+    };
+    let write_fd = match process::add_fd(writer) {
+        Some(fd) => fd,
+        None => {
+            process::close_fd(read_fd);
+            return EMFILE;
+        }
+    };
+    out[0..4].copy_from_slice(&(read_fd as u32).to_le_bytes());
+    out[4..8].copy_from_slice(&(write_fd as u32).to_le_bytes());
+    0
+}
+
+//fcntl(fd, cmd, arg)：只支持用 F_GETFL/F_SETFL 读取和设置 O_NONBLOCK
+fn sys_fcntl(fd: u64, cmd: u64, arg: u64) -> i64 {
+    match cmd {
+        F_GETFL => process::fd_flags(fd).map_or(EBADF, |flags| flags as i64),
+        F_SETFL => match process::set_fd_flags(fd, arg) {
+            Some(Ok(())) => 0,
+            Some(Err(err)) => errno(err),
+            None => EBADF,
+        },
+        _ => EINVAL,
+    }
+}
+
+//udp_connect(addr, peer_port, local_port)：建立只和一个对端通信的 UDP 套接字，返回描述符
+//addr 是按网络字节序排列的 IPv4 地址(10.0.2.2 为 0x0A000202)，local_port 为 0 时自动分配
+fn sys_udp_connect(addr: u64, peer_port: u64, local_port: u64) -> i64 {
+    if addr > u32::MAX as u64 || peer_port > u16::MAX as u64 || local_port > u16::MAX as u64 {
+        return EINVAL;
+    }
+    let peer = Ipv4Addr((addr as u32).to_be_bytes());
+    let stream = match UdpStream::connect(local_port as u16, peer, peer_port as u16) {
+        Ok(stream) => stream,
+        Err(StackError::AddrInUse) => return EADDRINUSE,
+        Err(_) => return EIO,
+    };
+    process::add_fd(Box::new(stream)).map_or(EMFILE, |fd| fd as i64)
+}
+
+//套接字系统调用用在不是套接字的描述符上时，句柄返回 Unsupported
+fn socket_errno(err: FsError) -> i64 {
+    match err {
+        FsError::Unsupported => ENOTSOCK,
+        err => errno(err),
+    }
+}
+
+fn socket_kind(kind: u64) -> Option<Kind> {
+    match kind {
+        SOCK_STREAM => Some(Kind::Stream),
+        SOCK_DGRAM => Some(Kind::Datagram),
+        _ => None,
+    }
+}
+
+fn user_str(ptr: u64, len: u64) -> Option<&'static str> {
+    core::str::from_utf8(usermode::user_slice(ptr, len)?).ok()
+}
+
+//socketpair(kind, fds)：建立一对互相连接的本地套接字，两个描述符依次写入 fds 指向的两个 u32
+fn sys_socketpair(kind: u64, fds: u64, _: u64) -> i64 {
+    let kind = match socket_kind(kind) {
+        Some(kind) => kind,
+        None => return EINVAL,
+    };
+    let out = match usermode::user_slice_mut(fds, 8) {
+        Some(out) => out,
+        None => return EFAULT,
+    };
+    let (first, second) = socket::pair(kind);
+    let first = match process::add_fd(Box::new(first)) {
+        Some(fd) => fd,
+        None => return EMFILE,
+    };
+    let second = match process::add_fd(Box::new(second)) {
+        Some(fd) => fd,
+        None => {
+            process::close_fd(first);
+            return EMFILE;
+        }
+    };
+    out[0..4].copy_from_slice(&(first as u32).to_le_bytes());
+    out[4..8].copy_from_slice(&(second as u32).to_le_bytes());
+    0
+}
+
+//bind(path, len, kind)：在 path(如 /run/name)上建立监听点，返回用来 accept 的描述符
+fn sys_bind(path: u64, len: u64, kind: u64) -> i64 {
+    let (path, kind) = match (user_str(path, len), socket_kind(kind)) {
+        (Some(path), Some(kind)) => (path, kind),
+        (None, _) => return EFAULT,
+        (_, None) => return EINVAL,
+    };
+    match socket::bind(path, kind) {
+        Ok(listener) => process::add_fd(Box::new(listener)).map_or(EMFILE, |fd| fd as i64),
+        Err(err) => errno(err),
+    }
+}
+
+//connect(path, len)：连接 path 上的监听点，返回已连接的套接字
+fn sys_connect(path: u64, len: u64, _: u64) -> i64 {
+    let path = match user_str(path, len) {
+        Some(path) => path,
+        None => return EFAULT,
+    };
+    match socket::connect(path) {
+        Ok(socket) => process::add_fd(Box::new(socket)).map_or(EMFILE, |fd| fd as i64),
+        Err(err) => errno(err),
+    }
+}
+
+//accept(fd)：取出一个连接，非阻塞模式下没有连接时返回 EAGAIN
+fn sys_accept(fd: u64, _: u64, _: u64) -> i64 {
+    match process::with_fd(fd, |handle| handle.accept()) {
+        Some(Ok(socket)) => process::add_fd(socket).map_or(EMFILE, |fd| fd as i64),
+        Some(Err(err)) => socket_errno(err),
+        None => EBADF,
+    }
+}
+
+//sendmsg/recvmsg 的参数，放在用户内存中，各字段都是 u64：
+//buf、len 是数据缓冲区，fds 指向 u32 数组，fd_count 是数组长度(recvmsg 返回时改写为收到的描述符数)
+pub const MSG_HEADER_SIZE: u64 = 32;
+
+fn msg_field(header: &[u8], index: usize) -> u64 {
+    u64::from_le_bytes(header[index * 8..index * 8 + 8].try_into().unwrap())
+}
+
+//sendmsg(fd, msg)：发送数据，并把 fds 中的描述符移交给对端(发送成功后它们在本进程中被关闭)
+fn sys_sendmsg(fd: u64, msg: u64, _: u64) -> i64 {
+    let header = match usermode::user_slice(msg, MSG_HEADER_SIZE) {
+        Some(header) => header,
+        None => return EFAULT,
+    };
+    let (buf, len, fds, fd_count) = (msg_field(header, 0), msg_field(header, 1), msg_field(header, 2), msg_field(header, 3));
+    if fd_count > socket::MAX_FDS as u64 {
+        return EINVAL;
+    }
+    let (data, numbers) = match (usermode::user_slice(buf, len), usermode::user_slice(fds, fd_count * 4)) {
+        (Some(data), Some(numbers)) => (data, numbers),
+        _ => return EFAULT,
+    };
+
+    let mut taken: Vec<(u64, u64)> = Vec::new(); //(描述符, 状态标志)
+    let mut handles: Vec<Box<dyn FileHandle>> = Vec::new();
+    let mut result = None;
+    for number in numbers.chunks(4).map(|n| u32::from_le_bytes(n.try_into().unwrap()) as u64) {
+        if number == fd || taken.iter().any(|&(taken, _)| taken == number) {
+            result = Some(EINVAL); //不能传递套接字自己，也不能重复传递同一个描述符
+            break;
+        }
+        match process::take_fd(number) {
+            Some((handle, flags)) => {
+                taken.push((number, flags));
+                handles.push(handle);
+            }
+            None => {
+                result = Some(EBADF);
+                break;
+            }
+        }
+    }
+    let result = result.unwrap_or_else(|| match process::with_fd(fd, |handle| handle.send_fds(data, &mut handles)) {
+        Some(Ok(n)) => n as i64,
+        Some(Err(err)) => socket_errno(err),
+        None => EBADF,
+    });
+    //发送失败时句柄还在 handles 里，放回原来的描述符
+    for ((number, flags), handle) in taken.into_iter().zip(handles) {
+        process::restore_fd(number, handle, flags);
+    }
+    result
+}
+
+//recvmsg(fd, msg)：接收数据和对端传来的描述符，返回读到的字节数
+//fds 数组放不下的描述符被关闭
+fn sys_recvmsg(fd: u64, msg: u64, _: u64) -> i64 {
+    let header = match usermode::user_slice_mut(msg, MSG_HEADER_SIZE) {
+        Some(header) => header,
+        None => return EFAULT,
+    };
+    let (buf, len, fds, fd_count) = (msg_field(header, 0), msg_field(header, 1), msg_field(header, 2), msg_field(header, 3));
+    let capacity = fd_count.min(socket::MAX_FDS as u64);
+    let (data, numbers) = match (usermode::user_slice_mut(buf, len), usermode::user_slice_mut(fds, capacity * 4)) {
+        (Some(data), Some(numbers)) => (data, numbers),
+        _ => return EFAULT,
+    };
+
+    let mut received = Vec::new();
+    let n = match process::with_fd(fd, |handle| handle.recv_fds(data, &mut received)) {
+        Some(Ok(n)) => n as i64,
+        Some(Err(err)) => return socket_errno(err),
+        None => return EBADF,
+    };
+    let mut count = 0;
+    for mut handle in received.into_iter().take(capacity as usize) {
+        let _ = handle.set_nonblocking(false); //新描述符的状态标志为 0
+        if let Some(new) = process::add_fd(handle) {
+            numbers[count * 4..count * 4 + 4].copy_from_slice(&(new as u32).to_le_bytes());
+            count += 1;
+        }
+    }
+    header[24..32].copy_from_slice(&(count as u64).to_le_bytes());
+    n
+}
+
+pub fn vm_errno(err: VmError) -> i64 {
+    match err {
+        VmError::InvalidArgument => EINVAL,
+        VmError::NoSpace | VmError::Memory(_) => ENOMEM,
+    }
+}
+
+//读取文件的全部内容作为映射的数据，不改变描述符的读写位置
+fn mapped_file(handle: &mut dyn FileHandle) -> Result<Backing, FsError> {
+    let pos = handle.seek(SeekFrom::Current(0))?;
+    handle.seek(SeekFrom::Start(0))?;
+    let data = handle.read_to_end();
+    handle.seek(SeekFrom::Start(pos))?;
+    Ok(Backing::File(data?.into()))
+}
+
+//mmap(len, prot, fd)：建立 len 字节的映射，返回地址；页面在第一次访问时才分配
+//fd 为 -1 时是清零的匿名映射，否则是从文件开头开始的私有映射(写入不会写回文件)
+//prot 是 PROT_READ/PROT_WRITE/PROT_EXEC 的组合
+fn sys_mmap(len: u64, prot: u64, fd: u64) -> i64 {
+    if prot & !(vm::PROT_READ | vm::PROT_WRITE | vm::PROT_EXEC) != 0 {
+        return EINVAL;
+    }
+    let backing = if fd == u64::MAX {
+        Backing::Anonymous
+    } else {
+        match process::with_fd(fd, mapped_file) {
+            Some(Ok(backing)) => backing,
+            Some(Err(FsError::Unsupported)) => return ENODEV, //管道、套接字等不能映射
+            Some(Err(err)) => return errno(err),
+            None => return EBADF,
+        }
+    };
+    match vm::mmap(len, prot, backing) {
+        Ok(addr) => addr as i64,
+        Err(err) => vm_errno(err),
+    }
+}
+
+//munmap(addr, len)：解除映射，addr 必须按页对齐
+pub fn sys_munmap(addr: u64, len: u64, _: u64) -> i64 {
+    match vm::munmap(addr, len) {
+        Ok(()) => 0,
+        Err(err) => vm_errno(err),
+    }
+}
+
+//shm_open(name, len, flags)：打开共享内存段，返回描述符
+//flags 是 O_RDWR/O_CREAT/O_EXCL 的组合，创建时的权限(如 0o600)左移 SHM_MODE_SHIFT 位放在 flags 里
+fn sys_shm_open(name: u64, len: u64, flags: u64) -> i64 {
+    let name = match user_str(name, len) {
+        Some(name) => name,
+        None => return EFAULT,
+    };
+    let mode = ((flags >> SHM_MODE_SHIFT) & 0o777) as u16;
+    match shm::open(name, flags & O_CREAT != 0, flags & O_EXCL != 0, mode, flags & O_RDWR != 0) {
+        Ok(handle) => process::add_fd(Box::new(handle)).map_or(EMFILE, |fd| fd as i64),
+        Err(err) => errno(err),
+    }
+}
+
+//shm_map(fd, len, prot)：把共享内存段的前 len 字节映射到当前进程，返回地址
+//新建的段大小为 0，第一次以可写方式映射时分配 len 字节
+fn sys_shm_map(fd: u64, len: u64, prot: u64) -> i64 {
+    if prot & !(vm::PROT_READ | vm::PROT_WRITE | vm::PROT_EXEC) != 0 {
+        return EINVAL;
+    }
+    let backing = match process::with_fd(fd, |handle| handle.map_shared(len, prot & vm::PROT_WRITE != 0)) {
+        Some(Ok(backing)) => backing,
+        Some(Err(FsError::Unsupported)) => return ENODEV, //不是共享内存段
+        Some(Err(err)) => return errno(err),
+        None => return EBADF,
+    };
+    match vm::mmap(len, prot, backing) {
+        Ok(addr) => addr as i64,
+        Err(err) => vm_errno(err),
+    }
+}
+
+//shm_unlink(name, len)：删除段的名字，只有创建者可以删除
+fn sys_shm_unlink(name: u64, len: u64, _: u64) -> i64 {
+    match user_str(name, len).map(shm::unlink) {
+        Some(Ok(())) => 0,
+        Some(Err(err)) => errno(err),
+        None => EFAULT,
+    }
+}
+
+fn process_errno(err: ProcessError) -> i64 {
+    match err {
+        ProcessError::NoSuchProcess | ProcessError::NotChild => ECHILD,
+        ProcessError::Busy => EAGAIN,
+        ProcessError::Fs(err) => errno(err),
+        ProcessError::Elf(_) => ENOEXEC,
+        ProcessError::User(_) | ProcessError::Memory(_) => ENOMEM,
+    }
+}
+
+//读取用户内存中以 0 结尾的字符串(不含结尾的 0)，超过 max 字节时返回 None
+fn user_cstr(ptr: u64, max: usize) -> Option<&'static [u8]> {
+    let mut len = 0;
+    loop {
+        let start = ptr.checked_add(len as u64)?; //指针是用户给的，可能接近 u64::MAX
+        let chunk_end = (start | 0xfff).checked_add(1)?; //每次检查到页面末尾
+        let chunk = usermode::user_slice(start, chunk_end - start)?;
+        if let Some(nul) = chunk.iter().position(|&b| b == 0) {
+            return usermode::user_slice(ptr, (len + nul) as u64);
+        }
+        len += chunk.len();
+        if len > max {
+            return None;
+        }
+    }
+}
+
+//fork()：复制当前进程，父进程中返回子进程的 PID，子进程中返回 0
+//子进程在父进程调用 wait 时才运行
+fn sys_fork(_: u64, _: u64, _: u64) -> i64 {
+    match process::fork() {
+        Ok(pid) => pid as i64,
+        Err(err) => process_errno(err),
+    }
+}
+
+//exec(path, len, argv)：用 path 处的程序替换当前程序，成功时不返回
+//argv 指向以空指针结束的字符串指针数组(字符串以 0 结尾)，为 0 时新程序只有一个参数 path
+fn sys_exec(path: u64, len: u64, argv: u64) -> i64 {
+    let path = match user_str(path, len) {
+        Some(path) => String::from(path), //切换地址空间后原来的用户内存就不能访问了
+        None => return EFAULT,
+    };
+    let mut args: Vec<Vec<u8>> = Vec::new();
+    if argv == 0 {
+        args.push(path.as_bytes().to_vec());
+    }
+    let mut total = 0;
+    for i in (0..).take_while(|_| argv != 0) {
+        let pointer = match argv.checked_add(i * 8).and_then(|entry| usermode::user_slice(entry, 8)) {
+            Some(bytes) => u64::from_le_bytes(bytes.try_into().unwrap()),
+            None => return EFAULT,
+        };
+        if pointer == 0 {
+            break;
+        }
+        if args.len() == MAX_ARGS {
+            return E2BIG;
+        }
+        let arg = match user_cstr(pointer, ARG_MAX) {
+            Some(arg) => arg,
+            None => return EFAULT,
+        };
+        total += arg.len() + 1;
+        if total > ARG_MAX {
+            return E2BIG;
+        }
+        args.push(arg.to_vec());
+    }
+    let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_slice()).collect();
+    match process::exec(&path, &args) {
+        Ok(context) => {
+            *usermode::syscall_context() = context; //系统调用返回时进入新程序
+            0
+        }
+        Err(err) => process_errno(err),
+    }
+}
+
+//wait(pid, status)：运行子进程直到它退出并回收它，退出码写入 status 指向的 i64(为 0 时不写)
+fn sys_wait(pid: u64, status: u64, _: u64) -> i64 {
+    let out = match status {
+        0 => None,
+        status => match usermode::user_slice_mut(status, 8) {
+            Some(out) => Some(out),
+            None => return EFAULT,
+        },
+    };
+    match process::wait(pid) {
+        Ok(code) => {
+            if let Some(out) = out {
+                out.copy_from_slice(&code.to_le_bytes());
+            }
+            0
+        }
+        Err(err) => process_errno(err),
+    }
+}
+
+fn signal_errno(err: SignalError) -> i64 {
+    match err {
+        SignalError::Invalid => EINVAL,
+        SignalError::Process(ProcessError::NoSuchProcess) => ESRCH,
+        SignalError::Process(err) => process_errno(err),
+    }
+}
+
+//kill(pid, sig)：给进程发送信号，sig 为 0 时只检查进程是否存在
+fn sys_kill(pid: u64, sig: u64, _: u64) -> i64 {
+    if sig > u32::MAX as u64 {
+        return EINVAL;
+    }
+    match signal::send(pid, sig as u32) {
+        Ok(()) => 0,
+        Err(err) => signal_errno(err),
+    }
+}
+
+//sigaction(sig, handler, restorer)：handler 为 SIG_DFL、SIG_IGN 或者处理函数的地址
+//处理函数以 handler(sig) 的方式调用，返回到 restorer，restorer 必须直接调用 sigreturn
+fn sys_sigaction(sig: u64, handler: u64, restorer: u64) -> i64 {
+    if sig > u32::MAX as u64 {
+        return EINVAL;
+    }
+    let action = match handler {
+        signal::SIG_DFL => Action::Default,
+        signal::SIG_IGN => Action::Ignore,
+        entry if usermode::user_slice(entry, 1).is_some() && usermode::user_slice(restorer, 1).is_some() => {
+            Action::Handler { entry, restorer }
+        }
+        _ => return EFAULT,
+    };
+    match signal::set_action(sig as u32, action) {
+        Ok(()) => 0,
+        Err(err) => signal_errno(err),
+    }
+}
+
+//sigreturn()：从信号处理函数返回，恢复被打断时的寄存器；用户栈上的信号帧不合法时结束进程
+fn sys_sigreturn(_: u64, _: u64, _: u64) -> i64 {
+    match signal::restore() {
+        Ok(rax) => rax,
+        Err(_) => usermode::exit(signal::exit_code(signal::SIGSEGV)),
+    }
+}
+
+//exit(code)：结束用户程序，回到 process::wait 中运行它的地方
+fn sys_exit(code: u64, _: u64, _: u64) -> i64 {
+    usermode::exit(code as i64)
+}
+
+//sleep(ms)：还没有调度器，在系统调用中忙等到期限
+fn sys_sleep(ms: u64, _: u64, _: u64) -> i64 {
+    time::sleep(Duration::from_millis(ms));
+    0
+}
+
+fn sys_getpid(_: u64, _: u64, _: u64) -> i64 {
+    process::current_pid() as i64
+}
+
+fn sys_getuid(_: u64, _: u64, _: u64) -> i64 {
+    cred::current().uid as i64
+}
+
+fn sys_getgid(_: u64, _: u64, _: u64) -> i64 {
+    cred::current().gid as i64
+}
+
+//setuid(uid, gid)：同时设置用户和组，只有 root 可以，之后不能再变回 root
+fn sys_setuid(uid: u64, gid: u64, _: u64) -> i64 {
+    if uid > u32::MAX as u64 || gid > u32::MAX as u64 {
+        return EINVAL;
+    }
+    match process::set_credentials(Credentials::new(uid as u32, gid as u32)) {
+        Ok(()) => 0,
+        Err(ProcessError::Fs(FsError::PermissionDenied)) => EPERM,
+        Err(err) => process_errno(err),
+    }
+}
@@ -0,0 +1,171 @@
+//内核线程和 CPU 隔离：线程绑定在某个 CPU 上，isolcpus= 给出的 CPU 不参与一般的负载分配
+//目前只启动了引导处理器(CPU 0)，也没有内核栈切换和抢占：线程是一个反复调用的函数，
+//由所在 CPU 的空闲循环(shell 等待按键时)轮流调用，返回 false 时结束
+//spawn_on 把线程放到指定的 CPU 上，即使它被隔离；spawn 在非隔离(housekeeping)的 CPU 中选线程最少的一个
+//isolcpus=<列表>，例如 isolcpus=1,3-5；不允许隔离全部在线的 CPU，否则一般的线程无处可放
+use crate::cmdline;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+pub const MAX_CPUS: usize = 64; //CPU 集合用一个 u64 表示
+
+const ONLINE: u64 = 1; //只有引导处理器
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadError {
+    NoSuchCpu,      //CPU 编号超出范围或者没有启动
+    NoHousekeeping, //所有在线的 CPU 都被隔离了
+}
+
+struct Thread {
+    id: u64,
+    name: &'static str,
+    cpu: usize,
+    runs: u64,
+    body: Box<dyn FnMut() -> bool + Send>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ThreadInfo {
+    pub id: u64,
+    pub name: &'static str,
+    pub cpu: usize,
+    pub runs: u64,
+}
+
+static THREADS: Mutex<Vec<Thread>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static ISOLATED: AtomicU64 = AtomicU64::new(0);
+
+pub fn online() -> u64 {
+    ONLINE
+}
+
+pub fn isolated() -> u64 {
+    ISOLATED.load(Ordering::Relaxed)
+}
+
+//一般的线程可以放置的 CPU
+pub fn housekeeping() -> u64 {
+    online() & !isolated()
+}
+
+//正在运行的 CPU：没有启动其他处理器，总是 0
+pub fn current_cpu() -> usize {
+    0
+}
+
+//"1,3-5" 这样的 CPU 列表
+pub fn parse_cpus(text: &str) -> Option<u64> {
+    let mut mask = 0u64;
+    for part in text.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first.parse::<usize>().ok()?, last.parse::<usize>().ok()?),
+            None => {
+                let cpu = part.parse::<usize>().ok()?;
+                (cpu, cpu)
+            }
+        };
+        if first > last || last >= MAX_CPUS {
+            return None;
+        }
+        for cpu in first..=last {
+            mask |= 1 << cpu;
+        }
+    }
+    Some(mask)
+}
+
+//把 CPU 集合写成 "0,2-3"，空集合写成 "-"
+pub fn format_cpus(mask: u64) -> String {
+    let mut parts = Vec::new();
+    let mut cpu = 0;
+    while cpu < MAX_CPUS {
+        if mask & (1 << cpu) == 0 {
+            cpu += 1;
+            continue;
+        }
+        let first = cpu;
+        while cpu + 1 < MAX_CPUS && mask & (1 << (cpu + 1)) != 0 {
+            cpu += 1;
+        }
+        parts.push(if first == cpu { format!("{}", first) } else { format!("{}-{}", first, cpu) });
+        cpu += 1;
+    }
+    if parts.is_empty() {
+        return String::from("-");
+    }
+    parts.join(",")
+}
+
+//启动时调用：isolcpus=<列表>；不在线的 CPU 忽略，参数无效或者会隔离全部 CPU 时返回原来的文字
+pub fn init() -> Result<(), &'static str> {
+    let text = match cmdline::get("isolcpus") {
+        Some(text) => text,
+        None => return Ok(()),
+    };
+    let mask = parse_cpus(text).ok_or(text)?;
+    if online() & !mask == 0 {
+        return Err(text);
+    }
+    if mask & !online() != 0 {
+        crate::log!(Warn, "isolcpus: cpus {} are not online", format_cpus(mask & !online()));
+    }
+    ISOLATED.store(mask & online(), Ordering::Relaxed);
+    Ok(())
+}
+
+//在 cpu 上创建线程，返回线程号；body 每次被调用时做一小段工作，返回 false 表示线程结束
+pub fn spawn_on(cpu: usize, name: &'static str, body: impl FnMut() -> bool + Send + 'static) -> Result<u64, ThreadError> {
+    if cpu >= MAX_CPUS || online() & (1 << cpu) == 0 {
+        return Err(ThreadError::NoSuchCpu);
+    }
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    THREADS.lock().push(Thread { id, name, cpu, runs: 0, body: Box::new(body) });
+    crate::log!(Debug, "thread {} ({}) started on cpu {}", id, name, cpu);
+    Ok(id)
+}
+
+//在 housekeeping 的 CPU 中选线程最少的一个创建线程
+pub fn spawn(name: &'static str, body: impl FnMut() -> bool + Send + 'static) -> Result<u64, ThreadError> {
+    let mask = housekeeping();
+    let threads = THREADS.lock();
+    let cpu = (0..MAX_CPUS)
+        .filter(|cpu| mask & (1 << cpu) != 0)
+        .min_by_key(|cpu| threads.iter().filter(|thread| thread.cpu == *cpu).count())
+        .ok_or(ThreadError::NoHousekeeping)?;
+    drop(threads);
+    spawn_on(cpu, name, body)
+}
+
+//空闲循环里调用：把当前 CPU 上的线程各运行一次
+//运行时不持有锁，线程里可以创建新线程；嵌套调用时线程已经被取出，不会重复运行
+pub fn poll() {
+    let cpu = current_cpu();
+    let all = core::mem::take(&mut *THREADS.lock());
+    let (mut mine, others): (Vec<Thread>, Vec<Thread>) = all.into_iter().partition(|thread| thread.cpu == cpu);
+    *THREADS.lock() = others;
+    mine.retain_mut(|thread| {
+        thread.runs += 1;
+        let alive = (thread.body)();
+        if !alive {
+            crate::log!(Debug, "thread {} ({}) exited", thread.id, thread.name);
+        }
+        alive
+    });
+    THREADS.lock().append(&mut mine);
+}
+
+pub fn list() -> Vec<ThreadInfo> {
+    let mut list: Vec<ThreadInfo> = THREADS
+        .lock()
+        .iter()
+        .map(|thread| ThreadInfo { id: thread.id, name: thread.name, cpu: thread.cpu, runs: thread.runs })
+        .collect();
+    list.sort_by_key(|info| info.id);
+    list
+}
@@ -0,0 +1,133 @@
+//高精度计时：启动时用 PIT 通道 2 校准 TSC 的频率，之后的时间都由 TSC 换算
+//Instant 是某一时刻的 TSC 计数，两个 Instant 相减得到 core::time::Duration
+//还没有 ACPI 表的解析，不能用 HPET 校准；TSC 不是恒定频率(invariant)时变频会让时间不准
+use core::arch::x86_64::{__cpuid, _rdtsc};
+use core::ops::{Add, Sub};
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use x86_64::instructions::port::Port;
+
+const PIT_HZ: u64 = 1_193_182; //PIT 的输入时钟频率
+const CALIBRATE_TICKS: u16 = 59_659; //校准时让 PIT 计数约 50 毫秒
+const FALLBACK_HZ: u64 = 2_000_000_000; //校准之前(或失败时)假定的频率
+
+static TSC_HZ: AtomicU64 = AtomicU64::new(FALLBACK_HZ);
+static BOOT_TSC: AtomicU64 = AtomicU64::new(0);
+
+//启动时调用一次：记录启动时刻并校准 TSC，返回测得的频率(Hz)
+pub fn init() -> u64 {
+    BOOT_TSC.store(rdtsc(), Ordering::Relaxed);
+    let hz = calibrate().unwrap_or(FALLBACK_HZ);
+    TSC_HZ.store(hz, Ordering::Relaxed);
+    hz
+}
+
+fn rdtsc() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+//CPUID 0x80000007 的 EDX 第 8 位表示 TSC 以恒定频率计数，不受变频和 C 状态影响
+pub fn invariant_tsc() -> bool {
+    let max = __cpuid(0x8000_0000).eax;
+    max >= 0x8000_0007 && __cpuid(0x8000_0007).edx & (1 << 8) != 0
+}
+
+pub fn tsc_hz() -> u64 {
+    TSC_HZ.load(Ordering::Relaxed)
+}
+
+//PIT 通道 2 以模式 0 从 CALIBRATE_TICKS 倒数，数到 0 时 0x61 端口的第 5 位变成 1
+//通道 2 的门控由 0x61 的第 0 位控制，第 1 位是扬声器，校准时关掉
+fn calibrate() -> Option<u64> {
+    let mut control = Port::<u8>::new(0x61);
+    let mut command = Port::<u8>::new(0x43);
+    let mut channel2 = Port::<u8>::new(0x42);
+    unsafe {
+        let value = control.read();
+        control.write((value & !0x02) | 0x01);
+        command.write(0b1011_0000); //通道 2，先低后高字节，模式 0，二进制计数
+        channel2.write(CALIBRATE_TICKS as u8);
+        channel2.write((CALIBRATE_TICKS >> 8) as u8);
+        let start = rdtsc();
+        let mut spins = 0u64;
+        while control.read() & 0x20 == 0 {
+            spins += 1;
+            if spins > 100_000_000 {
+                return None; //没有 PIT(或者被模拟得不完整)
+            }
+        }
+        let elapsed = rdtsc() - start;
+        control.write(value);
+        Some(elapsed * PIT_HZ / CALIBRATE_TICKS as u64).filter(|&hz| hz > 0)
+    }
+}
+
+//把 TSC 计数换算成时间
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    let nanos = ticks as u128 * 1_000_000_000 / tsc_hz() as u128;
+    Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32)
+}
+
+pub fn duration_to_ticks(duration: Duration) -> u64 {
+    (duration.as_nanos() * tsc_hz() as u128 / 1_000_000_000).min(u64::MAX as u128) as u64
+}
+
+//TSC 计数为 tsc 的时刻距离启动过了多久
+pub fn since_boot(tsc: u64) -> Duration {
+    ticks_to_duration(tsc.saturating_sub(BOOT_TSC.load(Ordering::Relaxed)))
+}
+
+pub fn uptime() -> Duration {
+    since_boot(rdtsc())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant(rdtsc())
+    }
+
+    //距离 earlier 过了多久，earlier 更晚时为 0
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        ticks_to_duration(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant(self.0.saturating_add(duration_to_ticks(duration)))
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        Instant(self.0.saturating_sub(duration_to_ticks(duration)))
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+//忙等到 duration 之后；没有抢占，没有别的事情可做
+pub fn sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        crate::watchdog::touch();
+        core::hint::spin_loop();
+    }
+}
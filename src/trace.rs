@@ -0,0 +1,174 @@
+//内核事件跟踪：每个 CPU 一个固定大小的环形缓冲区，记录 (时间戳, 子系统, 两个参数)，写满后覆盖最早的事件
+//trace! 只做一次原子加法和几次原子写，不加锁、不分配内存，可以在中断处理函数里使用
+//每个槽位带一个序号：写入前清零，写完后写入序号，读的时候前后两次序号相同才算完整的事件(读和写同时发生时丢弃这个槽位)
+//shell 的 tracedump 按时间顺序打印，也可以导出到串口给主机上的脚本分析
+use crate::time;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+const CPU_COUNT: usize = 1; //还不支持多处理器
+const EVENTS: usize = 4096; //每个 CPU 保留的事件数，必须是 2 的幂
+
+//记录 subsys 子系统的一个事件，a、b 的含义由子系统决定(见 Subsystem)
+#[macro_export]
+macro_rules! trace {
+    ($subsys:ident, $a:expr, $b:expr) => {
+        $crate::trace::record($crate::trace::Subsystem::$subsys, $a as u64, $b as u64)
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Subsystem {
+    Irq,     //a = 向量号
+    Fault,   //a = 出错地址，b = 错误码
+    Syscall, //a = 系统调用号，b = 返回值
+    Sched,   //a = 开始运行的 PID，b = 上一个 PID
+    Exit,    //a = 退出的 PID，b = 退出码
+    Block,   //a = 起始块号，b = 字节数(写入时最高位为 1)
+    Net,     //a = 收到的帧长度
+}
+
+const SUBSYSTEMS: [Subsystem; 7] =
+    [Subsystem::Irq, Subsystem::Fault, Subsystem::Syscall, Subsystem::Sched, Subsystem::Exit, Subsystem::Block, Subsystem::Net];
+
+impl Subsystem {
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsystem::Irq => "irq",
+            Subsystem::Fault => "fault",
+            Subsystem::Syscall => "syscall",
+            Subsystem::Sched => "sched",
+            Subsystem::Exit => "exit",
+            Subsystem::Block => "block",
+            Subsystem::Net => "net",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    pub cpu: usize,
+    pub timestamp: u64, //TSC 计数
+    pub subsys: Subsystem,
+    pub a: u64,
+    pub b: u64,
+}
+
+struct Slot {
+    sequence: AtomicU64, //写入序号 + 1，0 表示空或者正在写
+    timestamp: AtomicU64,
+    subsys: AtomicU64,
+    a: AtomicU64,
+    b: AtomicU64,
+}
+
+struct Ring {
+    head: AtomicU64, //下一个写入序号
+    slots: [Slot; EVENTS],
+}
+
+impl Slot {
+    const fn new() -> Slot {
+        Slot {
+            sequence: AtomicU64::new(0),
+            timestamp: AtomicU64::new(0),
+            subsys: AtomicU64::new(0),
+            a: AtomicU64::new(0),
+            b: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Ring {
+    const fn new() -> Ring {
+        Ring { head: AtomicU64::new(0), slots: [const { Slot::new() }; EVENTS] }
+    }
+}
+
+static RINGS: [Ring; CPU_COUNT] = [const { Ring::new() }; CPU_COUNT];
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+fn cpu() -> usize {
+    0
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[doc(hidden)]
+pub fn record(subsys: Subsystem, a: u64, b: u64) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let ring = &RINGS[cpu()];
+    let sequence = ring.head.fetch_add(1, Ordering::Relaxed);
+    let slot = &ring.slots[sequence as usize & (EVENTS - 1)];
+    slot.sequence.store(0, Ordering::Release);
+    slot.timestamp.store(unsafe { core::arch::x86_64::_rdtsc() }, Ordering::Relaxed);
+    slot.subsys.store(subsys as u64, Ordering::Relaxed);
+    slot.a.store(a, Ordering::Relaxed);
+    slot.b.store(b, Ordering::Relaxed);
+    slot.sequence.store(sequence + 1, Ordering::Release);
+}
+
+//所有 CPU 上还保留着的事件，按时间顺序排列
+pub fn events() -> Vec<Event> {
+    let mut events = Vec::new();
+    for (cpu, ring) in RINGS.iter().enumerate() {
+        for slot in ring.slots.iter() {
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            if sequence == 0 {
+                continue;
+            }
+            let event = Event {
+                cpu,
+                timestamp: slot.timestamp.load(Ordering::Relaxed),
+                subsys: SUBSYSTEMS[slot.subsys.load(Ordering::Relaxed) as usize % SUBSYSTEMS.len()],
+                a: slot.a.load(Ordering::Relaxed),
+                b: slot.b.load(Ordering::Relaxed),
+            };
+            if slot.sequence.load(Ordering::Acquire) == sequence {
+                events.push(event);
+            }
+        }
+    }
+    events.sort_unstable_by_key(|event| event.timestamp);
+    events
+}
+
+//被覆盖掉的事件数
+pub fn dropped() -> u64 {
+    RINGS.iter().map(|ring| ring.head.load(Ordering::Relaxed).saturating_sub(EVENTS as u64)).sum()
+}
+
+pub fn clear() {
+    for ring in RINGS.iter() {
+        ring.slots.iter().for_each(|slot| slot.sequence.store(0, Ordering::Release));
+        ring.head.store(0, Ordering::Relaxed);
+    }
+}
+
+//一行一个事件："[秒.微秒] cpu 子系统 a b"，a、b 用十六进制
+pub fn write_events(out: &mut dyn Write, events: &[Event]) -> fmt::Result {
+    for event in events {
+        let time = time::since_boot(event.timestamp);
+        writeln!(
+            out,
+            "[{:>5}.{:06}] {} {:<7} {:#x} {:#x}",
+            time.as_secs(),
+            time.subsec_micros(),
+            event.cpu,
+            event.subsys.name(),
+            event.a,
+            event.b
+        )?;
+    }
+    Ok(())
+}
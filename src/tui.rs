@@ -0,0 +1,379 @@
+//文本界面的小部件：矩形区域、边框、表格、进度条和输入对话框，用 CP437 的制表符画线
+//所有输出都经过 vga_buffer::put_char 直接写到屏幕的指定位置，不移动光标，也不记入滚动缓冲区
+//画图时按 Canvas 的裁剪区域裁剪，区域外(包括屏幕外)的字符直接丢弃；全屏界面用 Canvas::content() 给最后一行的状态栏留出位置
+use crate::line_editor::{KeySource, LineEditor, LineOutput};
+use crate::vga_buffer::{self, ColorCode, BUFFER_HEIGHT, BUFFER_WIDTH};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+//屏幕上的矩形区域，以字符为单位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub row: usize,
+    pub col: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const fn new(row: usize, col: usize, width: usize, height: usize) -> Rect {
+        Rect { row, col, width, height }
+    }
+
+    //整个屏幕
+    pub const fn screen() -> Rect {
+        Rect::new(0, 0, BUFFER_WIDTH, BUFFER_HEIGHT)
+    }
+
+    //最后一行：状态栏
+    pub const fn status_bar() -> Rect {
+        Rect::new(BUFFER_HEIGHT - 1, 0, BUFFER_WIDTH, 1)
+    }
+
+    pub fn bottom(&self) -> usize {
+        self.row + self.height
+    }
+
+    pub fn right(&self) -> usize {
+        self.col + self.width
+    }
+
+    pub fn contains(&self, row: usize, col: usize) -> bool {
+        row >= self.row && row < self.bottom() && col >= self.col && col < self.right()
+    }
+
+    //两个区域的交集，不相交时为空区域
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let row = self.row.max(other.row);
+        let col = self.col.max(other.col);
+        let bottom = self.bottom().min(other.bottom());
+        let right = self.right().min(other.right());
+        Rect::new(row, col, right.saturating_sub(col), bottom.saturating_sub(row))
+    }
+
+    //四边各向内收缩 n 格(画过边框之后的内部区域)
+    pub fn inset(&self, n: usize) -> Rect {
+        Rect::new(self.row + n, self.col + n, self.width.saturating_sub(2 * n), self.height.saturating_sub(2 * n))
+    }
+
+    //从顶部切下 rows 行，返回(切下的部分, 剩下的部分)
+    pub fn split_top(&self, rows: usize) -> (Rect, Rect) {
+        let rows = rows.min(self.height);
+        (Rect::new(self.row, self.col, self.width, rows), Rect::new(self.row + rows, self.col, self.width, self.height - rows))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoxStyle {
+    Single,
+    Double,
+}
+
+//一种线型的各个制表符(CP437)
+struct Lines {
+    horizontal: u8,
+    vertical: u8,
+    top_left: u8,
+    top_right: u8,
+    bottom_left: u8,
+    bottom_right: u8,
+    tee_down: u8,  //┬
+    tee_up: u8,    //┴
+    tee_right: u8, //├
+    tee_left: u8,  //┤
+    cross: u8,     //┼
+}
+
+const SINGLE: Lines = Lines {
+    horizontal: 0xC4,
+    vertical: 0xB3,
+    top_left: 0xDA,
+    top_right: 0xBF,
+    bottom_left: 0xC0,
+    bottom_right: 0xD9,
+    tee_down: 0xC2,
+    tee_up: 0xC1,
+    tee_right: 0xC3,
+    tee_left: 0xB4,
+    cross: 0xC5,
+};
+
+const DOUBLE: Lines = Lines {
+    horizontal: 0xCD,
+    vertical: 0xBA,
+    top_left: 0xC9,
+    top_right: 0xBB,
+    bottom_left: 0xC8,
+    bottom_right: 0xBC,
+    tee_down: 0xCB,
+    tee_up: 0xCA,
+    tee_right: 0xCC,
+    tee_left: 0xB9,
+    cross: 0xCE,
+};
+
+impl BoxStyle {
+    fn lines(self) -> &'static Lines {
+        match self {
+            BoxStyle::Single => &SINGLE,
+            BoxStyle::Double => &DOUBLE,
+        }
+    }
+}
+
+const FULL_BLOCK: u8 = 0xDB; //█
+const LIGHT_SHADE: u8 = 0xB0; //░
+
+//字符在屏幕上显示的字节：ASCII 原样显示，其他字符显示为 ■(与 Writer 一致)
+fn cp437(c: char) -> u8 {
+    match c {
+        ' '..='~' => c as u8,
+        _ => 0xFE,
+    }
+}
+
+//带裁剪区域的画布
+#[derive(Debug, Clone, Copy)]
+pub struct Canvas {
+    clip: Rect,
+}
+
+impl Canvas {
+    //clip 超出屏幕的部分不会被画出
+    pub fn new(clip: Rect) -> Canvas {
+        Canvas { clip: clip.intersect(&Rect::screen()) }
+    }
+
+    //整个屏幕，包括状态栏
+    pub fn screen() -> Canvas {
+        Canvas::new(Rect::screen())
+    }
+
+    //状态栏以上的部分
+    pub fn content() -> Canvas {
+        Canvas::new(Rect::new(0, 0, BUFFER_WIDTH, BUFFER_HEIGHT - 1))
+    }
+
+    //只能在 rect 以内画的子画布
+    pub fn sub(&self, rect: Rect) -> Canvas {
+        Canvas { clip: self.clip.intersect(&rect) }
+    }
+
+    pub fn put(&self, row: usize, col: usize, byte: u8, color: ColorCode) {
+        if self.clip.contains(row, col) {
+            vga_buffer::put_char(row, col, byte, color.foreground(), color.background());
+        }
+    }
+
+    pub fn fill(&self, rect: Rect, byte: u8, color: ColorCode) {
+        let rect = rect.intersect(&self.clip);
+        for row in rect.row..rect.bottom() {
+            for col in rect.col..rect.right() {
+                vga_buffer::put_char(row, col, byte, color.foreground(), color.background());
+            }
+        }
+    }
+
+    //从 (row, col) 开始写一行文字，最多 width 列，返回写了多少列
+    pub fn text(&self, row: usize, col: usize, text: &str, width: usize, color: ColorCode) -> usize {
+        let mut written = 0;
+        for c in text.chars().take(width) {
+            self.put(row, col + written, cp437(c), color);
+            written += 1;
+        }
+        written
+    }
+
+    pub fn hline(&self, row: usize, col: usize, len: usize, style: BoxStyle, color: ColorCode) {
+        (col..col + len).for_each(|c| self.put(row, c, style.lines().horizontal, color));
+    }
+
+    pub fn vline(&self, row: usize, col: usize, len: usize, style: BoxStyle, color: ColorCode) {
+        (row..row + len).for_each(|r| self.put(r, col, style.lines().vertical, color));
+    }
+
+    //画出 rect 的边框，title 显示在上边框的左侧；rect 小于 2x2 时什么也不画
+    pub fn draw_box(&self, rect: Rect, style: BoxStyle, color: ColorCode, title: Option<&str>) {
+        if rect.width < 2 || rect.height < 2 {
+            return;
+        }
+        let lines = style.lines();
+        let (bottom, right) = (rect.bottom() - 1, rect.right() - 1);
+        self.hline(rect.row, rect.col + 1, rect.width - 2, style, color);
+        self.hline(bottom, rect.col + 1, rect.width - 2, style, color);
+        self.vline(rect.row + 1, rect.col, rect.height - 2, style, color);
+        self.vline(rect.row + 1, right, rect.height - 2, style, color);
+        self.put(rect.row, rect.col, lines.top_left, color);
+        self.put(rect.row, right, lines.top_right, color);
+        self.put(bottom, rect.col, lines.bottom_left, color);
+        self.put(bottom, right, lines.bottom_right, color);
+        if let Some(title) = title {
+            if rect.width > 4 {
+                self.put(rect.row, rect.col + 1, b' ', color);
+                let n = self.text(rect.row, rect.col + 2, title, rect.width - 4, color);
+                self.put(rect.row, rect.col + 2 + n, b' ', color);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+//表格：一行表头加若干数据行，列宽按内容自动计算，放不下时截断最右边的列
+pub struct Table {
+    headers: Vec<(String, Align)>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[(&str, Align)]) -> Table {
+        Table { headers: headers.iter().map(|&(h, a)| (String::from(h), a)).collect(), rows: Vec::new() }
+    }
+
+    //列数不足的行用空白补齐，多出的列被忽略
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    fn cell(row: &[String], column: usize) -> &str {
+        row.get(column).map_or("", |s| s.as_str())
+    }
+
+    //每列的宽度：表头和所有单元格中最长的一个
+    fn widths(&self) -> Vec<usize> {
+        (0..self.headers.len())
+            .map(|c| {
+                let cells = self.rows.iter().map(|row| Table::cell(row, c).chars().count());
+                cells.chain(core::iter::once(self.headers[c].0.chars().count())).max().unwrap_or(0)
+            })
+            .collect()
+    }
+
+    //在 rect 内画出带边框的表格，数据行放不下时只画前面的行；返回画出的数据行数
+    pub fn draw(&self, canvas: &Canvas, rect: Rect, style: BoxStyle, color: ColorCode, header_color: ColorCode) -> usize {
+        if rect.width < 2 || rect.height < 4 || self.headers.is_empty() {
+            return 0;
+        }
+        let canvas = canvas.sub(rect);
+        let lines = style.lines();
+        let visible = self.rows.len().min(rect.height - 4);
+        let table = Rect::new(rect.row, rect.col, rect.width, visible + 4);
+        canvas.fill(table.inset(1), b' ', color);
+        canvas.draw_box(table, style, color, None);
+
+        //列之间的竖线位置，超出右边框的列被截断
+        let widths = self.widths();
+        let mut separators = Vec::new();
+        let mut col = rect.col + 1;
+        for &width in &widths {
+            separators.push(col + width);
+            col += width + 1;
+        }
+        separators.pop(); //最后一列的右边就是边框
+        let separator_row = rect.row + 2;
+        canvas.put(separator_row, rect.col, lines.tee_right, color);
+        canvas.hline(separator_row, rect.col + 1, rect.width - 2, style, color);
+        canvas.put(separator_row, table.right() - 1, lines.tee_left, color);
+        for &col in separators.iter().filter(|&&col| col < table.right() - 1) {
+            canvas.put(rect.row, col, lines.tee_down, color);
+            canvas.vline(rect.row + 1, col, visible + 2, style, color);
+            canvas.put(separator_row, col, lines.cross, color);
+            canvas.put(table.bottom() - 1, col, lines.tee_up, color);
+        }
+
+        let headers: Vec<&str> = self.headers.iter().map(|(h, _)| h.as_str()).collect();
+        self.draw_cells(&canvas, table, rect.row + 1, &headers, &widths, header_color);
+        for (i, row) in self.rows.iter().take(visible).enumerate() {
+            let cells: Vec<&str> = (0..self.headers.len()).map(|c| Table::cell(row, c)).collect();
+            self.draw_cells(&canvas, table, rect.row + 3 + i, &cells, &widths, color);
+        }
+        visible
+    }
+
+    //在表格的第 row 行依次写入各列的内容，按列的对齐方式补空格
+    fn draw_cells(&self, canvas: &Canvas, table: Rect, row: usize, cells: &[&str], widths: &[usize], color: ColorCode) {
+        let mut col = table.col + 1;
+        for ((text, &(_, align)), &width) in cells.iter().zip(self.headers.iter()).zip(widths) {
+            let width = width.min((table.right() - 1).saturating_sub(col));
+            let pad = width.saturating_sub(text.chars().count());
+            let start = if align == Align::Right { col + pad } else { col };
+            canvas.text(row, start, text, width - (start - col), color);
+            col += width + 1;
+        }
+    }
+}
+
+//一行高的进度条："label ████░░░░ 42%"，完成的部分用实心方块，未完成的部分用浅色阴影
+pub struct ProgressBar {
+    rect: Rect,
+    label: String,
+    total: u64,
+}
+
+impl ProgressBar {
+    //只使用 rect 的第一行
+    pub fn new(rect: Rect, label: &str, total: u64) -> ProgressBar {
+        ProgressBar { rect, label: String::from(label), total }
+    }
+
+    pub fn draw(&self, canvas: &Canvas, done: u64, color: ColorCode) {
+        let Rect { row, col, width, .. } = self.rect;
+        let label = self.label.chars().count().min(width);
+        let percent = (done.min(self.total) * 100).checked_div(self.total).unwrap_or(100);
+        let bar = width.saturating_sub(label + 6); //标签后的空格，百分比前的空格和 "100%"
+        let filled = (bar as u64 * percent / 100) as usize;
+        canvas.text(row, col, &self.label, label, color);
+        let start = col + label + 1;
+        for i in 0..bar {
+            canvas.put(row, start + i, if i < filled { FULL_BLOCK } else { LIGHT_SHADE }, color);
+        }
+        let text = alloc::format!(" {:>3}%", percent);
+        canvas.text(row, start + bar, &text, width.saturating_sub(label + 1 + bar), color);
+    }
+}
+
+//输入对话框：在 rect 中画出带标题的边框，第一行是提示文字，后面是编辑区，用行编辑器读取一行
+//编辑区只有一行，超出宽度的部分不显示；回车后返回输入的内容，对话框留在屏幕上由调用者恢复
+pub fn input_dialog(canvas: &Canvas, rect: Rect, title: &str, prompt: &str, editor: &mut LineEditor, input: &mut dyn KeySource, color: ColorCode) -> String {
+    canvas.fill(rect, b' ', color);
+    canvas.draw_box(rect, BoxStyle::Single, color, Some(title));
+    let inner = rect.inset(1);
+    let n = canvas.text(inner.row, inner.col, prompt, inner.width, color);
+    let field = Rect::new(inner.row, inner.col + n + 1, inner.width.saturating_sub(n + 1), 1);
+    let mut out = FieldOutput { canvas: canvas.sub(field), field, cursor: 0, color };
+    editor.read_line(input, &mut out)
+}
+
+//对话框的编辑区：行编辑器的输出画在一行之内，光标只是记下的列号
+struct FieldOutput {
+    canvas: Canvas,
+    field: Rect,
+    cursor: usize,
+    color: ColorCode,
+}
+
+impl LineOutput for FieldOutput {
+    fn write_str(&mut self, s: &str) {
+        for c in s.chars().filter(|&c| c != '\n') {
+            self.canvas.put(self.field.row, self.field.col + self.cursor, cp437(c), self.color);
+            self.cursor += 1;
+        }
+    }
+
+    fn cursor_left(&mut self, n: usize) {
+        self.cursor = self.cursor.saturating_sub(n);
+    }
+
+    fn cursor_right(&mut self, n: usize) {
+        self.cursor += n;
+    }
+
+    fn clear_to_end(&mut self) {
+        let rest = Rect::new(self.field.row, self.field.col + self.cursor, self.field.width.saturating_sub(self.cursor), 1);
+        self.canvas.fill(rest, b' ', self.color);
+    }
+}
@@ -0,0 +1,210 @@
+//在 ring 3 运行用户程序：把程序映射到用户地址空间，通过 sysretq 跳转过去，程序调用 exit 时回到内核
+use crate::memory::{self, MemoryError};
+use crate::vm;
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+
+pub const USER_BASE: u64 = 0x4000_0000_0000;          //用户程序的加载地址(位于独立的 4 级页表项)
+pub const USER_STACK_TOP: u64 = USER_BASE + 0x100_0000; //用户栈栈顶
+const USER_STACK_SIZE: u64 = 4096 * 4;
+pub const USER_STACK_BOTTOM: u64 = USER_STACK_TOP - USER_STACK_SIZE; //程序映像不能超过这里
+const USER_IMAGE_MAX: usize = (USER_STACK_BOTTOM - USER_BASE) as usize; //平坦二进制的最大长度
+pub const MMAP_BASE: u64 = USER_STACK_TOP + 0x1000; //mmap 使用的区域，与用户栈之间隔一个不映射的页面
+pub const MMAP_END: u64 = MMAP_BASE + 0x1000_0000; //同时也是用户地址空间的上界
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserError {
+    TooLarge,             //程序超出用户地址空间
+    Memory(MemoryError),  //映射用户页面失败
+}
+
+impl From<MemoryError> for UserError {
+    fn from(err: MemoryError) -> UserError {
+        UserError::Memory(err)
+    }
+}
+
+//用户程序的寄存器，字段顺序与 syscall_entry 在内核栈上保存的顺序一致(从低地址到高地址)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UserContext {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub r10: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rflags: u64, //sysretq 从 r11 取
+    pub rip: u64,    //sysretq 从 rcx 取
+    pub rsp: u64,
+}
+
+impl UserContext {
+    //从 entry 开始执行的新程序，除栈指针外的寄存器都清零，避免把内核数据泄露给用户程序
+    pub fn new(entry: u64, stack_top: u64) -> UserContext {
+        UserContext { rip: entry, rsp: stack_top, rflags: USER_RFLAGS, ..UserContext::default() }
+    }
+}
+
+pub const USER_RFLAGS: u64 = 0x2; //用户态关闭中断：时钟中断只给内核的看门狗用，还不做抢占
+
+global_asm!(
+    ".pushsection .bss",
+    ".balign 8",
+    "user_return_rsp: .zero 8", //user_resume 保存的内核栈位置，user_exit 由此返回
+    ".popsection",
+    //user_resume(context, kernel_stack)：保存内核的被调用者保存寄存器，然后按 context 以 ring 3 继续执行
+    //程序运行期间的系统调用使用 kernel_stack(进程自己的内核栈)
+    //user_return_rsp 和 syscall_kernel_rsp 也保存在栈上，子进程在父进程的系统调用中运行(wait)时返回后能恢复
+    ".global user_resume",
+    "user_resume:",
+    "push rbx",
+    "push rbp",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "push qword ptr [rip + user_return_rsp]",
+    "push qword ptr [rip + syscall_kernel_rsp]",
+    "sub rsp, 8", //保持栈的 16 字节对齐，user_exit 返回前对应地加回来
+    "mov [rip + user_return_rsp], rsp",
+    "mov [rip + syscall_kernel_rsp], rsi",
+    "mov rsp, rdi", //依次弹出 context 中的寄存器，最后一个是用户栈
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbp",
+    "pop rbx",
+    "pop r9",
+    "pop r8",
+    "pop r10",
+    "pop rdx",
+    "pop rsi",
+    "pop rdi",
+    "pop r11",
+    "pop rcx",
+    "pop rsp",
+    "xor eax, eax", //fork 在子进程中返回 0
+    "sysretq",
+    //user_exit(code)：丢弃系统调用的栈帧，让 user_resume 返回 code
+    ".global user_exit",
+    "user_exit:",
+    "mov rsp, [rip + user_return_rsp]",
+    "mov rax, rdi",
+    "add rsp, 8",
+    "pop qword ptr [rip + syscall_kernel_rsp]",
+    "pop qword ptr [rip + user_return_rsp]",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbp",
+    "pop rbx",
+    "ret",
+);
+
+extern "C" {
+    fn user_resume(context: *const UserContext, kernel_stack: u64) -> i64;
+    fn user_exit(code: i64) -> !;
+    static syscall_kernel_rsp: u64;
+}
+
+//检查用户传入的缓冲区是否完全位于已映射的用户地址空间内
+//mmap 区域中还没有分配的页面先按需映射
+pub fn user_slice(ptr: u64, len: u64) -> Option<&'static [u8]> {
+    let end = ptr.checked_add(len)?;
+    if ptr < USER_BASE || end > MMAP_END {
+        return None;
+    }
+    vm::populate(ptr, len, false);
+    if len > 0 && !memory::is_mapped(VirtAddr::new(ptr), len) {
+        return None;
+    }
+    Some(unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) })
+}
+
+//与 user_slice 相同，但还要求页面可写，内核要往里面写入数据(如 read 的缓冲区)
+pub fn user_slice_mut(ptr: u64, len: u64) -> Option<&'static mut [u8]> {
+    let end = ptr.checked_add(len)?;
+    if ptr < USER_BASE || end > MMAP_END {
+        return None;
+    }
+    vm::populate(ptr, len, true);
+    if len > 0 && !memory::is_writable(VirtAddr::new(ptr), len) {
+        return None;
+    }
+    Some(unsafe { core::slice::from_raw_parts_mut(ptr as *mut u8, len as usize) })
+}
+
+//映射并清零用户栈，返回栈顶地址
+pub fn setup_stack() -> Result<u64, UserError> {
+    let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    memory::map_user_range(VirtAddr::new(USER_STACK_BOTTOM), USER_STACK_SIZE, flags)?;
+    Ok(USER_STACK_TOP)
+}
+
+//按 context 在 ring 3 执行，直到程序调用 exit，返回程序的退出码
+//kernel_stack 是系统调用使用的内核栈栈顶，需要 16 字节对齐
+pub fn enter(context: &UserContext, kernel_stack: u64) -> i64 {
+    let context = *context; //进程表可能在程序运行期间变化，先复制到栈上
+    //程序退出时 RFLAGS 是用户态的值(中断关闭)，返回后恢复进入前的中断状态
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe { user_resume(&context, kernel_stack) })
+}
+
+//当前系统调用保存在内核栈顶的用户寄存器，只能在系统调用中使用
+//改写它会改变系统调用返回用户态时的寄存器(exec 用它跳转到新程序)
+pub fn syscall_context() -> &'static mut UserContext {
+    let top = unsafe { syscall_kernel_rsp };
+    unsafe { &mut *((top - core::mem::size_of::<UserContext>() as u64) as *mut UserContext) }
+}
+
+//把参数复制到新程序的栈顶(当前地址空间必须是新程序的)：参数字符串以 0 结尾，其下是指向它们的指针数组，以空指针结束
+//程序开始运行时 rdi 为参数个数，rsi 为指针数组的地址，调用者负责限制参数的总长度
+pub fn push_args(mut context: UserContext, args: &[&[u8]]) -> UserContext {
+    let mut sp = context.rsp;
+    let mut pointers = Vec::with_capacity(args.len() + 1);
+    for arg in args {
+        sp -= arg.len() as u64 + 1;
+        unsafe {
+            core::ptr::copy_nonoverlapping(arg.as_ptr(), sp as *mut u8, arg.len());
+            *((sp + arg.len() as u64) as *mut u8) = 0;
+        }
+        pointers.push(sp);
+    }
+    pointers.push(0);
+    sp = (sp - pointers.len() as u64 * 8) & !0xf;
+    unsafe {
+        core::ptr::copy_nonoverlapping(pointers.as_ptr(), sp as *mut u64, pointers.len());
+    }
+    context.rsp = sp;
+    context.rdi = args.len() as u64;
+    context.rsi = sp;
+    context
+}
+
+//加载一个平坦二进制：整个文件原样拷贝到 USER_BASE，返回入口地址(就是第一个字节)
+pub fn load_flat(image: &[u8]) -> Result<u64, UserError> {
+    if image.len() > USER_IMAGE_MAX {
+        return Err(UserError::TooLarge);
+    }
+    let size = image.len().max(1) as u64;
+    memory::map_user_range(VirtAddr::new(USER_BASE), size, PageTableFlags::WRITABLE)?;
+    unsafe {
+        core::ptr::copy_nonoverlapping(image.as_ptr(), USER_BASE as *mut u8, image.len());
+    }
+    Ok(USER_BASE)
+}
+
+//由 exit 系统调用使用，不会返回
+pub fn exit(code: i64) -> ! {
+    unsafe { user_exit(code) }
+}
@@ -1,8 +1,22 @@
+use crate::bell;
+use crate::cmdline;
+use crate::console_filter::{self, Action};
+use crate::drivers::uart::{self, Uart};
+use crate::framebuffer::{self, text::TextSnapshot};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
 use core::fmt;
 use core::fmt::{Result, Write};
 use volatile::Volatile;
 use lazy_static::lazy_static; //惰性初始化静态数据，其中值仅在第一次线程安全访问时初始化
 use spin::Mutex; //使用自旋锁，不使用标准库提供的互斥锁类 Mutex
+use crate::sync::{self, MutexGuard};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+use x86_64::PhysAddr;
 
 /*
 
@@ -30,10 +44,264 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
+//panic 处理函数使用的输出宏：不等待锁，见 _emergency_print
+#[macro_export]
+macro_rules! emergency_println {
+    ($($arg:tt)*) => ($crate::vga_buffer::_emergency_print(format_args!("{}\n", format_args!($($arg)*))));
+}
+
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     //use core::fmt::Write;
-    WRITER.lock().write_fmt(args).unwrap();
+    //持有锁期间关闭中断，否则中断处理函数里的 println! 会在同一把锁上永远自旋
+    interrupts::without_interrupts(|| {
+        print_screen(args);
+        crate::console::record(args); //同时记入带时间戳的滚动缓冲区
+    });
+}
+
+//只输出到屏幕(经过控制台过滤器)，不记入滚动缓冲区；日志的 vga 输出端使用
+pub fn print_screen(args: fmt::Arguments) {
+    interrupts::without_interrupts(|| {
+        if console_filter::active() {
+            print_filtered(args);
+        } else {
+            render(args, None);
+        }
+    });
+}
+
+//输出到屏幕，foreground 不为 None 时临时换成这个前景色
+fn render(args: fmt::Arguments, foreground: Option<Color>) {
+    //远程控制台执行命令期间输出写到会话的终端，同时记下来
+    let redirected = REDIRECT.lock().as_mut().map(|(index, captured)| {
+        let _ = captured.write_fmt(args);
+        *index
+    });
+    //进入图形模式后输出到帧缓冲上的文本控制台
+    let graphics = match redirected {
+        Some(_) => None,
+        None => framebuffer::with_console(|console| {
+            let previous = foreground.map(|color| console.set_foreground(color));
+            console.write_fmt(args).unwrap();
+            if let Some(color) = previous {
+                console.set_foreground(color);
+            }
+        }),
+    };
+    if graphics.is_none() && redirected.is_none() && serial_console() {
+        let _ = Uart::new(uart::COM1).write_fmt(args);
+    }
+    if graphics.is_none() {
+        let mut writer = match redirected {
+            Some(index) => WRITERS[index].lock(),
+            None => writer(), //整段输出只加一次锁
+        };
+        let previous = writer.color_code;
+        if let Some(color) = foreground {
+            writer.color_code = ColorCode(previous.0 & 0xF0 | color as u8);
+        }
+        match args.as_str() {
+            Some(s) => writer.write_bytes(s.as_bytes()), //没有格式化参数时直接批量输出
+            None => writer.write_fmt(args).unwrap(),
+        }
+        writer.color_code = previous;
+    }
+}
+
+//输出改写到的终端和记下的内容
+static REDIRECT: Mutex<Option<(usize, String)>> = Mutex::new(None);
+
+//执行 f，期间 print! 的输出写到终端 index(不管它是不是前台终端)，返回 f 的结果和这期间的全部输出
+pub fn with_terminal<T>(index: usize, f: impl FnOnce() -> T) -> (T, String) {
+    flush();
+    *REDIRECT.lock() = Some((index.min(TERMINALS - 1), String::new()));
+    let result = f();
+    flush();
+    let captured = REDIRECT.lock().take().map(|(_, captured)| captured).unwrap_or_default();
+    (result, captured)
+}
+
+//有控制台过滤器时还没有遇到换行符的输出
+static PENDING: Mutex<String> = Mutex::new(String::new());
+
+//凑成整行后交给过滤器决定怎样显示
+fn print_filtered(args: fmt::Arguments) {
+    let mut pending = PENDING.lock();
+    pending.write_fmt(args).unwrap();
+    while let Some(end) = pending.find('\n') {
+        let line: String = pending.drain(..=end).collect();
+        match console_filter::classify(&line[..end]) {
+            Some(Action::Suppress) => {}
+            Some(Action::Highlight(color)) => render(format_args!("{}", line), Some(color)),
+            None => render(format_args!("{}", line), None),
+        }
+    }
+}
+
+//把还没有换行的输出直接显示出来(不再过滤)，等待按键和移动光标之前调用，保证屏幕上的顺序正确
+pub fn flush() {
+    if PENDING.lock().is_empty() {
+        return;
+    }
+    interrupts::without_interrupts(|| {
+        let rest = core::mem::take(&mut *PENDING.lock());
+        if !rest.is_empty() {
+            render(format_args!("{}", rest), None);
+        }
+    });
+}
+
+//紧急输出：panic 或双重错误时，被打断的代码可能正持有输出的锁，并且再也不会释放
+//这里直接强制解锁后输出，屏幕上可能与被打断的输出交错，但不会死锁；也不记入滚动缓冲区(它同样有锁)
+#[doc(hidden)]
+pub fn _emergency_print(args: fmt::Arguments) {
+    interrupts::disable();
+    if framebuffer::force_write(args).is_some() {
+        return;
+    }
+    if serial_console() {
+        let _ = Uart::new(uart::COM1).write_fmt(args);
+    }
+    let writer = &WRITERS[ACTIVE.load(Ordering::SeqCst)];
+    unsafe { writer.force_unlock() };
+    let _ = writer.lock().write_fmt(args);
+}
+
+//以下三个函数供行编辑器移动光标、重画当前行
+//串口上用 ANSI 转义序列移动光标
+pub fn cursor_left(n: usize) {
+    flush();
+    if framebuffer::with_console(|console| console.cursor_left(n)).is_none() {
+        if serial_console() && n > 0 {
+            let _ = write!(Uart::new(uart::COM1), "\x1b[{}D", n);
+        }
+        writer().cursor_left(n);
+    }
+}
+
+pub fn cursor_right(n: usize) {
+    flush();
+    if framebuffer::with_console(|console| console.cursor_right(n)).is_none() {
+        if serial_console() && n > 0 {
+            let _ = write!(Uart::new(uart::COM1), "\x1b[{}C", n);
+        }
+        writer().cursor_right(n);
+    }
+}
+
+pub fn clear_to_end() {
+    flush();
+    if framebuffer::with_console(|console| console.clear_to_end()).is_none() {
+        if serial_console() {
+            let _ = Uart::new(uart::COM1).write_str("\x1b[K");
+        }
+        writer().clear_to_end();
+    }
+}
+
+//只写到屏幕，不记入滚动缓冲区(供进度条这类反复重画的内容使用)
+pub fn write_unrecorded(s: &str) {
+    flush();
+    if framebuffer::with_console(|console| console.write_str(s).unwrap()).is_none() {
+        if serial_console() {
+            let _ = Uart::new(uart::COM1).write_str(s);
+        }
+        writer().write_string(s);
+    }
+}
+
+//直接在屏幕的指定位置写一个字符，不移动光标(供全屏界面使用)
+pub fn put_char(row: usize, col: usize, byte: u8, foreground: Color, background: Color) {
+    if framebuffer::with_console(|console| console.put_char(row, col, byte, foreground, background)).is_some() {
+        return;
+    }
+    if row < BUFFER_HEIGHT && col < BUFFER_WIDTH {
+        writer().buffer.chars[row][col].write(ScreenChar {
+            ascii_character: byte,
+            color_code: ColorCode::new(foreground, background),
+        });
+    }
+}
+
+//整屏内容的副本，全屏界面退出时用来恢复原来的屏幕
+pub enum ScreenSnapshot {
+    Text {
+        chars: Box<[[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT]>,
+        column_position: usize,
+    },
+    Graphics(TextSnapshot),
+}
+
+pub fn snapshot() -> ScreenSnapshot {
+    if let Some(snapshot) = framebuffer::with_console(|console| console.snapshot()) {
+        return ScreenSnapshot::Graphics(snapshot);
+    }
+    let writer = writer();
+    let mut chars = Box::new([[ScreenChar { ascii_character: b' ', color_code: writer.color_code }; BUFFER_WIDTH]; BUFFER_HEIGHT]);
+    for row in 0..BUFFER_HEIGHT {
+        for col in 0..BUFFER_WIDTH {
+            chars[row][col] = writer.buffer.chars[row][col].read();
+        }
+    }
+    ScreenSnapshot::Text { chars, column_position: writer.column_position }
+}
+
+pub fn restore(snapshot: &ScreenSnapshot) {
+    match snapshot {
+        ScreenSnapshot::Text { chars, column_position } => {
+            let mut writer = writer();
+            for row in 0..BUFFER_HEIGHT {
+                for col in 0..BUFFER_WIDTH {
+                    writer.buffer.chars[row][col].write(chars[row][col]);
+                }
+            }
+            writer.column_position = *column_position;
+        }
+        ScreenSnapshot::Graphics(snapshot) => {
+            framebuffer::with_console(|console| console.restore(snapshot));
+        }
+    }
+}
+
+const FONT_GLYPHS: usize = 256;
+const FONT_GLYPH_STRIDE: usize = 32; //显存中每个字形占 32 字节，8x16 字体只用前 16 字节
+const SEQUENCER_INDEX: u16 = 0x3C4; //数据端口是索引端口 + 1
+const GRAPHICS_INDEX: u16 = 0x3CE;
+
+//写 VGA 的一个索引寄存器
+fn write_register(index_port: u16, index: u8, value: u8) {
+    unsafe {
+        Port::<u8>::new(index_port).write(index);
+        Port::<u8>::new(index_port + 1).write(value);
+    }
+}
+
+//读出 VGA 文本模式使用的字体(位于显存的第 2 个位平面)，只能在切换到图形模式之前调用；没有 VGA 时返回 None
+//读取期间需要临时把位平面 2 映射到 0xA0000，读完后恢复文本模式的设置
+pub fn read_font() -> Option<Vec<u8>> {
+    if !present() {
+        return None;
+    }
+    let _writer = writer(); //读取期间不能写文本缓冲区
+    write_register(SEQUENCER_INDEX, 0x02, 0x04); //只访问位平面 2
+    write_register(SEQUENCER_INDEX, 0x04, 0x07); //顺序寻址，关闭奇偶模式
+    write_register(GRAPHICS_INDEX, 0x04, 0x02);  //读取位平面 2
+    write_register(GRAPHICS_INDEX, 0x05, 0x00);  //关闭奇偶模式
+    write_register(GRAPHICS_INDEX, 0x06, 0x04);  //把显存映射到 0xA0000，大小 64 KiB
+
+    let base = crate::memory::phys_to_virt(PhysAddr::new(0xA0000)).as_ptr::<u8>();
+    let mut font = vec![0u8; FONT_GLYPHS * FONT_GLYPH_STRIDE];
+    for (i, byte) in font.iter_mut().enumerate() {
+        *byte = unsafe { base.add(i).read_volatile() };
+    }
+
+    write_register(SEQUENCER_INDEX, 0x02, 0x03); //恢复文本模式：位平面 0、1
+    write_register(SEQUENCER_INDEX, 0x04, 0x03);
+    write_register(GRAPHICS_INDEX, 0x04, 0x00);
+    write_register(GRAPHICS_INDEX, 0x05, 0x10);
+    write_register(GRAPHICS_INDEX, 0x06, 0x0E);  //显存映射回 0xB8000
+    Some(font)
 }
 
 #[allow(dead_code)] //使用 #[allow(dead_code)]，可以禁用编译器对每个未使用的变量发出警告
@@ -59,26 +327,40 @@ pub enum Color {
     White = 15,
 }
 
+//按数值排列的全部颜色，用来把颜色代码的半个字节换回 Color
+const COLORS: [Color; 16] = [
+    Color::Black, Color::Blue, Color::Green, Color::Cyan, Color::Red, Color::Magenta, Color::Brown, Color::LightGray,
+    Color::DarkGray, Color::LightBlue, Color::LightGreen, Color::LightCyan, Color::LightRed, Color::Pink, Color::Yellow, Color::White,
+];
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
-struct ColorCode(u8);
+pub struct ColorCode(u8);
 
 impl ColorCode { //ColorCode 类型包装了一个完整的颜色代码字节，包含前景色(字体颜色)和背景色信息(字体外的填充颜色)
     //impl 用以调用类型( struct )或特性( trait )
-    fn new(foreground: Color, background: Color) -> ColorCode {
+    pub const fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
+
+    pub fn foreground(self) -> Color {
+        COLORS[(self.0 & 0x0f) as usize]
+    }
+
+    pub fn background(self) -> Color {
+        COLORS[(self.0 >> 4) as usize]
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
-struct ScreenChar{ //成员变量
+pub struct ScreenChar{ //成员变量
     ascii_character: u8,
     color_code: ColorCode
 }
 
-const BUFFER_HEIGHT: usize = 25; //定义整块区域的行数为25
-const BUFFER_WIDTH: usize = 80;  //定义整块区域的列数为80
+pub const BUFFER_HEIGHT: usize = 25; //定义整块区域的行数为25
+pub const BUFFER_WIDTH: usize = 80;  //定义整块区域的列数为80
 
 #[repr(transparent)] //用以确保类型和它的单个成员有相同的内存布局
 struct Buffer{
@@ -89,65 +371,226 @@ struct Buffer{
 pub struct Writer { //输出字符到屏幕
     column_position: usize, //此变量将跟踪光标在最后一行的位置
     color_code: ColorCode, //字符的前景和背景色
-    buffer: &'static mut Buffer, //存入一个 VGA 字符缓冲区的可变借用( &mut )到buffer变量中, 'static为生命周期，意味着这个借用应该在整个程序的运行期间有效
+    buffer: &'static mut Buffer, //前台终端指向 VGA 显存，后台终端指向自己的后备缓冲区
+    spare: Option<&'static mut Buffer>, //前台终端暂时不用的后备缓冲区，切换到后台时把屏幕内容存进去
+}
+
+pub const TERMINALS: usize = 4; //虚拟终端个数，用 Alt+F1..F4 切换
+
+//每个终端的后备缓冲区，放在 .bss 中，不需要等堆初始化
+static mut BACKING: [[u16; BUFFER_WIDTH * BUFFER_HEIGHT]; TERMINALS] = [[0; BUFFER_WIDTH * BUFFER_HEIGHT]; TERMINALS];
+
+fn backing(index: usize) -> &'static mut Buffer {
+    unsafe { &mut *(core::ptr::addr_of_mut!(BACKING[index]) as *mut Buffer) }
+}
+
+const TERMINAL_LOCKS: [&str; TERMINALS] = ["WRITERS[0]", "WRITERS[1]", "WRITERS[2]", "WRITERS[3]"];
+
+//没有 VGA 的机器(例如只用 UEFI 启动的)上 0xb8000 处没有显存，VGA 的寄存器也不存在，读出的是 0xFF
+//这时 0 号终端也写在后备缓冲区里(全屏界面的快照、恢复照常工作，只是看不见)，屏幕输出改写到 COM1
+//命令行 novga 强制当作没有 VGA；检测在第一次输出时进行，只能看到编译时的命令行
+const MISC_OUTPUT_READ: u16 = 0x3CC;
+
+static PRESENT: AtomicBool = AtomicBool::new(true);
+static SERIAL_CONSOLE: AtomicBool = AtomicBool::new(false);
+
+fn detect() -> bool {
+    !cmdline::flag("novga") && unsafe { Port::<u8>::new(MISC_OUTPUT_READ).read() } != 0xFF
+}
+
+//有没有 VGA 文本模式的显存
+pub fn present() -> bool {
+    lazy_static::initialize(&WRITERS);
+    PRESENT.load(Ordering::Relaxed)
+}
+
+//屏幕输出是否改写到了串口(没有 VGA，也还没有切换到图形模式)
+pub fn serial_console() -> bool {
+    SERIAL_CONSOLE.load(Ordering::Relaxed)
+}
+
+fn new_terminal(index: usize) -> sync::Mutex<Writer> {
+    let (buffer, spare) = if index == 0 && detect() {
+        (unsafe { &mut *(0xb8000 as *mut Buffer) }, Some(backing(0))) //启动时 0 号终端在前台
+    } else if index == 0 {
+        PRESENT.store(false, Ordering::Relaxed);
+        let serial = Uart::new(uart::COM1);
+        if serial.present() {
+            serial.init();
+            SERIAL_CONSOLE.store(true, Ordering::Relaxed);
+        }
+        (backing(0), None)
+    } else {
+        (backing(index), None)
+    };
+    sync::Mutex::new(TERMINAL_LOCKS[index], Writer { column_position: 0, color_code: ColorCode::new(Color::Yellow, Color::Black), buffer, spare })
 }
 
 lazy_static! {
-    pub static ref WRITER: Mutex<Writer> = Mutex::new( //使用自旋的互斥锁，为 WRITER 类实现安全的内部可变性
-        Writer { 
-            column_position: 0,
-            color_code: ColorCode::new(Color::Yellow, Color::Black),
-            buffer: unsafe { 
-                &mut *(0xb8000 as *mut Buffer) 
-            },
+    static ref WRITERS: [sync::Mutex<Writer>; TERMINALS] = [new_terminal(0), new_terminal(1), new_terminal(2), new_terminal(3)];
+}
+
+static ACTIVE: AtomicUsize = AtomicUsize::new(0); //前台终端的序号
+
+//前台终端的 Writer，print! 等输出都写到这里
+fn writer() -> MutexGuard<'static, Writer> {
+    WRITERS[ACTIVE.load(Ordering::SeqCst)].lock()
+}
+
+fn copy_buffer(from: &Buffer, to: &mut Buffer) {
+    for row in 0..BUFFER_HEIGHT {
+        for col in 0..BUFFER_WIDTH {
+            to.chars[row][col].write(from.chars[row][col].read());
         }
-    );
+    }
+}
+
+//切换前台终端：当前屏幕存入原终端的后备缓冲区，显存交给新终端并显示它的内容
+pub fn switch_terminal(index: usize) {
+    let current = ACTIVE.load(Ordering::SeqCst);
+    if index >= TERMINALS || index == current || !present() {
+        return;
+    }
+    let mut old = WRITERS[current].lock();
+    let mut new = WRITERS[index].lock();
+    let saved = old.spare.take().expect("foreground terminal without a spare buffer");
+    copy_buffer(old.buffer, saved);
+    let screen = core::mem::replace(&mut old.buffer, saved);
+    let contents = core::mem::replace(&mut new.buffer, screen);
+    copy_buffer(contents, new.buffer);
+    new.spare = Some(contents);
+    ACTIVE.store(index, Ordering::SeqCst);
 }
 
 impl Writer {
-    pub fn write_byte(&mut self, byte: u8) { //输出ascii字符
-        match byte {
-            b'\n' => self.new_line(), //输入字符 '\n' ，调用new_line()方法
-            byte => {
-                if self.column_position >= BUFFER_WIDTH { //当前行的列数等于最大值时，创建新的一行
+    fn write_string(&mut self, s: &str) { //输出字符串
+        self.write_bytes(s.as_bytes());
+    }
+
+    //批量输出：先算出整段文字会换多少行，注定滚出屏幕的行不再绘制，
+    //其余部分按 "换行符或行尾" 切成若干段，每段整段拷贝到最后一行
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        if bytes.contains(&bell::BEL) {
+            //BEL 不占位置，响铃后接着输出后面的部分
+            for (i, part) in bytes.split(|&b| b == bell::BEL).enumerate() {
+                if i > 0 {
+                    bell::ring(|| self.invert());
+                }
+                self.write_bytes(part);
+            }
+            return;
+        }
+        let breaks = Writer::count_breaks(self.column_position, bytes);
+        let mut hidden = breaks.saturating_sub(BUFFER_HEIGHT - 1); //从当前行算起，会滚出屏幕的行数
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let newline = rest[0] == b'\n';
+            if newline || self.column_position >= BUFFER_WIDTH {
+                if hidden > 0 {
+                    //不可见的行不滚屏，最后一个不可见的行结束时整屏清空，之后的行正常输出
+                    hidden -= 1;
+                    self.column_position = 0;
+                    if hidden == 0 {
+                        self.clear_screen();
+                    }
+                } else {
                     self.new_line();
                 }
-
-                let row = BUFFER_HEIGHT - 1;
-                let col = self.column_position;
-                
-                let color_code = self.color_code;
-                self.buffer.chars[row][col].write(ScreenChar {
-                    ascii_character: byte,
-                    color_code: color_code,
-                });
-                self.column_position += 1; //当前列数+1
+                if newline {
+                    rest = &rest[1..];
+                }
+                continue;
             }
+            let room = BUFFER_WIDTH - self.column_position;
+            let line = &rest[..rest.len().min(room)];
+            let run = line.iter().position(|&b| b == b'\n').unwrap_or(line.len());
+            if hidden == 0 {
+                self.write_run(&rest[..run]);
+            }
+            self.column_position += run;
+            rest = &rest[run..];
         }
     }
-    fn write_string(&mut self, s: &str) { //输出字符串
-        for byte in s.bytes() {
-            match byte {
-                // 可以是能打印的 ASCII 码字节，也可以是换行符
-                0x20..=0x7e | b'\n' => self.write_byte(byte), // ' a ..= b ' 相当于 从 a 到 b 的值
-                // 不包含在上述范围之内的字节
-                _ => self.write_byte(0xfe), //打印的 '_' 在ga编码中为16进制的 (0xfe)
-            }
 
+    //模拟输出过程，数出会发生多少次换行(包括写满一行后的自动换行)
+    fn count_breaks(mut column: usize, bytes: &[u8]) -> usize {
+        let mut breaks = 0;
+        for &byte in bytes {
+            if byte == b'\n' {
+                breaks += 1;
+                column = 0;
+            } else {
+                if column >= BUFFER_WIDTH {
+                    breaks += 1;
+                    column = 0;
+                }
+                column += 1;
+            }
+        }
+        breaks
+    }
 
+    //在最后一行的光标处写入一段不含换行符的文字，先在栈上拼好整段字符，再一次拷贝到显存
+    fn write_run(&mut self, bytes: &[u8]) {
+        let mut run = [ScreenChar { ascii_character: b' ', color_code: self.color_code }; BUFFER_WIDTH];
+        for (cell, &byte) in run.iter_mut().zip(bytes) {
+            cell.ascii_character = match byte {
+                0x20..=0x7e => byte, //可以打印的 ASCII 码字节
+                _ => 0xfe,           //其他字节显示为 ■
+            };
+        }
+        let row = self.buffer.chars[BUFFER_HEIGHT - 1].as_mut_ptr() as *mut ScreenChar;
+        unsafe {
+            core::ptr::copy_nonoverlapping(run.as_ptr(), row.add(self.column_position), bytes.len());
         }
     }
+
     fn new_line(&mut self) {
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(character);
-            }
+        //整屏上移一行：一次内存拷贝，而不是逐个字符读写
+        let chars = self.buffer.chars.as_mut_ptr() as *mut ScreenChar;
+        unsafe {
+            core::ptr::copy(chars.add(BUFFER_WIDTH), chars, BUFFER_WIDTH * (BUFFER_HEIGHT - 1));
         }
         self.clear_row(BUFFER_HEIGHT - 1);
         self.column_position = 0;
     }
+
+    //整屏的前景色和背景色对调，视觉响铃用
+    fn invert(&mut self) {
+        for row in self.buffer.chars.iter_mut() {
+            for cell in row.iter_mut() {
+                let mut screen_char = cell.read();
+                screen_char.color_code = ColorCode(screen_char.color_code.0.rotate_left(4));
+                cell.write(screen_char);
+            }
+        }
+    }
+
+    fn clear_screen(&mut self) {
+        for row in 0..BUFFER_HEIGHT {
+            self.clear_row(row);
+        }
+    }
     
+    //光标只在最后一行内移动，不会回到上一行
+    fn cursor_left(&mut self, n: usize) {
+        self.column_position = self.column_position.saturating_sub(n);
+    }
+
+    fn cursor_right(&mut self, n: usize) {
+        self.column_position = (self.column_position + n).min(BUFFER_WIDTH);
+    }
+
+    fn clear_to_end(&mut self) { //清除光标到行尾的内容，光标位置不变
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        for col in self.column_position..BUFFER_WIDTH {
+            self.buffer.chars[BUFFER_HEIGHT - 1][col].write(blank);
+        }
+    }
+
     fn clear_row(&mut self, row: usize) {
         let blank = ScreenChar {
             ascii_character: b' ',
@@ -184,7 +627,7 @@ fn test_println_output() {
     let s = "Some test string that fits on a single line";
     println!("{}", s);
     for (i,c) in s.chars().enumerate() {
-        let screen_char = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][i].read();
+        let screen_char = writer().buffer.chars[BUFFER_HEIGHT - 2][i].read();
         assert_eq!(char::from(screen_char.ascii_character), c);
     } 
 }*/
\ No newline at end of file
@@ -0,0 +1,298 @@
+//进程的虚拟内存区域(vm_area)：mmap 只记录区域，页面在第一次访问触发缺页异常时才分配(按需分页)
+//程序映像和用户栈在加载时就已映射，不属于任何区域
+use crate::memory::{self, MemoryError};
+use crate::group;
+use crate::oom;
+use crate::process;
+use crate::shm::Segment;
+use crate::usermode::{MMAP_BASE, MMAP_END};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+
+const PAGE_SIZE: u64 = 4096;
+
+//mmap 的权限位，数值与 Linux 相同
+pub const PROT_READ: u64 = 1;
+pub const PROT_WRITE: u64 = 2;
+pub const PROT_EXEC: u64 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    InvalidArgument, //长度为 0、地址没有按页对齐或不在映射区域内
+    NoSpace,         //映射区域中没有足够大的空闲地址
+    Memory(MemoryError),
+}
+
+impl From<MemoryError> for VmError {
+    fn from(err: MemoryError) -> VmError {
+        VmError::Memory(err)
+    }
+}
+
+//缺页异常计数：minor 是分配清零页面或映射已有的共享帧，major 是需要读入文件内容，cow 是写时复制
+//还没有交换区，major 只来自文件映射
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultStats {
+    pub minor: u64,
+    pub major: u64,
+    pub cow: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    Minor,
+    Major,
+    Cow,
+}
+
+impl FaultStats {
+    pub fn add(&mut self, kind: FaultKind) {
+        match kind {
+            FaultKind::Minor => self.minor += 1,
+            FaultKind::Major => self.major += 1,
+            FaultKind::Cow => self.cow += 1,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.minor + self.major + self.cow
+    }
+}
+
+//全部进程的缺页异常计数，包括已经退出的进程
+static FAULTS: Mutex<FaultStats> = Mutex::new(FaultStats { minor: 0, major: 0, cow: 0 });
+
+pub fn fault_stats() -> FaultStats {
+    *FAULTS.lock()
+}
+
+//记入全局计数和当前进程的计数
+fn count_fault(kind: FaultKind) {
+    FAULTS.lock().add(kind);
+    process::count_fault(kind);
+}
+
+#[derive(Clone)]
+pub enum Backing {
+    Anonymous, //首次访问时分配清零的页面
+    //私有文件映射：首次访问时复制文件对应位置的内容，写入不会写回文件
+    //文件系统还没有按页读取的接口，文件内容在 mmap 时整个读入内核
+    File(Arc<[u8]>),
+    //共享内存段，offset 是区域开头在段中的偏移，映射同一个段的进程共享物理帧
+    Shared { segment: Arc<Segment>, offset: u64 },
+}
+
+#[derive(Clone)]
+pub struct VmArea {
+    pub start: u64,
+    pub end: u64,
+    pub prot: u64,
+    pub backing: Backing,
+}
+
+impl VmArea {
+    fn flags(&self) -> PageTableFlags {
+        let mut flags = PageTableFlags::empty();
+        if self.prot & PROT_WRITE != 0 {
+            flags |= PageTableFlags::WRITABLE;
+        }
+        if self.prot & PROT_EXEC == 0 {
+            flags |= PageTableFlags::NO_EXECUTE;
+        }
+        flags
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        self.start <= addr && addr < self.end
+    }
+}
+
+fn page_align_up(len: u64) -> Option<u64> {
+    Some(len.checked_add(PAGE_SIZE - 1)? & !(PAGE_SIZE - 1))
+}
+
+//在当前进程的映射区域中找一段空闲地址建立映射，返回起始地址
+pub fn mmap(len: u64, prot: u64, backing: Backing) -> Result<u64, VmError> {
+    let len = page_align_up(len).filter(|&len| len > 0).ok_or(VmError::InvalidArgument)?;
+    process::with_areas(|areas| {
+        let mut start = MMAP_BASE;
+        for area in areas.iter().filter(|area| area.start >= MMAP_BASE) { //跳过映射区域下方的 brk 堆
+            if area.start - start >= len {
+                break;
+            }
+            start = area.end;
+        }
+        if MMAP_END - start < len {
+            return Err(VmError::NoSpace);
+        }
+        let index = areas.iter().position(|area| area.start > start).unwrap_or(areas.len());
+        areas.insert(index, VmArea { start, end: start + len, prot, backing });
+        Ok(start)
+    })
+    .ok_or(VmError::InvalidArgument)?
+}
+
+//让从 start 开始的可读写匿名区域(Linux 兼容层的 brk 堆)结束于 end，两者都按页对齐，end 等于 start 时去掉这个区域
+//不能和后面的区域重叠；缩小时回收多出来的页面
+pub fn resize_heap(start: u64, end: u64) -> Result<(), VmError> {
+    if !start.is_multiple_of(PAGE_SIZE) || !end.is_multiple_of(PAGE_SIZE) || end < start {
+        return Err(VmError::InvalidArgument);
+    }
+    let old_end = process::with_areas(|areas| {
+        let index = areas.iter().position(|area| area.start == start);
+        let next = areas.iter().position(|area| area.start > start).unwrap_or(areas.len());
+        if areas.get(next).is_some_and(|area| area.start < end) {
+            return Err(VmError::NoSpace);
+        }
+        match index {
+            Some(index) if end == start => Ok(areas.remove(index).end),
+            Some(index) => Ok(core::mem::replace(&mut areas[index].end, end)),
+            None => {
+                if end > start {
+                    areas.insert(next, VmArea { start, end, prot: PROT_READ | PROT_WRITE, backing: Backing::Anonymous });
+                }
+                Ok(start)
+            }
+        }
+    })
+    .ok_or(VmError::InvalidArgument)??;
+    if end < old_end {
+        memory::unmap_user_range(VirtAddr::new(end), old_end - end).into_iter().for_each(memory::release_frame);
+    }
+    Ok(())
+}
+
+//解除 [addr, addr + len) 的映射，区域被部分覆盖时拆分
+//私有页面的物理帧被回收，共享内存段的帧在段被释放时才回收
+pub fn munmap(addr: u64, len: u64) -> Result<(), VmError> {
+    let len = page_align_up(len).filter(|&len| len > 0).ok_or(VmError::InvalidArgument)?;
+    let end = addr.checked_add(len).ok_or(VmError::InvalidArgument)?;
+    if !addr.is_multiple_of(PAGE_SIZE) || addr < MMAP_BASE || end > MMAP_END {
+        return Err(VmError::InvalidArgument);
+    }
+    let removed = process::with_areas(|areas| {
+        let mut kept = Vec::new();
+        let mut removed = Vec::new();
+        for area in areas.drain(..) {
+            if area.end <= addr || area.start >= end {
+                kept.push(area);
+                continue;
+            }
+            removed.push(VmArea { start: area.start.max(addr), end: area.end.min(end), ..area.clone() });
+            if area.start < addr {
+                kept.push(VmArea { end: addr, ..area.clone() });
+            }
+            if area.end > end {
+                //文件映射的后半部分从文件中对应的位置开始
+                let backing = match &area.backing {
+                    Backing::File(data) => {
+                        let skip = ((end - area.start) as usize).min(data.len());
+                        Backing::File(Arc::from(&data[skip..]))
+                    }
+                    Backing::Shared { segment, offset } => {
+                        Backing::Shared { segment: segment.clone(), offset: offset + (end - area.start) }
+                    }
+                    Backing::Anonymous => Backing::Anonymous,
+                };
+                kept.push(VmArea { start: end, end: area.end, prot: area.prot, backing });
+            }
+        }
+        *areas = kept;
+        removed
+    })
+    .ok_or(VmError::InvalidArgument)?;
+    for area in removed {
+        let frames = memory::unmap_user_range(VirtAddr::new(area.start), area.end - area.start);
+        if !matches!(area.backing, Backing::Shared { .. }) {
+            frames.into_iter().for_each(memory::release_frame); //fork 之后页面可能还被别的进程共享
+        }
+    }
+    Ok(())
+}
+
+//处理当前进程的缺页异常：写入写时复制的页面时复制它，addr 属于某个区域且权限允许时映射页面
+//处理成功时返回 true；页面已经存在(权限不足引起的异常)时返回 false
+pub fn handle_fault(addr: VirtAddr, write: bool) -> bool {
+    loop {
+        match resolve_fault(addr, write) {
+            Ok(kind) => {
+                count_fault(kind);
+                return true;
+            }
+            //分配不到物理帧或者进程组用到了上限时由 OOM 处理释放内存后重试
+            Err(FaultError::Memory(MemoryError::OutOfFrames)) if oom::out_of_memory(None) => {}
+            Err(FaultError::GroupLimit(group)) if oom::out_of_memory(Some(&group)) => {}
+            Err(_) => return false,
+        }
+    }
+}
+
+enum FaultError {
+    Invalid, //不属于任何区域、权限不足或页面已经存在
+    Memory(MemoryError),
+    GroupLimit(String), //所在的进程组用到了内存上限
+}
+
+impl From<MemoryError> for FaultError {
+    fn from(err: MemoryError) -> FaultError {
+        FaultError::Memory(err)
+    }
+}
+
+fn resolve_fault(addr: VirtAddr, write: bool) -> Result<FaultKind, FaultError> {
+    let page = addr.align_down(PAGE_SIZE);
+    if write && memory::is_cow(page) {
+        memory::copy_on_write(page)?;
+        return Ok(FaultKind::Cow);
+    }
+    let area = process::find_area(addr.as_u64()).ok_or(FaultError::Invalid)?;
+    if (write && area.prot & PROT_WRITE == 0) || memory::is_mapped(page, PAGE_SIZE) {
+        return Err(FaultError::Invalid);
+    }
+    //写时复制不改变常驻页面数，只有新映射的私有页面计入进程组的用量
+    if !matches!(area.backing, Backing::Shared { .. }) {
+        if let Some(group) = group::memory_exhausted() {
+            return Err(FaultError::GroupLimit(group));
+        }
+    }
+    let flags = area.flags();
+    match &area.backing {
+        Backing::Anonymous => {
+            memory::map_user_range(page, PAGE_SIZE, flags)?;
+            Ok(FaultKind::Minor)
+        }
+        Backing::File(data) => {
+            //先以可写方式映射，复制完内容后再改成区域的权限
+            memory::map_user_range(page, PAGE_SIZE, PageTableFlags::WRITABLE)?;
+            let offset = ((page.as_u64() - area.start) as usize).min(data.len());
+            let count = (data.len() - offset).min(PAGE_SIZE as usize);
+            unsafe {
+                core::ptr::copy_nonoverlapping(data[offset..].as_ptr(), page.as_mut_ptr::<u8>(), count);
+            }
+            memory::update_user_flags(page, PAGE_SIZE, flags)?;
+            Ok(FaultKind::Major)
+        }
+        Backing::Shared { segment, offset } => {
+            //超出段的末尾时没有对应的帧
+            let frame = segment.frame((offset + page.as_u64() - area.start) / PAGE_SIZE).ok_or(FaultError::Invalid)?;
+            memory::map_user_frame(page, frame, flags)?;
+            Ok(FaultKind::Minor)
+        }
+    }
+}
+
+//内核访问用户缓冲区之前，先把其中属于映射区域、还没有分配的页面映射好
+pub fn populate(start: u64, len: u64, write: bool) {
+    if len == 0 {
+        return;
+    }
+    let mut page = start & !(PAGE_SIZE - 1);
+    while page < start + len {
+        handle_fault(VirtAddr::new(page), write);
+        page += PAGE_SIZE;
+    }
+}
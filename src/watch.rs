@@ -0,0 +1,195 @@
+//观察表达式：各子系统登记有名字的数值来源，watch add 选出要看的，shell 等待输入时每秒取一次值显示在屏幕第一行的右边
+//计数器类的来源(Kind::Rate)显示每秒的增量，其他的显示当前值；想看一个数时不用加调试输出重新编译
+use crate::framebuffer;
+use crate::time::Instant;
+use crate::vga_buffer::{self, Color};
+use crate::{allocator, memory, process, stats, vm};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::time::Duration;
+use spin::Mutex;
+
+const INTERVAL: Duration = Duration::from_secs(1);
+const MAX_WATCHES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Gauge, //当前值
+    Rate,  //单调增加的计数器，显示每秒的增量
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Provider {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub unit: &'static str,
+    pub read: fn() -> u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchError {
+    NoSuchProvider,
+    AlreadyExists,
+    NotFound,
+    TooMany,
+}
+
+//内核核心部分的来源，其他子系统初始化时用 register 登记自己的
+const BUILTIN: &[Provider] = &[
+    Provider { name: "heap.free", kind: Kind::Gauge, unit: "KiB", read: heap_free },
+    Provider { name: "heap.used", kind: Kind::Gauge, unit: "KiB", read: heap_used },
+    Provider { name: "mem.free", kind: Kind::Gauge, unit: "KiB", read: mem_free },
+    Provider { name: "ctxsw", kind: Kind::Rate, unit: "/s", read: stats::context_switches },
+    Provider { name: "interrupts", kind: Kind::Rate, unit: "/s", read: stats::interrupts },
+    Provider { name: "faults", kind: Kind::Rate, unit: "/s", read: faults },
+    Provider { name: "processes", kind: Kind::Gauge, unit: "", read: processes },
+];
+
+fn heap_free() -> u64 {
+    let (used, size) = allocator::usage();
+    ((size - used) / 1024) as u64
+}
+
+fn heap_used() -> u64 {
+    (allocator::usage().0 / 1024) as u64
+}
+
+fn mem_free() -> u64 {
+    memory::free_frame_count() as u64 * 4
+}
+
+fn faults() -> u64 {
+    let faults = vm::fault_stats();
+    faults.minor + faults.major
+}
+
+fn processes() -> u64 {
+    process::list().len() as u64
+}
+
+static REGISTERED: Mutex<Vec<Provider>> = Mutex::new(Vec::new());
+
+struct Watch {
+    label: String,
+    provider: Provider,
+    last: u64,          //上一次读到的值
+    value: Option<u64>, //显示的值，Rate 要等第二次取值之后才有
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchInfo {
+    pub label: String,
+    pub provider: &'static str,
+    pub value: Option<u64>,
+    pub unit: &'static str,
+}
+
+static WATCHES: Mutex<Vec<Watch>> = Mutex::new(Vec::new());
+static LAST_SAMPLE: Mutex<Option<Instant>> = Mutex::new(None);
+static DRAWN: Mutex<usize> = Mutex::new(0); //上次在第一行画的宽度，变短时要擦掉多出的部分
+
+//登记一个来源，名字习惯用 "子系统.名字"
+pub fn register(name: &'static str, kind: Kind, unit: &'static str, read: fn() -> u64) -> Result<(), WatchError> {
+    if find(name).is_some() {
+        return Err(WatchError::AlreadyExists);
+    }
+    REGISTERED.lock().push(Provider { name, kind, unit, read });
+    Ok(())
+}
+
+pub fn providers() -> Vec<Provider> {
+    BUILTIN.iter().copied().chain(REGISTERED.lock().iter().copied()).collect()
+}
+
+fn find(name: &str) -> Option<Provider> {
+    providers().into_iter().find(|provider| provider.name == name)
+}
+
+pub fn add(label: &str, provider: &str) -> Result<(), WatchError> {
+    let provider = find(provider).ok_or(WatchError::NoSuchProvider)?;
+    let mut watches = WATCHES.lock();
+    if watches.iter().any(|watch| watch.label == label) {
+        return Err(WatchError::AlreadyExists);
+    }
+    if watches.len() >= MAX_WATCHES {
+        return Err(WatchError::TooMany);
+    }
+    let last = (provider.read)();
+    let value = match provider.kind {
+        Kind::Gauge => Some(last),
+        Kind::Rate => None,
+    };
+    watches.push(Watch { label: String::from(label), provider, last, value });
+    Ok(())
+}
+
+pub fn remove(label: &str) -> Result<(), WatchError> {
+    let mut watches = WATCHES.lock();
+    let index = watches.iter().position(|watch| watch.label == label).ok_or(WatchError::NotFound)?;
+    watches.remove(index);
+    if watches.is_empty() {
+        drop(watches);
+        draw(); //擦掉第一行的显示
+    }
+    Ok(())
+}
+
+pub fn list() -> Vec<WatchInfo> {
+    WATCHES
+        .lock()
+        .iter()
+        .map(|watch| WatchInfo { label: watch.label.clone(), provider: watch.provider.name, value: watch.value, unit: watch.provider.unit })
+        .collect()
+}
+
+//shell 等待输入时调用：距上次取值足够久时重新取值并显示
+pub fn idle() {
+    if WATCHES.lock().is_empty() {
+        return;
+    }
+    let elapsed = {
+        let mut last = LAST_SAMPLE.lock();
+        let elapsed = last.map(|last| last.elapsed());
+        if elapsed.is_some_and(|elapsed| elapsed < INTERVAL) {
+            return;
+        }
+        *last = Some(Instant::now());
+        elapsed
+    };
+    for watch in WATCHES.lock().iter_mut() {
+        let now = (watch.provider.read)();
+        watch.value = match (watch.provider.kind, elapsed) {
+            (Kind::Gauge, _) => Some(now),
+            (Kind::Rate, Some(elapsed)) => Some(now.wrapping_sub(watch.last) * 1000 / (elapsed.as_millis() as u64).max(1)),
+            (Kind::Rate, None) => None,
+        };
+        watch.last = now;
+    }
+    draw();
+}
+
+fn format_watch(label: &str, value: Option<u64>, unit: &str) -> String {
+    match value {
+        Some(value) => format!("{}={}{}", label, value, unit),
+        None => format!("{}=-", label),
+    }
+}
+
+//在第一行右对齐显示全部观察表达式，不移动光标，也不记入滚动缓冲区
+fn draw() {
+    let width = framebuffer::with_console(|console| console.cols()).unwrap_or(vga_buffer::BUFFER_WIDTH);
+    let text = list().iter().map(|watch| format_watch(&watch.label, watch.value, watch.unit)).collect::<Vec<_>>().join("  ");
+    let text = if text.is_empty() { text } else { format!(" {} ", text) };
+    let len = text.len().min(width);
+    let mut drawn = DRAWN.lock();
+    //上次画得更宽时，多出的部分用空格擦掉
+    let start = width - len.max(*drawn).min(width);
+    for col in start..width - len {
+        vga_buffer::put_char(0, col, b' ', Color::Yellow, Color::Black);
+    }
+    for (i, byte) in text.bytes().skip(text.len() - len).enumerate() {
+        vga_buffer::put_char(0, width - len + i, byte, Color::Black, Color::LightGray);
+    }
+    *drawn = len;
+}
@@ -0,0 +1,119 @@
+//软件看门狗：内核主循环定期调用 touch() 报告自己还在运行，时钟中断检查距离上次报告过了多少个时钟周期
+//超过超时时间说明 CPU 卡在了关中断以外的某个循环里(死锁、忙等不返回)，把寄存器、被持有的锁和调用栈打印到串口
+//每次卡住只打印一次，之后再调用 touch() 才重新开始检查；中断关闭时时钟不走，用户程序和系统调用运行期间不会被误判
+//打印发生在时钟中断里，被打断的代码可能正持有任何锁，所以只写串口(没有串口时用 emergency_println!)，不分配内存
+use crate::drivers::uart::{self, Uart};
+use crate::interrupts::{self, TrapFrame, TIMER_HZ};
+use crate::{emergency_println, memory, sync};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+use x86_64::VirtAddr;
+
+const CPU_COUNT: usize = 1; //还不支持多处理器
+const MAX_FRAMES: usize = 16;
+
+static TIMEOUT_TICKS: AtomicU64 = AtomicU64::new(0); //0 表示关闭
+static SERIAL: AtomicBool = AtomicBool::new(false);
+
+static LAST_TOUCH: [AtomicU64; CPU_COUNT] = [const { AtomicU64::new(0) }; CPU_COUNT];
+static FIRED: [AtomicBool; CPU_COUNT] = [const { AtomicBool::new(false) }; CPU_COUNT];
+
+fn cpu() -> usize {
+    0
+}
+
+//timeout 为 0 时关闭看门狗
+pub fn init(timeout: Duration) {
+    let serial = Uart::new(uart::COM1);
+    if serial.present() {
+        serial.init();
+        SERIAL.store(true, Ordering::Relaxed);
+    }
+    touch();
+    let ticks = timeout.as_millis() as u64 * TIMER_HZ as u64 / 1000;
+    TIMEOUT_TICKS.store(ticks, Ordering::Relaxed);
+}
+
+//报告当前 CPU 还在正常运行
+pub fn touch() {
+    LAST_TOUCH[cpu()].store(interrupts::ticks(), Ordering::Relaxed);
+    FIRED[cpu()].store(false, Ordering::Relaxed);
+}
+
+//每个时钟中断调用
+pub fn tick(frame: &TrapFrame) {
+    let timeout = TIMEOUT_TICKS.load(Ordering::Relaxed);
+    let cpu = cpu();
+    let stuck = interrupts::ticks().wrapping_sub(LAST_TOUCH[cpu].load(Ordering::Relaxed));
+    if timeout == 0 || stuck < timeout || FIRED[cpu].swap(true, Ordering::Relaxed) {
+        return;
+    }
+    if SERIAL.load(Ordering::Relaxed) {
+        let _ = dump(&mut Uart::new(uart::COM1), cpu, stuck, frame);
+    } else {
+        let _ = dump(&mut EmergencyConsole, cpu, stuck, frame);
+    }
+}
+
+//没有串口时的输出：按行交给 emergency_println!，不经过屏幕的锁
+struct EmergencyConsole;
+
+impl Write for EmergencyConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for line in s.split_terminator('\n') {
+            emergency_println!("{}", line);
+        }
+        Ok(())
+    }
+}
+
+fn dump(out: &mut dyn Write, cpu: usize, stuck: u64, frame: &TrapFrame) -> fmt::Result {
+    writeln!(out, "watchdog: cpu {} stuck for {} ms", cpu, stuck * 1000 / TIMER_HZ as u64)?;
+    writeln!(out, "rip {:#018x} rsp {:#018x} rbp {:#018x} rflags {:#x}", frame.rip, frame.rsp, frame.rbp, frame.rflags)?;
+    writeln!(out, "rax {:#018x} rbx {:#018x} rcx {:#018x} rdx {:#018x}", frame.rax, frame.rbx, frame.rcx, frame.rdx)?;
+    writeln!(out, "rsi {:#018x} rdi {:#018x} r8  {:#018x} r9  {:#018x}", frame.rsi, frame.rdi, frame.r8, frame.r9)?;
+    writeln!(out, "r10 {:#018x} r11 {:#018x} r12 {:#018x} r13 {:#018x}", frame.r10, frame.r11, frame.r12, frame.r13)?;
+    writeln!(out, "r14 {:#018x} r15 {:#018x}", frame.r14, frame.r15)?;
+    let mut result = Ok(());
+    let mut held = 0;
+    sync::for_each_held(&mut |name, location| {
+        held += 1;
+        result = result.and(match location {
+            Some(location) => writeln!(out, "held: {} at {}:{}", name, location.file(), location.line()),
+            None => writeln!(out, "held: {}", name),
+        });
+    });
+    result?;
+    if held == 0 {
+        writeln!(out, "held: none")?;
+    }
+    backtrace(out, frame)
+}
+
+//内核用 force-frame-pointers 编译，沿着 rbp 链回溯：[rbp] 是上一层的 rbp，[rbp+8] 是返回地址
+//每一层都先确认地址已经映射，栈被破坏时在第一个读不了的地址停下
+fn backtrace(out: &mut dyn Write, frame: &TrapFrame) -> fmt::Result {
+    writeln!(out, "backtrace:")?;
+    writeln!(out, "  #0 {:#018x}", frame.rip)?;
+    let mut rbp = frame.rbp;
+    for depth in 1..MAX_FRAMES {
+        if rbp == 0 || !rbp.is_multiple_of(8) || !readable(rbp) || !readable(rbp + 8) {
+            break;
+        }
+        let (next, ret) = unsafe { (*(rbp as *const u64), *((rbp + 8) as *const u64)) };
+        if ret == 0 {
+            break;
+        }
+        writeln!(out, "  #{} {:#018x}", depth, ret)?;
+        if next <= rbp {
+            break; //栈向低地址增长，上一层的 rbp 一定更高
+        }
+        rbp = next;
+    }
+    Ok(())
+}
+
+fn readable(addr: u64) -> bool {
+    VirtAddr::try_new(addr).ok().and_then(memory::linear_alias).is_some()
+}
@@ -0,0 +1,301 @@
+//XMODEM 文件传输：通过第一个串口(COM1)和主机交换文件，只有串口的真机上也能把文件传进传出
+//  主机接收：sx /tmp/dump.bin 之后在主机上运行 rx dump.bin(lrzsz)，或者用终端软件的 XMODEM 接收
+//  主机发送：rx /tmp/prog.elf 之后在主机上运行 sx prog.elf
+//接收时支持 128 字节(SOH)和 1 KiB(STX)的块，优先用 CRC-16 校验，主机不支持时退回到累加和；发送时默认 128 字节块，-k 用 1 KiB 块
+//协议不传文件长度，最后一块用 0x1A 补齐；接收时去掉末尾的 0x1A，知道长度时按长度截断
+use crate::drivers::uart::{self, Uart};
+use crate::fs::vfs::FileHandle;
+use crate::fs::FsError;
+use crate::time::Instant;
+use crate::watchdog;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const CRC_REQUEST: u8 = b'C';
+const PAD: u8 = 0x1A;
+
+const BLOCK: usize = 128;
+const BLOCK_1K: usize = 1024;
+const MAX_RETRIES: u32 = 10;
+const START_TIMEOUT: Duration = Duration::from_secs(60); //等待对方开始传输
+const CRC_ATTEMPTS: u32 = 4; //发送这么多次 'C' 没有回应后改用累加和
+const BYTE_TIMEOUT: Duration = Duration::from_secs(1);
+const BLOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum XmodemError {
+    NoPort,        //没有 COM1
+    Timeout,       //对方长时间没有回应
+    Cancelled,     //对方发来 CAN
+    TooManyErrors, //同一块重试太多次
+    Fs(FsError),
+}
+
+impl From<FsError> for XmodemError {
+    fn from(err: FsError) -> XmodemError {
+        XmodemError::Fs(err)
+    }
+}
+
+fn open_port() -> Result<Uart, XmodemError> {
+    let port = Uart::new(uart::COM1);
+    if !port.present() {
+        return Err(XmodemError::NoPort);
+    }
+    port.init();
+    Ok(port)
+}
+
+fn recv_timeout(port: Uart, timeout: Duration) -> Option<u8> {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if let Some(byte) = port.try_recv() {
+            return Some(byte);
+        }
+        watchdog::touch();
+        core::hint::spin_loop();
+    }
+    None
+}
+
+//丢弃对方还在发送的数据，直到线路安静下来
+fn drain(port: Uart) {
+    while recv_timeout(port, BYTE_TIMEOUT).is_some() {}
+}
+
+fn cancel(port: Uart) {
+    for _ in 0..3 {
+        port.send(CAN);
+    }
+}
+
+//CRC-16/XMODEM：多项式 0x1021，初值 0
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { crc << 1 ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+//从 input 读出整个文件发送给主机；progress 的参数为已经确认的字节数
+pub fn send(input: &mut dyn FileHandle, large_blocks: bool, progress: &mut dyn FnMut(u64)) -> Result<u64, XmodemError> {
+    let port = open_port()?;
+    //接收方用 'C' 请求 CRC 校验，用 NAK 请求累加和
+    let deadline = Instant::now() + START_TIMEOUT;
+    let use_crc = loop {
+        match recv_timeout(port, BYTE_TIMEOUT) {
+            Some(CRC_REQUEST) => break true,
+            Some(NAK) => break false,
+            Some(CAN) => return Err(XmodemError::Cancelled),
+            _ if Instant::now() >= deadline => return Err(XmodemError::Timeout),
+            _ => {}
+        }
+    };
+    //1 KiB 块只能和 CRC 一起用
+    let size = if large_blocks && use_crc { BLOCK_1K } else { BLOCK };
+    let mut block = vec![0u8; size];
+    let mut number = 1u8;
+    let mut sent = 0u64;
+    loop {
+        let n = read_full(input, &mut block).inspect_err(|_| cancel(port))?;
+        if n == 0 {
+            break;
+        }
+        block[n..].fill(PAD);
+        send_block(port, number, &block, use_crc)?;
+        number = number.wrapping_add(1);
+        sent += n as u64;
+        progress(sent);
+    }
+    for _ in 0..MAX_RETRIES {
+        port.send(EOT);
+        if recv_timeout(port, BLOCK_TIMEOUT) == Some(ACK) {
+            return Ok(sent);
+        }
+    }
+    Err(XmodemError::TooManyErrors)
+}
+
+//尽量读满 buf，返回读到的字节数，0 表示文件结束
+fn read_full(input: &mut dyn FileHandle, buf: &mut [u8]) -> Result<usize, FsError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn send_block(port: Uart, number: u8, data: &[u8], use_crc: bool) -> Result<(), XmodemError> {
+    for _ in 0..MAX_RETRIES {
+        port.send(if data.len() == BLOCK_1K { STX } else { SOH });
+        port.send(number);
+        port.send(!number);
+        data.iter().for_each(|&b| port.send(b));
+        if use_crc {
+            let crc = crc16(data);
+            port.send((crc >> 8) as u8);
+            port.send(crc as u8);
+        } else {
+            port.send(checksum(data));
+        }
+        match recv_timeout(port, BLOCK_TIMEOUT) {
+            Some(ACK) => return Ok(()),
+            Some(CAN) => return Err(XmodemError::Cancelled),
+            _ => {} //NAK、超时或者杂音：重发
+        }
+    }
+    cancel(port);
+    Err(XmodemError::TooManyErrors)
+}
+
+//接收主机发来的文件写入 output；知道文件长度时传入 size，否则去掉末尾的填充字节
+//返回写入的字节数，progress 的参数为已经收到的字节数
+pub fn receive(output: &mut dyn FileHandle, size: Option<u64>, progress: &mut dyn FnMut(u64)) -> Result<u64, XmodemError> {
+    let port = open_port()?;
+    drain(port);
+    let mut use_crc = true;
+    let mut attempts = 0;
+    let mut expected = 1u8;
+    let mut received = 0u64;
+    let mut written = 0u64;
+    let mut errors = 0;
+    //最后一块要等到 EOT 才知道哪些是填充，所以总是晚一块写入
+    let mut pending: Option<Vec<u8>> = None;
+    let deadline = Instant::now() + START_TIMEOUT;
+    //还没有收到第一块时反复发送开始请求
+    let mut request = Some(CRC_REQUEST);
+    loop {
+        if let Some(byte) = request {
+            port.send(byte);
+        }
+        let header = match recv_timeout(port, if received == 0 { Duration::from_secs(3) } else { BLOCK_TIMEOUT }) {
+            Some(byte) => byte,
+            None if received == 0 => {
+                if Instant::now() >= deadline {
+                    return Err(XmodemError::Timeout);
+                }
+                attempts += 1;
+                if attempts == CRC_ATTEMPTS {
+                    use_crc = false;
+                }
+                request = Some(if use_crc { CRC_REQUEST } else { NAK });
+                continue;
+            }
+            None => {
+                errors += 1;
+                if errors > MAX_RETRIES {
+                    cancel(port);
+                    return Err(XmodemError::Timeout);
+                }
+                request = Some(NAK);
+                continue;
+            }
+        };
+        let len = match header {
+            SOH => BLOCK,
+            STX => BLOCK_1K,
+            EOT => {
+                port.send(ACK);
+                if let Some(mut last) = pending.take() {
+                    match size {
+                        Some(size) => last.truncate(size.saturating_sub(written).min(last.len() as u64) as usize),
+                        None => {
+                            let end = last.iter().rposition(|&b| b != PAD).map_or(0, |i| i + 1);
+                            last.truncate(end);
+                        }
+                    }
+                    write_all(output, &last, port)?;
+                    written += last.len() as u64;
+                }
+                return Ok(written);
+            }
+            CAN => {
+                if recv_timeout(port, BYTE_TIMEOUT) == Some(CAN) {
+                    return Err(XmodemError::Cancelled);
+                }
+                request = None;
+                continue;
+            }
+            _ => {
+                request = None; //块之间的杂音
+                continue;
+            }
+        };
+        request = Some(NAK);
+        let mut packet = vec![0u8; len + 2 + if use_crc { 2 } else { 1 }];
+        if !packet.iter_mut().all(|b| recv_timeout(port, BYTE_TIMEOUT).map(|byte| *b = byte).is_some()) {
+            errors += 1;
+            drain(port);
+            continue;
+        }
+        let (number, inverse) = (packet[0], packet[1]);
+        let data = &packet[2..2 + len];
+        let valid = number == !inverse
+            && if use_crc {
+                crc16(data) == u16::from_be_bytes([packet[2 + len], packet[3 + len]])
+            } else {
+                checksum(data) == packet[2 + len]
+            };
+        if !valid {
+            errors += 1;
+            if errors > MAX_RETRIES {
+                cancel(port);
+                return Err(XmodemError::TooManyErrors);
+            }
+            drain(port);
+            continue;
+        }
+        if number == expected.wrapping_sub(1) && received > 0 {
+            request = Some(ACK); //对方没有收到上次的确认，重发了同一块
+            continue;
+        }
+        if number != expected {
+            cancel(port); //块号不连续，无法恢复
+            return Err(XmodemError::TooManyErrors);
+        }
+        if let Some(previous) = pending.replace(data.to_vec()) {
+            write_all(output, &previous, port)?;
+            written += previous.len() as u64;
+        }
+        errors = 0;
+        expected = expected.wrapping_add(1);
+        received += len as u64;
+        progress(received);
+        request = Some(ACK);
+    }
+}
+
+fn write_all(output: &mut dyn FileHandle, mut data: &[u8], port: Uart) -> Result<(), XmodemError> {
+    while !data.is_empty() {
+        let result = match output.write(data) {
+            Ok(0) => Err(FsError::NoSpace), //设备已写满
+            result => result,
+        };
+        match result {
+            Ok(n) => data = &data[n..],
+            Err(err) => {
+                cancel(port);
+                return Err(err.into());
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+//空闲时预先清零空闲的物理帧：用户页面、页表、共享内存等需要清零的分配可以直接取用，不用当场清零
+//shell 等待输入时每次清零一小批，有按键时很快就能回应；命中率看 memory::zero_hits
+use crate::memory;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+const BATCH: usize = 16; //每次空闲时最多清零的帧数(64 KiB)
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static ZEROED: AtomicU64 = AtomicU64::new(0); //累计在空闲时清零的帧数
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub zeroed: u64,  //累计在空闲时清零的帧数
+    pub pool: usize,  //现在预先清零的帧数
+    pub dirty: usize, //空闲链表上还没有清零的帧数
+    pub hits: u64,    //需要清零的分配直接取到预先清零的帧的次数
+    pub misses: u64,  //只能当场清零的次数
+}
+
+impl Stats {
+    //命中率(百分比)，还没有需要清零的分配时为 None
+    pub fn hit_rate(&self) -> Option<u64> {
+        let total = self.hits + self.misses;
+        (total > 0).then(|| self.hits * 100 / total)
+    }
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn stats() -> Stats {
+    let (pool, dirty) = memory::zero_pool();
+    let (hits, misses) = memory::zero_hits();
+    Stats { zeroed: ZEROED.load(Ordering::Relaxed), pool, dirty, hits, misses }
+}
+
+//shell 等待输入时调用
+pub fn idle() {
+    if enabled() {
+        ZEROED.fetch_add(memory::zero_free_frames(BATCH) as u64, Ordering::Relaxed);
+    }
+}